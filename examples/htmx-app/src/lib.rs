@@ -13,6 +13,10 @@ mod route_tree {
 
 static ASSET_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/static");
 
+/// Key for signing CSRF cookies. A real canister should draw a random
+/// secret (e.g. from `raw_rand`) and keep it in stable memory.
+const CSRF_SECRET: &[u8] = b"htmx-app example secret: replace me";
+
 // ---------------------------------------------------------------------------
 // Canister lifecycle
 // ---------------------------------------------------------------------------
@@ -29,6 +33,7 @@ fn setup() {
                 ..ic_asset_router::AssetConfig::default()
             })
             .with_assets(&ASSET_DIR)
+            .with_state(ic_asset_router::csrf::CsrfConfig::new(CSRF_SECRET))
             .build();
     });
 }
@@ -0,0 +1,14 @@
+use ic_asset_router::{HttpRequest, HttpResponse, RouteParams};
+
+/// Root-level CSRF middleware.
+///
+/// Rejects comment posts that do not carry the token from the CSRF cookie
+/// or that come from another origin, and hands the token to the handlers
+/// that render the comment form.
+pub fn middleware(
+    req: HttpRequest,
+    params: &RouteParams,
+    next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+) -> HttpResponse<'static> {
+    ic_asset_router::csrf::middleware(req, params, next)
+}
@@ -1,7 +1,7 @@
 use std::borrow::Cow;
 
 use askama::Template;
-use ic_asset_router::{HttpResponse, RouteContext, StatusCode};
+use ic_asset_router::{route, HttpResponse, RouteContext, StatusCode};
 
 use crate::data::{self, Comment};
 
@@ -13,14 +13,19 @@ use super::Params;
 struct CommentsTemplate {
     comments: Vec<Comment>,
     post_id: String,
+    csrf_field: String,
 }
 
+/// The partial embeds the visitor's CSRF token in the comment form, so it
+/// is rendered per request instead of cached.
+#[route(certification = "skip")]
 pub fn get(ctx: RouteContext<Params>) -> HttpResponse<'static> {
     let post_id = &ctx.params.post_id;
     let comments = data::comments_for_post(post_id);
     let template = CommentsTemplate {
         comments,
         post_id: post_id.to_string(),
+        csrf_field: ctx.csrf_field().unwrap_or_default(),
     };
 
     render_template(&template)
@@ -28,6 +33,7 @@ pub fn get(ctx: RouteContext<Params>) -> HttpResponse<'static> {
 
 pub fn post(ctx: RouteContext<Params>) -> HttpResponse<'static> {
     let post_id = ctx.params.post_id.clone();
+    let csrf_field = ctx.csrf_field().unwrap_or_default();
 
     let fields = ctx.form_data();
 
@@ -40,12 +46,20 @@ pub fn post(ctx: RouteContext<Params>) -> HttpResponse<'static> {
 
     if body.is_empty() {
         let comments = data::comments_for_post(&post_id);
-        let template = CommentsTemplate { comments, post_id };
+        let template = CommentsTemplate {
+            comments,
+            post_id,
+            csrf_field,
+        };
         return render_template(&template);
     }
 
     let comments = data::add_comment(&post_id, author, body);
-    let template = CommentsTemplate { comments, post_id };
+    let template = CommentsTemplate {
+        comments,
+        post_id,
+        csrf_field,
+    };
     render_template(&template)
 }
 
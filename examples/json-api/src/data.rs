@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 /// An item in our simple JSON API.
 #[derive(Clone, Serialize, Deserialize)]
@@ -14,54 +14,63 @@ pub struct CreateItem {
     pub name: String,
 }
 
-thread_local! {
-    static ITEMS: RefCell<Vec<Item>> = RefCell::new(vec![
-        Item { id: 1, name: "First item".to_string() },
-        Item { id: 2, name: "Second item".to_string() },
-    ]);
-    static NEXT_ID: RefCell<u64> = RefCell::new(3);
+/// The item store, registered as application state with
+/// `setup(..).with_state(Store::default())` and read by handlers with
+/// `ctx.state::<Store>()`.
+pub struct Store {
+    items: RefCell<Vec<Item>>,
+    next_id: Cell<u64>,
 }
 
-pub fn list_items() -> Vec<Item> {
-    ITEMS.with(|items| items.borrow().clone())
+impl Default for Store {
+    fn default() -> Self {
+        Store {
+            items: RefCell::new(vec![
+                Item {
+                    id: 1,
+                    name: "First item".to_string(),
+                },
+                Item {
+                    id: 2,
+                    name: "Second item".to_string(),
+                },
+            ]),
+            next_id: Cell::new(3),
+        }
+    }
 }
 
-pub fn get_item(id: u64) -> Option<Item> {
-    ITEMS.with(|items| items.borrow().iter().find(|i| i.id == id).cloned())
-}
+impl Store {
+    pub fn list_items(&self) -> Vec<Item> {
+        self.items.borrow().clone()
+    }
 
-pub fn create_item(input: CreateItem) -> Item {
-    let id = NEXT_ID.with(|n| {
-        let mut n = n.borrow_mut();
-        let id = *n;
-        *n += 1;
-        id
-    });
-    let item = Item {
-        id,
-        name: input.name,
-    };
-    ITEMS.with(|items| items.borrow_mut().push(item.clone()));
-    item
-}
+    pub fn get_item(&self, id: u64) -> Option<Item> {
+        self.items.borrow().iter().find(|i| i.id == id).cloned()
+    }
 
-pub fn update_item(id: u64, input: CreateItem) -> Option<Item> {
-    ITEMS.with(|items| {
-        let mut items = items.borrow_mut();
-        if let Some(item) = items.iter_mut().find(|i| i.id == id) {
-            item.name = input.name;
-            Some(item.clone())
-        } else {
-            None
-        }
-    })
-}
+    pub fn create_item(&self, input: CreateItem) -> Item {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let item = Item {
+            id,
+            name: input.name,
+        };
+        self.items.borrow_mut().push(item.clone());
+        item
+    }
+
+    pub fn update_item(&self, id: u64, input: CreateItem) -> Option<Item> {
+        let mut items = self.items.borrow_mut();
+        let item = items.iter_mut().find(|i| i.id == id)?;
+        item.name = input.name;
+        Some(item.clone())
+    }
 
-pub fn delete_item(id: u64) -> bool {
-    ITEMS.with(|items| {
-        let mut items = items.borrow_mut();
+    pub fn delete_item(&self, id: u64) -> bool {
+        let mut items = self.items.borrow_mut();
         let len = items.len();
         items.retain(|i| i.id != id);
         items.len() < len
-    })
+    }
 }
@@ -14,7 +14,9 @@ mod route_tree {
 
 fn setup() {
     route_tree::ROUTES.with(|routes| {
-        ic_asset_router::setup(routes).build();
+        ic_asset_router::setup(routes)
+            .with_state(data::Store::default())
+            .build();
     });
 }
 
@@ -1,12 +1,12 @@
 use ic_asset_router::{HttpResponse, RouteContext, StatusCode};
 use std::borrow::Cow;
 
-use crate::data;
+use crate::data::{self, Store};
 
 /// GET /items/:itemId — retrieve a single item by ID.
 pub fn get(ctx: RouteContext<Params>) -> HttpResponse<'static> {
     let id: u64 = ctx.params.item_id.parse().unwrap_or(0);
-    match data::get_item(id) {
+    match ctx.state::<Store>().get_item(id) {
         Some(item) => {
             let body = serde_json::to_string(&item).unwrap();
             HttpResponse::builder()
@@ -39,7 +39,7 @@ pub fn put(ctx: RouteContext<Params>) -> HttpResponse<'static> {
     let input: Result<data::CreateItem, _> = ctx.json();
 
     match input {
-        Ok(update) => match data::update_item(id, update) {
+        Ok(update) => match ctx.state::<Store>().update_item(id, update) {
             Some(item) => {
                 let body = serde_json::to_string(&item).unwrap();
                 HttpResponse::builder()
@@ -78,7 +78,7 @@ pub fn put(ctx: RouteContext<Params>) -> HttpResponse<'static> {
 /// DELETE /items/:itemId — delete an item.
 pub fn delete(ctx: RouteContext<Params>) -> HttpResponse<'static> {
     let id: u64 = ctx.params.item_id.parse().unwrap_or(0);
-    if data::delete_item(id) {
+    if ctx.state::<Store>().delete_item(id) {
         HttpResponse::builder()
             .with_status_code(StatusCode::OK)
             .with_headers(vec![(
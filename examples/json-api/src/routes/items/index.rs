@@ -1,11 +1,11 @@
 use ic_asset_router::{HttpResponse, RouteContext, StatusCode};
 use std::borrow::Cow;
 
-use crate::data;
+use crate::data::{self, Store};
 
 /// GET /items — list all items as a JSON array.
-pub fn get(_ctx: RouteContext<()>) -> HttpResponse<'static> {
-    let items = data::list_items();
+pub fn get(ctx: RouteContext<()>) -> HttpResponse<'static> {
+    let items = ctx.state::<Store>().list_items();
     let body = serde_json::to_string(&items).unwrap_or_else(|_| "[]".to_string());
     HttpResponse::builder()
         .with_status_code(StatusCode::OK)
@@ -25,7 +25,7 @@ pub fn post(ctx: RouteContext<()>) -> HttpResponse<'static> {
 
     match input {
         Ok(create) => {
-            let item = data::create_item(create);
+            let item = ctx.state::<Store>().create_item(create);
             let body = serde_json::to_string(&item).unwrap();
             HttpResponse::builder()
                 .with_status_code(StatusCode::CREATED)
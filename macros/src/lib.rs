@@ -10,7 +10,7 @@ use syn::{
     parse::{Parse, ParseStream},
     parse_macro_input,
     punctuated::Punctuated,
-    Ident, ItemFn, LitInt, LitStr, Token,
+    Ident, ItemFn, LitBool, LitInt, LitStr, Token,
 };
 
 /// Attribute macro for per-route certification configuration.
@@ -38,6 +38,68 @@ use syn::{
 /// ))]
 /// ```
 ///
+/// # Extra response headers
+///
+/// ```rust,ignore
+/// #[route(headers = [("x-frame-options", "SAMEORIGIN"), ("x-robots-tag", "noindex")])]
+/// ```
+///
+/// Route headers are merged into every response for the route, overriding
+/// global headers from `AssetConfig` with the same name.
+///
+/// # Runtime compression
+///
+/// ```rust,ignore
+/// #[route(compress = true)]
+/// ```
+///
+/// Overrides `AssetConfig::compression` for this route: `true` encodes
+/// dynamic responses with gzip and Brotli, `false` never compresses them.
+///
+/// # CORS
+///
+/// ```rust,ignore
+/// #[route(cors(
+///     origins = ["https://app.example.com"],
+///     methods = ["GET", "POST"],
+///     headers = ["content-type", "authorization"],
+///     expose_headers = ["x-request-id"],
+///     credentials = true,
+///     max_age = 600
+/// ))]
+/// ```
+///
+/// Replaces `AssetConfig::cors` for this route. Every field is optional:
+/// `origins` defaults to `["*"]`, `methods` to the methods the route
+/// registers, and `headers` to `["content-type"]`. `credentials = true`
+/// needs explicit `origins` without `"*"`. `OPTIONS` preflights are
+/// answered automatically unless the route exports its own `options`
+/// handler.
+///
+/// # Request body limits
+///
+/// ```rust,ignore
+/// #[route(max_body = 64_000, accepts = ["application/json", "image/*"])]
+/// ```
+///
+/// `max_body` overrides `AssetConfig::max_body` for this route: larger
+/// request bodies are refused with `413 Payload Too Large`. A non-empty body
+/// whose `Content-Type` is not listed in `accepts` is refused with
+/// `415 Unsupported Media Type`. Both checks run before middleware and the
+/// handler.
+///
+/// # Strict search params
+///
+/// ```rust,ignore
+/// #[route(search = "strict")]
+/// ```
+///
+/// By default a query string that fails to deserialize into the route's
+/// `SearchParams` yields `SearchParams::default()`. With `"strict"` the
+/// generated wiring responds `400 Bad Request` instead and the handler is
+/// not called. `"lenient"` spells out the default. Like `path`, this key is
+/// read by the build script; the macro only validates it.
+///
 /// # Path override
 ///
 /// ```rust,ignore
@@ -69,6 +131,21 @@ pub fn route(args: TokenStream, input: TokenStream) -> TokenStream {
 /// Parsed arguments from `#[route(...)]`.
 struct RouteArgs {
     certification: Option<CertificationArg>,
+    /// Additional `(name, value)` response headers for the route.
+    headers: Vec<(String, String)>,
+    /// Per-route runtime compression override.
+    compress: Option<bool>,
+    /// Per-route CORS policy.
+    cors: Option<CorsArgs>,
+    /// Per-route request body size limit, in bytes.
+    max_body: Option<usize>,
+    /// Media types accepted for request bodies.
+    accepts: Vec<String>,
+    /// The `search` mode is consumed by the build script, which generates
+    /// the strict or lenient `SearchParams` deserialization. Parsed here
+    /// only for validation.
+    #[allow(dead_code)]
+    search: Option<String>,
     /// The `path` override is consumed by the build script's text scanner,
     /// not by the macro itself. We parse it to avoid a syn error but do not
     /// use it at expansion time.
@@ -84,6 +161,17 @@ enum CertificationArg {
     Custom(CustomCertConfig),
 }
 
+/// Fields of `cors(...)`. `None` keeps the `CorsConfig` default.
+#[derive(Default)]
+struct CorsArgs {
+    origins: Option<Vec<String>>,
+    methods: Option<Vec<String>>,
+    headers: Option<Vec<String>>,
+    expose_headers: Option<Vec<String>>,
+    credentials: Option<bool>,
+    max_age: Option<u64>,
+}
+
 /// Custom certification configuration fields.
 struct CustomCertConfig {
     request_headers: Vec<String>,
@@ -95,18 +183,36 @@ struct CustomCertConfig {
 impl Parse for RouteArgs {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let mut certification = None;
+        let mut headers = Vec::new();
+        let mut compress = None;
+        let mut cors = None;
+        let mut max_body = None;
+        let mut accepts = Vec::new();
+        let mut search = None;
         let mut path = None;
 
         let args = Punctuated::<RouteArg, Token![,]>::parse_terminated(input)?;
         for arg in args {
             match arg {
                 RouteArg::Certification(c) => certification = Some(c),
+                RouteArg::Headers(h) => headers = h,
+                RouteArg::Compress(c) => compress = Some(c),
+                RouteArg::Cors(c) => cors = Some(c),
+                RouteArg::MaxBody(m) => max_body = Some(m),
+                RouteArg::Accepts(a) => accepts = a,
+                RouteArg::Search(s) => search = Some(s),
                 RouteArg::Path(p) => path = Some(p),
             }
         }
 
         Ok(RouteArgs {
             certification,
+            headers,
+            compress,
+            cors,
+            max_body,
+            accepts,
+            search,
             path,
         })
     }
@@ -115,12 +221,35 @@ impl Parse for RouteArgs {
 /// A single key-value argument in the `#[route(...)]` attribute.
 enum RouteArg {
     Certification(CertificationArg),
+    Headers(Vec<(String, String)>),
+    Compress(bool),
+    Cors(CorsArgs),
+    MaxBody(usize),
+    Accepts(Vec<String>),
+    Search(String),
     Path(String),
 }
 
 impl Parse for RouteArg {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let key: Ident = input.parse()?;
+        if key == "cors" {
+            let content;
+            syn::parenthesized!(content in input);
+            let cors = parse_cors_args(&content)?;
+            let any_origin = cors
+                .origins
+                .as_ref()
+                .is_none_or(|origins| origins.iter().any(|o| o == "*"));
+            if cors.credentials == Some(true) && any_origin {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "`credentials = true` requires listed `origins`; \
+                     it cannot be combined with \"*\"",
+                ));
+            }
+            return Ok(RouteArg::Cors(cors));
+        }
         let _eq: Token![=] = input.parse()?;
 
         match key.to_string().as_str() {
@@ -160,6 +289,53 @@ impl Parse for RouteArg {
                     Ok(RouteArg::Certification(CertificationArg::Custom(custom)))
                 }
             }
+            "headers" => {
+                let headers = parse_header_array(input)?;
+                Ok(RouteArg::Headers(headers))
+            }
+            "compress" => {
+                let lit: LitBool = input.parse()?;
+                Ok(RouteArg::Compress(lit.value))
+            }
+            "max_body" => {
+                let lit: LitInt = input.parse()?;
+                Ok(RouteArg::MaxBody(lit.base10_parse()?))
+            }
+            "accepts" => {
+                let content;
+                syn::bracketed!(content in input);
+                let items = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+                for lit in &items {
+                    let value = lit.value();
+                    match value.split_once('/') {
+                        Some((ty, subtype)) if !ty.is_empty() && !subtype.is_empty() => {}
+                        _ => {
+                            return Err(syn::Error::new(
+                                lit.span(),
+                                format!(
+                                    "invalid media type \"{value}\". Expected \"type/subtype\" \
+                                     or \"type/*\""
+                                ),
+                            ))
+                        }
+                    }
+                }
+                Ok(RouteArg::Accepts(
+                    items.iter().map(|lit| lit.value()).collect(),
+                ))
+            }
+            "search" => {
+                let lit: LitStr = input.parse()?;
+                match lit.value().as_str() {
+                    "strict" | "lenient" => Ok(RouteArg::Search(lit.value())),
+                    other => Err(syn::Error::new(
+                        lit.span(),
+                        format!(
+                            "unknown search mode \"{other}\". Expected \"strict\" or \"lenient\""
+                        ),
+                    )),
+                }
+            }
             "path" => {
                 let lit: LitStr = input.parse()?;
                 Ok(RouteArg::Path(lit.value()))
@@ -167,7 +343,8 @@ impl Parse for RouteArg {
             other => Err(syn::Error::new(
                 key.span(),
                 format!(
-                    "unknown route attribute key `{other}`. Expected `certification` or `path`"
+                    "unknown route attribute key `{other}`. Expected `certification`, \
+                     `headers`, `compress`, `cors`, `max_body`, `accepts`, `search`, or `path`"
                 ),
             )),
         }
@@ -241,6 +418,64 @@ impl Parse for CustomField {
     }
 }
 
+/// Parse the interior of `cors(...)`.
+fn parse_cors_args(input: ParseStream) -> syn::Result<CorsArgs> {
+    let mut cors = CorsArgs::default();
+
+    let fields = Punctuated::<CorsField, Token![,]>::parse_terminated(input)?;
+    for field in fields {
+        match field {
+            CorsField::Origins(v) => cors.origins = Some(v),
+            CorsField::Methods(v) => cors.methods = Some(v),
+            CorsField::Headers(v) => cors.headers = Some(v),
+            CorsField::ExposeHeaders(v) => cors.expose_headers = Some(v),
+            CorsField::Credentials(v) => cors.credentials = Some(v),
+            CorsField::MaxAge(v) => cors.max_age = Some(v),
+        }
+    }
+
+    Ok(cors)
+}
+
+/// A single field inside `cors(...)`.
+enum CorsField {
+    Origins(Vec<String>),
+    Methods(Vec<String>),
+    Headers(Vec<String>),
+    ExposeHeaders(Vec<String>),
+    Credentials(bool),
+    MaxAge(u64),
+}
+
+impl Parse for CorsField {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key: Ident = input.parse()?;
+        let _eq: Token![=] = input.parse()?;
+
+        match key.to_string().as_str() {
+            "origins" => Ok(CorsField::Origins(parse_string_array(input)?)),
+            "methods" => Ok(CorsField::Methods(parse_string_array(input)?)),
+            "headers" => Ok(CorsField::Headers(parse_string_array(input)?)),
+            "expose_headers" => Ok(CorsField::ExposeHeaders(parse_string_array(input)?)),
+            "credentials" => {
+                let lit: LitBool = input.parse()?;
+                Ok(CorsField::Credentials(lit.value))
+            }
+            "max_age" => {
+                let lit: LitInt = input.parse()?;
+                Ok(CorsField::MaxAge(lit.base10_parse()?))
+            }
+            other => Err(syn::Error::new(
+                key.span(),
+                format!(
+                    "unknown cors field `{other}`. Expected `origins`, `methods`, \
+                     `headers`, `expose_headers`, `credentials`, or `max_age`"
+                ),
+            )),
+        }
+    }
+}
+
 /// Parse a `["foo", "bar"]` array of string literals.
 fn parse_string_array(input: ParseStream) -> syn::Result<Vec<String>> {
     let content;
@@ -249,6 +484,43 @@ fn parse_string_array(input: ParseStream) -> syn::Result<Vec<String>> {
     Ok(items.iter().map(|lit| lit.value()).collect())
 }
 
+/// Parse a `[("name", "value"), ...]` array of header tuples.
+fn parse_header_array(input: ParseStream) -> syn::Result<Vec<(String, String)>> {
+    let content;
+    syn::bracketed!(content in input);
+    let items = Punctuated::<HeaderTuple, Token![,]>::parse_terminated(&content)?;
+    Ok(items.into_iter().map(|h| (h.name, h.value)).collect())
+}
+
+/// A single `("name", "value")` header tuple.
+struct HeaderTuple {
+    name: String,
+    value: String,
+}
+
+impl Parse for HeaderTuple {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let content;
+        syn::parenthesized!(content in input);
+        let name: LitStr = content.parse()?;
+        let _comma: Token![,] = content.parse()?;
+        let value: LitStr = content.parse()?;
+        if !content.is_empty() {
+            return Err(content.error("expected a (\"name\", \"value\") header tuple"));
+        }
+        if name.value().is_empty() {
+            return Err(syn::Error::new(
+                name.span(),
+                "header name must not be empty",
+            ));
+        }
+        Ok(HeaderTuple {
+            name: name.value(),
+            value: value.value(),
+        })
+    }
+}
+
 /// Generate the `RouteConfig { ... }` token stream from parsed args.
 fn generate_route_config(args: &RouteArgs) -> proc_macro2::TokenStream {
     let cert_tokens = match &args.certification {
@@ -302,11 +574,65 @@ fn generate_route_config(args: &RouteArgs) -> proc_macro2::TokenStream {
         _ => quote! { None },
     };
 
+    let header_names = args.headers.iter().map(|(name, _)| name);
+    let header_values = args.headers.iter().map(|(_, value)| value);
+
+    let compress_tokens = match args.compress {
+        Some(value) => quote! { Some(#value) },
+        None => quote! { None },
+    };
+
+    let cors_tokens = match &args.cors {
+        Some(cors) => {
+            let string_vec = |values: &Option<Vec<String>>| {
+                values
+                    .as_ref()
+                    .map(|v| quote! { vec![#(#v.to_string()),*] })
+            };
+            let mut fields = Vec::new();
+            if let Some(v) = string_vec(&cors.origins) {
+                fields.push(quote! { allowed_origins: #v, });
+            }
+            if let Some(v) = string_vec(&cors.methods) {
+                fields.push(quote! { allowed_methods: #v, });
+            }
+            if let Some(v) = string_vec(&cors.headers) {
+                fields.push(quote! { allowed_headers: #v, });
+            }
+            if let Some(v) = string_vec(&cors.expose_headers) {
+                fields.push(quote! { exposed_headers: #v, });
+            }
+            if let Some(v) = cors.credentials {
+                fields.push(quote! { allow_credentials: #v, });
+            }
+            if let Some(v) = cors.max_age {
+                fields.push(quote! { max_age: Some(#v), });
+            }
+            quote! {
+                Some(ic_asset_router::CorsConfig {
+                    #(#fields)*
+                    ..ic_asset_router::CorsConfig::default()
+                })
+            }
+        }
+        None => quote! { None },
+    };
+
+    let max_body_tokens = match args.max_body {
+        Some(value) => quote! { Some(#value) },
+        None => quote! { None },
+    };
+    let accepts = &args.accepts;
+
     quote! {
         ic_asset_router::RouteConfig {
             certification: #cert_tokens,
             ttl: #ttl_tokens,
-            headers: vec![],
+            headers: vec![#((#header_names.to_string(), #header_values.to_string())),*],
+            compress: #compress_tokens,
+            cors: #cors_tokens,
+            max_body: #max_body_tokens,
+            accepts: vec![#(#accepts.to_string()),*],
         }
     }
 }
@@ -20,32 +20,91 @@
 ///   (e.g., `/` and `/index.html`).
 /// - **TTL-based expiry** — dynamic assets can have an optional TTL for
 ///   automatic cache invalidation.
+/// - **Streaming, range requests and chunking** — assets larger than
+///   [`ASSET_CHUNK_SIZE`] are streamed through IC streaming callbacks, or
+///   served as `206 Partial Content` chunks selected by the request's
+///   `Range` header.
+/// - **`HEAD` requests** — answered with the asset's headers and no body,
+///   backed by their own certification.
+///
+/// # Large assets
+///
+/// A body larger than [`ASSET_CHUNK_SIZE`] cannot be sent in one response,
+/// so it is certified twice over:
+///
+/// - As a whole, for streaming. [`AssetRouter::serve_asset_streaming`]
+///   answers a `GET` without `Range` with the full response carrying the
+///   first chunk and a [`StreamingCallbackToken`];
+///   [`AssetRouter::streaming_chunk`] returns the following chunks to the
+///   HTTP gateway's streaming callback, and the gateway verifies the
+///   assembled body. See [`crate::streaming`] for the canister methods.
+/// - Chunk by chunk, for `Range` requests. As in `ic-asset-certification`
+///   and the IC asset canister, each chunk is certified as its own `206`
+///   response with a `Content-Range` header.
+///
+/// [`AssetRouter::serve_asset`] answers a request without `Range` with the
+/// full body in a `200`, which only fits in a response for bodies below the
+/// IC's response size limit; canisters serving larger assets must stream.
+///
+/// Only whole chunks are certified, so a range must start on a chunk
+/// boundary, as the gateway's ranges do. A range that starts elsewhere or
+/// past the end of the body gets a certified `416 Range Not Satisfiable`
+/// with `Content-Range: bytes */<length>`. Assets that fit in a single
+/// chunk ignore `Range` and are served in full with their own status code,
+/// as RFC 9110 permits.
+///
+/// # `HEAD` requests
+///
+/// A `HEAD` response has the identity variant's headers and an empty body,
+/// so the `GET` certification does not cover it. Response-only assets
+/// therefore certify a second, bodiless response using full certification
+/// with no request headers or query parameters: the proof binds the `HEAD`
+/// method, so an empty body can never verify as the answer to a `GET`.
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::time::Duration;
 
+use candid::CandidType;
 use ic_http_certification::{
-    cel::DefaultRequestCertification, DefaultCelBuilder, DefaultResponseCertification,
-    DefaultResponseOnlyCelExpression, HeaderField, HttpCertification, HttpCertificationPath,
-    HttpCertificationTree, HttpCertificationTreeEntry, HttpRequest, HttpResponse, StatusCode,
-    CERTIFICATE_EXPRESSION_HEADER_NAME,
+    cel::DefaultRequestCertification, DefaultCelBuilder, DefaultFullCelExpression,
+    DefaultResponseCertification, DefaultResponseOnlyCelExpression, HeaderField, HttpCertification,
+    HttpCertificationPath, HttpCertificationTree, HttpCertificationTreeEntry, HttpRequest,
+    HttpResponse, Method, StatusCode, CERTIFICATE_EXPRESSION_HEADER_NAME,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::certification::{CertificationMode, ResponseOnlyConfig};
+use crate::conditional::{etag_for, format_http_date, is_not_modified};
 use crate::mime::get_mime_type;
+use crate::streaming::{StreamingCallbackHttpResponse, StreamingCallbackToken};
 
 // ---------------------------------------------------------------------------
 // Types
 // ---------------------------------------------------------------------------
 
+/// Largest body served in a single response, in bytes.
+///
+/// Assets with a larger identity body are split into chunks of this size.
+/// Matches the chunk size used by `ic-asset-certification`, keeping each
+/// response well below the IC's response size limit.
+///
+/// Only the identity body is chunked. A Gzip or Brotli variant larger than
+/// this is dropped when the asset is certified (logged with the
+/// `debug-logging` feature), and the asset is served uncompressed: a large compressible
+/// asset such as a 3 MB JavaScript bundle loses its encoded variants unless
+/// they compress below this size.
+pub const ASSET_CHUNK_SIZE: usize = 2_000_000;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
 /// Content encoding variants supported by the asset router.
 ///
 /// When serving an asset, the router selects the best encoding based on
 /// the client's `Accept-Encoding` header, preferring Brotli over Gzip
 /// over Identity.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, CandidType, Serialize, Deserialize)]
 pub enum AssetEncoding {
     /// No encoding (identity). Always available for every asset.
     Identity,
@@ -101,6 +160,48 @@ pub struct CertifiedAsset {
     /// Tree entry for generating witnesses.
     pub tree_entry: HttpCertificationTreeEntry<'static>,
 
+    /// Tree entries for the encoded variants in [`encodings`](Self::encodings).
+    ///
+    /// Each encoded body is certified as its own response (with the matching
+    /// `content-encoding` header), so serving a variant needs a witness for
+    /// that variant rather than for the identity body. Empty in `Skip` mode,
+    /// where [`tree_entry`](Self::tree_entry) covers every variant.
+    pub encoding_entries: HashMap<AssetEncoding, HttpCertificationTreeEntry<'static>>,
+
+    /// Tree entries for the `206` chunks of a [chunked](Self::is_chunked)
+    /// asset, in order.
+    ///
+    /// [`tree_entry`](Self::tree_entry) certifies the whole body, which is
+    /// streamed through the streaming callback. Empty for assets that fit
+    /// in one chunk and in `Skip` mode.
+    pub chunk_entries: Vec<HttpCertificationTreeEntry<'static>>,
+
+    /// Tree entry for the `416 Range Not Satisfiable` response of a
+    /// [chunked](Self::is_chunked) asset.
+    ///
+    /// `None` for assets that fit in one chunk and in `Skip` mode, where
+    /// [`tree_entry`](Self::tree_entry) covers the 416 as well.
+    pub range_not_satisfiable_entry: Option<HttpCertificationTreeEntry<'static>>,
+
+    /// `ETag` served with each variant in [`encodings`](Self::encodings).
+    ///
+    /// A quoted SHA-256 of the variant's body, unless the asset's headers
+    /// already carry an `etag`, in which case every variant uses that value.
+    pub etags: HashMap<AssetEncoding, String>,
+
+    /// Tree entries for the `304 Not Modified` response of each variant.
+    ///
+    /// Empty in `Skip` mode, where [`tree_entry`](Self::tree_entry) covers
+    /// the 304 as well.
+    pub not_modified_entries: HashMap<AssetEncoding, HttpCertificationTreeEntry<'static>>,
+
+    /// Tree entry for the bodiless response to a `HEAD` request.
+    ///
+    /// Only response-only assets have one. In `Skip` mode
+    /// [`tree_entry`](Self::tree_entry) covers `HEAD` as well; `Full` mode
+    /// assets are never served from the query path.
+    pub head_entry: Option<HttpCertificationTreeEntry<'static>>,
+
     /// Whether this asset can serve as a fallback for paths in its scope.
     pub fallback_scope: Option<String>,
 
@@ -145,6 +246,141 @@ impl CertifiedAsset {
             }
         }
     }
+
+    /// Tree entry that certifies the response served with `encoding`.
+    ///
+    /// Falls back to [`tree_entry`](Self::tree_entry) for `Identity` and for
+    /// `Skip` mode assets.
+    pub fn tree_entry_for(&self, encoding: AssetEncoding) -> &HttpCertificationTreeEntry<'static> {
+        self.encoding_entries
+            .get(&encoding)
+            .unwrap_or(&self.tree_entry)
+    }
+
+    /// Returns true if the identity body is larger than [`ASSET_CHUNK_SIZE`]
+    /// and is served in `206 Partial Content` chunks.
+    pub fn is_chunked(&self) -> bool {
+        self.content.len() > ASSET_CHUNK_SIZE
+    }
+
+    /// Number of chunks the identity body is served in (`1` if unchunked).
+    pub fn chunk_count(&self) -> usize {
+        self.content.len().div_ceil(ASSET_CHUNK_SIZE).max(1)
+    }
+
+    /// Tree entry that certifies chunk `index` of the identity body.
+    ///
+    /// Falls back to [`tree_entry`](Self::tree_entry) for `Skip` mode
+    /// assets.
+    pub fn tree_entry_for_chunk(&self, index: usize) -> &HttpCertificationTreeEntry<'static> {
+        self.chunk_entries.get(index).unwrap_or(&self.tree_entry)
+    }
+
+    /// Seconds since the epoch advertised in `Last-Modified`, or `None` for
+    /// assets certified without a timestamp (static assets).
+    pub fn last_modified(&self) -> Option<u64> {
+        (self.certified_at > 0).then_some(self.certified_at / NANOS_PER_SEC)
+    }
+
+    /// All tree entries owned by this asset (identity, encoded variants,
+    /// chunks, 416, 304 and `HEAD` responses).
+    fn tree_entries(&self) -> impl Iterator<Item = &HttpCertificationTreeEntry<'static>> {
+        std::iter::once(&self.tree_entry)
+            .chain(self.encoding_entries.values())
+            .chain(self.chunk_entries.iter())
+            .chain(self.range_not_satisfiable_entry.iter())
+            .chain(self.not_modified_entries.values())
+            .chain(self.head_entry.iter())
+    }
+
+    /// Capture this asset as a serializable [`CertifiedAssetSnapshot`].
+    ///
+    /// `path` is the canonical path the asset is stored under. The tree
+    /// entry and CEL expression are not captured; both are derived again
+    /// from the certification mode when the snapshot is restored.
+    pub fn to_snapshot(&self, path: &str) -> CertifiedAssetSnapshot {
+        let mut encodings: Vec<(AssetEncoding, Vec<u8>)> = self
+            .encodings
+            .iter()
+            .filter(|(encoding, _)| **encoding != AssetEncoding::Identity)
+            .map(|(encoding, content)| (*encoding, content.clone()))
+            .collect();
+        encodings.sort_by_key(|(encoding, _)| encoding.as_str());
+
+        CertifiedAssetSnapshot {
+            path: path.to_string(),
+            content: self.content.clone(),
+            encodings,
+            content_type: self.content_type.clone(),
+            status_code: self.status_code.as_u16(),
+            headers: self.headers.clone(),
+            certification_mode: self.certification_mode.clone(),
+            fallback_scope: self.fallback_scope.clone(),
+            aliases: self.aliases.clone(),
+            certified_at: self.certified_at,
+            ttl: self.ttl,
+            dynamic: self.dynamic,
+        }
+    }
+}
+
+/// Serializable form of a [`CertifiedAsset`].
+///
+/// Holds everything needed to re-certify an asset after a canister upgrade:
+/// content, encoded variants, response metadata, certification mode,
+/// fallback scope, aliases, certification timestamp, and TTL. Produced by
+/// [`CertifiedAsset::to_snapshot`] and consumed by
+/// [`AssetRouter::restore_snapshot`].
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CertifiedAssetSnapshot {
+    /// Canonical path the asset is stored under.
+    pub path: String,
+
+    /// Raw content (for Identity encoding).
+    pub content: Vec<u8>,
+
+    /// Encoded variants (Gzip, Brotli). Identity is not repeated here.
+    pub encodings: Vec<(AssetEncoding, Vec<u8>)>,
+
+    /// MIME type (e.g., "text/html").
+    pub content_type: String,
+
+    /// HTTP status code for the response.
+    pub status_code: u16,
+
+    /// Additional headers included in the response.
+    pub headers: Vec<HeaderField>,
+
+    /// Certification mode for this asset.
+    pub certification_mode: CertificationMode,
+
+    /// Fallback scope, if the asset serves as a fallback.
+    pub fallback_scope: Option<String>,
+
+    /// Additional paths that alias to this asset.
+    pub aliases: Vec<String>,
+
+    /// Timestamp (nanoseconds) when the asset was originally certified.
+    pub certified_at: u64,
+
+    /// Optional TTL for cache invalidation.
+    pub ttl: Option<Duration>,
+
+    /// Whether this asset was dynamically generated.
+    pub dynamic: bool,
+}
+
+/// Serializable snapshot of the dynamic assets held by an [`AssetRouter`].
+///
+/// Store this in stable memory during `pre_upgrade` and pass it to
+/// [`AssetRouter::restore_snapshot`] during `post_upgrade` to keep
+/// previously generated responses cached across upgrades. See
+/// [`pre_upgrade`](crate::assets::pre_upgrade) and
+/// [`post_upgrade`](crate::assets::post_upgrade) for ready-made helpers.
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct AssetRouterSnapshot {
+    /// Captured assets, sorted by canonical path.
+    pub assets: Vec<CertifiedAssetSnapshot>,
 }
 
 /// Configuration passed to [`AssetRouter::certify_asset`] or
@@ -221,6 +457,8 @@ pub enum AssetRouterError {
     FullModeRequiresRequest,
     /// Asset not found at the given path.
     AssetNotFound(String),
+    /// A snapshot entry could not be restored (e.g. invalid status code).
+    InvalidSnapshot(String),
 }
 
 impl std::fmt::Display for AssetRouterError {
@@ -238,6 +476,9 @@ impl std::fmt::Display for AssetRouterError {
             AssetRouterError::AssetNotFound(path) => {
                 write!(f, "Asset not found: {}", path)
             }
+            AssetRouterError::InvalidSnapshot(msg) => {
+                write!(f, "Invalid asset snapshot: {}", msg)
+            }
         }
     }
 }
@@ -341,6 +582,60 @@ impl AssetRouter {
             .map(|(path, _)| path.clone())
             .collect()
     }
+
+    /// Capture all dynamic assets as a serializable [`AssetRouterSnapshot`].
+    ///
+    /// Static assets are not included — they are re-certified from the
+    /// embedded directories during `post_upgrade`. `Full`-mode assets are
+    /// also skipped: they are certified against the original request, and
+    /// the query path never serves them from the cache anyway.
+    pub fn dynamic_snapshot(&self) -> AssetRouterSnapshot {
+        let mut assets: Vec<CertifiedAssetSnapshot> = self
+            .assets
+            .iter()
+            .filter(|(_, asset)| {
+                asset.is_dynamic()
+                    && !matches!(asset.certification_mode, CertificationMode::Full(_))
+            })
+            .map(|(path, asset)| asset.to_snapshot(path))
+            .collect();
+        assets.sort_by(|a, b| a.path.cmp(&b.path));
+        AssetRouterSnapshot { assets }
+    }
+
+    /// Re-certify every asset in `snapshot`, rebuilding its tree entry.
+    ///
+    /// Each asset keeps its original `certified_at` timestamp so TTL expiry
+    /// continues where it left off. Assets already present at the same path
+    /// are replaced. The caller is responsible for calling
+    /// `certified_data_set` with the new [`root_hash`](Self::root_hash).
+    pub fn restore_snapshot(
+        &mut self,
+        snapshot: AssetRouterSnapshot,
+    ) -> Result<(), AssetRouterError> {
+        for asset in snapshot.assets {
+            let status_code = StatusCode::from_u16(asset.status_code).map_err(|_| {
+                AssetRouterError::InvalidSnapshot(format!(
+                    "invalid status code {} for {}",
+                    asset.status_code, asset.path
+                ))
+            })?;
+            let config = AssetCertificationConfig {
+                mode: asset.certification_mode,
+                content_type: Some(asset.content_type),
+                status_code,
+                headers: asset.headers,
+                encodings: asset.encodings,
+                fallback_for: asset.fallback_scope,
+                aliases: asset.aliases,
+                certified_at: asset.certified_at,
+                ttl: asset.ttl,
+                dynamic: asset.dynamic,
+            };
+            self.certify_asset(&asset.path, asset.content, config)?;
+        }
+        Ok(())
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -395,6 +690,41 @@ fn build_response_certification<'a>(
     }
 }
 
+/// CEL expression for the `HEAD` response of a response-only asset.
+///
+/// Full certification with no request headers or query parameters, so only
+/// the method (and the empty request body) is bound. Returns `None` for
+/// `Skip` and `Full` modes, which get no separate `HEAD` certification.
+fn head_cel_expression_string(mode: &CertificationMode) -> Option<String> {
+    let CertificationMode::ResponseOnly(config) = mode else {
+        return None;
+    };
+    Some(
+        DefaultCelBuilder::full_certification()
+            .with_response_certification(build_response_certification(config))
+            .build()
+            .to_string(),
+    )
+}
+
+/// Certify `response` as the answer to a `HEAD` request for `path`.
+fn create_head_certification(
+    config: &ResponseOnlyConfig,
+    path: &str,
+    response: &HttpResponse<'_>,
+) -> Result<HttpCertification, AssetRouterError> {
+    let expr = DefaultFullCelExpression {
+        request: DefaultRequestCertification::new(vec![], vec![]),
+        response: build_response_certification(config),
+    };
+    let request = HttpRequest::builder()
+        .with_method(Method::HEAD)
+        .with_url(path)
+        .build();
+    HttpCertification::full(&expr, &request, response, None)
+        .map_err(|e| AssetRouterError::CertificationFailed(e.to_string()))
+}
+
 /// Create an `HttpCertification` for the Skip or ResponseOnly modes.
 ///
 /// `Full` mode is not handled here — use `create_full_certification` instead.
@@ -446,6 +776,184 @@ fn create_certification_with_request(
     }
 }
 
+/// Returns true if `headers` contains `name` (case-insensitive).
+fn has_header(headers: &[HeaderField], name: &str) -> bool {
+    headers.iter().any(|(k, _)| k.eq_ignore_ascii_case(name))
+}
+
+/// Append `last-modified` for assets certified at a known time.
+///
+/// Dynamic assets advertise when they were generated. Static assets are
+/// certified with `certified_at: 0` and rely on the ETag alone. An existing
+/// `last-modified` header is left untouched.
+fn push_last_modified(headers: &mut Vec<HeaderField>, certified_at: u64) {
+    if certified_at > 0 && !has_header(headers, "last-modified") {
+        headers.push((
+            "last-modified".to_string(),
+            format_http_date(certified_at / NANOS_PER_SEC),
+        ));
+    }
+}
+
+/// Append `vary: accept-encoding` unless a `vary` header already covers it.
+///
+/// Added to every variant of an asset certified in more than one encoding,
+/// so caches between the gateway and the client key on the negotiated
+/// encoding.
+fn vary_by_accept_encoding(headers: &mut Vec<HeaderField>) {
+    let varies = headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("vary")
+            && value
+                .split(',')
+                .any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case("accept-encoding"))
+    });
+    if !varies {
+        headers.push(("vary".to_string(), "accept-encoding".to_string()));
+    }
+}
+
+/// Headers shared by every variant of a stored asset's response.
+fn asset_base_headers(asset: &CertifiedAsset) -> Vec<HeaderField> {
+    let mut headers = vec![
+        ("content-type".to_string(), asset.content_type.clone()),
+        (
+            CERTIFICATE_EXPRESSION_HEADER_NAME.to_string(),
+            asset.cel_expression.clone(),
+        ),
+    ];
+    for (name, value) in &asset.headers {
+        headers.push((name.clone(), value.clone()));
+    }
+    push_last_modified(&mut headers, asset.certified_at);
+    if asset.encodings.len() > 1 {
+        vary_by_accept_encoding(&mut headers);
+    }
+    headers
+}
+
+/// Headers for the `encoding` variant of an asset response.
+///
+/// Appends the variant's `etag` (unless `base_headers` already carries one)
+/// and, for encoded variants, `content-encoding`. Used both when certifying
+/// and when serving, so the two always agree.
+fn variant_headers(
+    base_headers: &[HeaderField],
+    encoding: AssetEncoding,
+    etag: Option<&String>,
+) -> Vec<HeaderField> {
+    let mut headers = base_headers.to_vec();
+    if let Some(etag) = etag {
+        if !has_header(base_headers, "etag") {
+            headers.push(("etag".to_string(), etag.clone()));
+        }
+    }
+    if encoding != AssetEncoding::Identity {
+        headers.push((
+            "content-encoding".to_string(),
+            encoding.as_str().to_string(),
+        ));
+    }
+    headers
+}
+
+/// Build the bodiless `304 Not Modified` response for a variant.
+fn not_modified_response(headers: &[HeaderField]) -> HttpResponse<'static> {
+    HttpResponse::builder()
+        .with_status_code(StatusCode::NOT_MODIFIED)
+        .with_headers(headers.to_vec())
+        .build()
+}
+
+/// Build the bodiless response to a `HEAD` request.
+///
+/// `headers` are the identity variant's headers; when `head_cel` is given it
+/// replaces the `ic-certificateexpression` value. Used both when certifying
+/// and when serving, so the two always agree.
+fn head_response(
+    status_code: StatusCode,
+    headers: &[HeaderField],
+    head_cel: Option<&str>,
+) -> HttpResponse<'static> {
+    let mut headers = headers.to_vec();
+    if let Some(cel) = head_cel {
+        for (name, value) in headers.iter_mut() {
+            if name.eq_ignore_ascii_case(CERTIFICATE_EXPRESSION_HEADER_NAME) {
+                *value = cel.to_string();
+            }
+        }
+    }
+    HttpResponse::builder()
+        .with_status_code(status_code)
+        .with_headers(headers)
+        .build()
+}
+
+/// Build the `206 Partial Content` response for chunk `index` of `body`.
+///
+/// `accept-ranges` and `content-range` are appended to `base_headers`. Used
+/// both when certifying and when serving a chunk, so the served response
+/// always matches its certification.
+fn chunk_response(
+    base_headers: &[HeaderField],
+    body: &[u8],
+    index: usize,
+) -> HttpResponse<'static> {
+    let start = index * ASSET_CHUNK_SIZE;
+    let end = (start + ASSET_CHUNK_SIZE).min(body.len());
+    let mut headers = base_headers.to_vec();
+    headers.push(("accept-ranges".to_string(), "bytes".to_string()));
+    headers.push((
+        "content-range".to_string(),
+        format!("bytes {}-{}/{}", start, end - 1, body.len()),
+    ));
+    HttpResponse::builder()
+        .with_status_code(StatusCode::PARTIAL_CONTENT)
+        .with_headers(headers)
+        .with_body(Cow::<[u8]>::Owned(body[start..end].to_vec()))
+        .build()
+}
+
+/// Build the `416 Range Not Satisfiable` response for a chunked `body`.
+///
+/// Like [`chunk_response`], used both when certifying and when serving.
+fn range_not_satisfiable_response(
+    base_headers: &[HeaderField],
+    body: &[u8],
+) -> HttpResponse<'static> {
+    let mut headers = base_headers.to_vec();
+    headers.push(("accept-ranges".to_string(), "bytes".to_string()));
+    headers.push((
+        "content-range".to_string(),
+        format!("bytes */{}", body.len()),
+    ));
+    HttpResponse::builder()
+        .with_status_code(StatusCode::RANGE_NOT_SATISFIABLE)
+        .with_headers(headers)
+        .build()
+}
+
+/// Parse the first byte offset requested by a `Range: bytes=...` header.
+///
+/// Supports `bytes=N-`, `bytes=N-M`, and suffix ranges `bytes=-N` (resolved
+/// against `total_len`). Only the first range of a multi-range request is
+/// used. Returns `None` if there is no `Range` header or it cannot be
+/// parsed, in which case the header is ignored.
+fn parse_range_start(request: &HttpRequest, total_len: usize) -> Option<usize> {
+    let value = request
+        .headers()
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("range"))
+        .map(|(_, v)| v.as_str())?;
+    let spec = value.trim().strip_prefix("bytes=")?.split(',').next()?;
+    let (start, end) = spec.trim().split_once('-')?;
+    if start.is_empty() {
+        let suffix_len: usize = end.trim().parse().ok()?;
+        Some(total_len.saturating_sub(suffix_len))
+    } else {
+        start.trim().parse().ok()
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Core implementation
 // ---------------------------------------------------------------------------
@@ -458,24 +966,44 @@ impl AssetRouter {
     ///   method builds one from `body` + config headers (static path).
     /// - `request`: when `Some`, uses request-aware certification (Full mode).
     ///   When `None`, uses response-only or skip certification.
+    ///
+    /// Bodies larger than [`ASSET_CHUNK_SIZE`] are certified chunk by chunk
+    /// as well as whole. Encoded variants larger than one chunk are dropped
+    /// (logged with the `debug-logging` feature), since they could not be
+    /// served in a single response.
     fn certify_inner(
         &mut self,
         path: &str,
         body: Vec<u8>,
         response_for_cert: Option<&HttpResponse<'static>>,
         request: Option<&HttpRequest>,
-        config: AssetCertificationConfig,
+        mut config: AssetCertificationConfig,
     ) -> Result<(), AssetRouterError> {
+        config.encodings.retain(|(_encoding, encoded)| {
+            let fits = encoded.len() <= ASSET_CHUNK_SIZE;
+            if !fits {
+                debug_log!(
+                    "certify_inner: dropping the {} variant of {} ({} bytes); \
+                     encoded variants must fit in one {}-byte chunk",
+                    _encoding.as_str(),
+                    path,
+                    encoded.len(),
+                    ASSET_CHUNK_SIZE
+                );
+            }
+            fits
+        });
+
         let content_type = config
             .content_type
             .unwrap_or_else(|| get_mime_type(path).to_string());
 
         let cel_str = build_cel_expression_string(&config.mode);
 
-        // Build or augment the response used for certification.
-        let cert_response = match response_for_cert {
+        // Status and headers shared by every variant of the response.
+        let (status_code, mut base_headers) = match response_for_cert {
             None => {
-                // Static path: build from body + config headers.
+                // Static path: build from config headers.
                 let mut all_headers = vec![
                     ("content-type".to_string(), content_type.clone()),
                     (
@@ -486,11 +1014,7 @@ impl AssetRouter {
                 for (name, value) in &config.headers {
                     all_headers.push((name.clone(), value.clone()));
                 }
-                HttpResponse::builder()
-                    .with_status_code(config.status_code)
-                    .with_headers(all_headers)
-                    .with_body(body.as_slice())
-                    .build()
+                (config.status_code, all_headers)
             }
             Some(resp) => {
                 // Dynamic path: add CEL header to a copy of the provided response.
@@ -499,20 +1023,62 @@ impl AssetRouter {
                     CERTIFICATE_EXPRESSION_HEADER_NAME.to_string(),
                     cel_str.clone(),
                 ));
-                HttpResponse::builder()
-                    .with_status_code(resp.status_code())
-                    .with_headers(cert_headers)
-                    .with_body(Cow::<[u8]>::Owned(resp.body().to_vec()))
-                    .build()
+                (resp.status_code(), cert_headers)
             }
         };
+        push_last_modified(&mut base_headers, config.certified_at);
+        if config
+            .encodings
+            .iter()
+            .any(|(encoding, _)| *encoding != AssetEncoding::Identity)
+        {
+            vary_by_accept_encoding(&mut base_headers);
+        }
+
+        // One ETag per variant: the content hash of that variant's body, or
+        // the ETag the response already carries.
+        let provided_etag = base_headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("etag"))
+            .map(|(_, v)| v.clone());
+        let etag_of = |content: &[u8]| provided_etag.clone().unwrap_or_else(|| etag_for(content));
+        let mut etags = HashMap::new();
+        etags.insert(AssetEncoding::Identity, etag_of(&body));
+        for (encoding, encoded_content) in &config.encodings {
+            etags.insert(*encoding, etag_of(encoded_content));
+        }
+
+        let cert_response = HttpResponse::builder()
+            .with_status_code(status_code)
+            .with_headers(variant_headers(
+                &base_headers,
+                AssetEncoding::Identity,
+                etags.get(&AssetEncoding::Identity),
+            ))
+            .with_body(body.as_slice())
+            .build();
 
-        // Create certification.
-        let certification = match request {
-            Some(req) => create_certification_with_request(&config.mode, req, &cert_response)?,
-            None => create_certification(&config.mode, &cert_response)?,
+        let certify = |response: &HttpResponse<'_>| match request {
+            Some(req) => create_certification_with_request(&config.mode, req, response),
+            None => create_certification(&config.mode, response),
         };
 
+        // Create certification. A chunked body is also certified chunk by
+        // chunk for range requests, while the full-body certification covers
+        // the streamed response. Skip mode has no response hash, so its
+        // single skip entry covers every chunk.
+        let mut chunk_certifications = Vec::new();
+        let mut range_not_satisfiable = None;
+        if body.len() > ASSET_CHUNK_SIZE && !matches!(config.mode, CertificationMode::Skip) {
+            for index in 0..body.len().div_ceil(ASSET_CHUNK_SIZE) {
+                let chunk = chunk_response(cert_response.headers(), &body, index);
+                chunk_certifications.push(certify(&chunk)?);
+            }
+            let unsatisfiable = range_not_satisfiable_response(cert_response.headers(), &body);
+            range_not_satisfiable = Some(certify(&unsatisfiable)?);
+        }
+        let certification = certify(&cert_response)?;
+
         // Create tree entry and insert. We pass an owned String so the
         // `HttpCertificationPath` stores a `Cow::Owned`, making the
         // resulting `HttpCertificationTreeEntry` have `'static` lifetime.
@@ -525,14 +1091,93 @@ impl AssetRouter {
         } else {
             HttpCertificationPath::exact(path.to_string())
         };
+        // Certify each encoded variant as its own response: same headers
+        // plus `content-encoding` and the variant's ETag, with the encoded
+        // body. Each variant (identity included) of a 2xx asset also gets a
+        // certified `304 Not Modified` response for conditional requests.
+        // Skip mode
+        // has no response hash, so the identity entry already covers every
+        // variant.
+        let mut encoding_entries = HashMap::new();
+        let mut not_modified_entries = HashMap::new();
+        if !matches!(config.mode, CertificationMode::Skip) {
+            for (encoding, encoded_content) in &config.encodings {
+                if *encoding == AssetEncoding::Identity {
+                    continue;
+                }
+                let encoded_response = HttpResponse::builder()
+                    .with_status_code(status_code)
+                    .with_headers(variant_headers(
+                        &base_headers,
+                        *encoding,
+                        etags.get(encoding),
+                    ))
+                    .with_body(encoded_content.as_slice())
+                    .build();
+                let encoded_certification = certify(&encoded_response)?;
+                encoding_entries.insert(
+                    *encoding,
+                    HttpCertificationTreeEntry::new(tree_path.clone(), encoded_certification),
+                );
+            }
+        }
+        if status_code.is_success() && !matches!(config.mode, CertificationMode::Skip) {
+            for (encoding, etag) in &etags {
+                let not_modified =
+                    not_modified_response(&variant_headers(&base_headers, *encoding, Some(etag)));
+                not_modified_entries.insert(
+                    *encoding,
+                    HttpCertificationTreeEntry::new(tree_path.clone(), certify(&not_modified)?),
+                );
+            }
+        }
+
+        // A bodiless HEAD response with the identity headers, certified
+        // under its own full-certification CEL expression.
+        let head_entry = match (&config.mode, head_cel_expression_string(&config.mode)) {
+            (CertificationMode::ResponseOnly(response_config), Some(head_cel)) => {
+                let head = head_response(status_code, cert_response.headers(), Some(&head_cel));
+                // No query parameters are certified, so the request URL is
+                // not hashed and the entry covers every path in a fallback
+                // asset's scope too.
+                let certification = create_head_certification(response_config, path, &head)?;
+                Some(HttpCertificationTreeEntry::new(
+                    tree_path.clone(),
+                    certification,
+                ))
+            }
+            _ => None,
+        };
+
+        let chunk_entries: Vec<_> = chunk_certifications
+            .into_iter()
+            .map(|c| HttpCertificationTreeEntry::new(tree_path.clone(), c))
+            .collect();
+        let range_not_satisfiable_entry =
+            range_not_satisfiable.map(|c| HttpCertificationTreeEntry::new(tree_path.clone(), c));
         let tree_entry = HttpCertificationTreeEntry::new(tree_path, certification);
 
-        // Remove stale tree entry if re-certifying an existing path.
+        // Remove stale tree entries if re-certifying an existing path.
         if let Some(old_asset) = self.assets.get(path) {
-            self.tree.borrow_mut().delete(&old_asset.tree_entry);
+            let mut tree = self.tree.borrow_mut();
+            for entry in old_asset.tree_entries() {
+                tree.delete(entry);
+            }
         }
 
-        self.tree.borrow_mut().insert(&tree_entry);
+        {
+            let mut tree = self.tree.borrow_mut();
+            tree.insert(&tree_entry);
+            for entry in encoding_entries
+                .values()
+                .chain(chunk_entries.iter())
+                .chain(range_not_satisfiable_entry.iter())
+                .chain(not_modified_entries.values())
+                .chain(head_entry.iter())
+            {
+                tree.insert(entry);
+            }
+        }
 
         // Build encodings map.
         let mut encodings = HashMap::new();
@@ -551,6 +1196,12 @@ impl AssetRouter {
             certification_mode: config.mode,
             cel_expression: cel_str,
             tree_entry,
+            encoding_entries,
+            chunk_entries,
+            range_not_satisfiable_entry,
+            etags,
+            not_modified_entries,
+            head_entry,
             fallback_scope: config.fallback_for.clone(),
             aliases: config.aliases.clone(),
             certified_at: config.certified_at,
@@ -563,6 +1214,7 @@ impl AssetRouter {
         // Register fallback if specified (sorted longest-first).
         if let Some(scope) = config.fallback_for {
             self.fallbacks.push((scope, path.to_string()));
+            #[allow(clippy::unnecessary_sort_by)]
             self.fallbacks.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
         }
 
@@ -612,6 +1264,50 @@ impl AssetRouter {
         )
     }
 
+    /// Re-certify the asset stored at `path` as certified at
+    /// `certified_at`, keeping its content and configuration.
+    ///
+    /// `Last-Modified` is derived from `certified_at` and is part of the
+    /// certified response, so moving the timestamp (for example to restart
+    /// the TTL after a `NotModified` result) must rebuild the tree entries.
+    /// `request` is required for `Full` mode assets. Returns `Ok(false)` if
+    /// no asset is stored at `path`. The caller is responsible for calling
+    /// `certified_data_set` with the new [`root_hash`](Self::root_hash).
+    pub fn recertify_at(
+        &mut self,
+        path: &str,
+        certified_at: u64,
+        request: Option<&HttpRequest>,
+    ) -> Result<bool, AssetRouterError> {
+        let canonical = self
+            .aliases
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| path.to_string());
+        let Some(asset) = self.assets.get(&canonical) else {
+            return Ok(false);
+        };
+        if matches!(asset.certification_mode, CertificationMode::Full(_)) && request.is_none() {
+            return Err(AssetRouterError::FullModeRequiresRequest);
+        }
+        let snapshot = asset.to_snapshot(&canonical);
+        let config = AssetCertificationConfig {
+            mode: snapshot.certification_mode,
+            content_type: Some(snapshot.content_type),
+            status_code: asset.status_code,
+            headers: snapshot.headers,
+            encodings: snapshot.encodings,
+            fallback_for: snapshot.fallback_scope,
+            aliases: snapshot.aliases,
+            certified_at,
+            ttl: snapshot.ttl,
+            dynamic: snapshot.dynamic,
+        };
+        self.delete_asset(&canonical);
+        self.certify_inner(&canonical, snapshot.content, None, request, config)?;
+        Ok(true)
+    }
+
     /// Serve an asset for the given request.
     ///
     /// Returns `None` if no matching asset is found.
@@ -630,16 +1326,103 @@ impl AssetRouter {
         Vec<String>,
     )> {
         let path = request.get_path().ok()?;
+        let (_, asset) = self.resolve(&path)?;
+        self.serve_matched_asset(request, &path, asset)
+    }
+
+    /// Serve an asset like [`serve_asset`](Self::serve_asset), streaming
+    /// the body of a [chunked](CertifiedAsset::is_chunked) asset.
+    ///
+    /// A `GET` without a `Range` header whose negotiated variant is the
+    /// chunked identity body is answered with the certified full response,
+    /// carrying only the first chunk. The returned token names the second
+    /// chunk, which [`streaming_chunk`](Self::streaming_chunk) returns. Any
+    /// other request is served as by [`serve_asset`](Self::serve_asset),
+    /// with no token.
+    #[allow(clippy::type_complexity)]
+    pub fn serve_asset_streaming(
+        &self,
+        request: &HttpRequest,
+    ) -> Option<(
+        HttpResponse<'static>,
+        ic_certification::HashTree,
+        Vec<String>,
+        Option<StreamingCallbackToken>,
+    )> {
+        let path = request.get_path().ok()?;
+        let (canonical, asset) = self.resolve(&path)?;
+        let etag = asset.etags.get(&AssetEncoding::Identity);
+        let streams = request.method() == Method::GET
+            && asset.is_chunked()
+            && parse_range_start(request, asset.content.len()).is_none()
+            && self.select_encoding(request, asset) == AssetEncoding::Identity
+            && !(asset.status_code.is_success()
+                && is_not_modified(request, etag.map(String::as_str), asset.last_modified()));
+        if !streams {
+            let (response, witness, expr_path) = self.serve_matched_asset(request, &path, asset)?;
+            return Some((response, witness, expr_path, None));
+        }
+
+        let headers = variant_headers(&asset_base_headers(asset), AssetEncoding::Identity, etag);
+        let response = HttpResponse::builder()
+            .with_status_code(asset.status_code)
+            .with_headers(headers)
+            .with_body(Cow::<[u8]>::Owned(
+                asset.content[..ASSET_CHUNK_SIZE].to_vec(),
+            ))
+            .build();
+        let tree = self.tree.borrow();
+        let witness = tree.witness(&asset.tree_entry, &path).ok()?;
+        let expr_path = asset.tree_entry.path.to_expr_path();
+        let token = StreamingCallbackToken {
+            path: canonical.to_string(),
+            index: 1,
+            etag: etag.cloned().unwrap_or_default(),
+        };
+        Some((response, witness, expr_path, Some(token)))
+    }
 
+    /// The chunk of a streamed asset named by `token`, with the token for
+    /// the chunk after it.
+    ///
+    /// Returns `None` if the asset is gone, was re-certified with a
+    /// different body since streaming started, or has no such chunk.
+    pub fn streaming_chunk(
+        &self,
+        token: &StreamingCallbackToken,
+    ) -> Option<StreamingCallbackHttpResponse> {
+        let asset = self.assets.get(&token.path)?;
+        if asset.etags.get(&AssetEncoding::Identity) != Some(&token.etag) {
+            return None;
+        }
+        let index = usize::try_from(token.index).ok()?;
+        let start = index.checked_mul(ASSET_CHUNK_SIZE)?;
+        if start >= asset.content.len() {
+            return None;
+        }
+        let end = (start + ASSET_CHUNK_SIZE).min(asset.content.len());
+        let next = (end < asset.content.len()).then(|| StreamingCallbackToken {
+            index: token.index + 1,
+            ..token.clone()
+        });
+        Some(StreamingCallbackHttpResponse {
+            body: asset.content[start..end].to_vec(),
+            token: next,
+        })
+    }
+
+    /// The canonical path and asset serving `path`: an exact match, an
+    /// alias, or the longest matching fallback scope.
+    fn resolve(&self, path: &str) -> Option<(&str, &CertifiedAsset)> {
         // 1. Try exact match (canonical path).
-        if let Some(asset) = self.assets.get(&path) {
-            return self.serve_matched_asset(request, &path, asset);
+        if let Some((canonical, asset)) = self.assets.get_key_value(path) {
+            return Some((canonical, asset));
         }
 
         // 2. Try alias -> canonical resolution.
-        if let Some(canonical) = self.aliases.get(&path) {
+        if let Some(canonical) = self.aliases.get(path) {
             if let Some(asset) = self.assets.get(canonical) {
-                return self.serve_matched_asset(request, &path, asset);
+                return Some((canonical, asset));
             }
         }
 
@@ -647,7 +1430,7 @@ impl AssetRouter {
         for (scope, fallback_path) in &self.fallbacks {
             if path.starts_with(scope) {
                 if let Some(asset) = self.assets.get(fallback_path) {
-                    return self.serve_matched_asset(request, &path, asset);
+                    return Some((fallback_path, asset));
                 }
             }
         }
@@ -665,33 +1448,68 @@ impl AssetRouter {
         ic_certification::HashTree,
         Vec<String>,
     )> {
-        // 1. Select encoding based on Accept-Encoding header.
-        let encoding = self.select_encoding(request, asset);
+        // 1. Select encoding based on Accept-Encoding header. A range
+        // request against a chunked asset always addresses the identity body.
+        // HEAD responses carry the identity headers only.
+        let is_head = request.method() == Method::HEAD;
+        let range_start = parse_range_start(request, asset.content.len());
+        let encoding = if is_head || (asset.is_chunked() && range_start.is_some()) {
+            AssetEncoding::Identity
+        } else {
+            self.select_encoding(request, asset)
+        };
         let content = asset.encodings.get(&encoding)?;
 
         // 2. Build response headers.
-        let mut headers = vec![
-            ("content-type".to_string(), asset.content_type.clone()),
+        let etag = asset.etags.get(&encoding);
+        let headers = variant_headers(&asset_base_headers(asset), encoding, etag);
+
+        let (response, tree_entry) = if asset.status_code.is_success()
+            && is_not_modified(request, etag.map(String::as_str), asset.last_modified())
+        {
+            // The client's cached copy is current: answer with the
+            // certified 304 for this variant.
+            let tree_entry = asset
+                .not_modified_entries
+                .get(&encoding)
+                .unwrap_or(&asset.tree_entry);
+            (not_modified_response(&headers), tree_entry)
+        } else if is_head {
+            let head_cel = asset
+                .head_entry
+                .as_ref()
+                .and(head_cel_expression_string(&asset.certification_mode));
             (
-                CERTIFICATE_EXPRESSION_HEADER_NAME.to_string(),
-                asset.cel_expression.clone(),
-            ),
-        ];
-        for (name, value) in &asset.headers {
-            headers.push((name.clone(), value.clone()));
-        }
-        if encoding != AssetEncoding::Identity {
-            headers.push((
-                "content-encoding".to_string(),
-                encoding.as_str().to_string(),
-            ));
-        }
-
-        let response = HttpResponse::builder()
-            .with_status_code(asset.status_code)
-            .with_headers(headers)
-            .with_body(Cow::<[u8]>::Owned(content.clone()))
-            .build();
+                head_response(asset.status_code, &headers, head_cel.as_deref()),
+                asset.head_entry.as_ref().unwrap_or(&asset.tree_entry),
+            )
+        } else if let (Some(start), true) = (range_start, asset.is_chunked()) {
+            // Range request against a chunked identity body: serve the
+            // chunk that starts at the requested byte. Only whole chunks are
+            // certified, so any other start gets a certified 416.
+            if start % ASSET_CHUNK_SIZE == 0 && start < asset.content.len() {
+                let index = start / ASSET_CHUNK_SIZE;
+                (
+                    chunk_response(&headers, &asset.content, index),
+                    asset.tree_entry_for_chunk(index),
+                )
+            } else {
+                (
+                    range_not_satisfiable_response(&headers, &asset.content),
+                    asset
+                        .range_not_satisfiable_entry
+                        .as_ref()
+                        .unwrap_or(&asset.tree_entry),
+                )
+            }
+        } else {
+            let response = HttpResponse::builder()
+                .with_status_code(asset.status_code)
+                .with_headers(headers)
+                .with_body(Cow::<[u8]>::Owned(content.clone()))
+                .build();
+            (response, asset.tree_entry_for(encoding))
+        };
 
         // 3. Generate witness and expression path from the certification tree.
         // All modes (including Skip) need a valid witness so the boundary
@@ -699,8 +1517,8 @@ impl AssetRouter {
         // the boundary node that the canister intentionally chose not to
         // certify this path.
         let tree = self.tree.borrow();
-        let witness = tree.witness(&asset.tree_entry, request_path).ok()?;
-        let expr_path = asset.tree_entry.path.to_expr_path();
+        let witness = tree.witness(tree_entry, request_path).ok()?;
+        let expr_path = tree_entry.path.to_expr_path();
         Some((response, witness, expr_path))
     }
 
@@ -730,7 +1548,10 @@ impl AssetRouter {
             .unwrap_or_else(|| path.to_string());
 
         if let Some(asset) = self.assets.remove(&canonical) {
-            self.tree.borrow_mut().delete(&asset.tree_entry);
+            let mut tree = self.tree.borrow_mut();
+            for entry in asset.tree_entries() {
+                tree.delete(entry);
+            }
             self.fallbacks.retain(|(_, v)| v != &canonical);
             self.aliases.retain(|_, v| v != &canonical);
         }
@@ -976,48 +1797,148 @@ mod tests {
         );
     }
 
+    fn encoded_config() -> AssetCertificationConfig {
+        AssetCertificationConfig {
+            encodings: vec![
+                (AssetEncoding::Gzip, b"gzip-content".to_vec()),
+                (AssetEncoding::Brotli, b"brotli-content".to_vec()),
+            ],
+            ..Default::default()
+        }
+    }
+
     #[test]
-    fn certify_asset_stores_certified_at_and_ttl() {
+    fn certify_asset_certifies_each_encoding_separately() {
         let mut router = make_router();
-        let config = AssetCertificationConfig {
-            certified_at: 1_000_000,
-            ttl: Some(Duration::from_secs(3600)),
-            ..Default::default()
-        };
         router
-            .certify_asset("/page", b"content".to_vec(), config)
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), encoded_config())
             .unwrap();
-        let asset = router.get_asset("/page").unwrap();
-        assert_eq!(asset.certified_at, 1_000_000);
-        assert_eq!(asset.ttl, Some(Duration::from_secs(3600)));
+        let asset = router.get_asset("/index.html").unwrap();
+        assert_eq!(asset.encoding_entries.len(), 2);
+        let identity = &asset.tree_entry.certification;
+        let gzip = &asset.tree_entry_for(AssetEncoding::Gzip).certification;
+        let brotli = &asset.tree_entry_for(AssetEncoding::Brotli).certification;
+        assert_ne!(identity, gzip);
+        assert_ne!(identity, brotli);
+        assert_ne!(gzip, brotli);
     }
 
-    // ==================================================================
-    // 7.2.12 — serve_asset tests
-    // ==================================================================
-
     #[test]
-    fn serve_asset_exact_match() {
+    fn served_encoded_response_matches_its_certification() {
         let mut router = make_router();
         router
-            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), default_config())
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), encoded_config())
             .unwrap();
+        let asset = router.get_asset("/index.html").unwrap();
 
-        let request = make_get_request("/index.html");
-        let result = router.serve_asset(&request);
-        assert!(result.is_some());
-        let (response, _witness, expr_path) = result.unwrap();
-        assert_eq!(response.status_code(), StatusCode::OK);
-        assert_eq!(response.body(), b"<h1>Hello</h1>");
-        // expr_path should be non-empty for ResponseOnly mode.
-        assert!(!expr_path.is_empty());
+        for (accept, encoding) in [
+            ("br", AssetEncoding::Brotli),
+            ("gzip", AssetEncoding::Gzip),
+            ("", AssetEncoding::Identity),
+        ] {
+            let req = make_get_request_with_encoding("/index.html", accept);
+            let (resp, _witness, _expr_path) = router.serve_asset(&req).unwrap();
+            assert!(resp
+                .headers()
+                .iter()
+                .any(|(k, v)| k == "vary" && v == "accept-encoding"));
+            let recomputed = create_certification(&asset.certification_mode, &resp).unwrap();
+            assert_eq!(
+                recomputed,
+                *asset.tree_entry_for(encoding).certification,
+                "served {encoding:?} response must match its certified hash"
+            );
+        }
     }
 
     #[test]
-    fn serve_asset_alias_resolves() {
+    fn skip_mode_does_not_add_encoding_entries() {
         let mut router = make_router();
         let config = AssetCertificationConfig {
-            aliases: vec!["/".to_string()],
+            mode: CertificationMode::skip(),
+            ..encoded_config()
+        };
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), config)
+            .unwrap();
+        let asset = router.get_asset("/index.html").unwrap();
+        assert!(asset.encoding_entries.is_empty());
+        let req = make_get_request_with_encoding("/index.html", "br");
+        assert!(router.serve_asset(&req).is_some());
+    }
+
+    #[test]
+    fn delete_asset_removes_encoding_entries() {
+        let mut router = make_router();
+        let empty_hash = router.root_hash();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), encoded_config())
+            .unwrap();
+        router.delete_asset("/index.html");
+        assert_eq!(router.root_hash(), empty_hash);
+    }
+
+    #[test]
+    fn recertify_replaces_encoding_entries() {
+        let mut router = make_router();
+        let empty_hash = router.root_hash();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), encoded_config())
+            .unwrap();
+        router
+            .certify_asset("/index.html", b"<h1>Bye</h1>".to_vec(), default_config())
+            .unwrap();
+        assert!(router
+            .get_asset("/index.html")
+            .unwrap()
+            .encoding_entries
+            .is_empty());
+        router.delete_asset("/index.html");
+        assert_eq!(router.root_hash(), empty_hash);
+    }
+
+    #[test]
+    fn certify_asset_stores_certified_at_and_ttl() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            certified_at: 1_000_000,
+            ttl: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        router
+            .certify_asset("/page", b"content".to_vec(), config)
+            .unwrap();
+        let asset = router.get_asset("/page").unwrap();
+        assert_eq!(asset.certified_at, 1_000_000);
+        assert_eq!(asset.ttl, Some(Duration::from_secs(3600)));
+    }
+
+    // ==================================================================
+    // 7.2.12 — serve_asset tests
+    // ==================================================================
+
+    #[test]
+    fn serve_asset_exact_match() {
+        let mut router = make_router();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), default_config())
+            .unwrap();
+
+        let request = make_get_request("/index.html");
+        let result = router.serve_asset(&request);
+        assert!(result.is_some());
+        let (response, _witness, expr_path) = result.unwrap();
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.body(), b"<h1>Hello</h1>");
+        // expr_path should be non-empty for ResponseOnly mode.
+        assert!(!expr_path.is_empty());
+    }
+
+    #[test]
+    fn serve_asset_alias_resolves() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            aliases: vec!["/".to_string()],
             ..Default::default()
         };
         router
@@ -1143,6 +2064,7 @@ mod tests {
             .find(|(k, _)| k == "content-encoding")
             .map(|(_, v)| v.as_str());
         assert_eq!(ce, Some("br"));
+        assert_eq!(vary_of(&response), Some("accept-encoding"));
     }
 
     #[test]
@@ -1162,6 +2084,7 @@ mod tests {
         assert!(result.is_some());
         let (response, _, _) = result.unwrap();
         assert_eq!(response.body(), b"gzip-content");
+        assert_eq!(vary_of(&response), Some("accept-encoding"));
     }
 
     #[test]
@@ -1187,6 +2110,28 @@ mod tests {
             .iter()
             .find(|(k, _)| k == "content-encoding");
         assert!(ce.is_none());
+        // The identity variant varies by encoding too.
+        assert_eq!(vary_of(&response), Some("accept-encoding"));
+    }
+
+    #[test]
+    fn serve_asset_without_encodings_has_no_vary() {
+        let mut router = make_router();
+        router
+            .certify_asset("/index.html", b"raw".to_vec(), default_config())
+            .unwrap();
+
+        let request = make_get_request_with_encoding("/index.html", "gzip, br");
+        let (response, _, _) = router.serve_asset(&request).unwrap();
+        assert_eq!(vary_of(&response), None);
+    }
+
+    fn vary_of<'a>(response: &'a HttpResponse<'_>) -> Option<&'a str> {
+        response
+            .headers()
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("vary"))
+            .map(|(_, v)| v.as_str())
     }
 
     #[test]
@@ -1493,6 +2438,9 @@ mod tests {
 
         let e = AssetRouterError::AssetNotFound("/missing".into());
         assert!(e.to_string().contains("/missing"));
+
+        let e = AssetRouterError::InvalidSnapshot("bad status".into());
+        assert!(e.to_string().contains("bad status"));
     }
 
     #[test]
@@ -1619,4 +2567,830 @@ mod tests {
         assert!(router.contains_asset("/exists"));
         assert!(!router.contains_asset("/does-not-exist"));
     }
+
+    // ==================================================================
+    // Dynamic cache snapshot / restore
+    // ==================================================================
+
+    fn dynamic_config() -> AssetCertificationConfig {
+        AssetCertificationConfig {
+            certified_at: 1_000,
+            ttl: Some(Duration::from_secs(60)),
+            dynamic: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dynamic_snapshot_excludes_static_assets() {
+        let mut router = make_router();
+        router
+            .certify_asset("/static.css", b"body{}".to_vec(), default_config())
+            .unwrap();
+        router
+            .certify_asset("/page", b"dynamic".to_vec(), dynamic_config())
+            .unwrap();
+
+        let snapshot = router.dynamic_snapshot();
+        assert_eq!(snapshot.assets.len(), 1);
+        assert_eq!(snapshot.assets[0].path, "/page");
+    }
+
+    #[test]
+    fn dynamic_snapshot_excludes_full_mode_assets() {
+        let mut router = make_router();
+        let request = make_get_request("/me");
+        let response = make_response(b"private");
+        let config = AssetCertificationConfig {
+            dynamic: true,
+            ..full_config()
+        };
+        router
+            .certify_dynamic_asset("/me", &request, &response, config)
+            .unwrap();
+
+        assert!(router.dynamic_snapshot().assets.is_empty());
+    }
+
+    #[test]
+    fn dynamic_snapshot_is_sorted_by_path() {
+        let mut router = make_router();
+        for path in ["/c", "/a", "/b"] {
+            router
+                .certify_asset(path, b"x".to_vec(), dynamic_config())
+                .unwrap();
+        }
+        let paths: Vec<String> = router
+            .dynamic_snapshot()
+            .assets
+            .into_iter()
+            .map(|a| a.path)
+            .collect();
+        assert_eq!(paths, vec!["/a", "/b", "/c"]);
+    }
+
+    #[test]
+    fn restore_snapshot_round_trips_asset_metadata() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            content_type: Some("application/json".to_string()),
+            status_code: StatusCode::CREATED,
+            headers: vec![("x-custom".to_string(), "value".to_string())],
+            encodings: vec![(AssetEncoding::Gzip, b"gz".to_vec())],
+            aliases: vec!["/data.json".to_string()],
+            ..dynamic_config()
+        };
+        router
+            .certify_asset("/data", b"{}".to_vec(), config)
+            .unwrap();
+        let snapshot = router.dynamic_snapshot();
+
+        let mut restored = make_router();
+        restored.restore_snapshot(snapshot).unwrap();
+
+        let asset = restored.get_asset("/data.json").unwrap();
+        assert_eq!(asset.content, b"{}");
+        assert_eq!(asset.content_type, "application/json");
+        assert_eq!(asset.status_code, StatusCode::CREATED);
+        assert_eq!(
+            asset.headers,
+            vec![("x-custom".to_string(), "value".to_string())]
+        );
+        assert_eq!(asset.encodings.get(&AssetEncoding::Gzip).unwrap(), b"gz");
+        assert_eq!(
+            asset.encodings.get(&AssetEncoding::Identity).unwrap(),
+            b"{}"
+        );
+        assert_eq!(asset.certified_at, 1_000);
+        assert_eq!(asset.ttl, Some(Duration::from_secs(60)));
+        assert!(asset.is_dynamic());
+    }
+
+    #[test]
+    fn restore_snapshot_rebuilds_identical_root_hash() {
+        let mut router = make_router();
+        router
+            .certify_asset("/a", b"alpha".to_vec(), dynamic_config())
+            .unwrap();
+        let config = AssetCertificationConfig {
+            fallback_for: Some("/app".to_string()),
+            ..dynamic_config()
+        };
+        router
+            .certify_asset("/app/index.html", b"spa".to_vec(), config)
+            .unwrap();
+        let snapshot = router.dynamic_snapshot();
+
+        let mut restored = make_router();
+        restored.restore_snapshot(snapshot).unwrap();
+
+        assert_eq!(restored.root_hash(), router.root_hash());
+        let request = make_get_request("/app/settings");
+        let (response, _, _) = restored.serve_asset(&request).unwrap();
+        assert_eq!(response.body(), b"spa");
+    }
+
+    #[test]
+    fn restore_snapshot_survives_candid_encoding() {
+        let mut router = make_router();
+        router
+            .certify_asset("/page", b"hello".to_vec(), dynamic_config())
+            .unwrap();
+        let snapshot = router.dynamic_snapshot();
+
+        let bytes = candid::encode_one(&snapshot).unwrap();
+        let decoded: AssetRouterSnapshot = candid::decode_one(&bytes).unwrap();
+
+        let mut restored = make_router();
+        restored.restore_snapshot(decoded).unwrap();
+        assert_eq!(restored.root_hash(), router.root_hash());
+    }
+
+    #[test]
+    fn restore_snapshot_rejects_invalid_status_code() {
+        let mut router = make_router();
+        router
+            .certify_asset("/page", b"x".to_vec(), dynamic_config())
+            .unwrap();
+        let mut snapshot = router.dynamic_snapshot();
+        snapshot.assets[0].status_code = 9999;
+
+        let result = make_router().restore_snapshot(snapshot);
+        assert!(matches!(result, Err(AssetRouterError::InvalidSnapshot(_))));
+    }
+
+    // ==================================================================
+    // Range requests and chunked assets
+    // ==================================================================
+
+    const LARGE_LEN: usize = ASSET_CHUNK_SIZE + 500_000;
+
+    fn large_body() -> Vec<u8> {
+        (0..LARGE_LEN).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn make_range_request(url: &str, range: &str) -> HttpRequest<'static> {
+        HttpRequest::get(url.to_string())
+            .with_headers(vec![("range".to_string(), range.to_string())])
+            .build()
+    }
+
+    fn header<'a>(resp: &'a HttpResponse<'_>, name: &str) -> Option<&'a str> {
+        resp.headers()
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn large_video_router(mode: CertificationMode) -> AssetRouter {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            mode,
+            content_type: Some("video/mp4".to_string()),
+            ..Default::default()
+        };
+        router
+            .certify_asset("/movie.mp4", large_body(), config)
+            .unwrap();
+        router
+    }
+
+    #[test]
+    fn parse_range_start_forms() {
+        let total = 1000;
+        let start = |range: &str| parse_range_start(&make_range_request("/", range), total);
+        assert_eq!(start("bytes=0-"), Some(0));
+        assert_eq!(start("bytes=100-199"), Some(100));
+        assert_eq!(start("bytes=-100"), Some(900));
+        assert_eq!(start("bytes=-5000"), Some(0));
+        assert_eq!(start("bytes=10-20, 30-40"), Some(10));
+        assert_eq!(start("items=0-"), None);
+        assert_eq!(start("bytes=abc-"), None);
+        assert_eq!(parse_range_start(&make_get_request("/"), total), None);
+    }
+
+    #[test]
+    fn small_asset_ignores_range_header() {
+        let mut router = make_router();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), default_config())
+            .unwrap();
+        let asset = router.get_asset("/index.html").unwrap();
+        assert!(!asset.is_chunked());
+        assert_eq!(asset.chunk_count(), 1);
+
+        let req = make_range_request("/index.html", "bytes=5-");
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::OK);
+        assert_eq!(resp.body(), b"<h1>Hello</h1>");
+        assert!(header(&resp, "content-range").is_none());
+    }
+
+    #[test]
+    fn large_asset_is_certified_per_chunk() {
+        let router = large_video_router(CertificationMode::response_only());
+        let asset = router.get_asset("/movie.mp4").unwrap();
+        assert!(asset.is_chunked());
+        assert_eq!(asset.chunk_count(), 2);
+        assert_eq!(asset.chunk_entries.len(), 2);
+        assert_ne!(
+            asset.tree_entry_for_chunk(0).certification,
+            asset.tree_entry_for_chunk(1).certification
+        );
+    }
+
+    /// A 206 only answers a `Range` request: a plain `GET` gets the
+    /// certified full body.
+    #[test]
+    fn large_asset_without_range_serves_full_body() {
+        let router = large_video_router(CertificationMode::response_only());
+        let asset = router.get_asset("/movie.mp4").unwrap();
+        let (resp, _, _) = router.serve_asset(&make_get_request("/movie.mp4")).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::OK);
+        assert!(header(&resp, "content-range").is_none());
+        assert_eq!(resp.body(), large_body().as_slice());
+        let recomputed = create_certification(&asset.certification_mode, &resp).unwrap();
+        assert_eq!(recomputed, *asset.tree_entry.certification);
+    }
+
+    /// A `GET` without `Range` streams the certified full response: the
+    /// first chunk now, the rest through the callback token.
+    #[test]
+    fn large_asset_streams_full_response_without_range() {
+        let router = large_video_router(CertificationMode::response_only());
+        let asset = router.get_asset("/movie.mp4").unwrap();
+        let (resp, _, _, token) = router
+            .serve_asset_streaming(&make_get_request("/movie.mp4"))
+            .unwrap();
+        assert_eq!(resp.status_code(), StatusCode::OK);
+        assert!(header(&resp, "content-range").is_none());
+        assert_eq!(resp.body(), &large_body()[..ASSET_CHUNK_SIZE]);
+
+        let token = token.expect("streaming token");
+        assert_eq!(token.path, "/movie.mp4");
+        assert_eq!(token.index, 1);
+        let chunk = router.streaming_chunk(&token).unwrap();
+        assert_eq!(chunk.body, &large_body()[ASSET_CHUNK_SIZE..]);
+        assert!(chunk.token.is_none());
+
+        // The assembled body is what the full-body certification covers.
+        let mut body = resp.body().to_vec();
+        body.extend(chunk.body);
+        let assembled = HttpResponse::builder()
+            .with_status_code(resp.status_code())
+            .with_headers(resp.headers().to_vec())
+            .with_body(body)
+            .build();
+        let recomputed = create_certification(&asset.certification_mode, &assembled).unwrap();
+        assert_eq!(recomputed, *asset.tree_entry.certification);
+    }
+
+    #[test]
+    fn streaming_chunk_refuses_stale_or_unknown_tokens() {
+        let router = large_video_router(CertificationMode::response_only());
+        let (_, _, _, token) = router
+            .serve_asset_streaming(&make_get_request("/movie.mp4"))
+            .unwrap();
+        let token = token.unwrap();
+        let stale = StreamingCallbackToken {
+            etag: "\"other\"".to_string(),
+            ..token.clone()
+        };
+        assert!(router.streaming_chunk(&stale).is_none());
+        let past_end = StreamingCallbackToken {
+            index: 2,
+            ..token.clone()
+        };
+        assert!(router.streaming_chunk(&past_end).is_none());
+        let missing = StreamingCallbackToken {
+            path: "/other.mp4".to_string(),
+            ..token
+        };
+        assert!(router.streaming_chunk(&missing).is_none());
+    }
+
+    /// Range requests and small assets are served as by `serve_asset`.
+    #[test]
+    fn serve_asset_streaming_only_streams_unranged_chunked_gets() {
+        let mut router = large_video_router(CertificationMode::response_only());
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), default_config())
+            .unwrap();
+        let ranged = make_range_request("/movie.mp4", &format!("bytes={}-", ASSET_CHUNK_SIZE));
+        let (resp, _, _, token) = router.serve_asset_streaming(&ranged).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::PARTIAL_CONTENT);
+        assert!(token.is_none());
+
+        let (resp, _, _, token) = router
+            .serve_asset_streaming(&make_get_request("/index.html"))
+            .unwrap();
+        assert_eq!(resp.body(), b"<h1>Hello</h1>");
+        assert!(token.is_none());
+    }
+
+    #[test]
+    fn large_asset_range_selects_chunk_at_its_start() {
+        let router = large_video_router(CertificationMode::response_only());
+        let expected_range = format!("bytes {}-{}/{}", ASSET_CHUNK_SIZE, LARGE_LEN - 1, LARGE_LEN);
+        for range in [
+            format!("bytes={}-", ASSET_CHUNK_SIZE),
+            format!("bytes={}-{}", ASSET_CHUNK_SIZE, ASSET_CHUNK_SIZE + 10),
+        ] {
+            let req = make_range_request("/movie.mp4", &range);
+            let (resp, _, _) = router.serve_asset(&req).unwrap();
+            assert_eq!(resp.status_code(), StatusCode::PARTIAL_CONTENT, "{range}");
+            assert_eq!(
+                header(&resp, "content-range"),
+                Some(expected_range.as_str())
+            );
+            assert_eq!(resp.body(), &large_body()[ASSET_CHUNK_SIZE..]);
+        }
+    }
+
+    /// Only whole chunks are certified, so a seek into the middle of a
+    /// chunk, like a range past the end, gets the certified 416.
+    #[test]
+    fn large_asset_unaligned_or_past_end_range_is_certified_416() {
+        let router = large_video_router(CertificationMode::response_only());
+        let asset = router.get_asset("/movie.mp4").unwrap();
+        let entry = asset.range_not_satisfiable_entry.as_ref().unwrap();
+        for range in [
+            "bytes=12345-".to_string(),
+            format!("bytes={}-", ASSET_CHUNK_SIZE + 12_345),
+            "bytes=-100".to_string(),
+            format!("bytes={}-", LARGE_LEN),
+            format!("bytes={}-{}", LARGE_LEN + 10, LARGE_LEN + 20),
+        ] {
+            let req = make_range_request("/movie.mp4", &range);
+            let (resp, _, _) = router.serve_asset(&req).unwrap();
+            assert_eq!(
+                resp.status_code(),
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                "{range}"
+            );
+            assert_eq!(
+                header(&resp, "content-range"),
+                Some(format!("bytes */{LARGE_LEN}").as_str())
+            );
+            assert!(resp.body().is_empty());
+            let recomputed = create_certification(&asset.certification_mode, &resp).unwrap();
+            assert_eq!(recomputed, *entry.certification);
+        }
+    }
+
+    #[test]
+    fn served_chunks_match_their_certification() {
+        let router = large_video_router(CertificationMode::response_only());
+        let asset = router.get_asset("/movie.mp4").unwrap();
+        for (index, range) in ["bytes=0-", &format!("bytes={}-", ASSET_CHUNK_SIZE)]
+            .into_iter()
+            .enumerate()
+        {
+            let req = make_range_request("/movie.mp4", range);
+            let (resp, _, _) = router.serve_asset(&req).unwrap();
+            let recomputed = create_certification(&asset.certification_mode, &resp).unwrap();
+            assert_eq!(recomputed, *asset.tree_entry_for_chunk(index).certification);
+        }
+    }
+
+    #[test]
+    fn chunked_skip_asset_has_no_chunk_entries() {
+        let router = large_video_router(CertificationMode::skip());
+        let asset = router.get_asset("/movie.mp4").unwrap();
+        assert!(asset.chunk_entries.is_empty());
+        let req = make_range_request("/movie.mp4", &format!("bytes={}-", ASSET_CHUNK_SIZE));
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::PARTIAL_CONTENT);
+    }
+
+    #[test]
+    fn delete_chunked_asset_removes_all_chunk_entries() {
+        let mut router = make_router();
+        let empty_hash = router.root_hash();
+        router
+            .certify_asset("/movie.mp4", large_body(), default_config())
+            .unwrap();
+        router.delete_asset("/movie.mp4");
+        assert_eq!(router.root_hash(), empty_hash);
+    }
+
+    #[test]
+    fn encoded_variants_larger_than_a_chunk_are_dropped() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            content_type: Some("application/javascript".to_string()),
+            encodings: vec![
+                (AssetEncoding::Brotli, vec![1; 1000]),
+                (AssetEncoding::Gzip, vec![2; ASSET_CHUNK_SIZE + 1]),
+            ],
+            ..Default::default()
+        };
+        router
+            .certify_asset("/app.js", large_body(), config)
+            .unwrap();
+        let asset = router.get_asset("/app.js").unwrap();
+        assert!(asset.encodings.contains_key(&AssetEncoding::Brotli));
+        assert!(!asset.encodings.contains_key(&AssetEncoding::Gzip));
+
+        // Negotiated Brotli variant is served whole; a Range request gets a
+        // chunk of the identity body.
+        let req = make_get_request_with_encoding("/app.js", "br, gzip");
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::OK);
+        assert_eq!(header(&resp, "content-encoding"), Some("br"));
+
+        let req = HttpRequest::get("/app.js".to_string())
+            .with_headers(vec![
+                ("accept-encoding".to_string(), "br".to_string()),
+                ("range".to_string(), "bytes=0-".to_string()),
+            ])
+            .build();
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::PARTIAL_CONTENT);
+        assert!(header(&resp, "content-encoding").is_none());
+    }
+
+    // ==================================================================
+    // ETags and conditional requests
+    // ==================================================================
+
+    fn make_conditional_request(url: &str, headers: &[(&str, &str)]) -> HttpRequest<'static> {
+        HttpRequest::get(url.to_string())
+            .with_headers(
+                headers
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn served_asset_carries_content_hash_etag() {
+        let mut router = make_router();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), encoded_config())
+            .unwrap();
+
+        let (resp, _, _) = router
+            .serve_asset(&make_get_request("/index.html"))
+            .unwrap();
+        let identity_etag = etag_for(b"<h1>Hello</h1>");
+        assert_eq!(header(&resp, "etag"), Some(identity_etag.as_str()));
+
+        let req = make_get_request_with_encoding("/index.html", "br");
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        let brotli_etag = etag_for(b"brotli-content");
+        assert_eq!(header(&resp, "etag"), Some(brotli_etag.as_str()));
+    }
+
+    #[test]
+    fn static_asset_has_no_last_modified() {
+        let mut router = make_router();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), default_config())
+            .unwrap();
+        let (resp, _, _) = router
+            .serve_asset(&make_get_request("/index.html"))
+            .unwrap();
+        assert!(header(&resp, "last-modified").is_none());
+    }
+
+    #[test]
+    fn dynamic_asset_has_last_modified() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            certified_at: 784_111_777 * NANOS_PER_SEC,
+            dynamic: true,
+            ..Default::default()
+        };
+        router
+            .certify_asset("/page", b"<h1>Page</h1>".to_vec(), config)
+            .unwrap();
+        let (resp, _, _) = router.serve_asset(&make_get_request("/page")).unwrap();
+        assert_eq!(
+            header(&resp, "last-modified"),
+            Some("Sun, 06 Nov 1994 08:49:37 GMT")
+        );
+    }
+
+    #[test]
+    fn recertify_at_moves_last_modified_and_keeps_responses_certified() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            certified_at: 784_111_777 * NANOS_PER_SEC,
+            ttl: Some(Duration::from_secs(60)),
+            dynamic: true,
+            ..encoded_config()
+        };
+        router
+            .certify_asset("/page", b"<h1>Page</h1>".to_vec(), config)
+            .unwrap();
+        let old_root = router.root_hash();
+
+        let refreshed_at = 784_111_837 * NANOS_PER_SEC;
+        assert!(router.recertify_at("/page", refreshed_at, None).unwrap());
+        assert!(!router.recertify_at("/missing", refreshed_at, None).unwrap());
+        assert_ne!(router.root_hash(), old_root);
+
+        let asset = router.get_asset("/page").unwrap();
+        assert_eq!(asset.certified_at, refreshed_at);
+        assert_eq!(asset.ttl, Some(Duration::from_secs(60)));
+        assert_eq!(asset.encodings.len(), 3);
+
+        let (resp, _, _) = router.serve_asset(&make_get_request("/page")).unwrap();
+        assert_eq!(
+            header(&resp, "last-modified"),
+            Some("Sun, 06 Nov 1994 08:50:37 GMT")
+        );
+        let recomputed = create_certification(&asset.certification_mode, &resp).unwrap();
+        assert_eq!(recomputed, *asset.tree_entry.certification);
+
+        let (resp, _, _) = router
+            .serve_asset(&make_get_request_with_encoding("/page", "br"))
+            .unwrap();
+        let recomputed = create_certification(&asset.certification_mode, &resp).unwrap();
+        assert_eq!(
+            recomputed,
+            *asset.tree_entry_for(AssetEncoding::Brotli).certification
+        );
+
+        let req = make_conditional_request(
+            "/page",
+            &[("if-modified-since", "Sun, 06 Nov 1994 08:50:37 GMT")],
+        );
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::NOT_MODIFIED);
+        let recomputed = create_certification(&asset.certification_mode, &resp).unwrap();
+        assert_eq!(
+            recomputed,
+            *asset.not_modified_entries[&AssetEncoding::Identity].certification
+        );
+    }
+
+    #[test]
+    fn if_none_match_returns_certified_304() {
+        let mut router = make_router();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), encoded_config())
+            .unwrap();
+        let asset = router.get_asset("/index.html").unwrap();
+
+        for (accept, encoding, body) in [
+            ("", AssetEncoding::Identity, &b"<h1>Hello</h1>"[..]),
+            ("br", AssetEncoding::Brotli, &b"brotli-content"[..]),
+        ] {
+            let etag = etag_for(body);
+            let req = make_conditional_request(
+                "/index.html",
+                &[("accept-encoding", accept), ("if-none-match", &etag)],
+            );
+            let (resp, _, _) = router.serve_asset(&req).unwrap();
+            assert_eq!(resp.status_code(), StatusCode::NOT_MODIFIED);
+            assert!(resp.body().is_empty());
+            assert_eq!(header(&resp, "etag"), Some(etag.as_str()));
+
+            let recomputed = create_certification(&asset.certification_mode, &resp).unwrap();
+            assert_eq!(
+                recomputed, *asset.not_modified_entries[&encoding].certification,
+                "304 for {encoding:?} must match its certified hash"
+            );
+        }
+    }
+
+    #[test]
+    fn if_none_match_mismatch_serves_full_response() {
+        let mut router = make_router();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), default_config())
+            .unwrap();
+        let req = make_conditional_request("/index.html", &[("if-none-match", "\"stale\"")]);
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::OK);
+        assert_eq!(resp.body(), b"<h1>Hello</h1>");
+    }
+
+    #[test]
+    fn if_modified_since_returns_304_for_unchanged_dynamic_asset() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            certified_at: 784_111_777 * NANOS_PER_SEC,
+            dynamic: true,
+            ..Default::default()
+        };
+        router
+            .certify_asset("/page", b"<h1>Page</h1>".to_vec(), config)
+            .unwrap();
+
+        let req = make_conditional_request(
+            "/page",
+            &[("if-modified-since", "Sun, 06 Nov 1994 08:49:37 GMT")],
+        );
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::NOT_MODIFIED);
+
+        let req = make_conditional_request(
+            "/page",
+            &[("if-modified-since", "Sat, 05 Nov 1994 08:49:37 GMT")],
+        );
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::OK);
+    }
+
+    #[test]
+    fn provided_etag_header_is_kept() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            headers: vec![("ETag".to_string(), "\"v1\"".to_string())],
+            ..encoded_config()
+        };
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), config)
+            .unwrap();
+
+        let req = make_get_request_with_encoding("/index.html", "gzip");
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        let etags: Vec<_> = resp
+            .headers()
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case("etag"))
+            .collect();
+        assert_eq!(etags.len(), 1);
+        assert_eq!(etags[0].1, "\"v1\"");
+
+        let req = make_conditional_request("/index.html", &[("if-none-match", "\"v1\"")]);
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn non_success_asset_is_never_not_modified() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            status_code: StatusCode::NOT_FOUND,
+            ..Default::default()
+        };
+        router
+            .certify_asset("/missing", b"Not Found".to_vec(), config)
+            .unwrap();
+        let asset = router.get_asset("/missing").unwrap();
+        assert!(asset.not_modified_entries.is_empty());
+
+        let etag = etag_for(b"Not Found");
+        let req = make_conditional_request("/missing", &[("if-none-match", &etag)]);
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn skip_mode_serves_304_without_extra_entries() {
+        let mut router = make_router();
+        let config = AssetCertificationConfig {
+            mode: CertificationMode::skip(),
+            ..Default::default()
+        };
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), config)
+            .unwrap();
+        assert!(router
+            .get_asset("/index.html")
+            .unwrap()
+            .not_modified_entries
+            .is_empty());
+
+        let etag = etag_for(b"<h1>Hello</h1>");
+        let req = make_conditional_request("/index.html", &[("if-none-match", &etag)]);
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn delete_asset_removes_not_modified_entries() {
+        let mut router = make_router();
+        let empty_hash = router.root_hash();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), encoded_config())
+            .unwrap();
+        assert_eq!(
+            router
+                .get_asset("/index.html")
+                .unwrap()
+                .not_modified_entries
+                .len(),
+            3
+        );
+        router.delete_asset("/index.html");
+        assert_eq!(router.root_hash(), empty_hash);
+    }
+
+    // ---- HEAD requests ----
+
+    fn make_head_request(url: &str, headers: &[(&str, &str)]) -> HttpRequest<'static> {
+        HttpRequest::builder()
+            .with_method(Method::HEAD)
+            .with_url(url)
+            .with_headers(
+                headers
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn head_serves_bodiless_response_matching_its_certification() {
+        let mut router = make_router();
+        router
+            .certify_asset("/index.html", b"<h1>Hello</h1>".to_vec(), encoded_config())
+            .unwrap();
+        let asset = router.get_asset("/index.html").unwrap();
+        let head_entry = asset
+            .head_entry
+            .as_ref()
+            .expect("response-only asset has a HEAD entry");
+
+        let req = make_head_request("/index.html", &[("accept-encoding", "br")]);
+        let (resp, _witness, expr_path) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::OK);
+        assert!(resp.body().is_empty());
+        assert_eq!(header(&resp, "content-encoding"), None);
+        assert_eq!(
+            header(&resp, "etag"),
+            Some(etag_for(b"<h1>Hello</h1>").as_str())
+        );
+        assert_eq!(expr_path, head_entry.path.to_expr_path());
+
+        let cel = header(&resp, CERTIFICATE_EXPRESSION_HEADER_NAME).unwrap();
+        assert_ne!(cel, asset.cel_expression);
+        assert_eq!(
+            Some(cel.to_string()),
+            head_cel_expression_string(&asset.certification_mode)
+        );
+
+        let CertificationMode::ResponseOnly(config) = &asset.certification_mode else {
+            panic!("expected response-only mode");
+        };
+        let recomputed = create_head_certification(config, "/index.html", &resp).unwrap();
+        assert_eq!(recomputed, *head_entry.certification);
+    }
+
+    #[test]
+    fn head_certification_does_not_match_get_response() {
+        let mut router = make_router();
+        router
+            .certify_asset("/page", b"body".to_vec(), default_config())
+            .unwrap();
+        let asset = router.get_asset("/page").unwrap();
+        let (get_resp, _, _) = router.serve_asset(&make_get_request("/page")).unwrap();
+        let as_response_only = create_certification(&asset.certification_mode, &get_resp).unwrap();
+        assert_ne!(
+            as_response_only,
+            *asset.head_entry.as_ref().unwrap().certification
+        );
+        assert_eq!(get_resp.body(), b"body");
+    }
+
+    #[test]
+    fn conditional_head_gets_certified_304() {
+        let mut router = make_router();
+        router
+            .certify_asset("/page", b"body".to_vec(), default_config())
+            .unwrap();
+        let etag = etag_for(b"body");
+        let req = make_head_request("/page", &[("if-none-match", &etag)]);
+        let (resp, _, _) = router.serve_asset(&req).unwrap();
+        assert_eq!(resp.status_code(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn skip_and_full_assets_have_no_head_entry() {
+        let mut router = make_router();
+        router
+            .certify_asset("/skip", b"body".to_vec(), skip_config())
+            .unwrap();
+        assert!(router.get_asset("/skip").unwrap().head_entry.is_none());
+        let (resp, _, _) = router
+            .serve_asset(&make_head_request("/skip", &[]))
+            .unwrap();
+        assert!(resp.body().is_empty());
+
+        let req = make_get_request("/full");
+        router
+            .certify_dynamic_asset("/full", &req, &make_response(b"body"), full_config())
+            .unwrap();
+        assert!(router.get_asset("/full").unwrap().head_entry.is_none());
+    }
+
+    #[test]
+    fn delete_asset_removes_head_entry() {
+        let mut router = make_router();
+        let empty_hash = router.root_hash();
+        router
+            .certify_asset("/page", b"body".to_vec(), default_config())
+            .unwrap();
+        assert!(router.get_asset("/page").unwrap().head_entry.is_some());
+        router.delete_asset("/page");
+        assert_eq!(router.root_hash(), empty_hash);
+    }
 }
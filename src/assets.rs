@@ -2,8 +2,12 @@ use ic_cdk::api::certified_data_set;
 use ic_http_certification::HeaderField;
 use include_dir::Dir;
 
-use crate::asset_router::{AssetCertificationConfig, AssetEncoding, AssetRouter};
+use crate::asset_router::{
+    AssetCertificationConfig, AssetEncoding, AssetRouter, AssetRouterSnapshot,
+};
 use crate::certification::CertificationMode;
+use crate::compression;
+use crate::config::CompressionConfig;
 use crate::{mime::get_mime_type, ASSET_ROUTER, ROUTER_CONFIG};
 
 /// Certify all static assets from the given embedded directory using the
@@ -47,8 +51,38 @@ pub fn certify_assets(asset_dir: &Dir<'static>) {
 /// );
 /// ```
 pub fn certify_assets_with_mode(asset_dir: &Dir<'static>, mode: CertificationMode) {
+    certify_assets_with_compression(asset_dir, mode, &CompressionConfig::disabled())
+}
+
+/// Certify all assets in the given directory, generating gzip/Brotli
+/// variants for text-like files that have no pre-compressed sibling.
+///
+/// Behaves like [`certify_assets_with_mode`], except that for every
+/// compressible file (see [`compression::is_compressible`]) at least
+/// [`CompressionConfig::min_size`] bytes long, each encoding enabled in
+/// `compression` is produced in the canister unless a `.br` / `.gz` sibling
+/// already provides it. Variants that are not smaller than the original are
+/// dropped.
+///
+/// Compression runs once per certification, so the instruction cost is paid
+/// in `init` / `post_upgrade` rather than per request.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// certify_assets_with_compression(
+///     &include_dir!("assets"),
+///     CertificationMode::response_only(),
+///     &CompressionConfig::enabled(),
+/// );
+/// ```
+pub fn certify_assets_with_compression(
+    asset_dir: &Dir<'static>,
+    mode: CertificationMode,
+    compression: &CompressionConfig,
+) {
     ASSET_ROUTER.with_borrow_mut(|asset_router| {
-        certify_dir_recursive(asset_router, asset_dir, &mode);
+        certify_dir_recursive(asset_router, asset_dir, &mode, compression);
     });
 
     // Set certified data AFTER all tree modifications
@@ -58,7 +92,12 @@ pub fn certify_assets_with_mode(asset_dir: &Dir<'static>, mode: CertificationMod
 }
 
 /// Recursively certify all files in the directory and its subdirectories.
-fn certify_dir_recursive(router: &mut AssetRouter, dir: &Dir<'static>, mode: &CertificationMode) {
+fn certify_dir_recursive(
+    router: &mut AssetRouter,
+    dir: &Dir<'static>,
+    mode: &CertificationMode,
+    compression: &CompressionConfig,
+) {
     for file in dir.files() {
         let raw_path = file.path().to_string_lossy().to_string();
 
@@ -77,16 +116,20 @@ fn certify_dir_recursive(router: &mut AssetRouter, dir: &Dir<'static>, mode: &Ce
 
         let mime_type = get_mime_type(&path);
 
-        // Collect pre-compressed encodings (.br, .gz) from the directory.
+        // Collect pre-compressed encodings (.br, .gz) from the directory,
+        // then generate any enabled encoding that has no sibling.
         // Only text-like assets are expected to have compressed variants.
-        let use_encodings = if mime_type.starts_with("text/")
-            || mime_type == "application/javascript"
-            || mime_type == "application/json"
-            || mime_type == "application/xml"
-            || mime_type == "image/svg+xml"
-        {
+        let use_encodings = if compression::is_compressible(mime_type) {
             let rel_path = file.path().to_string_lossy().to_string();
-            collect_encoded_variants(dir, &rel_path)
+            let mut encodings = collect_encoded_variants(dir, &rel_path);
+            let has = |encoding| encodings.iter().any(|(e, _)| *e == encoding);
+            let missing = CompressionConfig {
+                brotli: compression.brotli && !has(AssetEncoding::Brotli),
+                gzip: compression.gzip && !has(AssetEncoding::Gzip),
+                min_size: compression.min_size,
+            };
+            encodings.extend(compression::encode_variants(&content, mime_type, &missing));
+            encodings
         } else {
             vec![]
         };
@@ -124,7 +167,7 @@ fn certify_dir_recursive(router: &mut AssetRouter, dir: &Dir<'static>, mode: &Ce
 
     // Recurse into subdirectories
     for subdir in dir.dirs() {
-        certify_dir_recursive(router, subdir, mode);
+        certify_dir_recursive(router, subdir, mode, compression);
     }
 }
 
@@ -327,9 +370,110 @@ pub fn register_dynamic_path(path: &str) {
     });
 }
 
+/// Capture the dynamic response cache as a serializable snapshot.
+///
+/// Use this together with [`restore_dynamic_cache`] when the canister keeps
+/// other state in stable memory: embed the snapshot in your own stable
+/// structure during `pre_upgrade` and hand it back after the upgrade.
+/// Canisters without other stable state can use [`pre_upgrade`] and
+/// [`post_upgrade`] instead.
+///
+/// Static assets and `Full`-mode responses are not included. See
+/// [`AssetRouter::dynamic_snapshot`].
+pub fn dynamic_cache_snapshot() -> AssetRouterSnapshot {
+    ASSET_ROUTER.with_borrow(|asset_router| asset_router.dynamic_snapshot())
+}
+
+/// Restore a dynamic response cache captured by [`dynamic_cache_snapshot`].
+///
+/// Re-certifies every cached response, rebuilding the certification tree,
+/// and commits the new root hash. Call this in `post_upgrade` **after**
+/// [`setup`](crate::setup) so the restored responses are layered on top of
+/// the freshly certified static assets.
+///
+/// Traps if a snapshot entry cannot be certified.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[post_upgrade]
+/// fn post_upgrade() {
+///     let (state, cache): (MyState, AssetRouterSnapshot) =
+///         ic_cdk::storage::stable_restore().unwrap();
+///     setup();
+///     ic_asset_router::restore_dynamic_cache(cache);
+/// }
+/// ```
+pub fn restore_dynamic_cache(snapshot: AssetRouterSnapshot) {
+    ASSET_ROUTER.with_borrow_mut(|asset_router| {
+        if let Err(err) = asset_router.restore_snapshot(snapshot) {
+            ic_cdk::trap(format!("Failed to restore dynamic cache: {err}"));
+        }
+        certified_data_set(asset_router.root_hash());
+    });
+}
+
+/// Save the dynamic response cache to stable memory.
+///
+/// Convenience wrapper around [`dynamic_cache_snapshot`] that writes the
+/// snapshot with `ic_cdk::storage::stable_save`. This **overwrites** stable
+/// memory, so only use it when the canister keeps no other stable state;
+/// otherwise store the snapshot alongside your own data.
+///
+/// Traps if the snapshot cannot be written.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[pre_upgrade]
+/// fn pre_upgrade() {
+///     ic_asset_router::pre_upgrade();
+/// }
+/// ```
+pub fn pre_upgrade() {
+    let snapshot = dynamic_cache_snapshot();
+    if let Err(err) = ic_cdk::storage::stable_save((snapshot,)) {
+        ic_cdk::trap(format!("Failed to save dynamic cache: {err}"));
+    }
+}
+
+/// Restore the dynamic response cache saved by [`pre_upgrade`].
+///
+/// Reads the snapshot from stable memory and passes it to
+/// [`restore_dynamic_cache`]. Call this **after** [`setup`](crate::setup).
+/// If stable memory is empty (e.g. the previous version did not call
+/// [`pre_upgrade`]), the cache simply starts empty.
+///
+/// Traps if stable memory holds something that does not decode as a
+/// snapshot, such as a corrupt snapshot or one written by a version with a
+/// different snapshot format, rather than silently dropping the cache.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[post_upgrade]
+/// fn post_upgrade() {
+///     setup();
+///     ic_asset_router::post_upgrade();
+/// }
+/// ```
+pub fn post_upgrade() {
+    if ic_cdk::stable::stable_size() == 0 {
+        debug_log!("no dynamic cache snapshot in stable memory");
+        return;
+    }
+    match ic_cdk::storage::stable_restore::<(AssetRouterSnapshot,)>() {
+        Ok((snapshot,)) => restore_dynamic_cache(snapshot),
+        Err(err) => ic_cdk::trap(format!("Failed to read dynamic cache: {err}")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use include_dir::{DirEntry, File};
+    use std::cell::RefCell;
+    use std::rc::Rc;
     use std::time::Duration;
 
     /// Helper: reset the ASSET_ROUTER before each test to avoid cross-test leakage.
@@ -659,12 +803,15 @@ mod tests {
         register_dynamic_with_ttl("/posts/1", original_time, Some(Duration::from_secs(3600)));
 
         // Simulate the NotModified TTL reset logic from http_request_update:
-        // if asset.ttl.is_some(), reset certified_at to the new time.
+        // if asset.ttl.is_some(), re-certify it at the new time.
         ASSET_ROUTER.with_borrow_mut(|asset_router| {
-            if let Some(asset) = asset_router.get_asset_mut("/posts/1") {
-                if asset.ttl.is_some() {
-                    asset.certified_at = new_time;
-                }
+            if asset_router
+                .get_asset("/posts/1")
+                .is_some_and(|asset| asset.ttl.is_some())
+            {
+                asset_router
+                    .recertify_at("/posts/1", new_time, None)
+                    .unwrap();
             }
         });
 
@@ -676,4 +823,123 @@ mod tests {
             assert_eq!(asset.ttl, Some(Duration::from_secs(3600)));
         });
     }
+
+    // ---- Dynamic cache snapshot survives a simulated upgrade ----
+    //
+    // `restore_dynamic_cache` calls `certified_data_set()`, so the restore
+    // step goes through the router directly.
+
+    #[test]
+    fn dynamic_cache_snapshot_round_trips_through_router() {
+        reset_router();
+        register_dynamic_with_ttl("/posts/1", 42, Some(Duration::from_secs(600)));
+        register_dynamic_with_ttl("/posts/2", 43, None);
+
+        let snapshot = dynamic_cache_snapshot();
+        assert_eq!(snapshot.assets.len(), 2);
+
+        // Simulate the upgrade wiping the heap.
+        reset_router();
+        assert_eq!(dynamic_path_count(), 0);
+
+        ASSET_ROUTER.with_borrow_mut(|router| router.restore_snapshot(snapshot).unwrap());
+        assert!(is_dynamic_path("/posts/1"));
+        assert!(is_dynamic_path("/posts/2"));
+        assert_eq!(last_certified_at("/posts/1"), Some(42));
+        ASSET_ROUTER.with_borrow(|r| {
+            assert_eq!(
+                r.get_asset("/posts/1").unwrap().ttl,
+                Some(Duration::from_secs(600))
+            );
+        });
+    }
+
+    // ---- Static asset compression at certification time ----
+
+    /// Build a `'static` in-memory directory from `(path, contents)` pairs.
+    fn leak_dir(files: Vec<(&'static str, Vec<u8>)>) -> &'static Dir<'static> {
+        let entries: Vec<DirEntry<'static>> = files
+            .into_iter()
+            .map(|(path, contents)| {
+                DirEntry::File(File::new(path, Box::leak(contents.into_boxed_slice())))
+            })
+            .collect();
+        Box::leak(Box::new(Dir::new(
+            "",
+            Box::leak(entries.into_boxed_slice()),
+        )))
+    }
+
+    fn certify_dir(dir: &Dir<'static>, compression: &CompressionConfig) -> AssetRouter {
+        let mut router = AssetRouter::with_tree(Rc::new(RefCell::new(Default::default())));
+        certify_dir_recursive(
+            &mut router,
+            dir,
+            &CertificationMode::response_only(),
+            compression,
+        );
+        router
+    }
+
+    fn large_css() -> Vec<u8> {
+        "body { color: red; }\n".repeat(200).into_bytes()
+    }
+
+    fn encodings_of(router: &AssetRouter, path: &str) -> Vec<AssetEncoding> {
+        let mut encodings: Vec<AssetEncoding> = router
+            .get_asset(path)
+            .unwrap()
+            .encodings
+            .keys()
+            .copied()
+            .filter(|e| *e != AssetEncoding::Identity)
+            .collect();
+        encodings.sort_by_key(|e| e.as_str());
+        encodings
+    }
+
+    #[test]
+    fn static_compression_disabled_adds_no_variants() {
+        let dir = leak_dir(vec![("style.css", large_css())]);
+        let router = certify_dir(dir, &CompressionConfig::disabled());
+        assert!(encodings_of(&router, "/style.css").is_empty());
+    }
+
+    #[test]
+    fn static_compression_generates_missing_variants() {
+        let dir = leak_dir(vec![("style.css", large_css())]);
+        let router = certify_dir(dir, &CompressionConfig::enabled());
+        assert_eq!(
+            encodings_of(&router, "/style.css"),
+            vec![AssetEncoding::Brotli, AssetEncoding::Gzip]
+        );
+        let asset = router.get_asset("/style.css").unwrap();
+        assert_eq!(asset.encoding_entries.len(), 2);
+    }
+
+    #[test]
+    fn static_compression_prefers_precompressed_sibling() {
+        let dir = leak_dir(vec![
+            ("style.css", large_css()),
+            ("style.css.br", b"prebuilt-br".to_vec()),
+        ]);
+        let router = certify_dir(dir, &CompressionConfig::enabled());
+        let asset = router.get_asset("/style.css").unwrap();
+        assert_eq!(
+            asset.encodings.get(&AssetEncoding::Brotli).unwrap(),
+            b"prebuilt-br"
+        );
+        assert!(asset.encodings.contains_key(&AssetEncoding::Gzip));
+    }
+
+    #[test]
+    fn static_compression_skips_binary_and_small_files() {
+        let dir = leak_dir(vec![
+            ("logo.png", vec![0u8; 4096]),
+            ("tiny.css", b"a{}".to_vec()),
+        ]);
+        let router = certify_dir(dir, &CompressionConfig::enabled());
+        assert!(encodings_of(&router, "/logo.png").is_empty());
+        assert!(encodings_of(&router, "/tiny.css").is_empty());
+    }
 }
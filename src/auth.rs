@@ -0,0 +1,807 @@
+//! Signed-request authentication for IC identities (requires the `auth`
+//! feature).
+//!
+//! The client signs each request with its identity and sends the signature,
+//! its public key and, for Internet Identity and other delegated identities,
+//! the delegation chain in one header:
+//!
+//! ```text
+//! Authorization: IcSignature {"publicKey":"<hex>","delegations":[...],"expiry":"<hex>","signature":"<hex>"}
+//! ```
+//!
+//! - `publicKey` and `delegations` are the fields of agent-js
+//!   `DelegationChain.toJSON()`: DER public keys, signatures and target
+//!   principals in hex, expirations as hex nanoseconds. An identity without
+//!   delegations sends its own public key and `"delegations": []`.
+//! - `expiry` is the hex nanosecond time after which the request signature
+//!   is void. It may be at most [`AuthConfig::max_request_ttl`] ahead.
+//! - `signature` is made with the innermost key (the session key, or the
+//!   identity key without delegations) over
+//!   `"\x17ic-asset-router-request"` followed by [`request_hash`]. agent-js
+//!   `requestIdOf({ method, url, body, expiry, canister_id })` computes the
+//!   same hash.
+//!
+//! [`middleware`] verifies the signature, every delegation (Ed25519, ECDSA
+//! P-256 and secp256k1 keys, and canister signatures such as Internet
+//! Identity's), the expirations, and that delegation targets, if any,
+//! include this canister. It then attaches a [`VerifiedCaller`] to the
+//! request [extensions](crate::extensions):
+//!
+//! ```rust,ignore
+//! // src/routes/account/middleware.rs
+//! pub fn middleware(
+//!     req: HttpRequest,
+//!     params: &RouteParams,
+//!     next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+//! ) -> HttpResponse<'static> {
+//!     ic_asset_router::auth::middleware(req, params, next)
+//! }
+//!
+//! // src/routes/account/index.rs
+//! #[route(certification = "authenticated")]
+//! pub fn get(ctx: RouteContext<()>) -> HttpResponse<'static> {
+//!     let caller = ctx.extensions().get::<VerifiedCaller>().unwrap().principal();
+//!     // ...
+//! }
+//! ```
+//!
+//! The whole proof is in the `Authorization` header, so
+//! [`CertificationMode::authenticated`](crate::CertificationMode::authenticated)
+//! certifies each response for the exact signed request. Such full-mode
+//! routes are never cached: every query upgrades to an update call, where
+//! the middleware verifies the signature before the handler runs.
+//!
+//! A signed request carries no nonce, so anyone who captures it can send
+//! it again, and it verifies each time until its `expiry`. Keep
+//! [`AuthConfig::max_request_ttl`] short (a few seconds) for canisters
+//! whose `POST` and other non-idempotent routes sit behind [`middleware`],
+//! or make those handlers idempotent.
+//!
+//! Configure verification by registering an [`AuthConfig`] as application
+//! state with [`SetupBuilder::with_state`](crate::SetupBuilder::with_state);
+//! without one, [`AuthConfig::default`] applies.
+
+use std::borrow::Cow;
+use std::time::Duration;
+
+use candid::Principal;
+use ic_http_certification::{HttpRequest, HttpResponse, Method, StatusCode};
+use ic_representation_independent_hash::{representation_independent_hash, Value};
+use serde::Deserialize;
+
+use crate::extensions;
+use crate::router::RouteParams;
+use crate::state::AppState;
+
+/// The `Authorization` scheme carrying a signed request.
+pub const SCHEME: &str = "IcSignature";
+
+/// The most delegations accepted in a chain, as for IC ingress messages.
+pub const MAX_DELEGATIONS: usize = 20;
+
+/// Domain separator prefixed to [`request_hash`] before signing.
+const REQUEST_DOMAIN: &[u8] = b"\x17ic-asset-router-request";
+
+/// Domain separator for delegation signatures, from the IC interface spec.
+const DELEGATION_DOMAIN: &[u8] = b"\x1Aic-request-auth-delegation";
+
+const ED25519_DER_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+const P256_DER_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+const SECP256K1_DER_PREFIX: [u8; 23] = [
+    0x30, 0x56, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b,
+    0x81, 0x04, 0x00, 0x0a, 0x03, 0x42, 0x00,
+];
+/// Algorithm identifier of canister signature keys (OID 1.3.6.1.4.1.56387.1.2),
+/// found after the outer `SEQUENCE` header.
+const CANISTER_SIG_ALGORITHM: [u8; 14] = [
+    0x30, 0x0c, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xb8, 0x43, 0x01, 0x02,
+];
+const IC_ROOT_KEY_DER_PREFIX: [u8; 37] = [
+    0x30, 0x81, 0x82, 0x30, 0x1d, 0x06, 0x0d, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05,
+    0x03, 0x01, 0x02, 0x01, 0x06, 0x0c, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05, 0x03,
+    0x02, 0x01, 0x03, 0x61, 0x00,
+];
+
+/// Verification settings, registered as application state.
+///
+/// ```rust,ignore
+/// ic_asset_router::setup(routes)
+///     .with_state(AuthConfig {
+///         max_request_ttl: Duration::from_secs(60),
+///         ..AuthConfig::default()
+///     })
+///     .build();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthConfig {
+    /// How far ahead of the current time a request `expiry` may be.
+    /// Default: 5 minutes.
+    pub max_request_ttl: Duration,
+    /// The raw 96-byte IC root public key, used to verify canister
+    /// signatures such as Internet Identity delegations. `None` reads the
+    /// root key of the network the canister runs on.
+    pub root_key: Option<Vec<u8>>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            max_request_ttl: Duration::from_secs(5 * 60),
+            root_key: None,
+        }
+    }
+}
+
+/// The identity a request was signed by, attached to the request
+/// extensions by [`middleware`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerifiedCaller {
+    principal: Principal,
+    expires_at: u64,
+}
+
+impl VerifiedCaller {
+    /// The self-authenticating principal of the signing identity.
+    pub fn principal(&self) -> Principal {
+        self.principal
+    }
+
+    /// Nanosecond time at which the signature or a delegation expires,
+    /// whichever comes first.
+    pub fn expires_at(&self) -> u64 {
+        self.expires_at
+    }
+}
+
+/// Error returned when a request does not carry a valid signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No `Authorization: IcSignature` header.
+    Missing,
+    /// The header value is not a valid signed-request envelope.
+    Malformed(String),
+    /// The request expiry has passed.
+    Expired,
+    /// The request expiry is further ahead than [`AuthConfig::max_request_ttl`].
+    ExpiryTooFar,
+    /// A delegation in the chain has expired.
+    DelegationExpired,
+    /// A delegation is restricted to targets that exclude this canister.
+    TargetMismatch,
+    /// The chain has more than [`MAX_DELEGATIONS`] delegations.
+    TooManyDelegations,
+    /// A public key uses an unsupported algorithm.
+    UnsupportedKey,
+    /// A signature does not verify.
+    InvalidSignature,
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "missing `Authorization: {SCHEME}` header"),
+            Self::Malformed(msg) => write!(f, "malformed signed request: {msg}"),
+            Self::Expired => write!(f, "request signature has expired"),
+            Self::ExpiryTooFar => write!(f, "request expiry is too far in the future"),
+            Self::DelegationExpired => write!(f, "delegation has expired"),
+            Self::TargetMismatch => write!(f, "delegation does not target this canister"),
+            Self::TooManyDelegations => {
+                write!(f, "more than {MAX_DELEGATIONS} delegations")
+            }
+            Self::UnsupportedKey => write!(f, "unsupported public key algorithm"),
+            Self::InvalidSignature => write!(f, "invalid signature"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// The signed-request envelope in the `Authorization` header.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+    public_key: String,
+    #[serde(default)]
+    delegations: Vec<SignedDelegation>,
+    expiry: String,
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct SignedDelegation {
+    delegation: Delegation,
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct Delegation {
+    pubkey: String,
+    expiration: String,
+    #[serde(default)]
+    targets: Option<Vec<String>>,
+}
+
+/// The hash a request signature is made over, after the
+/// `"\x17ic-asset-router-request"` domain separator.
+///
+/// It is the representation-independent hash of the map
+/// `{ method, url, body, expiry, canister_id }`: the method name and the
+/// request URL (path and query, as sent) as text, the body and the
+/// canister's principal as blobs, and the expiry as a number.
+pub fn request_hash(
+    method: &Method,
+    url: &str,
+    body: &[u8],
+    expiry: u64,
+    canister_id: Principal,
+) -> [u8; 32] {
+    representation_independent_hash(&[
+        ("method".to_string(), Value::String(method.to_string())),
+        ("url".to_string(), Value::String(url.to_string())),
+        ("body".to_string(), Value::Bytes(body.to_vec())),
+        ("expiry".to_string(), Value::Number(expiry)),
+        (
+            "canister_id".to_string(),
+            Value::Bytes(canister_id.as_slice().to_vec()),
+        ),
+    ])
+}
+
+/// Verify the signed request in `req` for canister `canister_id` at
+/// nanosecond time `now`.
+///
+/// [`middleware`] calls this with the IC time and canister id.
+pub fn verify_request(
+    req: &HttpRequest,
+    canister_id: Principal,
+    now: u64,
+    config: &AuthConfig,
+) -> Result<VerifiedCaller, AuthError> {
+    let envelope = envelope(req)?;
+    let sender_key = decode_hex(&envelope.public_key)?;
+    let expiry = decode_hex_u64(&envelope.expiry)?;
+    let signature = decode_hex(&envelope.signature)?;
+    if expiry <= now {
+        return Err(AuthError::Expired);
+    }
+    let max_ttl = u64::try_from(config.max_request_ttl.as_nanos()).unwrap_or(u64::MAX);
+    if expiry > now.saturating_add(max_ttl) {
+        return Err(AuthError::ExpiryTooFar);
+    }
+    if envelope.delegations.len() > MAX_DELEGATIONS {
+        return Err(AuthError::TooManyDelegations);
+    }
+
+    // Check every expiration and target before verifying any signature.
+    let mut chain = Vec::with_capacity(envelope.delegations.len());
+    let mut expires_at = expiry;
+    for signed in &envelope.delegations {
+        let pubkey = decode_hex(&signed.delegation.pubkey)?;
+        let expiration = decode_hex_u64(&signed.delegation.expiration)?;
+        let targets = signed
+            .delegation
+            .targets
+            .as_ref()
+            .map(|targets| {
+                targets
+                    .iter()
+                    .map(|t| decode_hex(t))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+        if expiration <= now {
+            return Err(AuthError::DelegationExpired);
+        }
+        if let Some(targets) = &targets {
+            if !targets
+                .iter()
+                .any(|t: &Vec<u8>| t == canister_id.as_slice())
+            {
+                return Err(AuthError::TargetMismatch);
+            }
+        }
+        expires_at = expires_at.min(expiration);
+        let message = delegation_message(&pubkey, expiration, targets);
+        chain.push((pubkey, message, decode_hex(&signed.signature)?));
+    }
+
+    let mut signing_key = &sender_key;
+    for (pubkey, message, delegation_signature) in &chain {
+        verify_signature(signing_key, message, delegation_signature, config)?;
+        signing_key = pubkey;
+    }
+    let hash = request_hash(req.method(), req.url(), req.body(), expiry, canister_id);
+    verify_signature(
+        signing_key,
+        &[REQUEST_DOMAIN, &hash].concat(),
+        &signature,
+        config,
+    )?;
+
+    Ok(VerifiedCaller {
+        principal: Principal::self_authenticating(&sender_key),
+        expires_at,
+    })
+}
+
+/// Middleware that requires a valid signed request.
+///
+/// Requests without one get `401 Unauthorized` with
+/// `WWW-Authenticate: IcSignature`. Otherwise a [`VerifiedCaller`] is
+/// attached to the request extensions and the chain continues.
+pub fn middleware(
+    req: HttpRequest,
+    params: &RouteParams,
+    next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+) -> HttpResponse<'static> {
+    authenticate(
+        req,
+        params,
+        next,
+        true,
+        ic_cdk::api::canister_self(),
+        ic_cdk::api::time(),
+    )
+}
+
+/// Middleware that verifies a signed request when there is one.
+///
+/// Requests without an `IcSignature` header continue without a
+/// [`VerifiedCaller`]; requests with an invalid one get `401 Unauthorized`.
+pub fn optional_middleware(
+    req: HttpRequest,
+    params: &RouteParams,
+    next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+) -> HttpResponse<'static> {
+    authenticate(
+        req,
+        params,
+        next,
+        false,
+        ic_cdk::api::canister_self(),
+        ic_cdk::api::time(),
+    )
+}
+
+fn authenticate(
+    req: HttpRequest,
+    params: &RouteParams,
+    next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    required: bool,
+    canister_id: Principal,
+    now: u64,
+) -> HttpResponse<'static> {
+    let config = AppState::current()
+        .get::<AuthConfig>()
+        .cloned()
+        .unwrap_or_default();
+    match verify_request(&req, canister_id, now, &config) {
+        Ok(caller) => {
+            extensions::insert(caller);
+            next(req, params)
+        }
+        Err(AuthError::Missing) if !required => next(req, params),
+        Err(e) => unauthorized(&e),
+    }
+}
+
+fn unauthorized(error: &AuthError) -> HttpResponse<'static> {
+    HttpResponse::builder()
+        .with_status_code(StatusCode::UNAUTHORIZED)
+        .with_headers(vec![
+            ("content-type".to_string(), "text/plain".to_string()),
+            ("www-authenticate".to_string(), SCHEME.to_string()),
+        ])
+        .with_body(Cow::<[u8]>::Owned(
+            format!("Unauthorized: {error}").into_bytes(),
+        ))
+        .build()
+}
+
+/// Parse the `Authorization: IcSignature <json>` header.
+fn envelope(req: &HttpRequest) -> Result<Envelope, AuthError> {
+    let value = req
+        .headers()
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+        .map(|(_, value)| value.trim())
+        .ok_or(AuthError::Missing)?;
+    let json = value
+        .split_once(' ')
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case(SCHEME))
+        .map(|(_, json)| json)
+        .ok_or(AuthError::Missing)?;
+    serde_json::from_str(json).map_err(|e| AuthError::Malformed(e.to_string()))
+}
+
+/// The signed message of a delegation, as in the IC interface spec.
+fn delegation_message(pubkey: &[u8], expiration: u64, targets: Option<Vec<Vec<u8>>>) -> Vec<u8> {
+    let mut fields = vec![
+        ("pubkey".to_string(), Value::Bytes(pubkey.to_vec())),
+        ("expiration".to_string(), Value::Number(expiration)),
+    ];
+    if let Some(targets) = targets {
+        let targets = targets.into_iter().map(Value::Bytes).collect();
+        fields.push(("targets".to_string(), Value::Array(targets)));
+    }
+    [DELEGATION_DOMAIN, &representation_independent_hash(&fields)].concat()
+}
+
+/// Verify `signature` over `message` with a DER-encoded public key.
+fn verify_signature(
+    public_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+    config: &AuthConfig,
+) -> Result<(), AuthError> {
+    let invalid = |_| AuthError::InvalidSignature;
+    if let Some(key) = public_key.strip_prefix(&ED25519_DER_PREFIX[..]) {
+        let key = <[u8; 32]>::try_from(key).map_err(|_| AuthError::UnsupportedKey)?;
+        let key = ed25519_dalek::VerifyingKey::from_bytes(&key).map_err(invalid)?;
+        let signature = ed25519_dalek::Signature::from_slice(signature).map_err(invalid)?;
+        key.verify_strict(message, &signature).map_err(invalid)
+    } else if let Some(key) = public_key.strip_prefix(&P256_DER_PREFIX[..]) {
+        use p256::ecdsa::signature::Verifier;
+        let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(key).map_err(invalid)?;
+        let signature = p256::ecdsa::Signature::from_slice(signature).map_err(invalid)?;
+        key.verify(message, &signature).map_err(invalid)
+    } else if let Some(key) = public_key.strip_prefix(&SECP256K1_DER_PREFIX[..]) {
+        use k256::ecdsa::signature::Verifier;
+        let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(key).map_err(invalid)?;
+        let signature = k256::ecdsa::Signature::from_slice(signature).map_err(invalid)?;
+        let signature = signature.normalize_s().unwrap_or(signature);
+        key.verify(message, &signature).map_err(invalid)
+    } else if public_key.get(2..2 + CANISTER_SIG_ALGORITHM.len()) == Some(&CANISTER_SIG_ALGORITHM) {
+        let root_key = match &config.root_key {
+            Some(key) => key.clone(),
+            None => {
+                let der = ic_cdk::api::root_key();
+                match der.strip_prefix(&IC_ROOT_KEY_DER_PREFIX[..]) {
+                    Some(raw) => raw.to_vec(),
+                    None => der,
+                }
+            }
+        };
+        ic_signature_verification::verify_canister_sig(message, signature, public_key, &root_key)
+            .map_err(|_| AuthError::InvalidSignature)
+    } else {
+        Err(AuthError::UnsupportedKey)
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, AuthError> {
+    let malformed = || AuthError::Malformed(format!("invalid hex `{hex}`"));
+    if !hex.len().is_multiple_of(2) {
+        return Err(malformed());
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let text = std::str::from_utf8(pair).map_err(|_| malformed())?;
+            u8::from_str_radix(text, 16).map_err(|_| malformed())
+        })
+        .collect()
+}
+
+fn decode_hex_u64(hex: &str) -> Result<u64, AuthError> {
+    u64::from_str_radix(hex, 16)
+        .map_err(|_| AuthError::Malformed(format!("invalid hex number `{hex}`")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    const NOW: u64 = 1_700_000_000_000_000_000;
+    const MINUTE: u64 = 60_000_000_000;
+
+    fn canister() -> Principal {
+        Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 7, 1, 1])
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// A test identity: its DER public key and a signing function.
+    enum Key {
+        Ed25519(ed25519_dalek::SigningKey),
+        P256(p256::ecdsa::SigningKey),
+        Secp256k1(k256::ecdsa::SigningKey),
+    }
+
+    impl Key {
+        fn der(&self) -> Vec<u8> {
+            match self {
+                Key::Ed25519(key) => {
+                    [&ED25519_DER_PREFIX[..], key.verifying_key().as_bytes()].concat()
+                }
+                Key::P256(key) => [
+                    &P256_DER_PREFIX[..],
+                    key.verifying_key().to_encoded_point(false).as_bytes(),
+                ]
+                .concat(),
+                Key::Secp256k1(key) => [
+                    &SECP256K1_DER_PREFIX[..],
+                    key.verifying_key().to_encoded_point(false).as_bytes(),
+                ]
+                .concat(),
+            }
+        }
+
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            use ed25519_dalek::Signer;
+            match self {
+                Key::Ed25519(key) => key.sign(message).to_bytes().to_vec(),
+                Key::P256(key) => {
+                    let signature: p256::ecdsa::Signature = key.sign(message);
+                    signature.to_bytes().to_vec()
+                }
+                Key::Secp256k1(key) => {
+                    let signature: k256::ecdsa::Signature = key.sign(message);
+                    signature.to_bytes().to_vec()
+                }
+            }
+        }
+    }
+
+    fn ed25519(seed: u8) -> Key {
+        Key::Ed25519(ed25519_dalek::SigningKey::from_bytes(&[seed; 32]))
+    }
+
+    fn p256_key(seed: u8) -> Key {
+        Key::P256(p256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap())
+    }
+
+    fn secp256k1(seed: u8) -> Key {
+        Key::Secp256k1(k256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap())
+    }
+
+    /// A delegation from `from` to `to` in agent-js JSON form.
+    fn delegation(
+        from: &Key,
+        to: &Key,
+        expiration: u64,
+        targets: Option<Vec<Principal>>,
+    ) -> serde_json::Value {
+        let raw_targets = targets
+            .as_ref()
+            .map(|t| t.iter().map(|p| p.as_slice().to_vec()).collect());
+        let message = delegation_message(&to.der(), expiration, raw_targets);
+        let mut inner = serde_json::json!({
+            "pubkey": hex(&to.der()),
+            "expiration": format!("{expiration:x}"),
+        });
+        if let Some(targets) = targets {
+            inner["targets"] = targets.iter().map(|p| hex(p.as_slice())).collect();
+        }
+        serde_json::json!({ "delegation": inner, "signature": hex(&from.sign(&message)) })
+    }
+
+    /// A request to `url` signed by `signer` on behalf of `identity`.
+    fn signed_request(
+        identity: &Key,
+        delegations: Vec<serde_json::Value>,
+        signer: &Key,
+        url: &str,
+        expiry: u64,
+    ) -> HttpRequest<'static> {
+        let hash = request_hash(&Method::POST, url, b"{}", expiry, canister());
+        let envelope = serde_json::json!({
+            "publicKey": hex(&identity.der()),
+            "delegations": delegations,
+            "expiry": format!("{expiry:x}"),
+            "signature": hex(&signer.sign(&[REQUEST_DOMAIN, &hash].concat())),
+        });
+        HttpRequest::builder()
+            .with_method(Method::POST)
+            .with_url(url)
+            .with_headers(vec![(
+                "authorization".to_string(),
+                format!("{SCHEME} {envelope}"),
+            )])
+            .with_body(b"{}".to_vec())
+            .build()
+    }
+
+    fn verify(req: &HttpRequest) -> Result<VerifiedCaller, AuthError> {
+        verify_request(req, canister(), NOW, &AuthConfig::default())
+    }
+
+    #[test]
+    fn verifies_a_request_signed_by_the_identity_key() {
+        for key in [ed25519(1), p256_key(2), secp256k1(3)] {
+            let req = signed_request(&key, vec![], &key, "/items?x=1", NOW + MINUTE);
+            let caller = verify(&req).expect("valid signature");
+            assert_eq!(
+                caller.principal(),
+                Principal::self_authenticating(key.der())
+            );
+            assert_eq!(caller.expires_at(), NOW + MINUTE);
+        }
+    }
+
+    #[test]
+    fn verifies_a_delegation_chain() {
+        let identity = ed25519(1);
+        let device = secp256k1(2);
+        let session = p256_key(3);
+        let chain = vec![
+            delegation(
+                &identity,
+                &device,
+                NOW + 10 * MINUTE,
+                Some(vec![canister()]),
+            ),
+            delegation(&device, &session, NOW + 3 * MINUTE, None),
+        ];
+        let req = signed_request(&identity, chain, &session, "/items", NOW + MINUTE);
+        let caller = verify(&req).expect("valid chain");
+        assert_eq!(
+            caller.principal(),
+            Principal::self_authenticating(identity.der())
+        );
+        assert_eq!(caller.expires_at(), NOW + MINUTE);
+    }
+
+    #[test]
+    fn rejects_a_request_signed_by_the_wrong_key() {
+        let identity = ed25519(1);
+        let chain = vec![delegation(&identity, &ed25519(2), NOW + MINUTE, None)];
+        // Signed by the identity instead of the delegated session key.
+        let req = signed_request(&identity, chain, &identity, "/items", NOW + MINUTE);
+        assert_eq!(verify(&req), Err(AuthError::InvalidSignature));
+
+        let req = signed_request(&identity, vec![], &ed25519(3), "/items", NOW + MINUTE);
+        assert_eq!(verify(&req), Err(AuthError::InvalidSignature));
+    }
+
+    #[test]
+    fn rejects_a_tampered_request() {
+        let key = ed25519(1);
+        let req = signed_request(&key, vec![], &key, "/items", NOW + MINUTE);
+        let tampered = HttpRequest::builder()
+            .with_method(Method::POST)
+            .with_url("/admin")
+            .with_headers(req.headers().to_vec())
+            .with_body(req.body().to_vec())
+            .build();
+        assert_eq!(verify(&tampered), Err(AuthError::InvalidSignature));
+
+        let other_canister = Principal::from_slice(&[9; 10]);
+        assert_eq!(
+            verify_request(&req, other_canister, NOW, &AuthConfig::default()),
+            Err(AuthError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn rejects_expired_and_far_future_requests() {
+        let key = ed25519(1);
+        let req = signed_request(&key, vec![], &key, "/items", NOW);
+        assert_eq!(verify(&req), Err(AuthError::Expired));
+
+        let req = signed_request(&key, vec![], &key, "/items", NOW + 6 * MINUTE);
+        assert_eq!(verify(&req), Err(AuthError::ExpiryTooFar));
+        let config = AuthConfig {
+            max_request_ttl: Duration::from_secs(10 * 60),
+            ..AuthConfig::default()
+        };
+        assert!(verify_request(&req, canister(), NOW, &config).is_ok());
+    }
+
+    #[test]
+    fn rejects_expired_or_mistargeted_delegations() {
+        let identity = ed25519(1);
+        let session = ed25519(2);
+        let chain = vec![delegation(&identity, &session, NOW - 1, None)];
+        let req = signed_request(&identity, chain, &session, "/items", NOW + MINUTE);
+        assert_eq!(verify(&req), Err(AuthError::DelegationExpired));
+
+        let elsewhere = Principal::from_slice(&[9; 10]);
+        let chain = vec![delegation(
+            &identity,
+            &session,
+            NOW + MINUTE,
+            Some(vec![elsewhere]),
+        )];
+        let req = signed_request(&identity, chain, &session, "/items", NOW + MINUTE);
+        assert_eq!(verify(&req), Err(AuthError::TargetMismatch));
+    }
+
+    #[test]
+    fn rejects_too_many_delegations() {
+        let key = ed25519(1);
+        let chain = vec![delegation(&key, &key, NOW + MINUTE, None); MAX_DELEGATIONS + 1];
+        let req = signed_request(&key, chain, &key, "/items", NOW + MINUTE);
+        assert_eq!(verify(&req), Err(AuthError::TooManyDelegations));
+    }
+
+    #[test]
+    fn reports_missing_and_malformed_headers() {
+        let bare = HttpRequest::builder().with_url("/items").build();
+        assert_eq!(verify(&bare), Err(AuthError::Missing));
+
+        let bearer = HttpRequest::builder()
+            .with_url("/items")
+            .with_headers(vec![(
+                "Authorization".to_string(),
+                "Bearer token".to_string(),
+            )])
+            .build();
+        assert_eq!(verify(&bearer), Err(AuthError::Missing));
+
+        let garbage = HttpRequest::builder()
+            .with_url("/items")
+            .with_headers(vec![(
+                "authorization".to_string(),
+                format!("{SCHEME} {{not json"),
+            )])
+            .build();
+        assert!(matches!(verify(&garbage), Err(AuthError::Malformed(_))));
+    }
+
+    #[test]
+    fn rejects_unsupported_keys_and_bad_canister_signatures() {
+        assert_eq!(
+            verify_signature(&[1, 2, 3], b"m", b"s", &AuthConfig::default()),
+            Err(AuthError::UnsupportedKey)
+        );
+        let mut canister_key = vec![0x30, 0x1d];
+        canister_key.extend_from_slice(&CANISTER_SIG_ALGORITHM);
+        canister_key.extend_from_slice(&[0x03, 0x0d, 0x00, 0x0a, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 1]);
+        let config = AuthConfig {
+            root_key: Some(vec![0; 96]),
+            ..AuthConfig::default()
+        };
+        assert_eq!(
+            verify_signature(&canister_key, b"m", b"not cbor", &config),
+            Err(AuthError::InvalidSignature)
+        );
+    }
+
+    thread_local! {
+        static SEEN: RefCell<Option<VerifiedCaller>> = const { RefCell::new(None) };
+    }
+
+    fn record_caller(_: HttpRequest, _: &RouteParams) -> HttpResponse<'static> {
+        SEEN.set(extensions::take().remove::<VerifiedCaller>());
+        HttpResponse::builder()
+            .with_status_code(StatusCode::OK)
+            .build()
+    }
+
+    #[test]
+    fn middleware_attaches_the_caller_or_rejects() {
+        let key = ed25519(1);
+        let req = signed_request(&key, vec![], &key, "/items", NOW + MINUTE);
+        let params = RouteParams::new();
+        let resp = authenticate(req, &params, &record_caller, true, canister(), NOW);
+        assert_eq!(resp.status_code(), StatusCode::OK);
+        assert_eq!(
+            SEEN.take().map(|c| c.principal()),
+            Some(Principal::self_authenticating(key.der()))
+        );
+
+        let bare = HttpRequest::builder().with_url("/items").build();
+        let resp = authenticate(bare.clone(), &params, &record_caller, true, canister(), NOW);
+        assert_eq!(resp.status_code(), StatusCode::UNAUTHORIZED);
+        assert!(resp
+            .headers()
+            .contains(&("www-authenticate".to_string(), SCHEME.to_string())));
+
+        let resp = authenticate(bare, &params, &record_caller, false, canister(), NOW);
+        assert_eq!(resp.status_code(), StatusCode::OK);
+        assert_eq!(SEEN.take(), None);
+
+        let expired = signed_request(&key, vec![], &key, "/items", NOW);
+        let resp = authenticate(expired, &params, &record_caller, false, canister(), NOW);
+        assert_eq!(resp.status_code(), StatusCode::UNAUTHORIZED);
+    }
+}
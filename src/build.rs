@@ -2,6 +2,8 @@ use std::fs::{self, File};
 use std::io::Write;
 use std::path::Path;
 
+use crate::router::{split_param_constraint, ParamConstraint};
+
 /// Reserved filenames that are never registered as route handlers.
 ///
 /// These files have special semantics in the file-based routing convention:
@@ -38,6 +40,21 @@ const METHOD_NAMES: &[(&str, &str)] = &[
     ("options", "Method::OPTIONS"),
 ];
 
+/// `#[route(...)]` keys that contribute to the generated `RouteConfig`.
+///
+/// When any of these is present, the generated route tree calls the
+/// `__route_config()` function emitted by the `#[route]` macro instead of
+/// using `RouteConfig::default()`. `path` is deliberately absent — it is
+/// consumed by the build script itself.
+const ROUTE_CONFIG_KEYS: &[&str] = &[
+    "certification",
+    "headers",
+    "compress",
+    "cors",
+    "max_body",
+    "accepts",
+];
+
 /// A detected method export from a route file.
 struct MethodExport {
     /// The route path (e.g. "/api/users")
@@ -56,13 +73,24 @@ struct MethodExport {
     /// "routes::posts::index::SearchParams"). `None` for routes without typed
     /// search params.
     search_params_type_path: Option<String>,
+    /// Whether the route opted into `#[route(search = "strict")]`. When true,
+    /// a query string that fails to deserialize into `SearchParams` returns
+    /// 400 instead of falling back to `SearchParams::default()`.
+    strict_search: bool,
     /// The Rust module path to the route file (e.g. "routes::api::users").
     /// Used to reference the generated `__route_config()` function.
     module_path: String,
-    /// Whether the route file has a `#[route(certification = ...)]` attribute.
-    /// When true, the generated route tree calls `module_path::__route_config()`.
+    /// Whether the route file has a `#[route(...)]` attribute with a key that
+    /// feeds the route config (see [`ROUTE_CONFIG_KEYS`]). When true, the generated route tree calls `module_path::__route_config()`.
     /// When false, `RouteConfig::default()` is used.
-    has_certification_attribute: bool,
+    has_route_config_attribute: bool,
+    /// Whether the handler returns `HandlerResult` instead of `HttpResponse`.
+    /// When true, the generated route tree also registers the wrapper via
+    /// `insert_result` so `HandlerResult::NotModified` is honoured.
+    returns_handler_result: bool,
+    /// Whether the handler is an `async fn`. When true, the wrapper returns a
+    /// `HandlerFuture` and is registered via `insert_async`.
+    is_async: bool,
 }
 
 /// Mapping from a route param name to its struct field name.
@@ -72,6 +100,22 @@ struct ParamMapping {
     route_name: String,
     /// The snake_case field name on the Params struct (e.g. "post_id").
     field_name: String,
+    /// The declared field type from a `_postId:u64` directory name. `None`
+    /// means the field is a plain `String`.
+    ty: Option<String>,
+    /// Whether the segment is required, optional, or a catch-all.
+    kind: ParamKind,
+}
+
+/// How a param directory matches its URL segment(s).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ParamKind {
+    /// `_name` — exactly one segment.
+    Required,
+    /// `__name` — zero or one segment; the field is an `Option`.
+    Optional,
+    /// `_...name` — all remaining segments; the field is a `Vec<String>`.
+    CatchAll,
 }
 
 /// A detected middleware file in a route directory.
@@ -80,6 +124,9 @@ struct MiddlewareExport {
     prefix: String,
     /// The Rust module path to the middleware function (e.g. "routes::middleware::middleware")
     handler_path: String,
+    /// Whether the middleware uses the response returned by `next` other
+    /// than by returning it (see [`scan_middleware_uses_response`]).
+    uses_response: bool,
 }
 
 /// A detected `not_found.rs` file in the routes root directory.
@@ -121,6 +168,10 @@ pub fn generate_routes() {
 /// | `about.rs` | `/about` | Named route |
 /// | `og.png.rs` | `/og.png` | Dotted filename — dots are preserved in the URL |
 /// | `_postId/index.rs` | `/:postId` | Dynamic segment with typed `Params` struct |
+/// | `_postId:u64/index.rs` | `/:postId` | Typed dynamic segment — parsed with `FromStr`, 400 on failure |
+/// | `_id[int]/index.rs` | `/:id[int]` | Constrained dynamic segment — only matches integers |
+/// | `__lang/index.rs` | `/:lang?` | Optional segment — `Option` field, matches with or without it |
+/// | `_...slug/index.rs` | `/*slug` | Named catch-all — one or more segments as `Vec<String>` |
 /// | `all.rs` | `/*` | Catch-all wildcard |
 /// | `middleware.rs` | — | Scoped middleware |
 /// | `not_found.rs` | — | Custom 404 handler |
@@ -132,6 +183,53 @@ pub fn generate_routes() {
 /// The Rust module name replaces dots with underscores (`og_png`), and a
 /// `#[path = "og.png.rs"]` attribute is emitted in the generated `mod.rs` so
 /// the compiler can locate the source file.
+///
+/// ## Typed parameters
+///
+/// A param directory may declare its field type after a colon:
+/// `_postId:u64` generates `pub post_id: u64` instead of `String`. Any type
+/// implementing `FromStr` (with a `Display` error) plus `Debug` and `Clone`
+/// works, including paths such as `_id:uuid::Uuid`. The generated wrapper
+/// URL-decodes and parses the segment before calling the handler; when
+/// parsing fails it returns `400 Bad Request` without invoking the handler.
+/// The module name replaces the colon with an underscore (`_postId_u64`).
+///
+/// Colons are not allowed in Windows file names, so projects that must build
+/// on Windows should keep `String` params and parse them in the handler.
+///
+/// ## Constrained parameters
+///
+/// A param directory may end in a bracketed
+/// [`ParamConstraint`](crate::router::ParamConstraint) so sibling param
+/// directories can coexist:
+///
+/// ```text
+/// posts/_id:u64[int]/index.rs     → /posts/:id[int]       (e.g. /posts/42)
+/// posts/_lang[en,fr]/index.rs     → /posts/:lang[en,fr]   (e.g. /posts/fr)
+/// posts/_slug[re=[a-z-]+]/index.rs → /posts/:slug[re=[a-z-]+]
+/// posts/_other/index.rs           → /posts/:other         (everything else)
+/// ```
+///
+/// Constrained segments are tried before the unconstrained one: value lists
+/// first, then `int`, then regular expressions (same-kind siblings in
+/// route-path order). The constraint does not affect the field type; combine
+/// it with a declared type (`_id:u64[int]`) to get a parsed value. The build
+/// fails on an invalid constraint. `/` cannot appear in a directory name, so
+/// regular expressions cannot match across segments.
+///
+/// ## Optional segments and named catch-alls
+///
+/// A directory starting with two underscores is an optional segment:
+/// `docs/__lang/intro/index.rs` serves both `/docs/en/intro` and
+/// `/docs/intro`, with `pub lang: Option<String>` (or `Option<T>` for
+/// `__lang:T`). Capturing the segment is tried first; when that leads to no
+/// match, the segment is skipped.
+///
+/// A directory named `_...name` is a catch-all: `docs/_...slug/index.rs`
+/// matches `/docs/a/b` with `pub slug: Vec<String>` holding the URL-decoded
+/// segments (`["a", "b"]`). Unlike `all.rs` it requires at least one segment,
+/// and `RouteContext::wildcard` stays `None`. Neither kind accepts a
+/// `[constraint]`, and catch-alls cannot declare a type.
 pub fn generate_routes_from(dir: &str) {
     let routes_dir = Path::new(dir);
     let out_dir = std::env::var("OUT_DIR")
@@ -175,6 +273,7 @@ pub fn generate_routes_from(dir: &str) {
 
     // Sort middleware by prefix for deterministic output
     middleware_exports.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+    check_async_middleware(&exports, &middleware_exports);
 
     let mut output = String::new();
     output.push_str("#[allow(unused_imports)]\n");
@@ -182,21 +281,44 @@ pub fn generate_routes_from(dir: &str) {
     output.push_str("#[allow(unused_imports)]\n");
     output.push_str("use ic_asset_router::Method;\n");
     output.push_str("#[allow(unused_imports)]\n");
-    output.push_str("use ic_asset_router::router::{NodeType, RouteNode, RouteParams};\n");
+    output.push_str(
+        "use ic_asset_router::router::{HandlerFuture, NodeType, RouteNode, RouteParams};\n",
+    );
     output.push_str("#[allow(unused_imports)]\n");
     output
-        .push_str("use ic_asset_router::{HttpRequest, HttpResponse, RouteConfig, RouteContext, parse_query, deserialize_search_params};\n");
+        .push_str("use ic_asset_router::{HandlerResult, HttpRequest, HttpResponse, RouteConfig, RouteContext, parse_query, deserialize_search_params};\n");
     output.push('\n');
 
     // Generate wrapper functions for each route handler.
     // Each wrapper bridges the router's internal (HttpRequest, RouteParams) signature
     // to the user-facing RouteContext<Params, SearchParams> signature.
+    //
+    // Handlers returning `HandlerResult` get a `__route_result_handler_{i}`
+    // wrapper with the same body, plus a plain `__route_handler_{i}` fallback
+    // that converts the result into a response. Async handlers get a
+    // `__route_async_handler_{i}` wrapper that runs the same body inside the
+    // returned future.
     for (i, export) in exports.iter().enumerate() {
-        let wrapper_name = format!("__route_handler_{i}");
+        let (wrapper_name, return_type) = if export.is_async {
+            (format!("__route_async_handler_{i}"), "HandlerFuture")
+        } else if export.returns_handler_result {
+            output.push_str(&format!(
+                "fn __route_handler_{i}(req: HttpRequest, raw_params: RouteParams) -> HttpResponse<'static> {{\n    __route_result_handler_{i}(req, raw_params).into_response()\n}}\n\n"
+            ));
+            (format!("__route_result_handler_{i}"), "HandlerResult")
+        } else {
+            (format!("__route_handler_{i}"), "HttpResponse<'static>")
+        };
+        let request_type = if export.is_async {
+            "HttpRequest<'static>"
+        } else {
+            "HttpRequest"
+        };
         output.push_str("#[allow(unused_variables)]\n");
         output.push_str(&format!(
-            "fn {wrapper_name}(req: HttpRequest, raw_params: RouteParams) -> HttpResponse<'static> {{\n"
+            "fn {wrapper_name}(req: {request_type}, raw_params: RouteParams) -> {return_type} {{\n"
         ));
+        let body_start = output.len();
 
         // Extract query string for both untyped (query) and typed (search) access.
         // Strips the fragment (#...) if present so serde_urlencoded sees clean input.
@@ -206,20 +328,26 @@ pub fn generate_routes_from(dir: &str) {
 
         // Deserialize typed search params if the route defines SearchParams.
         if let Some(ref search_path) = export.search_params_type_path {
-            output.push_str(&format!(
-                "    let __search: {search_path} = deserialize_search_params(__query_str);\n"
-            ));
+            if export.strict_search {
+                output.push_str(&strict_search_binding(search_path));
+            } else {
+                output.push_str(&format!(
+                    "    let __search: {search_path} = deserialize_search_params(__query_str);\n"
+                ));
+            }
         }
 
         if let Some(ref params_path) = export.params_type_path {
+            // Typed params are parsed up front so a value that fails
+            // `FromStr` short-circuits with a 400 before the handler runs.
+            for pm in export.params.iter().filter(|pm| pm.ty.is_some()) {
+                output.push_str(&typed_param_binding(pm));
+            }
             // Route has dynamic params — construct the typed Params struct.
             output.push_str("    let ctx = RouteContext {\n");
             output.push_str(&format!("        params: {params_path} {{\n"));
             for pm in &export.params {
-                output.push_str(&format!(
-                    "            {}: ic_asset_router::url_decode(&raw_params.get(\"{}\").cloned().unwrap_or_default()).into_owned(),\n",
-                    pm.field_name, pm.route_name,
-                ));
+                output.push_str(&param_field_init(pm));
             }
             output.push_str("        },\n");
         } else {
@@ -241,8 +369,29 @@ pub fn generate_routes_from(dir: &str) {
         output.push_str("        body: req.body().to_vec(),\n");
         output.push_str("        url: req.url().to_string(),\n");
         output.push_str("        wildcard: raw_params.get(\"*\").map(|w| ic_asset_router::url_decode(w).into_owned()),\n");
+        output.push_str("        app_state: ic_asset_router::AppState::current(),\n");
+        if export.is_async {
+            output.push_str("        extensions: __extensions,\n");
+        } else {
+            output.push_str("        extensions: ic_asset_router::extensions::take(),\n");
+        }
+        output.push_str("        ic: ic_asset_router::IcContext::current(),\n");
         output.push_str("    };\n");
-        output.push_str(&format!("    {}(ctx)\n", export.handler_path));
+        if export.is_async {
+            output.push_str(&format!("    {}(ctx).await\n", export.handler_path));
+            // Early returns (e.g. a 400 for a bad typed param) become the
+            // future's output. The extensions are taken when the wrapper is
+            // called, not when the future is first polled.
+            let body = output.split_off(body_start);
+            output.push_str("    let __extensions = ic_asset_router::extensions::take();\n");
+            output.push_str("    Box::pin(async move {\n");
+            for line in body.lines() {
+                output.push_str(&format!("    {line}\n"));
+            }
+            output.push_str("    })\n");
+        } else {
+            output.push_str(&format!("    {}(ctx)\n", export.handler_path));
+        }
         output.push_str("}\n\n");
     }
 
@@ -261,6 +410,9 @@ pub fn generate_routes_from(dir: &str) {
         output.push_str("        body: req.body().to_vec(),\n");
         output.push_str("        url: req.url().to_string(),\n");
         output.push_str("        wildcard: None,\n");
+        output.push_str("        app_state: ic_asset_router::AppState::current(),\n");
+        output.push_str("        extensions: ic_asset_router::extensions::take(),\n");
+        output.push_str("        ic: ic_asset_router::IcContext::current(),\n");
         output.push_str("    };\n");
         output.push_str(&format!("    {}(ctx)\n", nf.handler_path));
         output.push_str("}\n\n");
@@ -271,10 +423,23 @@ pub fn generate_routes_from(dir: &str) {
     output.push_str("        let mut root = RouteNode::new(NodeType::Static(\"\".into()));\n");
 
     for (i, export) in exports.iter().enumerate() {
+        if export.is_async {
+            output.push_str(&format!(
+                "        root.insert_async({:?}, {}, __route_async_handler_{i});\n",
+                export.route_path, export.method_variant,
+            ));
+            continue;
+        }
         output.push_str(&format!(
-            "        root.insert(\"{}\", {}, __route_handler_{i});\n",
+            "        root.insert({:?}, {}, __route_handler_{i});\n",
             export.route_path, export.method_variant,
         ));
+        if export.returns_handler_result {
+            output.push_str(&format!(
+                "        root.insert_result({:?}, {}, __route_result_handler_{i});\n",
+                export.route_path, export.method_variant,
+            ));
+        }
     }
 
     // Generate set_route_config calls. Multiple methods on the same path share
@@ -283,14 +448,14 @@ pub fn generate_routes_from(dir: &str) {
         let mut seen_paths = std::collections::HashSet::new();
         for export in exports.iter() {
             if seen_paths.insert(export.route_path.clone()) {
-                if export.has_certification_attribute {
+                if export.has_route_config_attribute {
                     output.push_str(&format!(
-                        "        root.set_route_config(\"{}\", {}::__route_config());\n",
+                        "        root.set_route_config({:?}, {}::__route_config());\n",
                         export.route_path, export.module_path,
                     ));
                 } else {
                     output.push_str(&format!(
-                        "        root.set_route_config(\"{}\", RouteConfig::default());\n",
+                        "        root.set_route_config({:?}, RouteConfig::default());\n",
                         export.route_path,
                     ));
                 }
@@ -300,7 +465,7 @@ pub fn generate_routes_from(dir: &str) {
 
     for mw in &middleware_exports {
         output.push_str(&format!(
-            "        root.set_middleware(\"{}\", {});\n",
+            "        root.set_middleware({:?}, {});\n",
             mw.prefix, mw.handler_path,
         ));
     }
@@ -335,12 +500,19 @@ fn generate_manifest(
     let mut json = String::from("{\n  \"routes\": [\n");
 
     for (i, export) in exports.iter().enumerate() {
-        // Extract parameter names from the route path (segments starting with ':')
+        // Extract parameter names from the route path (`:name` segments and
+        // named `*name` catch-alls)
         let params: Vec<&str> = export
             .route_path
             .split('/')
-            .filter(|s| s.starts_with(':'))
-            .map(|s| &s[1..])
+            .filter_map(|s| {
+                if let Some(param) = s.strip_prefix(':') {
+                    let name = split_param_constraint(param).0;
+                    Some(name.strip_suffix('?').unwrap_or(name))
+                } else {
+                    s.strip_prefix('*').filter(|name| !name.is_empty())
+                }
+            })
             .collect();
 
         // Extract the method name from the variant string (e.g. "Method::GET" → "GET")
@@ -420,6 +592,10 @@ struct AccumulatedParam {
     route_name: String,
     /// The snake_case field name for the Params struct (e.g. "post_id").
     field_name: String,
+    /// The declared type for typed params (e.g. "u64" from `_postId:u64`).
+    ty: Option<String>,
+    /// Whether the segment is required, optional, or a catch-all.
+    kind: ParamKind,
 }
 
 fn process_directory(
@@ -465,21 +641,40 @@ fn process_directory(
         }
 
         // Warn on conflicting param directories at the same level.
-        // Two `_`-prefixed directories as siblings produce ambiguous routing
-        // because only one param child is allowed per trie node.
-        let param_dirs: Vec<&String> = dir_names.iter().filter(|n| n.starts_with('_')).collect();
-        if param_dirs.len() > 1 {
-            println!(
-                "cargo:warning=Conflicting param directories in '{}': {} \
-                 — only one dynamic parameter directory is allowed per level. \
-                 The first one encountered will win at runtime.",
-                dir.display(),
-                param_dirs
-                    .iter()
-                    .map(|d| format!("'{d}/'"))
-                    .collect::<Vec<_>>()
-                    .join(", "),
-            );
+        // The trie holds one unconstrained param, one optional param and one
+        // catch-all child per node, so two siblings of the same kind produce
+        // ambiguous routing. Constrained directories (`_id[int]`) may coexist
+        // with each other and with one unconstrained directory.
+        for (kind, label, hint) in [
+            (
+                ParamKind::Required,
+                "unconstrained dynamic parameter",
+                " Add a constraint (e.g. `_id[int]`) to let them coexist.",
+            ),
+            (ParamKind::Optional, "optional parameter", ""),
+            (ParamKind::CatchAll, "catch-all", ""),
+        ] {
+            let param_dirs: Vec<&String> = dir_names
+                .iter()
+                .filter(|n| {
+                    param_dir(n).is_some_and(|(k, param)| {
+                        k == kind && split_param_constraint(param).1.is_none()
+                    })
+                })
+                .collect();
+            if param_dirs.len() > 1 {
+                println!(
+                    "cargo:warning=Conflicting param directories in '{}': {} \
+                     — only one {label} directory is allowed per level. \
+                     The first one encountered will win at runtime.{hint}",
+                    dir.display(),
+                    param_dirs
+                        .iter()
+                        .map(|d| format!("'{d}/'"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                );
+            }
         }
 
         // Warn on unreachable post-wildcard routes.
@@ -524,19 +719,58 @@ fn process_directory(
             };
             fs::create_dir_all(&path)
                 .unwrap_or_else(|e| panic!("failed to create directory {}: {e}", path.display()));
-            // If this directory is a dynamic param (starts with `_`), accumulate
-            // it for Params struct generation in child directories.
+            // If this directory is a dynamic param (`_name`, `__name` or
+            // `_...name`), accumulate it for Params struct generation in child
+            // directories.
             let mut child_params: Vec<AccumulatedParam> = accumulated_params
                 .iter()
                 .map(|p| AccumulatedParam {
                     route_name: p.route_name.clone(),
                     field_name: p.field_name.clone(),
+                    ty: p.ty.clone(),
+                    kind: p.kind,
                 })
                 .collect();
-            if let Some(param_name) = name.strip_prefix('_') {
+            if let Some((kind, param)) = param_dir(name) {
+                let (param_name, ty) = split_param_type(param);
+                let constraint = split_param_constraint(param).1;
+                if kind != ParamKind::Required && constraint.is_some() {
+                    panic!(
+                        "Constraints are only supported on required param directories, \
+                         not on optional (`__name`) or catch-all (`_...name`) ones: '{}'.",
+                        path.display()
+                    );
+                }
+                if kind == ParamKind::CatchAll && ty.is_some() {
+                    panic!(
+                        "Catch-all directory '{}' cannot declare a type — its field is \
+                         always `Vec<String>`.",
+                        path.display()
+                    );
+                }
+                if let Some(constraint) = constraint {
+                    if let Err(e) = ParamConstraint::parse(constraint) {
+                        panic!(
+                            "Invalid parameter constraint in directory '{}': {e}. \
+                             Use `_name[int]`, `_name[a,b,c]` or `_name[re=<regex>]`.",
+                            path.display()
+                        );
+                    }
+                }
+                if let Some(ty) = ty {
+                    if syn::parse_str::<syn::Type>(ty).is_err() {
+                        panic!(
+                            "Invalid parameter type '{ty}' in directory '{}'. \
+                             Use `_name:Type` where `Type` implements `FromStr` (e.g. `_postId:u64`).",
+                            path.display()
+                        );
+                    }
+                }
                 child_params.push(AccumulatedParam {
                     route_name: param_name.to_string(),
                     field_name: camel_to_snake(param_name),
+                    ty: ty.map(str::to_string),
+                    kind,
                 });
             }
             process_directory(
@@ -548,10 +782,21 @@ fn process_directory(
                 &child_params,
             );
             let mod_name = sanitize_mod(name);
+            // Typed and constrained param directories (`_postId:u64`,
+            // `_id[int]`) are not valid module names, so point the sanitized
+            // module at the directory's mod.rs. `{:?}` escapes backslashes
+            // and quotes a regex constraint may contain.
+            let path_attr = if mod_name != name {
+                format!("#[path = {:?}]\n", format!("{name}/mod.rs"))
+            } else {
+                String::new()
+            };
             if mod_name.starts_with('_') {
-                children.push(format!("#[allow(non_snake_case)]\npub mod {mod_name};\n"));
+                children.push(format!(
+                    "{path_attr}#[allow(non_snake_case)]\npub mod {mod_name};\n"
+                ));
             } else {
-                children.push(format!("pub mod {mod_name};\n"));
+                children.push(format!("{path_attr}pub mod {mod_name};\n"));
             }
         } else if path.extension().and_then(|s| s.to_str()) == Some("rs") {
             let stem = path
@@ -597,6 +842,7 @@ fn process_directory(
                         middleware_exports.push(MiddlewareExport {
                             prefix: mw_prefix,
                             handler_path: mw_handler_path,
+                            uses_response: scan_middleware_uses_response(&path),
                         });
                     }
                     "not_found" => {
@@ -690,6 +936,8 @@ fn process_directory(
                 .map(|p| ParamMapping {
                     route_name: p.route_name.clone(),
                     field_name: p.field_name.clone(),
+                    ty: p.ty.clone(),
+                    kind: p.kind,
                 })
                 .collect();
             let params_type_path = if param_mappings.is_empty() {
@@ -717,10 +965,30 @@ fn process_directory(
             } else {
                 None
             };
+            let strict_search = scan_strict_search(&path);
+            if strict_search && search_params_type_path.is_none() {
+                println!(
+                    "cargo:warning=Route file '{}' sets `search = \"strict\"` but defines no \
+                     `pub struct SearchParams` — the setting has no effect.",
+                    path.display()
+                );
+            }
 
-            // Detect #[route(certification = ...)] attribute for per-route
-            // certification configuration.
-            let has_cert_attr = scan_certification_attribute(&path);
+            // Detect #[route(certification = ..., headers = ...)] attributes
+            // for per-route configuration.
+            let has_config_attr = scan_route_config_attribute(&path);
+
+            // Handlers returning `HandlerResult` are additionally registered
+            // as result handlers for conditional regeneration.
+            let result_fns = scan_handler_result_fns(&path);
+            let async_fns = scan_async_fns(&path);
+            if let Some(name) = async_fns.iter().find(|n| result_fns.contains(n)) {
+                panic!(
+                    "Route handler `{name}` in '{}' is async and returns `HandlerResult`; \
+                     async handlers must return `HttpResponse<'static>`.",
+                    path.display()
+                );
+            }
 
             for (fn_name, variant) in &methods {
                 exports.push(MethodExport {
@@ -730,8 +998,11 @@ fn process_directory(
                     params: param_mappings.clone(),
                     params_type_path: params_type_path.clone(),
                     search_params_type_path: search_params_type_path.clone(),
+                    strict_search,
                     module_path: module_path.clone(),
-                    has_certification_attribute: has_cert_attr,
+                    has_route_config_attribute: has_config_attr,
+                    returns_handler_result: result_fns.iter().any(|n| n == fn_name),
+                    is_async: async_fns.iter().any(|n| n == fn_name),
                 });
             }
         }
@@ -749,7 +1020,11 @@ fn process_directory(
             contents.push_str("#[derive(Debug, Clone)]\n");
             contents.push_str("pub struct Params {\n");
             for param in accumulated_params {
-                contents.push_str(&format!("    pub {}: String,\n", param.field_name));
+                contents.push_str(&format!(
+                    "    pub {}: {},\n",
+                    param.field_name,
+                    param_field_type(param.kind, param.ty.as_deref())
+                ));
             }
             contents.push_str("}\n\n");
         }
@@ -787,6 +1062,186 @@ fn scan_pub_fns(path: &Path) -> Vec<String> {
         .collect()
 }
 
+/// Scan a Rust source file for `pub fn` declarations that return
+/// `HandlerResult` (bare or path-qualified, e.g. `ic_asset_router::HandlerResult`).
+fn scan_handler_result_fns(path: &Path) -> Vec<String> {
+    let Ok(source) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    let Ok(file) = syn::parse_file(&source) else {
+        return vec![];
+    };
+    file.items
+        .iter()
+        .filter_map(|item| {
+            let syn::Item::Fn(func) = item else {
+                return None;
+            };
+            if !matches!(func.vis, syn::Visibility::Public(_)) {
+                return None;
+            }
+            let syn::ReturnType::Type(_, ty) = &func.sig.output else {
+                return None;
+            };
+            let syn::Type::Path(type_path) = ty.as_ref() else {
+                return None;
+            };
+            let last = type_path.path.segments.last()?;
+            (last.ident == "HandlerResult").then(|| func.sig.ident.to_string())
+        })
+        .collect()
+}
+
+/// Panic if middleware that uses the response of `next` covers an async
+/// route.
+///
+/// The middleware chain runs before an async handler is awaited, so `next`
+/// returns a placeholder there and changes made to it are lost. Such
+/// middleware must register a hook with `middleware::on_response` instead.
+fn check_async_middleware(exports: &[MethodExport], middleware_exports: &[MiddlewareExport]) {
+    for mw in middleware_exports.iter().filter(|mw| mw.uses_response) {
+        let covered = exports.iter().find(|export| {
+            export.is_async
+                && (mw.prefix == "/"
+                    || export.route_path == mw.prefix
+                    || export.route_path.starts_with(&format!("{}/", mw.prefix)))
+        });
+        if let Some(export) = covered {
+            panic!(
+                "Middleware `{}` uses the response returned by `next`, but it covers the \
+                 async route '{}', where `next` returns a placeholder. Change the response \
+                 in a hook registered with `ic_asset_router::middleware::on_response` instead.",
+                mw.handler_path, export.route_path
+            );
+        }
+    }
+}
+
+/// Best-effort check: does `pub fn middleware` in this file use the response
+/// returned by `next` other than by returning it?
+///
+/// Calls to `next` in tail or `return` position pass the response through;
+/// any other call (bound to a variable, a method called on it, ...) counts as
+/// using it. Middleware that hands `next` to another function is not
+/// inspected.
+fn scan_middleware_uses_response(path: &Path) -> bool {
+    let Ok(source) = fs::read_to_string(path) else {
+        return false;
+    };
+    let Ok(file) = syn::parse_file(&source) else {
+        return false;
+    };
+    file.items.iter().any(|item| {
+        let syn::Item::Fn(func) = item else {
+            return false;
+        };
+        if func.sig.ident != "middleware" {
+            return false;
+        }
+        let next = match func.sig.inputs.iter().nth(2) {
+            Some(syn::FnArg::Typed(arg)) => match &*arg.pat {
+                syn::Pat::Ident(ident) => ident.ident.to_string(),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        block_uses_response(&func.block, true, &next)
+    })
+}
+
+fn block_uses_response(block: &syn::Block, tail: bool, next: &str) -> bool {
+    let last = block.stmts.len().saturating_sub(1);
+    block.stmts.iter().enumerate().any(|(i, stmt)| match stmt {
+        syn::Stmt::Local(local) => local.init.as_ref().is_some_and(|init| {
+            expr_uses_response(&init.expr, false, next)
+                || init
+                    .diverge
+                    .as_ref()
+                    .is_some_and(|(_, expr)| expr_uses_response(expr, false, next))
+        }),
+        syn::Stmt::Expr(expr, semi) => {
+            expr_uses_response(expr, tail && i == last && semi.is_none(), next)
+        }
+        _ => false,
+    })
+}
+
+/// Whether `expr` calls `next` anywhere but in tail position (`tail` says
+/// whether `expr` itself is the value being returned).
+fn expr_uses_response(expr: &syn::Expr, tail: bool, next: &str) -> bool {
+    use syn::Expr;
+    let sub = |e: &Expr| expr_uses_response(e, false, next);
+    match expr {
+        Expr::Call(call) => {
+            let calls_next = matches!(&*call.func, Expr::Path(p) if p.path.is_ident(next));
+            (calls_next && !tail) || sub(&call.func) || call.args.iter().any(sub)
+        }
+        Expr::Return(ret) => ret
+            .expr
+            .as_deref()
+            .is_some_and(|e| expr_uses_response(e, true, next)),
+        Expr::Paren(paren) => expr_uses_response(&paren.expr, tail, next),
+        Expr::Block(block) => block_uses_response(&block.block, tail, next),
+        Expr::Unsafe(block) => block_uses_response(&block.block, tail, next),
+        Expr::If(expr_if) => {
+            sub(&expr_if.cond)
+                || block_uses_response(&expr_if.then_branch, tail, next)
+                || expr_if
+                    .else_branch
+                    .as_ref()
+                    .is_some_and(|(_, e)| expr_uses_response(e, tail, next))
+        }
+        Expr::Match(expr_match) => {
+            sub(&expr_match.expr)
+                || expr_match.arms.iter().any(|arm| {
+                    arm.guard.as_ref().is_some_and(|(_, g)| sub(g))
+                        || expr_uses_response(&arm.body, tail, next)
+                })
+        }
+        Expr::Closure(closure) => expr_uses_response(&closure.body, true, next),
+        Expr::Loop(expr_loop) => block_uses_response(&expr_loop.body, false, next),
+        Expr::While(expr_while) => {
+            sub(&expr_while.cond) || block_uses_response(&expr_while.body, false, next)
+        }
+        Expr::ForLoop(expr_for) => {
+            sub(&expr_for.expr) || block_uses_response(&expr_for.body, false, next)
+        }
+        Expr::MethodCall(call) => sub(&call.receiver) || call.args.iter().any(sub),
+        Expr::Field(field) => sub(&field.base),
+        Expr::Reference(reference) => sub(&reference.expr),
+        Expr::Unary(unary) => sub(&unary.expr),
+        Expr::Binary(binary) => sub(&binary.left) || sub(&binary.right),
+        Expr::Assign(assign) => sub(&assign.left) || sub(&assign.right),
+        Expr::Let(expr_let) => sub(&expr_let.expr),
+        Expr::Try(expr_try) => sub(&expr_try.expr),
+        Expr::Tuple(tuple) => tuple.elems.iter().any(sub),
+        Expr::Array(array) => array.elems.iter().any(sub),
+        Expr::Struct(expr_struct) => expr_struct.fields.iter().any(|f| sub(&f.expr)),
+        _ => false,
+    }
+}
+
+/// Scan a Rust source file for `pub async fn` declarations and return their
+/// names.
+fn scan_async_fns(path: &Path) -> Vec<String> {
+    let Ok(source) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    let Ok(file) = syn::parse_file(&source) else {
+        return vec![];
+    };
+    file.items
+        .iter()
+        .filter_map(|item| {
+            let syn::Item::Fn(func) = item else {
+                return None;
+            };
+            (matches!(func.vis, syn::Visibility::Public(_)) && func.sig.asyncness.is_some())
+                .then(|| func.sig.ident.to_string())
+        })
+        .collect()
+}
+
 /// Best-effort check: does the file contain `pub fn <name>(`?
 ///
 /// Used for signature validation of reserved files (e.g. checking that
@@ -828,6 +1283,20 @@ fn is_route_attribute(attr: &syn::Attribute) -> bool {
 }
 
 fn scan_route_attribute(path: &Path) -> Option<String> {
+    scan_route_str_arg(path, "path")
+}
+
+/// Scan a Rust source file for `#[route(search = "strict")]`.
+///
+/// The `#[route]` macro validates the value, so anything other than
+/// `"strict"` (including `"lenient"` or no `search` key) is lenient.
+fn scan_strict_search(path: &Path) -> bool {
+    scan_route_str_arg(path, "search").as_deref() == Some("strict")
+}
+
+/// Return the string literal value of `key = "..."` from the first
+/// `#[route(...)]` attribute in the file that sets it.
+fn scan_route_str_arg(path: &Path, key: &str) -> Option<String> {
     let source = fs::read_to_string(path).ok()?;
     let file = syn::parse_file(&source).ok()?;
     for item in &file.items {
@@ -841,7 +1310,7 @@ fn scan_route_attribute(path: &Path) -> Option<String> {
                         .ok()?;
                     for meta in &nested {
                         if let syn::Meta::NameValue(nv) = meta {
-                            if nv.path.is_ident("path") {
+                            if nv.path.is_ident(key) {
                                 if let syn::Expr::Lit(syn::ExprLit {
                                     lit: syn::Lit::Str(lit_str),
                                     ..
@@ -859,13 +1328,13 @@ fn scan_route_attribute(path: &Path) -> Option<String> {
     None
 }
 
-/// Scan a Rust source file for a `#[route(...)]` attribute that contains a
-/// `certification` key.
+/// Scan a Rust source file for a `#[route(...)]` attribute that contains one
+/// of the [`ROUTE_CONFIG_KEYS`].
 ///
 /// Parses the file with `syn` and walks top-level function items looking
 /// for `#[route(...)]` attributes. Returns `true` if any such attribute
-/// contains a `certification` key. Handles multi-line attributes correctly.
-fn scan_certification_attribute(path: &Path) -> bool {
+/// contains a route config key. Handles multi-line attributes correctly.
+fn scan_route_config_attribute(path: &Path) -> bool {
     let source = fs::read_to_string(path).unwrap_or_default();
     let file = match syn::parse_file(&source) {
         Ok(f) => f,
@@ -885,10 +1354,13 @@ fn scan_certification_attribute(path: &Path) -> bool {
                             Err(_) => continue,
                         };
                     for meta in &nested {
-                        if let syn::Meta::NameValue(nv) = meta {
-                            if nv.path.is_ident("certification") {
-                                return true;
-                            }
+                        let key = match meta {
+                            syn::Meta::NameValue(nv) => &nv.path,
+                            syn::Meta::List(list) => &list.path,
+                            syn::Meta::Path(_) => continue,
+                        };
+                        if ROUTE_CONFIG_KEYS.iter().any(|k| key.is_ident(k)) {
+                            return true;
                         }
                     }
                 }
@@ -952,11 +1424,115 @@ fn camel_to_snake(s: &str) -> String {
 
 /// Sanitize a filesystem name into a valid Rust module identifier.
 ///
-/// Dots are replaced with underscores so that dotted filenames like `og.png.rs`
-/// produce valid module names (`og_png`). When the sanitized name differs from
-/// the original, the caller emits a `#[path = "..."]` attribute.
+/// Every character that is not valid in an identifier is replaced with an
+/// underscore, so dotted filenames like `og.png.rs`, typed param directories
+/// like `_postId:u64` and constrained ones like `_id[int]` produce valid module
+/// names (`og_png`, `_postId_u64`, `_id_int_`). When the sanitized name differs
+/// from the original, the caller emits a `#[path = "..."]` attribute.
 fn sanitize_mod(name: &str) -> String {
-    name.replace('.', "_")
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Classify a param directory name, returning its kind and the remainder
+/// after the prefix: `_...slug` → `(CatchAll, "slug")`, `__lang` →
+/// `(Optional, "lang")`, `_postId` → `(Required, "postId")`.
+fn param_dir(name: &str) -> Option<(ParamKind, &str)> {
+    if let Some(param) = name.strip_prefix("_...") {
+        Some((ParamKind::CatchAll, param))
+    } else if let Some(param) = name.strip_prefix("__") {
+        Some((ParamKind::Optional, param))
+    } else {
+        name.strip_prefix('_')
+            .map(|param| (ParamKind::Required, param))
+    }
+}
+
+/// The `Params` struct field type for a param of the given kind and
+/// declared type.
+fn param_field_type(kind: ParamKind, ty: Option<&str>) -> String {
+    let ty = ty.unwrap_or("String");
+    match kind {
+        ParamKind::Required => ty.to_string(),
+        ParamKind::Optional => format!("Option<{ty}>"),
+        ParamKind::CatchAll => "Vec<String>".to_string(),
+    }
+}
+
+/// Split a param directory name (without the leading `_`) into the route
+/// parameter name and its optional declared type. A trailing `[constraint]`
+/// is ignored.
+///
+/// - `postId` → `("postId", None)`
+/// - `postId:u64` → `("postId", Some("u64"))`
+/// - `id:uuid::Uuid` → `("id", Some("uuid::Uuid"))`
+/// - `id:u64[int]` → `("id", Some("u64"))`
+fn split_param_type(name: &str) -> (&str, Option<&str>) {
+    let (name, _) = split_param_constraint(name);
+    match name.split_once(':') {
+        Some((param, ty)) => (param, Some(ty)),
+        None => (name, None),
+    }
+}
+
+/// Generate the `let` binding that parses a typed param before the
+/// `RouteContext` is built, returning a 400 from the wrapper on failure.
+///
+/// The `.into()` lets the same binding serve wrappers returning either
+/// `HttpResponse` or `HandlerResult`.
+fn typed_param_binding(pm: &ParamMapping) -> String {
+    let parse_fn = if pm.kind == ParamKind::Optional {
+        "parse_optional_param"
+    } else {
+        "parse_param"
+    };
+    format!(
+        "    let __param_{field}: {ty} = match ic_asset_router::{parse_fn}(&raw_params, \"{route}\") {{\n        Ok(v) => v,\n        Err(e) => return e.into_response().into(),\n    }};\n",
+        field = pm.field_name,
+        ty = param_field_type(pm.kind, pm.ty.as_deref()),
+        route = pm.route_name,
+    )
+}
+
+/// Generate the strict `SearchParams` binding for `#[route(search = "strict")]`,
+/// returning a 400 from the wrapper when the query string does not
+/// deserialize.
+fn strict_search_binding(search_path: &str) -> String {
+    format!(
+        "    let __search: {search_path} = match ic_asset_router::deserialize_search_params_strict(__query_str) {{\n        Ok(v) => v,\n        Err(e) => return e.into_response().into(),\n    }};\n"
+    )
+}
+
+/// Generate the `Params` struct field initializer for one param.
+///
+/// Untyped params are URL-decoded in place; typed params use the binding
+/// emitted by [`typed_param_binding`].
+fn param_field_init(pm: &ParamMapping) -> String {
+    if pm.ty.is_some() {
+        format!("            {0}: __param_{0},\n", pm.field_name)
+    } else if pm.kind == ParamKind::Optional {
+        format!(
+            "            {}: raw_params.get(\"{}\").map(|v| ic_asset_router::url_decode(v).into_owned()),\n",
+            pm.field_name, pm.route_name,
+        )
+    } else if pm.kind == ParamKind::CatchAll {
+        format!(
+            "            {}: ic_asset_router::catch_all_segments(&raw_params, \"{}\"),\n",
+            pm.field_name, pm.route_name,
+        )
+    } else {
+        format!(
+            "            {}: ic_asset_router::url_decode(&raw_params.get(\"{}\").cloned().unwrap_or_default()).into_owned(),\n",
+            pm.field_name, pm.route_name,
+        )
+    }
 }
 
 /// Convert a raw filesystem prefix (e.g. `/_postId/edit`) to a route prefix
@@ -979,14 +1555,25 @@ fn prefix_to_route_path(prefix: &str) -> String {
 /// - `index` → `""` (maps to the parent directory path)
 /// - `all` → `*` (catch-all wildcard)
 /// - `_param` → `:param` (dynamic segment)
+/// - `_param:Type` → `:param` (typed dynamic segment)
+/// - `_param[constraint]` / `_param:Type[constraint]` → `:param[constraint]`
+///   (constrained dynamic segment)
+/// - `__param` / `__param:Type` → `:param?` (optional segment)
+/// - `_...param` → `*param` (named catch-all)
 /// - anything else → literal segment
 fn name_to_route_segment(name: &str) -> String {
     if name == "index" {
         String::new()
     } else if name == "all" {
         "*".to_string()
-    } else if let Some(param) = name.strip_prefix('_') {
-        format!(":{param}")
+    } else if let Some((kind, param)) = param_dir(name) {
+        let param_name = split_param_type(param).0;
+        match (kind, split_param_constraint(param).1) {
+            (ParamKind::Optional, _) => format!(":{param_name}?"),
+            (ParamKind::CatchAll, _) => format!("*{param_name}"),
+            (ParamKind::Required, Some(constraint)) => format!(":{param_name}[{constraint}]"),
+            (ParamKind::Required, None) => format!(":{param_name}"),
+        }
     } else {
         name.to_string()
     }
@@ -1314,92 +1901,344 @@ fn get(ctx: RouteContext<()>) -> HttpResponse<'static> { todo!() }
         assert!(methods.is_empty());
     }
 
-    // --- has_pub_fn tests ---
+    // --- strict search params tests ---
 
     #[test]
-    fn has_pub_fn_present() {
-        let path = write_temp_file(
-            "hpf_present.rs",
-            r#"
-pub fn middleware(req: HttpRequest, params: &RouteParams, next: &dyn Fn()) -> HttpResponse<'static> {
-    todo!()
-}
-"#,
+    fn scan_strict_search_detects_strict_only() {
+        let strict = write_temp_file(
+            "search_strict.rs",
+            "#[route(search = \"strict\")]\npub fn get() -> () { todo!() }",
         );
-        assert!(has_pub_fn(&path, "middleware"));
-    }
-
-    #[test]
-    fn has_pub_fn_absent() {
-        let path = write_temp_file(
-            "hpf_absent.rs",
-            r#"
-pub fn handler() -> String { todo!() }
-"#,
+        let lenient = write_temp_file(
+            "search_lenient.rs",
+            "#[route(search = \"lenient\", certification = \"skip\")]\npub fn get() -> () { todo!() }",
         );
-        assert!(!has_pub_fn(&path, "middleware"));
+        let absent = write_temp_file("search_absent.rs", "pub fn get() -> () { todo!() }");
+        assert!(scan_strict_search(&strict));
+        assert!(!scan_strict_search(&lenient));
+        assert!(!scan_strict_search(&absent));
     }
 
     #[test]
-    fn has_pub_fn_near_miss() {
-        // `pub fn middleware_v2` should not match `middleware`
+    fn search_key_does_not_trigger_route_config() {
         let path = write_temp_file(
-            "hpf_near_miss.rs",
-            r#"
-pub fn middleware_v2(req: HttpRequest) -> HttpResponse<'static> { todo!() }
-"#,
+            "search_no_config.rs",
+            "#[route(search = \"strict\")]\npub fn get() -> () { todo!() }",
         );
-        assert!(!has_pub_fn(&path, "middleware"));
+        assert!(!scan_route_config_attribute(&path));
     }
 
-    // --- scan_pub_fns tests ---
-
     #[test]
-    fn scan_pub_fns_ignores_private_functions() {
-        let path = write_temp_file(
-            "spf_private.rs",
-            r#"
-fn get() -> () { todo!() }
-pub fn post() -> () { todo!() }
-"#,
+    fn generated_strict_search_code_returns_400() {
+        let binding = strict_search_binding("routes::posts::index::SearchParams");
+        assert!(
+            binding.contains("let __search: routes::posts::index::SearchParams = match ic_asset_router::deserialize_search_params_strict(__query_str)"),
+            "{binding}"
+        );
+        assert!(
+            binding.contains("return e.into_response().into()"),
+            "{binding}"
         );
-        let fns = scan_pub_fns(&path);
-        assert_eq!(fns, vec!["post"]);
     }
 
     #[test]
-    fn scan_pub_fns_handles_multiline_signature() {
-        let path = write_temp_file(
-            "spf_multiline.rs",
-            r#"
-pub fn get(
-    ctx: RouteContext<()>,
-) -> HttpResponse<'static> {
-    todo!()
-}
-"#,
+    fn process_directory_strict_search() {
+        let dir = setup_temp_routes(&[
+            (
+                "posts.rs",
+                "#[derive(serde::Deserialize, Default)]\npub struct SearchParams { pub page: Option<u32> }\n\
+                 #[route(search = \"strict\")]\npub fn get() -> () { todo!() }",
+            ),
+            (
+                "tags.rs",
+                "#[derive(serde::Deserialize, Default)]\npub struct SearchParams { pub page: Option<u32> }\n\
+                 pub fn get() -> () { todo!() }",
+            ),
+        ]);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        let mut nf = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut nf,
+            &[],
         );
-        let fns = scan_pub_fns(&path);
-        assert_eq!(fns, vec!["get"]);
+        let posts = exports.iter().find(|e| e.route_path == "/posts").unwrap();
+        let tags = exports.iter().find(|e| e.route_path == "/tags").unwrap();
+        assert!(posts.strict_search);
+        assert!(!tags.strict_search);
     }
 
+    // --- scan_handler_result_fns tests ---
+
     #[test]
-    fn scan_pub_fns_handles_generics() {
+    fn scan_handler_result_fns_detects_bare_and_qualified() {
         let path = write_temp_file(
-            "spf_generics.rs",
+            "result_fns.rs",
             r#"
-pub fn get<T: Default>(x: T) -> T { x }
+pub fn get(ctx: RouteContext<()>) -> HandlerResult { todo!() }
+pub fn put(ctx: RouteContext<()>) -> ic_asset_router::HandlerResult { todo!() }
+pub fn post(ctx: RouteContext<()>) -> HttpResponse<'static> { todo!() }
+fn delete(ctx: RouteContext<()>) -> HandlerResult { todo!() }
 "#,
         );
-        let fns = scan_pub_fns(&path);
-        assert_eq!(fns, vec!["get"]);
-    }
-
-    // --- sanitize_mod tests ---
-
-    #[test]
-    fn sanitize_mod_plain() {
-        assert_eq!(sanitize_mod("about"), "about");
+        let mut fns = scan_handler_result_fns(&path);
+        fns.sort();
+        assert_eq!(fns, vec!["get".to_string(), "put".to_string()]);
+    }
+
+    #[test]
+    fn process_directory_marks_handler_result_exports() {
+        let dir = setup_temp_routes(&[(
+            "feed.rs",
+            "pub fn get(ctx: RouteContext<()>) -> HandlerResult { todo!() }\n\
+             pub fn post(ctx: RouteContext<()>) -> HttpResponse<'static> { todo!() }",
+        )]);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        let mut nf = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut nf,
+            &[],
+        );
+        assert_eq!(exports.len(), 2);
+        let get = exports
+            .iter()
+            .find(|e| e.method_variant == "Method::GET")
+            .unwrap();
+        let post = exports
+            .iter()
+            .find(|e| e.method_variant == "Method::POST")
+            .unwrap();
+        assert!(get.returns_handler_result);
+        assert!(!post.returns_handler_result);
+    }
+
+    #[test]
+    fn process_directory_marks_async_exports() {
+        let dir = setup_temp_routes(&[(
+            "balance.rs",
+            "pub async fn post(ctx: RouteContext<()>) -> HttpResponse<'static> { todo!() }\n\
+             pub fn get(ctx: RouteContext<()>) -> HttpResponse<'static> { todo!() }\n\
+             async fn put(ctx: RouteContext<()>) -> HttpResponse<'static> { todo!() }",
+        )]);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        let mut nf = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut nf,
+            &[],
+        );
+        assert_eq!(exports.len(), 2);
+        let post = exports
+            .iter()
+            .find(|e| e.method_variant == "Method::POST")
+            .unwrap();
+        let get = exports
+            .iter()
+            .find(|e| e.method_variant == "Method::GET")
+            .unwrap();
+        assert!(post.is_async);
+        assert!(!get.is_async);
+    }
+
+    #[test]
+    #[should_panic(expected = "async handlers must return")]
+    fn process_directory_rejects_async_handler_result() {
+        let dir = setup_temp_routes(&[(
+            "feed.rs",
+            "pub async fn get(ctx: RouteContext<()>) -> HandlerResult { todo!() }",
+        )]);
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &[],
+        );
+    }
+
+    const EDITING_MIDDLEWARE: &str = "pub fn middleware(req: HttpRequest, params: &RouteParams, \
+         next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>) -> HttpResponse<'static> {\n\
+             let mut response = next(req, params);\n\
+             response.add_header((\"x-a\".into(), \"1\".into()));\n\
+             response\n\
+         }";
+
+    fn check_routes(structure: &[(&str, &str)]) {
+        let dir = setup_temp_routes(structure);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut Vec::new(),
+            &[],
+        );
+        check_async_middleware(&exports, &mw);
+    }
+
+    #[test]
+    fn scan_middleware_uses_response_cases() {
+        for (source, uses) in [
+            (
+                "pub fn middleware(r: R, p: &P, next: &N) -> H { next(r, p) }",
+                false,
+            ),
+            (
+                "pub fn middleware(r: R, p: &P, next: &N) -> H { \
+                 if r.ok() { return next(r, p); } match r.x() { 1 => next(r, p), _ => deny() } }",
+                false,
+            ),
+            (
+                "pub fn middleware(r: R, p: &P, next: &N) -> H { other::middleware(r, p, next) }",
+                false,
+            ),
+            (
+                "pub fn middleware(r: R, p: &P, handler: &N) -> H { \
+                 let resp = handler(r, p); resp }",
+                true,
+            ),
+            (
+                "pub fn middleware(r: R, p: &P, next: &N) -> H { wrap(next(r, p)) }",
+                true,
+            ),
+            (
+                "pub fn middleware(r: R, p: &P, next: &N) -> H { \
+                 if next(r, p).status_code() == 404 { deny() } else { ok() } }",
+                true,
+            ),
+            (EDITING_MIDDLEWARE, true),
+        ] {
+            let path = write_temp_file("smur.rs", source);
+            assert_eq!(scan_middleware_uses_response(&path), uses, "{source}");
+        }
+    }
+
+    #[test]
+    fn response_editing_middleware_is_allowed_over_sync_routes() {
+        check_routes(&[
+            ("middleware.rs", EDITING_MIDDLEWARE),
+            ("index.rs", "pub fn get() -> () { todo!() }"),
+            ("api/balance.rs", "pub fn post() -> () { todo!() }"),
+        ]);
+        check_routes(&[
+            ("api/middleware.rs", EDITING_MIDDLEWARE),
+            ("api/index.rs", "pub fn get() -> () { todo!() }"),
+            ("apiary.rs", "pub async fn get() -> () { todo!() }"),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "middleware::on_response")]
+    fn response_editing_middleware_over_async_route_is_rejected() {
+        check_routes(&[
+            ("index.rs", "pub fn get() -> () { todo!() }"),
+            ("api/middleware.rs", EDITING_MIDDLEWARE),
+            ("api/balance.rs", "pub async fn post() -> () { todo!() }"),
+        ]);
+    }
+
+    // --- has_pub_fn tests ---
+
+    #[test]
+    fn has_pub_fn_present() {
+        let path = write_temp_file(
+            "hpf_present.rs",
+            r#"
+pub fn middleware(req: HttpRequest, params: &RouteParams, next: &dyn Fn()) -> HttpResponse<'static> {
+    todo!()
+}
+"#,
+        );
+        assert!(has_pub_fn(&path, "middleware"));
+    }
+
+    #[test]
+    fn has_pub_fn_absent() {
+        let path = write_temp_file(
+            "hpf_absent.rs",
+            r#"
+pub fn handler() -> String { todo!() }
+"#,
+        );
+        assert!(!has_pub_fn(&path, "middleware"));
+    }
+
+    #[test]
+    fn has_pub_fn_near_miss() {
+        // `pub fn middleware_v2` should not match `middleware`
+        let path = write_temp_file(
+            "hpf_near_miss.rs",
+            r#"
+pub fn middleware_v2(req: HttpRequest) -> HttpResponse<'static> { todo!() }
+"#,
+        );
+        assert!(!has_pub_fn(&path, "middleware"));
+    }
+
+    // --- scan_pub_fns tests ---
+
+    #[test]
+    fn scan_pub_fns_ignores_private_functions() {
+        let path = write_temp_file(
+            "spf_private.rs",
+            r#"
+fn get() -> () { todo!() }
+pub fn post() -> () { todo!() }
+"#,
+        );
+        let fns = scan_pub_fns(&path);
+        assert_eq!(fns, vec!["post"]);
+    }
+
+    #[test]
+    fn scan_pub_fns_handles_multiline_signature() {
+        let path = write_temp_file(
+            "spf_multiline.rs",
+            r#"
+pub fn get(
+    ctx: RouteContext<()>,
+) -> HttpResponse<'static> {
+    todo!()
+}
+"#,
+        );
+        let fns = scan_pub_fns(&path);
+        assert_eq!(fns, vec!["get"]);
+    }
+
+    #[test]
+    fn scan_pub_fns_handles_generics() {
+        let path = write_temp_file(
+            "spf_generics.rs",
+            r#"
+pub fn get<T: Default>(x: T) -> T { x }
+"#,
+        );
+        let fns = scan_pub_fns(&path);
+        assert_eq!(fns, vec!["get"]);
+    }
+
+    // --- sanitize_mod tests ---
+
+    #[test]
+    fn sanitize_mod_plain() {
+        assert_eq!(sanitize_mod("about"), "about");
     }
 
     #[test]
@@ -1823,10 +2662,10 @@ pub fn get() -> () { todo!() }
         assert!(exports[0].search_params_type_path.is_some());
     }
 
-    // --- scan_certification_attribute tests ---
+    // --- scan_route_config_attribute tests ---
 
     #[test]
-    fn scan_certification_attribute_skip() {
+    fn scan_route_config_attribute_skip() {
         let path = write_temp_file(
             "cert_skip.rs",
             r#"
@@ -1834,11 +2673,11 @@ pub fn get() -> () { todo!() }
 pub fn get() -> () { todo!() }
 "#,
         );
-        assert!(scan_certification_attribute(&path));
+        assert!(scan_route_config_attribute(&path));
     }
 
     #[test]
-    fn scan_certification_attribute_response_only() {
+    fn scan_route_config_attribute_response_only() {
         let path = write_temp_file(
             "cert_ro.rs",
             r#"
@@ -1846,11 +2685,11 @@ pub fn get() -> () { todo!() }
 pub fn get() -> () { todo!() }
 "#,
         );
-        assert!(scan_certification_attribute(&path));
+        assert!(scan_route_config_attribute(&path));
     }
 
     #[test]
-    fn scan_certification_attribute_authenticated() {
+    fn scan_route_config_attribute_authenticated() {
         let path = write_temp_file(
             "cert_auth.rs",
             r#"
@@ -1858,11 +2697,11 @@ pub fn get() -> () { todo!() }
 pub fn get() -> () { todo!() }
 "#,
         );
-        assert!(scan_certification_attribute(&path));
+        assert!(scan_route_config_attribute(&path));
     }
 
     #[test]
-    fn scan_certification_attribute_custom() {
+    fn scan_route_config_attribute_custom() {
         let path = write_temp_file(
             "cert_custom.rs",
             r#"
@@ -1870,22 +2709,22 @@ pub fn get() -> () { todo!() }
 pub fn get() -> () { todo!() }
 "#,
         );
-        assert!(scan_certification_attribute(&path));
+        assert!(scan_route_config_attribute(&path));
     }
 
     #[test]
-    fn scan_certification_attribute_absent() {
+    fn scan_route_config_attribute_absent() {
         let path = write_temp_file(
             "cert_absent.rs",
             r#"
 pub fn get() -> () { todo!() }
 "#,
         );
-        assert!(!scan_certification_attribute(&path));
+        assert!(!scan_route_config_attribute(&path));
     }
 
     #[test]
-    fn scan_certification_attribute_path_only() {
+    fn scan_route_config_attribute_path_only() {
         let path = write_temp_file(
             "cert_path_only.rs",
             r#"
@@ -1894,11 +2733,11 @@ pub fn get() -> () { todo!() }
 "#,
         );
         // Has #[route(...)] but no certification key
-        assert!(!scan_certification_attribute(&path));
+        assert!(!scan_route_config_attribute(&path));
     }
 
     #[test]
-    fn scan_certification_attribute_multiline() {
+    fn scan_route_config_attribute_multiline() {
         let path = write_temp_file(
             "cert_multiline.rs",
             r#"
@@ -1911,11 +2750,11 @@ pub fn get() -> () { todo!() }
 pub fn get() -> () { todo!() }
 "#,
         );
-        assert!(scan_certification_attribute(&path));
+        assert!(scan_route_config_attribute(&path));
     }
 
     #[test]
-    fn scan_certification_attribute_fully_qualified_path() {
+    fn scan_route_config_attribute_fully_qualified_path() {
         let path = write_temp_file(
             "cert_fq.rs",
             r#"
@@ -1923,7 +2762,55 @@ pub fn get() -> () { todo!() }
 pub fn get() -> () { todo!() }
 "#,
         );
-        assert!(scan_certification_attribute(&path));
+        assert!(scan_route_config_attribute(&path));
+    }
+
+    #[test]
+    fn scan_route_config_attribute_headers_only() {
+        let path = write_temp_file(
+            "cert_headers_only.rs",
+            r#"
+#[route(headers = [("x-frame-options", "DENY")])]
+pub fn get() -> () { todo!() }
+"#,
+        );
+        assert!(scan_route_config_attribute(&path));
+    }
+
+    #[test]
+    fn scan_route_config_attribute_compress_only() {
+        let path = write_temp_file(
+            "cert_compress_only.rs",
+            r#"
+#[route(compress = true)]
+pub fn get() -> () { todo!() }
+"#,
+        );
+        assert!(scan_route_config_attribute(&path));
+    }
+
+    #[test]
+    fn scan_route_config_attribute_cors_only() {
+        let path = write_temp_file(
+            "cert_cors_only.rs",
+            r#"
+#[route(cors(origins = ["https://app.example.com"], max_age = 600))]
+pub fn post() -> () { todo!() }
+"#,
+        );
+        assert!(scan_route_config_attribute(&path));
+    }
+
+    #[test]
+    fn scan_route_config_attribute_body_limits_only() {
+        let path = write_temp_file(
+            "cert_body_limits_only.rs",
+            r#"
+#[route(max_body = 64_000, accepts = ["application/json"])]
+pub fn post() -> () { todo!() }
+"#,
+        );
+        assert!(scan_route_config_attribute(&path));
     }
 
     #[test]
@@ -1935,7 +2822,7 @@ pub fn get() -> () { todo!() }
 pub fn get() -> () { todo!() }
 "#,
         );
-        assert!(!scan_certification_attribute(&path));
+        assert!(!scan_route_config_attribute(&path));
     }
 
     #[test]
@@ -1991,7 +2878,7 @@ pub fn get() -> () { todo!() }
             &[],
         );
         assert_eq!(exports.len(), 1);
-        assert!(exports[0].has_certification_attribute);
+        assert!(exports[0].has_route_config_attribute);
     }
 
     #[test]
@@ -2009,7 +2896,7 @@ pub fn get() -> () { todo!() }
             &[],
         );
         assert_eq!(exports.len(), 1);
-        assert!(!exports[0].has_certification_attribute);
+        assert!(!exports[0].has_route_config_attribute);
     }
 
     // --- 8.5.6: Generated code includes url_decode for params and wildcard ---
@@ -2023,12 +2910,10 @@ pub fn get() -> () { todo!() }
         let pm = ParamMapping {
             route_name: "postId".to_string(),
             field_name: "post_id".to_string(),
+            ty: None,
+            kind: ParamKind::Required,
         };
-        // This is the exact format string used in generate_routes_from.
-        let line = format!(
-            "            {}: ic_asset_router::url_decode(&raw_params.get(\"{}\").cloned().unwrap_or_default()).into_owned(),\n",
-            pm.field_name, pm.route_name,
-        );
+        let line = param_field_init(&pm);
         assert!(
             line.contains("ic_asset_router::url_decode"),
             "generated param field line must include url_decode: {line}"
@@ -2039,6 +2924,340 @@ pub fn get() -> () { todo!() }
         );
     }
 
+    // --- Typed route params ---
+
+    #[test]
+    fn split_param_type_untyped_and_typed() {
+        assert_eq!(split_param_type("postId"), ("postId", None));
+        assert_eq!(split_param_type("postId:u64"), ("postId", Some("u64")));
+        assert_eq!(
+            split_param_type("id:uuid::Uuid"),
+            ("id", Some("uuid::Uuid"))
+        );
+    }
+
+    #[test]
+    fn name_to_route_segment_typed_param() {
+        assert_eq!(name_to_route_segment("_postId:u64"), ":postId");
+    }
+
+    #[test]
+    fn sanitize_mod_typed_param() {
+        assert_eq!(sanitize_mod("_postId:u64"), "_postId_u64");
+    }
+
+    #[test]
+    fn generated_typed_param_code_parses_with_from_str() {
+        let pm = ParamMapping {
+            route_name: "postId".to_string(),
+            field_name: "post_id".to_string(),
+            ty: Some("u64".to_string()),
+            kind: ParamKind::Required,
+        };
+        let binding = typed_param_binding(&pm);
+        assert!(binding.contains("let __param_post_id: u64"), "{binding}");
+        assert!(
+            binding.contains("ic_asset_router::parse_param(&raw_params, \"postId\")"),
+            "{binding}"
+        );
+        assert!(
+            binding.contains("return e.into_response().into()"),
+            "{binding}"
+        );
+        assert_eq!(
+            param_field_init(&pm),
+            "            post_id: __param_post_id,\n"
+        );
+    }
+
+    #[test]
+    fn process_directory_typed_param_directory() {
+        let dir = setup_temp_routes(&[
+            (
+                "posts/_postId:u64/index.rs",
+                "pub fn get() -> () { todo!() }",
+            ),
+            (
+                "posts/_postId:u64/_slug/index.rs",
+                "pub fn get() -> () { todo!() }",
+            ),
+        ]);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        let mut nf = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut nf,
+            &[],
+        );
+        exports.sort_by(|a, b| a.route_path.cmp(&b.route_path));
+        assert_eq!(exports.len(), 2);
+        assert_eq!(exports[0].route_path, "/posts/:postId");
+        assert_eq!(exports[1].route_path, "/posts/:postId/:slug");
+        assert_eq!(
+            exports[0].params_type_path.as_deref(),
+            Some("routes::posts::_postId_u64::Params")
+        );
+        assert_eq!(
+            exports[0].handler_path,
+            "routes::posts::_postId_u64::index::get"
+        );
+        assert_eq!(exports[1].params[0].ty.as_deref(), Some("u64"));
+        assert_eq!(exports[1].params[1].ty, None);
+
+        let posts_mod = fs::read_to_string(dir.path().join("posts/mod.rs")).unwrap();
+        assert!(
+            posts_mod.contains(
+                "#[path = \"_postId:u64/mod.rs\"]\n#[allow(non_snake_case)]\npub mod _postId_u64;"
+            ),
+            "{posts_mod}"
+        );
+        let param_mod = fs::read_to_string(dir.path().join("posts/_postId:u64/mod.rs")).unwrap();
+        assert!(param_mod.contains("pub post_id: u64,"), "{param_mod}");
+        let slug_mod =
+            fs::read_to_string(dir.path().join("posts/_postId:u64/_slug/mod.rs")).unwrap();
+        assert!(slug_mod.contains("pub post_id: u64,"), "{slug_mod}");
+        assert!(slug_mod.contains("pub slug: String,"), "{slug_mod}");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid parameter type")]
+    fn process_directory_rejects_invalid_param_type() {
+        let dir =
+            setup_temp_routes(&[("_id:not a type/index.rs", "pub fn get() -> () { todo!() }")]);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        let mut nf = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut nf,
+            &[],
+        );
+    }
+
+    // --- Constrained route params ---
+
+    #[test]
+    fn split_param_type_ignores_constraint() {
+        assert_eq!(split_param_type("id[int]"), ("id", None));
+        assert_eq!(split_param_type("id:u64[int]"), ("id", Some("u64")));
+        assert_eq!(split_param_type("t[re=a:b]"), ("t", None));
+    }
+
+    #[test]
+    fn name_to_route_segment_constrained_param() {
+        assert_eq!(name_to_route_segment("_id[int]"), ":id[int]");
+        assert_eq!(name_to_route_segment("_id:u64[int]"), ":id[int]");
+        assert_eq!(
+            name_to_route_segment("_slug[re=[a-z]+]"),
+            ":slug[re=[a-z]+]"
+        );
+    }
+
+    #[test]
+    fn sanitize_mod_constrained_param() {
+        assert_eq!(sanitize_mod("_id:u64[int]"), "_id_u64_int_");
+        assert_eq!(sanitize_mod("_lang[en,fr]"), "_lang_en_fr_");
+        assert_eq!(sanitize_mod("_slug[re=\\w+]"), "_slug_re__w__");
+    }
+
+    #[test]
+    fn process_directory_constrained_param_siblings() {
+        let dir = setup_temp_routes(&[
+            (
+                "posts/_id:u64[int]/index.rs",
+                "pub fn get() -> () { todo!() }",
+            ),
+            (
+                "posts/_lang[en,fr]/index.rs",
+                "pub fn get() -> () { todo!() }",
+            ),
+            (
+                "posts/_slug[re=\\w+]/index.rs",
+                "pub fn get() -> () { todo!() }",
+            ),
+            ("posts/_other/index.rs", "pub fn get() -> () { todo!() }"),
+        ]);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        let mut nf = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut nf,
+            &[],
+        );
+        exports.sort_by(|a, b| a.route_path.cmp(&b.route_path));
+        let paths: Vec<&str> = exports.iter().map(|e| e.route_path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/posts/:id[int]",
+                "/posts/:lang[en,fr]",
+                "/posts/:other",
+                "/posts/:slug[re=\\w+]",
+            ]
+        );
+        assert_eq!(exports[0].params[0].route_name, "id");
+        assert_eq!(exports[0].params[0].ty.as_deref(), Some("u64"));
+        assert_eq!(
+            exports[0].params_type_path.as_deref(),
+            Some("routes::posts::_id_u64_int_::Params")
+        );
+        assert_eq!(exports[1].params[0].field_name, "lang");
+
+        let posts_mod = fs::read_to_string(dir.path().join("posts/mod.rs")).unwrap();
+        assert!(
+            posts_mod.contains(
+                "#[path = \"_slug[re=\\\\w+]/mod.rs\"]\n#[allow(non_snake_case)]\npub mod _slug_re__w__;"
+            ),
+            "{posts_mod}"
+        );
+        let lang_mod = fs::read_to_string(dir.path().join("posts/_lang[en,fr]/mod.rs")).unwrap();
+        assert!(lang_mod.contains("pub lang: String,"), "{lang_mod}");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid parameter constraint")]
+    fn process_directory_rejects_invalid_constraint() {
+        let dir = setup_temp_routes(&[("_id[re=(]/index.rs", "pub fn get() -> () { todo!() }")]);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        let mut nf = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut nf,
+            &[],
+        );
+    }
+
+    // --- Optional params and named catch-alls ---
+
+    #[test]
+    fn param_dir_classifies_prefixes() {
+        assert_eq!(param_dir("_postId"), Some((ParamKind::Required, "postId")));
+        assert_eq!(param_dir("__lang"), Some((ParamKind::Optional, "lang")));
+        assert_eq!(param_dir("_...slug"), Some((ParamKind::CatchAll, "slug")));
+        assert_eq!(param_dir("posts"), None);
+    }
+
+    #[test]
+    fn name_to_route_segment_optional_and_catch_all() {
+        assert_eq!(name_to_route_segment("__lang"), ":lang?");
+        assert_eq!(name_to_route_segment("__page:u32"), ":page?");
+        assert_eq!(name_to_route_segment("_...slug"), "*slug");
+        assert_eq!(sanitize_mod("_...slug"), "____slug");
+    }
+
+    #[test]
+    fn generated_optional_and_catch_all_field_code() {
+        let optional = ParamMapping {
+            route_name: "lang".to_string(),
+            field_name: "lang".to_string(),
+            ty: None,
+            kind: ParamKind::Optional,
+        };
+        assert_eq!(
+            param_field_init(&optional),
+            "            lang: raw_params.get(\"lang\").map(|v| ic_asset_router::url_decode(v).into_owned()),\n"
+        );
+
+        let typed_optional = ParamMapping {
+            ty: Some("u32".to_string()),
+            ..optional
+        };
+        let binding = typed_param_binding(&typed_optional);
+        assert!(
+            binding.contains(
+                "let __param_lang: Option<u32> = match ic_asset_router::parse_optional_param(&raw_params, \"lang\")"
+            ),
+            "{binding}"
+        );
+
+        let catch_all = ParamMapping {
+            route_name: "slug".to_string(),
+            field_name: "slug".to_string(),
+            ty: None,
+            kind: ParamKind::CatchAll,
+        };
+        assert_eq!(
+            param_field_init(&catch_all),
+            "            slug: ic_asset_router::catch_all_segments(&raw_params, \"slug\"),\n"
+        );
+    }
+
+    #[test]
+    fn process_directory_optional_and_catch_all_directories() {
+        let dir = setup_temp_routes(&[
+            (
+                "docs/__lang/intro/index.rs",
+                "pub fn get() -> () { todo!() }",
+            ),
+            (
+                "docs/__lang/_...slug/index.rs",
+                "pub fn get() -> () { todo!() }",
+            ),
+        ]);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        let mut nf = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut nf,
+            &[],
+        );
+        exports.sort_by(|a, b| a.route_path.cmp(&b.route_path));
+        let paths: Vec<&str> = exports.iter().map(|e| e.route_path.as_str()).collect();
+        assert_eq!(paths, vec!["/docs/:lang?/*slug", "/docs/:lang?/intro"]);
+        assert_eq!(
+            exports[0].params_type_path.as_deref(),
+            Some("routes::docs::__lang::____slug::Params")
+        );
+        assert_eq!(exports[0].params[1].kind, ParamKind::CatchAll);
+
+        let slug_mod = fs::read_to_string(dir.path().join("docs/__lang/_...slug/mod.rs")).unwrap();
+        assert!(slug_mod.contains("pub lang: Option<String>,"), "{slug_mod}");
+        assert!(slug_mod.contains("pub slug: Vec<String>,"), "{slug_mod}");
+        let lang_mod = fs::read_to_string(dir.path().join("docs/__lang/mod.rs")).unwrap();
+        assert!(
+            lang_mod.contains(
+                "#[path = \"_...slug/mod.rs\"]\n#[allow(non_snake_case)]\npub mod ____slug;"
+            ),
+            "{lang_mod}"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot declare a type")]
+    fn process_directory_rejects_typed_catch_all() {
+        let dir = setup_temp_routes(&[("_...slug:u32/index.rs", "pub fn get() -> () { todo!() }")]);
+        let mut exports = Vec::new();
+        let mut mw = Vec::new();
+        let mut nf = Vec::new();
+        process_directory(
+            dir.path(),
+            String::new(),
+            &mut exports,
+            &mut mw,
+            &mut nf,
+            &[],
+        );
+    }
+
     /// Verify that the generated wildcard field includes `url_decode`.
     #[test]
     fn generated_wildcard_code_includes_url_decode() {
@@ -0,0 +1,191 @@
+//! Canonical request paths: detecting non-canonical spellings of a path and
+//! building the redirects that point to the canonical one.
+//!
+//! A path is canonical when it has no empty segments (`//`), no trailing
+//! slash (the root `/` excepted) and, if [`PathNormalization::lowercase`] is
+//! set, no uppercase ASCII letters. Canonicalization works on the raw,
+//! still percent-encoded path so the redirect target round-trips exactly;
+//! percent escapes are never case-folded.
+
+use std::borrow::Cow;
+
+use ic_http_certification::{HttpResponse, StatusCode};
+
+use crate::config::{PathNormalization, PathPolicy};
+
+/// What to do with a request whose path is not canonical.
+#[derive(Debug, PartialEq, Eq)]
+pub enum NonCanonical {
+    /// Answer with a redirect to `location` (canonical path plus the
+    /// original query string).
+    Redirect {
+        /// The redirect status: `301` or `308`.
+        status: StatusCode,
+        /// The `Location` header value.
+        location: String,
+    },
+    /// Answer as if no route matched.
+    NotFound,
+}
+
+/// Check `url` against `normalization`.
+///
+/// Returns `None` when the path is canonical or the policy is
+/// [`PathPolicy::Lenient`], so the request is routed as usual.
+pub fn check(url: &str, normalization: &PathNormalization) -> Option<NonCanonical> {
+    let status = match normalization.policy {
+        PathPolicy::Lenient => return None,
+        PathPolicy::Strict => None,
+        PathPolicy::RedirectMovedPermanently => Some(StatusCode::MOVED_PERMANENTLY),
+        PathPolicy::RedirectPermanent => Some(StatusCode::PERMANENT_REDIRECT),
+    };
+    let raw = raw_path(url);
+    let canonical = canonical_path(raw, normalization.lowercase);
+    if canonical == raw {
+        return None;
+    }
+    Some(match status {
+        Some(status) => NonCanonical::Redirect {
+            status,
+            location: match query(url) {
+                Some(q) => format!("{canonical}?{q}"),
+                None => canonical,
+            },
+        },
+        None => NonCanonical::NotFound,
+    })
+}
+
+/// The canonical form of a raw request path: empty segments removed, no
+/// trailing slash, and ASCII letters outside percent escapes lowercased when
+/// `lowercase` is set.
+pub fn canonical_path(raw: &str, lowercase: bool) -> String {
+    let segments: Vec<&str> = raw.split('/').filter(|s| !s.is_empty()).collect();
+    let path = format!("/{}", segments.join("/"));
+    if !lowercase {
+        return path;
+    }
+    let mut out = String::with_capacity(path.len());
+    let mut escape = 0;
+    for c in path.chars() {
+        if c == '%' {
+            escape = 2;
+            out.push(c);
+        } else if escape > 0 {
+            escape -= 1;
+            out.push(c);
+        } else {
+            out.push(c.to_ascii_lowercase());
+        }
+    }
+    out
+}
+
+/// Build a redirect response with an empty body.
+pub fn redirect_response(status: StatusCode, location: &str) -> HttpResponse<'static> {
+    HttpResponse::builder()
+        .with_status_code(status)
+        .with_headers(vec![
+            ("location".to_string(), location.to_string()),
+            ("content-type".to_string(), "text/plain".to_string()),
+        ])
+        .with_body(Cow::<[u8]>::Owned(Vec::new()))
+        .build()
+}
+
+/// The raw path of a request URL, without scheme, authority, query string
+/// or fragment.
+pub(crate) fn raw_path(url: &str) -> &str {
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+        None => url,
+    }
+}
+
+/// The query string of a request URL, without the fragment.
+pub(crate) fn query(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once('?')?;
+    let q = rest.split('#').next().unwrap_or_default();
+    (!q.is_empty()).then_some(q)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalization(policy: PathPolicy, lowercase: bool) -> PathNormalization {
+        PathNormalization { policy, lowercase }
+    }
+
+    #[test]
+    fn canonical_path_collapses_slashes_and_trailing_slash() {
+        assert_eq!(canonical_path("/about/", false), "/about");
+        assert_eq!(canonical_path("//a///b/", false), "/a/b");
+        assert_eq!(canonical_path("/", false), "/");
+        assert_eq!(canonical_path("", false), "/");
+        assert_eq!(canonical_path("///", false), "/");
+    }
+
+    #[test]
+    fn canonical_path_lowercase_keeps_percent_escapes() {
+        assert_eq!(canonical_path("/About/Caf%C3%A9", true), "/about/caf%C3%A9");
+        assert_eq!(canonical_path("/About", false), "/About");
+    }
+
+    #[test]
+    fn raw_path_and_query_handle_absolute_urls() {
+        assert_eq!(raw_path("/a/b?x=1#frag"), "/a/b");
+        assert_eq!(raw_path("https://example.com/a/?x=1"), "/a/");
+        assert_eq!(raw_path("https://example.com"), "/");
+        assert_eq!(query("/a?x=1#frag"), Some("x=1"));
+        assert_eq!(query("/a?"), None);
+        assert_eq!(query("/a"), None);
+    }
+
+    #[test]
+    fn lenient_policy_never_rejects() {
+        let n = normalization(PathPolicy::Lenient, true);
+        assert_eq!(check("/About//", &n), None);
+    }
+
+    #[test]
+    fn strict_policy_rejects_non_canonical_paths() {
+        let n = normalization(PathPolicy::Strict, false);
+        assert_eq!(check("/about", &n), None);
+        assert_eq!(check("/", &n), None);
+        assert_eq!(check("/about/", &n), Some(NonCanonical::NotFound));
+        assert_eq!(check("//about", &n), Some(NonCanonical::NotFound));
+        assert_eq!(check("/About", &n), None);
+    }
+
+    #[test]
+    fn redirect_policy_keeps_query_string() {
+        let n = normalization(PathPolicy::RedirectPermanent, true);
+        assert_eq!(
+            check("/Docs/Intro/?page=2#top", &n),
+            Some(NonCanonical::Redirect {
+                status: StatusCode::PERMANENT_REDIRECT,
+                location: "/docs/intro?page=2".to_string(),
+            })
+        );
+        let n = normalization(PathPolicy::RedirectMovedPermanently, false);
+        assert_eq!(
+            check("/docs/", &n),
+            Some(NonCanonical::Redirect {
+                status: StatusCode::MOVED_PERMANENTLY,
+                location: "/docs".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn redirect_response_has_location_and_empty_body() {
+        let response = redirect_response(StatusCode::PERMANENT_REDIRECT, "/docs");
+        assert_eq!(response.status_code(), StatusCode::PERMANENT_REDIRECT);
+        assert!(response.body().is_empty());
+        assert!(response
+            .headers()
+            .contains(&("location".to_string(), "/docs".to_string())));
+    }
+}
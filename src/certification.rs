@@ -28,6 +28,9 @@
 //! (e.g., the `Authorization` header). Without full certification a
 //! malicious replica could serve one user's response to another.
 
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
 /// Certification mode for HTTP responses.
 ///
 /// Determines which parts of the HTTP request/response are hashed and
@@ -60,7 +63,7 @@
 /// let mode = CertificationMode::authenticated();
 /// assert!(matches!(mode, CertificationMode::Full(_)));
 /// ```
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub enum CertificationMode {
     /// No certification. The response is served without cryptographic
     /// verification.
@@ -196,7 +199,7 @@ impl Default for CertificationMode {
 /// `date`, `ic-certificate`, and `ic-certificate-expression` (which
 /// are either non-deterministic or managed by the certification layer
 /// itself).
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct ResponseOnlyConfig {
     /// Response headers to include in the certification hash.
     ///
@@ -262,7 +265,7 @@ impl Default for ResponseOnlyConfig {
 /// assert_eq!(config.request_headers, vec!["authorization", "accept"]);
 /// assert_eq!(config.query_params, vec!["page", "limit"]);
 /// ```
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 #[derive(Default)]
 pub struct FullConfig {
     /// Request headers to include in the certification hash.
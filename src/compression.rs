@@ -0,0 +1,212 @@
+//! Runtime gzip and Brotli encoding for certified responses.
+//!
+//! Encoding happens inside the canister, so both encoders use settings that
+//! trade a little compression ratio for a much lower instruction count:
+//! gzip at the default level (6) and Brotli at quality 5 with a 4 MiB window.
+//!
+//! Only text-like MIME types are compressed (see [`is_compressible`]);
+//! images, fonts, and media are already compressed and rarely shrink.
+
+use std::io::Write;
+
+use flate2::{write::GzEncoder, Compression};
+
+use crate::asset_router::AssetEncoding;
+use crate::config::CompressionConfig;
+
+/// Brotli quality level used for runtime encoding (0–11).
+const BROTLI_QUALITY: u32 = 5;
+
+/// Brotli window size (log2 of bytes) used for runtime encoding.
+const BROTLI_LGWIN: u32 = 22;
+
+/// Internal buffer size for the Brotli writer.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Returns `true` if content of the given MIME type benefits from
+/// compression.
+///
+/// Matches `text/*`, JavaScript, JSON, XML, and SVG. Parameters such as
+/// `; charset=utf-8` are ignored.
+pub fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime == "application/javascript"
+        || mime == "application/json"
+        || mime == "application/xml"
+        || mime == "image/svg+xml"
+}
+
+/// Compress `content` with gzip. Returns `None` if encoding fails.
+pub fn gzip(content: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content).ok()?;
+    encoder.finish().ok()
+}
+
+/// Compress `content` with Brotli. Returns `None` if encoding fails.
+pub fn brotli(content: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder =
+        brotli::CompressorWriter::new(Vec::new(), BROTLI_BUFFER_SIZE, BROTLI_QUALITY, BROTLI_LGWIN);
+    encoder.write_all(content).ok()?;
+    encoder.flush().ok()?;
+    Some(encoder.into_inner())
+}
+
+/// Build the encoded variants of `content` requested by `config`.
+///
+/// Returns an empty list when compression is disabled, the body is smaller
+/// than [`CompressionConfig::min_size`], or `content_type` is not
+/// compressible. A variant is only kept if it is strictly smaller than the
+/// original content.
+pub fn encode_variants(
+    content: &[u8],
+    content_type: &str,
+    config: &CompressionConfig,
+) -> Vec<(AssetEncoding, Vec<u8>)> {
+    if !config.is_enabled() || content.len() < config.min_size || !is_compressible(content_type) {
+        return vec![];
+    }
+
+    let mut variants = Vec::new();
+    if config.brotli {
+        if let Some(encoded) = brotli(content).filter(|e| e.len() < content.len()) {
+            variants.push((AssetEncoding::Brotli, encoded));
+        }
+    }
+    if config.gzip {
+        if let Some(encoded) = gzip(content).filter(|e| e.len() < content.len()) {
+            variants.push((AssetEncoding::Gzip, encoded));
+        }
+    }
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn compressible_body() -> Vec<u8> {
+        "<p>hello world</p>".repeat(200).into_bytes()
+    }
+
+    #[test]
+    fn is_compressible_matches_text_like_types() {
+        assert!(is_compressible("text/html"));
+        assert!(is_compressible("text/html; charset=utf-8"));
+        assert!(is_compressible("application/json"));
+        assert!(is_compressible("application/javascript"));
+        assert!(is_compressible("application/xml"));
+        assert!(is_compressible("image/svg+xml"));
+        assert!(is_compressible("Text/CSS"));
+    }
+
+    #[test]
+    fn is_compressible_rejects_binary_types() {
+        assert!(!is_compressible("image/png"));
+        assert!(!is_compressible("font/woff2"));
+        assert!(!is_compressible("video/mp4"));
+        assert!(!is_compressible("application/octet-stream"));
+    }
+
+    #[test]
+    fn gzip_round_trips() {
+        let body = compressible_body();
+        let encoded = gzip(&body).unwrap();
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(encoded.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+    }
+
+    #[test]
+    fn brotli_round_trips() {
+        let body = compressible_body();
+        let encoded = brotli(&body).unwrap();
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(encoded.as_slice(), 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+    }
+
+    #[test]
+    fn encode_variants_disabled_returns_nothing() {
+        let variants = encode_variants(
+            &compressible_body(),
+            "text/html",
+            &CompressionConfig::disabled(),
+        );
+        assert!(variants.is_empty());
+    }
+
+    #[test]
+    fn encode_variants_produces_brotli_and_gzip() {
+        let variants = encode_variants(
+            &compressible_body(),
+            "text/html",
+            &CompressionConfig::enabled(),
+        );
+        let encodings: Vec<AssetEncoding> = variants.iter().map(|(e, _)| *e).collect();
+        assert_eq!(encodings, vec![AssetEncoding::Brotli, AssetEncoding::Gzip]);
+    }
+
+    #[test]
+    fn encode_variants_respects_min_size() {
+        let config = CompressionConfig {
+            min_size: 10_000,
+            ..CompressionConfig::enabled()
+        };
+        let variants = encode_variants(&compressible_body(), "text/html", &config);
+        assert!(variants.is_empty());
+    }
+
+    #[test]
+    fn encode_variants_skips_non_compressible_types() {
+        let variants = encode_variants(
+            &compressible_body(),
+            "image/png",
+            &CompressionConfig::enabled(),
+        );
+        assert!(variants.is_empty());
+    }
+
+    #[test]
+    fn encode_variants_drops_variants_that_do_not_shrink() {
+        // Pseudo-random bytes do not compress; gzip/brotli framing makes
+        // the output larger than the input.
+        let mut state: u32 = 0x1234_5678;
+        let body: Vec<u8> = (0..2048)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let config = CompressionConfig {
+            min_size: 0,
+            ..CompressionConfig::enabled()
+        };
+        let variants = encode_variants(&body, "text/plain", &config);
+        assert!(variants.is_empty());
+    }
+
+    #[test]
+    fn encode_variants_gzip_only() {
+        let config = CompressionConfig {
+            brotli: false,
+            ..CompressionConfig::enabled()
+        };
+        let variants = encode_variants(&compressible_body(), "application/json", &config);
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].0, AssetEncoding::Gzip);
+    }
+}
@@ -0,0 +1,260 @@
+//! Conditional request support: entity tags, HTTP dates, and evaluation of
+//! `If-None-Match` / `If-Modified-Since` (RFC 9110 §13).
+//!
+//! The [`AssetRouter`](crate::asset_router::AssetRouter) uses these helpers
+//! to attach `ETag` and `Last-Modified` headers to certified assets and to
+//! answer matching conditional `GET` requests with `304 Not Modified`.
+
+use ic_http_certification::{HttpRequest, Method};
+use sha2::{Digest, Sha256};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Compute a strong entity tag for `content`.
+///
+/// The tag is the quoted hex SHA-256 of the bytes, so identical content
+/// always yields the same tag across upgrades and re-certification.
+pub fn etag_for(content: &[u8]) -> String {
+    let digest = Sha256::digest(content);
+    let mut tag = String::with_capacity(digest.len() * 2 + 2);
+    tag.push('"');
+    for byte in digest {
+        tag.push_str(&format!("{byte:02x}"));
+    }
+    tag.push('"');
+    tag
+}
+
+/// Format seconds since the Unix epoch as an IMF-fixdate
+/// (e.g. `"Sun, 06 Nov 1994 08:49:37 GMT"`).
+pub fn format_http_date(secs: u64) -> String {
+    let days = secs / 86_400;
+    let rem = secs % 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+    // 1970-01-01 was a Thursday.
+    let weekday = WEEKDAYS[((days + 4) % 7) as usize];
+    format!(
+        "{weekday}, {day:02} {} {year:04} {:02}:{:02}:{:02} GMT",
+        MONTHS[(month - 1) as usize],
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60,
+    )
+}
+
+/// Parse an IMF-fixdate into seconds since the Unix epoch.
+///
+/// The obsolete RFC 850 and asctime formats are not accepted; an
+/// unparseable date yields `None` and the condition is ignored, as RFC 9110
+/// requires.
+pub fn parse_http_date(value: &str) -> Option<u64> {
+    let mut parts = value.split_whitespace();
+    let weekday = parts.next()?.strip_suffix(',')?;
+    if !WEEKDAYS.contains(&weekday) {
+        return None;
+    }
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)? as u32 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':');
+    let hour: u64 = time.next()?.parse().ok()?;
+    let minute: u64 = time.next()?.parse().ok()?;
+    let second: u64 = time.next()?.parse().ok()?;
+    if parts.next()? != "GMT" || parts.next().is_some() || time.next().is_some() {
+        return None;
+    }
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Returns `true` if `request` is a conditional `GET`/`HEAD` whose
+/// validators show the client's cached copy is still current.
+///
+/// `If-None-Match` takes precedence: when present, only the entity tag is
+/// compared (weak comparison, `*` matches any tag) and `If-Modified-Since`
+/// is ignored. Otherwise `If-Modified-Since` is compared against
+/// `last_modified` (seconds since the epoch).
+pub fn is_not_modified(
+    request: &HttpRequest,
+    etag: Option<&str>,
+    last_modified: Option<u64>,
+) -> bool {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return false;
+    }
+
+    if let Some(if_none_match) = find_header(request, "if-none-match") {
+        let Some(etag) = etag else {
+            return false;
+        };
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || weak_eq(tag, etag));
+    }
+
+    match (find_header(request, "if-modified-since"), last_modified) {
+        (Some(since), Some(modified)) => {
+            parse_http_date(since).is_some_and(|since| modified <= since)
+        }
+        _ => false,
+    }
+}
+
+/// Weak entity-tag comparison: `W/` prefixes are ignored.
+fn weak_eq(a: &str, b: &str) -> bool {
+    let strip = |tag: &str| tag.strip_prefix("W/").unwrap_or(tag).to_string();
+    strip(a) == strip(b)
+}
+
+fn find_header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Convert days since 1970-01-01 to a `(year, month, day)` civil date.
+///
+/// Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Convert a civil date to days since 1970-01-01.
+///
+/// Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_with(headers: &[(&str, &str)]) -> HttpRequest<'static> {
+        HttpRequest::get("/".to_string())
+            .with_headers(
+                headers
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn etag_is_quoted_sha256_hex() {
+        let tag = etag_for(b"hello");
+        assert_eq!(
+            tag,
+            "\"2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824\""
+        );
+        assert_ne!(etag_for(b"hello"), etag_for(b"world"));
+    }
+
+    #[test]
+    fn format_http_date_matches_rfc_example() {
+        assert_eq!(
+            format_http_date(784_111_777),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(format_http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn parse_http_date_round_trips() {
+        for secs in [0, 784_111_777, 951_782_400, 1_771_459_200] {
+            assert_eq!(parse_http_date(&format_http_date(secs)), Some(secs));
+        }
+    }
+
+    #[test]
+    fn parse_http_date_rejects_other_formats() {
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+        assert_eq!(parse_http_date("garbage"), None);
+    }
+
+    #[test]
+    fn if_none_match_matches_exact_weak_and_wildcard() {
+        let etag = Some("\"abc\"");
+        assert!(is_not_modified(
+            &get_with(&[("If-None-Match", "\"abc\"")]),
+            etag,
+            None
+        ));
+        assert!(is_not_modified(
+            &get_with(&[("if-none-match", "W/\"abc\"")]),
+            etag,
+            None
+        ));
+        assert!(is_not_modified(
+            &get_with(&[("if-none-match", "\"x\", \"abc\"")]),
+            etag,
+            None
+        ));
+        assert!(is_not_modified(
+            &get_with(&[("if-none-match", "*")]),
+            etag,
+            None
+        ));
+        assert!(!is_not_modified(
+            &get_with(&[("if-none-match", "\"x\"")]),
+            etag,
+            None
+        ));
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        let req = get_with(&[
+            ("if-none-match", "\"stale\""),
+            ("if-modified-since", "Sun, 06 Nov 1994 08:49:37 GMT"),
+        ]);
+        assert!(!is_not_modified(&req, Some("\"abc\""), Some(0)));
+    }
+
+    #[test]
+    fn if_modified_since_compares_last_modified() {
+        let req = get_with(&[("if-modified-since", "Sun, 06 Nov 1994 08:49:37 GMT")]);
+        assert!(is_not_modified(&req, None, Some(784_111_777)));
+        assert!(is_not_modified(&req, None, Some(784_111_000)));
+        assert!(!is_not_modified(&req, None, Some(784_111_778)));
+        assert!(!is_not_modified(&req, None, None));
+    }
+
+    #[test]
+    fn unconditional_or_non_get_requests_are_modified() {
+        assert!(!is_not_modified(&get_with(&[]), Some("\"abc\""), Some(0)));
+        let post = HttpRequest::post("/".to_string())
+            .with_headers(vec![("if-none-match".to_string(), "\"abc\"".to_string())])
+            .build();
+        assert!(!is_not_modified(&post, Some("\"abc\""), None));
+    }
+}
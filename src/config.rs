@@ -51,6 +51,264 @@ impl CacheConfig {
     }
 }
 
+/// Runtime compression configuration for dynamic responses.
+///
+/// When enabled, handler output with a text-like content type is encoded
+/// inside the canister and each smaller variant is certified alongside the
+/// identity body. The encoding served is negotiated from the request's
+/// `Accept-Encoding` header.
+///
+/// Compression is off by default. Individual routes can opt in or out with
+/// `#[route(compress = true)]` / `#[route(compress = false)]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Produce a gzip-encoded variant.
+    pub gzip: bool,
+    /// Produce a Brotli-encoded variant.
+    pub brotli: bool,
+    /// Bodies smaller than this many bytes are not compressed.
+    /// Default: `1024`
+    pub min_size: usize,
+}
+
+impl CompressionConfig {
+    /// Default minimum body size for compression.
+    pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+    /// Both gzip and Brotli enabled with the default size threshold.
+    pub fn enabled() -> Self {
+        Self {
+            gzip: true,
+            brotli: true,
+            min_size: Self::DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// No runtime compression.
+    pub fn disabled() -> Self {
+        Self {
+            gzip: false,
+            brotli: false,
+            min_size: Self::DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// Returns `true` if at least one encoding is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.gzip || self.brotli
+    }
+
+    /// Resolve the effective configuration for a route.
+    ///
+    /// `route_override` comes from `#[route(compress = ...)]`: `Some(true)`
+    /// enables both encodings (keeping this config's `min_size`),
+    /// `Some(false)` disables compression, and `None` uses this config as is.
+    pub fn for_route(&self, route_override: Option<bool>) -> Self {
+        match route_override {
+            Some(true) if !self.is_enabled() => Self {
+                gzip: true,
+                brotli: true,
+                min_size: self.min_size,
+            },
+            Some(true) | None => self.clone(),
+            Some(false) => Self {
+                min_size: self.min_size,
+                ..Self::disabled()
+            },
+        }
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+/// Cross-origin resource sharing (CORS) policy.
+///
+/// When set on [`AssetConfig::cors`] (or per route via `#[route(cors(...))]`),
+/// the router answers `OPTIONS` preflight requests automatically and adds
+/// `Access-Control-*` headers to every response. Preflight responses are
+/// certified like any other response.
+///
+/// `Access-Control-Allow-Origin` is chosen per request from the request's
+/// `Origin` header, so it is excluded from response certification. All other
+/// CORS headers are part of the certified response.
+///
+/// # Examples
+///
+/// ```
+/// use ic_asset_router::{AssetConfig, CorsConfig};
+///
+/// let config = AssetConfig {
+///     cors: Some(CorsConfig {
+///         allow_credentials: true,
+///         max_age: Some(600),
+///         ..CorsConfig::with_origins(&["https://app.example.com"])
+///     }),
+///     ..AssetConfig::default()
+/// };
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// Origins allowed to make cross-origin requests, e.g.
+    /// `"https://app.example.com"`. `"*"` allows any origin.
+    pub allowed_origins: Vec<String>,
+    /// Methods advertised in preflight responses. When empty, the methods the
+    /// route actually registers are advertised.
+    pub allowed_methods: Vec<String>,
+    /// Request headers advertised in preflight responses.
+    /// Default: `["content-type"]`
+    pub allowed_headers: Vec<String>,
+    /// Response headers exposed to the calling script
+    /// (`Access-Control-Expose-Headers`).
+    pub exposed_headers: Vec<String>,
+    /// Emit `Access-Control-Allow-Credentials: true`. Requires listed
+    /// origins: browsers refuse credentialed responses to `"*"`, and
+    /// echoing any origin back would let every site read them.
+    pub allow_credentials: bool,
+    /// Seconds a preflight result may be cached by the browser
+    /// (`Access-Control-Max-Age`).
+    pub max_age: Option<u64>,
+}
+
+impl CorsConfig {
+    /// Allow any origin, without credentials.
+    pub fn any_origin() -> Self {
+        Self::with_origins(&["*"])
+    }
+
+    /// Allow only the listed origins.
+    pub fn with_origins(origins: &[&str]) -> Self {
+        Self {
+            allowed_origins: origins.iter().map(|o| o.to_string()).collect(),
+            allowed_methods: Vec::new(),
+            allowed_headers: vec!["content-type".to_string()],
+            exposed_headers: Vec::new(),
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Returns `true` if every origin is allowed.
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|o| o == "*")
+    }
+
+    /// The `Access-Control-Allow-Origin` value for a request from `origin`,
+    /// or `None` if the origin is not allowed.
+    ///
+    /// Returns `"*"` for any-origin policies; otherwise the matching origin
+    /// is echoed back.
+    pub fn allow_origin_for(&self, origin: &str) -> Option<String> {
+        if self.allows_any_origin() {
+            return Some("*".to_string());
+        }
+        self.allowed_origins
+            .iter()
+            .find(|o| o.eq_ignore_ascii_case(origin))
+            .map(|_| origin.to_string())
+    }
+
+    /// Returns `true` if `Access-Control-Allow-Origin` depends on the
+    /// request, in which case responses carry `Vary: Origin`.
+    pub fn varies_by_origin(&self) -> bool {
+        !self.allows_any_origin()
+    }
+
+    /// Panic if the policy allows credentials for any origin.
+    ///
+    /// The router calls this when a policy is installed, so a misconfigured
+    /// canister fails at setup instead of exposing credentialed responses.
+    pub(crate) fn assert_valid(&self) {
+        assert!(
+            !(self.allow_credentials && self.allows_any_origin()),
+            "CorsConfig: allow_credentials cannot be combined with \"*\" in allowed_origins; \
+             list the allowed origins instead"
+        );
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self::any_origin()
+    }
+}
+
+/// How requests for a non-canonical path spelling are answered.
+///
+/// See [`PathNormalization`] for what makes a path canonical.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathPolicy {
+    /// Route `/about/`, `//about` and `/about` to the same handler. Each
+    /// spelling is certified and cached under its own path, because a
+    /// response is only verifiable for the path it was certified at.
+    #[default]
+    Lenient,
+    /// Answer non-canonical paths like unknown ones: custom or default 404.
+    Strict,
+    /// Answer with a certified `301 Moved Permanently` to the canonical
+    /// path. Clients may switch a redirected `POST` to `GET`.
+    RedirectMovedPermanently,
+    /// Answer with a certified `308 Permanent Redirect` to the canonical
+    /// path, preserving the method and body.
+    RedirectPermanent,
+}
+
+/// Request path normalization policy.
+///
+/// A path is canonical when it has no empty segments (`//`), no trailing
+/// slash (except the root `/`) and, with `lowercase`, no uppercase ASCII
+/// letters outside percent escapes. Under [`PathPolicy::Strict`] and the
+/// redirect policies, only canonical paths reach route handlers, so they
+/// are also the only keys used for certification and caching.
+///
+/// # Examples
+///
+/// ```
+/// use ic_asset_router::{AssetConfig, PathNormalization};
+///
+/// let config = AssetConfig {
+///     path_normalization: PathNormalization::redirect(),
+///     ..AssetConfig::default()
+/// };
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathNormalization {
+    /// What to do with non-canonical paths.
+    /// Default: [`PathPolicy::Lenient`]
+    pub policy: PathPolicy,
+    /// Treat uppercase ASCII letters as non-canonical. This applies to the
+    /// whole path, including param values and static asset paths, so only
+    /// enable it when every route and asset path is lowercase.
+    /// Default: `false`
+    pub lowercase: bool,
+}
+
+impl PathNormalization {
+    /// Route every spelling of a path to the same handler (the default).
+    pub fn lenient() -> Self {
+        Self::default()
+    }
+
+    /// Answer non-canonical paths with 404.
+    pub fn strict() -> Self {
+        Self {
+            policy: PathPolicy::Strict,
+            lowercase: false,
+        }
+    }
+
+    /// Redirect non-canonical paths with `308 Permanent Redirect`.
+    pub fn redirect() -> Self {
+        Self {
+            policy: PathPolicy::RedirectPermanent,
+            lowercase: false,
+        }
+    }
+}
+
 /// Typed fields for well-known security headers.
 ///
 /// Based on the OWASP Secure Headers Project "active" list and Helmet.js defaults.
@@ -294,6 +552,31 @@ pub struct AssetConfig {
     /// TTL-based cache configuration for dynamic assets.
     pub cache_config: CacheConfig,
 
+    /// Runtime gzip/Brotli compression for dynamic responses.
+    /// Disabled by default.
+    pub compression: CompressionConfig,
+
+    /// Cross-origin resource sharing policy applied to every route.
+    /// Routes can override it with `#[route(cors(...))]`.
+    /// Disabled (`None`) by default.
+    pub cors: Option<CorsConfig>,
+
+    /// CORS policies for path prefixes, such as `("/api", policy)`. For a
+    /// route under one or more of them, the longest matching prefix
+    /// replaces [`cors`](Self::cors); `#[route(cors(...))]` still takes
+    /// precedence. Empty by default.
+    pub cors_prefixes: Vec<(String, CorsConfig)>,
+
+    /// Handling of non-canonical request paths (`/about/`, `//about`).
+    /// Lenient by default.
+    pub path_normalization: PathNormalization,
+
+    /// Request body size limit in bytes for every route, enforced with
+    /// `413 Payload Too Large` before middleware and the handler run.
+    /// Routes can override it with `#[route(max_body = ...)]`.
+    /// Unlimited (`None`) by default.
+    pub max_body: Option<usize>,
+
     /// Arbitrary headers appended after security headers.
     ///
     /// If a custom header has the same name as a security header, the custom
@@ -320,23 +603,29 @@ impl AssetConfig {
         }
 
         // Layer custom_headers -- override any security header with the same name
-        for h in &self.custom_headers {
-            let name_lower = h.0.to_lowercase();
-            merged.retain(|(k, _)| k.to_lowercase() != name_lower);
-            merged.push(h.clone());
-        }
+        layer_headers(&mut merged, &self.custom_headers);
 
         // Layer additional_headers (per-route overrides)
-        for h in &additional_headers {
-            let name_lower = h.0.to_lowercase();
-            merged.retain(|(k, _)| k.to_lowercase() != name_lower);
-            merged.push(h.clone());
-        }
+        layer_headers(&mut merged, &additional_headers);
 
         merged
     }
 }
 
+/// Layer `overrides` on top of `headers` with last-write-wins semantics.
+///
+/// Any existing header whose name matches an override (case-insensitive) is
+/// removed before the override is appended. This is the merge step used by
+/// [`AssetConfig::merged_headers`] and when applying per-route headers to a
+/// handler response.
+pub(crate) fn layer_headers(headers: &mut Vec<HeaderField>, overrides: &[HeaderField]) {
+    for h in overrides {
+        let name_lower = h.0.to_lowercase();
+        headers.retain(|(k, _)| k.to_lowercase() != name_lower);
+        headers.push(h.clone());
+    }
+}
+
 // Test coverage audit (Session 7, Spec 5.5):
 //
 // Covered:
@@ -437,6 +726,11 @@ mod tests {
             security_headers: SecurityHeaders::strict(),
             cache_control: CacheControl::default(),
             cache_config: CacheConfig::default(),
+            compression: CompressionConfig::default(),
+            cors: None,
+            cors_prefixes: Vec::new(),
+            path_normalization: PathNormalization::default(),
+            max_body: None,
             custom_headers: vec![("x-frame-options".to_string(), "SAMEORIGIN".to_string())],
         };
         let merged = config.merged_headers(vec![]);
@@ -454,6 +748,11 @@ mod tests {
             security_headers: SecurityHeaders::strict(),
             cache_control: CacheControl::default(),
             cache_config: CacheConfig::default(),
+            compression: CompressionConfig::default(),
+            cors: None,
+            cors_prefixes: Vec::new(),
+            path_normalization: PathNormalization::default(),
+            max_body: None,
             custom_headers: vec![("x-frame-options".to_string(), "SAMEORIGIN".to_string())],
         };
         let merged = config.merged_headers(vec![(
@@ -583,6 +882,11 @@ mod tests {
             security_headers: SecurityHeaders::none(),
             cache_control: CacheControl::default(),
             cache_config: CacheConfig::default(),
+            compression: CompressionConfig::default(),
+            cors: None,
+            cors_prefixes: Vec::new(),
+            path_normalization: PathNormalization::default(),
+            max_body: None,
             custom_headers: vec![
                 ("x-custom".to_string(), "first".to_string()),
                 ("x-custom".to_string(), "second".to_string()),
@@ -601,6 +905,11 @@ mod tests {
             security_headers: SecurityHeaders::none(),
             cache_control: CacheControl::default(),
             cache_config: CacheConfig::default(),
+            compression: CompressionConfig::default(),
+            cors: None,
+            cors_prefixes: Vec::new(),
+            path_normalization: PathNormalization::default(),
+            max_body: None,
             custom_headers: vec![("content-type".to_string(), "text/plain".to_string())],
         };
         // Additional header with different casing overrides custom.
@@ -617,4 +926,134 @@ mod tests {
         // The surviving header preserves the casing from the additional layer.
         assert_eq!(ct[0].0, "Content-Type");
     }
+
+    // ---- Runtime compression config ----
+
+    #[test]
+    fn compression_disabled_by_default() {
+        let config = AssetConfig::default();
+        assert!(!config.compression.is_enabled());
+        assert_eq!(
+            config.compression.min_size,
+            CompressionConfig::DEFAULT_MIN_SIZE
+        );
+    }
+
+    #[test]
+    fn compression_route_override_none_keeps_global() {
+        let global = CompressionConfig {
+            brotli: false,
+            ..CompressionConfig::enabled()
+        };
+        assert_eq!(global.for_route(None), global);
+        assert_eq!(
+            CompressionConfig::disabled().for_route(None),
+            CompressionConfig::disabled()
+        );
+    }
+
+    #[test]
+    fn compression_route_override_true_enables_with_global_min_size() {
+        let global = CompressionConfig {
+            min_size: 4096,
+            ..CompressionConfig::disabled()
+        };
+        let effective = global.for_route(Some(true));
+        assert!(effective.gzip);
+        assert!(effective.brotli);
+        assert_eq!(effective.min_size, 4096);
+    }
+
+    #[test]
+    fn compression_route_override_true_keeps_enabled_global_encodings() {
+        let global = CompressionConfig {
+            brotli: false,
+            ..CompressionConfig::enabled()
+        };
+        assert_eq!(global.for_route(Some(true)), global);
+    }
+
+    #[test]
+    fn compression_route_override_false_disables() {
+        let effective = CompressionConfig::enabled().for_route(Some(false));
+        assert!(!effective.is_enabled());
+    }
+
+    // ---- Path normalization ----
+
+    #[test]
+    fn path_normalization_is_lenient_by_default() {
+        assert_eq!(
+            AssetConfig::default().path_normalization,
+            PathNormalization {
+                policy: PathPolicy::Lenient,
+                lowercase: false,
+            }
+        );
+        assert_eq!(PathNormalization::strict().policy, PathPolicy::Strict);
+        assert_eq!(
+            PathNormalization::redirect().policy,
+            PathPolicy::RedirectPermanent
+        );
+    }
+
+    // ---- CORS ----
+
+    #[test]
+    fn cors_disabled_by_default() {
+        assert!(AssetConfig::default().cors.is_none());
+    }
+
+    #[test]
+    fn cors_any_origin_allows_wildcard() {
+        let cors = CorsConfig::any_origin();
+        assert_eq!(
+            cors.allow_origin_for("https://a.example"),
+            Some("*".to_string())
+        );
+        assert!(!cors.varies_by_origin());
+    }
+
+    #[test]
+    fn cors_any_origin_with_credentials_never_echoes_origin() {
+        let cors = CorsConfig {
+            allow_credentials: true,
+            ..CorsConfig::any_origin()
+        };
+        assert_eq!(
+            cors.allow_origin_for("https://evil.example"),
+            Some("*".to_string())
+        );
+        assert!(!cors.varies_by_origin());
+    }
+
+    #[test]
+    #[should_panic(expected = "allow_credentials cannot be combined")]
+    fn cors_any_origin_with_credentials_is_rejected() {
+        CorsConfig {
+            allow_credentials: true,
+            ..CorsConfig::any_origin()
+        }
+        .assert_valid();
+    }
+
+    #[test]
+    fn cors_listed_origins_with_credentials_are_valid() {
+        CorsConfig {
+            allow_credentials: true,
+            ..CorsConfig::with_origins(&["https://a.example"])
+        }
+        .assert_valid();
+    }
+
+    #[test]
+    fn cors_listed_origins_echo_or_reject() {
+        let cors = CorsConfig::with_origins(&["https://a.example"]);
+        assert_eq!(
+            cors.allow_origin_for("https://a.example"),
+            Some("https://a.example".to_string())
+        );
+        assert_eq!(cors.allow_origin_for("https://evil.example"), None);
+        assert!(cors.varies_by_origin());
+    }
 }
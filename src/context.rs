@@ -1,6 +1,18 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::str::FromStr;
 
-use ic_http_certification::{HeaderField, Method};
+use ic_http_certification::{HeaderField, HttpResponse, Method, StatusCode};
+
+use crate::cookie;
+use crate::csrf::CsrfToken;
+use crate::extensions::Extensions;
+use crate::ic_context::IcContext;
+use crate::multipart::{Multipart, MultipartError, DEFAULT_MAX_MULTIPART_SIZE};
+use crate::router::RouteParams;
+#[cfg(feature = "sessions")]
+use crate::session::Session;
+use crate::state::AppState;
 
 /// Error returned by [`RouteContext::json()`].
 #[derive(Debug)]
@@ -56,6 +68,89 @@ impl std::error::Error for FormBodyError {
     }
 }
 
+/// Error returned by [`parse_param()`] when a typed route parameter does not
+/// parse into its declared type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamParseError {
+    /// The route-level parameter name (e.g. `"postId"`).
+    pub name: String,
+    /// The URL-decoded path segment that failed to parse.
+    pub value: String,
+    /// The `FromStr` error message.
+    pub message: String,
+}
+
+impl ParamParseError {
+    /// Convert the error into a `400 Bad Request` plain-text response.
+    ///
+    /// This is what the generated route wiring returns when a typed
+    /// parameter fails to parse. The response is marked
+    /// `Cache-Control: no-store`, so the router neither certifies nor caches
+    /// it.
+    pub fn into_response(self) -> HttpResponse<'static> {
+        HttpResponse::builder()
+            .with_status_code(StatusCode::BAD_REQUEST)
+            .with_headers(vec![
+                ("content-type".to_string(), "text/plain".to_string()),
+                ("cache-control".to_string(), "no-store".to_string()),
+            ])
+            .with_body(Cow::<[u8]>::Owned(self.to_string().into_bytes()))
+            .build()
+    }
+}
+
+impl std::fmt::Display for ParamParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid value {:?} for route parameter `{}`: {}",
+            self.value, self.name, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParamParseError {}
+
+/// Error returned by [`deserialize_search_params_strict()`] when the query
+/// string does not deserialize into the route's `SearchParams`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchParamsError {
+    /// The `serde_urlencoded` error message (e.g. `invalid digit found in string`).
+    pub message: String,
+}
+
+impl SearchParamsError {
+    /// Convert the error into a `400 Bad Request` JSON response of the form
+    /// `{"error":"invalid_search_params","message":"..."}`.
+    ///
+    /// This is what the generated route wiring returns for routes marked
+    /// `#[route(search = "strict")]`. The response is marked
+    /// `Cache-Control: no-store`: the router caches responses by path, so a
+    /// cached 400 would be served for every query string.
+    pub fn into_response(self) -> HttpResponse<'static> {
+        let body = serde_json::json!({
+            "error": "invalid_search_params",
+            "message": self.message,
+        });
+        HttpResponse::builder()
+            .with_status_code(StatusCode::BAD_REQUEST)
+            .with_headers(vec![
+                ("content-type".to_string(), "application/json".to_string()),
+                ("cache-control".to_string(), "no-store".to_string()),
+            ])
+            .with_body(Cow::<[u8]>::Owned(body.to_string().into_bytes()))
+            .build()
+    }
+}
+
+impl std::fmt::Display for SearchParamsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid search params: {}", self.message)
+    }
+}
+
+impl std::error::Error for SearchParamsError {}
+
 /// A type alias for query string parameters parsed from the URL.
 pub type QueryParams = HashMap<String, String>;
 
@@ -131,6 +226,22 @@ pub struct RouteContext<P, S = ()> {
     /// `Some("docs/report.pdf")` for a request to `/files/docs/report.pdf`
     /// matching `/files/*`.
     pub wildcard: Option<String>,
+    /// Shared application state, read with [`state`](Self::state).
+    ///
+    /// The generated wiring passes [`AppState::current`]; tests can pass
+    /// their own.
+    pub app_state: AppState,
+    /// Values attached to this request by middleware, read with
+    /// [`extensions`](Self::extensions).
+    ///
+    /// The generated wiring passes [`extensions::take`](crate::extensions::take);
+    /// tests can pass their own.
+    pub extensions: Extensions,
+    /// IC metadata: query or update mode, time, canister id and caller.
+    ///
+    /// The generated wiring passes [`IcContext::current`]; tests can pass
+    /// their own.
+    pub ic: IcContext,
 }
 
 impl<P, S> RouteContext<P, S> {
@@ -151,6 +262,83 @@ impl<P, S> RouteContext<P, S> {
             .map(|(_, v)| v.as_str())
     }
 
+    /// Returns the value of the first request cookie named `name`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// let theme = ctx.cookie("theme"); // Option<String>
+    /// ```
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        cookie::find_cookie(&self.headers, name)
+    }
+
+    /// Returns the application state of type `T` registered with
+    /// [`SetupBuilder::with_state`](crate::SetupBuilder::with_state).
+    ///
+    /// # Panics
+    ///
+    /// Panics if no state of type `T` is registered; use
+    /// [`try_state`](Self::try_state) to handle that case.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// let items = ctx.state::<Store>().items.borrow();
+    /// ```
+    pub fn state<T: 'static>(&self) -> &T {
+        self.app_state.expect()
+    }
+
+    /// Returns the application state of type `T`, or `None` if none is
+    /// registered.
+    pub fn try_state<T: 'static>(&self) -> Option<&T> {
+        self.app_state.get()
+    }
+
+    /// Returns the values middleware attached to this request with
+    /// [`extensions::insert`](crate::extensions::insert).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// let user = ctx.extensions().get::<User>();
+    /// ```
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Returns the CSRF token attached by [`csrf::middleware`](crate::csrf::middleware),
+    /// for forms and `fetch` calls that send unsafe requests.
+    ///
+    /// Returns `None` on routes the CSRF middleware does not cover.
+    pub fn csrf_token(&self) -> Option<&str> {
+        self.extensions.get::<CsrfToken>().map(CsrfToken::value)
+    }
+
+    /// Returns a hidden `<input>` carrying the CSRF token, to embed in forms.
+    ///
+    /// Returns `None` on routes the CSRF middleware does not cover.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// let form = format!(r#"<form method="post">{}</form>"#, ctx.csrf_field().unwrap_or_default());
+    /// ```
+    pub fn csrf_field(&self) -> Option<String> {
+        self.extensions
+            .get::<CsrfToken>()
+            .map(CsrfToken::hidden_field)
+    }
+
+    /// Returns the session loaded by [`session::middleware`](crate::session::middleware).
+    ///
+    /// Returns `None` on routes the session middleware does not cover.
+    #[cfg(feature = "sessions")]
+    pub fn session(&self) -> Option<&Session> {
+        self.extensions.get()
+    }
+
     /// Returns the request body as a UTF-8 string.
     ///
     /// Returns `Err` if the body is not valid UTF-8. For lossy conversion,
@@ -234,6 +422,40 @@ impl<P, S> RouteContext<P, S> {
         let text = std::str::from_utf8(&self.body).map_err(FormBodyError::Utf8)?;
         serde_urlencoded::from_str(text).map_err(FormBodyError::Deserialize)
     }
+
+    /// Parses the request body as `multipart/form-data`.
+    ///
+    /// The boundary is taken from the `content-type` header. Bodies larger
+    /// than [`DEFAULT_MAX_MULTIPART_SIZE`] are rejected; use
+    /// [`multipart_with_max_size()`](Self::multipart_with_max_size) to pick a
+    /// different limit. Use [`Multipart::fields_as()`] for typed access to
+    /// the text fields.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// pub fn post(ctx: RouteContext<()>) -> HttpResponse<'static> {
+    ///     let form = match ctx.multipart() {
+    ///         Ok(v) => v,
+    ///         Err(_) => return bad_request("invalid upload"),
+    ///     };
+    ///     for file in form.files() {
+    ///         // file.filename, file.content_type, file.data
+    ///     }
+    ///     // ...
+    ///     # todo!()
+    /// }
+    /// ```
+    pub fn multipart(&self) -> Result<Multipart, MultipartError> {
+        self.multipart_with_max_size(DEFAULT_MAX_MULTIPART_SIZE)
+    }
+
+    /// Parses the request body as `multipart/form-data`, rejecting bodies
+    /// larger than `max_size` bytes with [`MultipartError::TooLarge`].
+    pub fn multipart_with_max_size(&self, max_size: usize) -> Result<Multipart, MultipartError> {
+        let content_type = self.header("content-type").unwrap_or_default();
+        Multipart::parse(content_type, &self.body, max_size)
+    }
 }
 
 /// Parse query string key-value pairs from a URL.
@@ -329,6 +551,124 @@ where
     serde_urlencoded::from_str(qs).unwrap_or_default()
 }
 
+/// Deserialize a URL query string into a typed struct, reporting failures.
+///
+/// The strict counterpart of [`deserialize_search_params`], used by the
+/// generated route wiring for routes marked `#[route(search = "strict")]`.
+/// Missing `Option` fields are still fine; a value that does not parse
+/// (e.g. `?page=abc` for `page: Option<u32>`) is an error. Unknown keys are
+/// ignored unless the struct uses `#[serde(deny_unknown_fields)]`.
+///
+/// # Examples
+///
+/// ```
+/// #[derive(serde::Deserialize)]
+/// struct SearchParams {
+///     page: Option<u32>,
+/// }
+///
+/// use ic_asset_router::deserialize_search_params_strict;
+///
+/// let ok: SearchParams = deserialize_search_params_strict("page=2").unwrap();
+/// assert_eq!(ok.page, Some(2));
+/// assert!(deserialize_search_params_strict::<SearchParams>("page=abc").is_err());
+/// ```
+pub fn deserialize_search_params_strict<S>(query_str: &str) -> Result<S, SearchParamsError>
+where
+    S: serde::de::DeserializeOwned,
+{
+    let qs = query_str.strip_prefix('?').unwrap_or(query_str);
+    serde_urlencoded::from_str(qs).map_err(|e| SearchParamsError {
+        message: e.to_string(),
+    })
+}
+
+/// URL-decode the route parameter `name` and parse it with `FromStr`.
+///
+/// This is the helper used by the generated route wiring for typed param
+/// directories (e.g. `_postId:u64`). A missing parameter is treated as an
+/// empty string, so it fails to parse for every type except `String`.
+///
+/// # Examples
+///
+/// ```
+/// use ic_asset_router::{parse_param, RouteParams};
+///
+/// let mut params = RouteParams::new();
+/// params.insert("postId".to_string(), "42".to_string());
+/// assert_eq!(parse_param::<u64>(&params, "postId"), Ok(42));
+///
+/// params.insert("postId".to_string(), "abc".to_string());
+/// assert!(parse_param::<u64>(&params, "postId").is_err());
+/// ```
+pub fn parse_param<T>(raw_params: &RouteParams, name: &str) -> Result<T, ParamParseError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let raw = raw_params.get(name).map(String::as_str).unwrap_or_default();
+    let value = url_decode(raw);
+    value.parse().map_err(|e: T::Err| ParamParseError {
+        name: name.to_string(),
+        value: value.clone().into_owned(),
+        message: e.to_string(),
+    })
+}
+
+/// Like [`parse_param`], for optional segments (e.g. `__lang:u32`): a
+/// skipped segment yields `Ok(None)`.
+///
+/// # Examples
+///
+/// ```
+/// use ic_asset_router::{parse_optional_param, RouteParams};
+///
+/// let mut params = RouteParams::new();
+/// assert_eq!(parse_optional_param::<u32>(&params, "page"), Ok(None));
+/// params.insert("page".to_string(), "3".to_string());
+/// assert_eq!(parse_optional_param::<u32>(&params, "page"), Ok(Some(3)));
+/// ```
+pub fn parse_optional_param<T>(
+    raw_params: &RouteParams,
+    name: &str,
+) -> Result<Option<T>, ParamParseError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    if raw_params.contains_key(name) {
+        parse_param(raw_params, name).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Split the tail captured by the named catch-all `name` (e.g. `_...slug`)
+/// into URL-decoded segments.
+///
+/// An empty or missing capture yields an empty `Vec`.
+///
+/// # Examples
+///
+/// ```
+/// use ic_asset_router::{catch_all_segments, RouteParams};
+///
+/// let mut params = RouteParams::new();
+/// params.insert("slug".to_string(), "guides/getting%20started".to_string());
+/// assert_eq!(catch_all_segments(&params, "slug"), vec!["guides", "getting started"]);
+/// ```
+pub fn catch_all_segments(raw_params: &RouteParams, name: &str) -> Vec<String> {
+    raw_params
+        .get(name)
+        .map(|tail| {
+            tail.split('/')
+                .filter(|s| !s.is_empty())
+                .map(|s| url_decode(s).into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 /// Parse an `application/x-www-form-urlencoded` body into key-value pairs.
 ///
 /// This is the encoding used by HTML `<form>` submissions. The body is
@@ -371,6 +711,65 @@ fn hex_val(b: u8) -> Option<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ic_context::ExecutionMode;
+
+    fn raw_params(pairs: &[(&str, &str)]) -> RouteParams {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_param_parses_typed_value() {
+        let params = raw_params(&[("postId", "42")]);
+        assert_eq!(parse_param::<u64>(&params, "postId"), Ok(42));
+        assert_eq!(
+            parse_param::<String>(&params, "postId"),
+            Ok("42".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_param_url_decodes_before_parsing() {
+        let params = raw_params(&[("n", "%2D7")]);
+        assert_eq!(parse_param::<i32>(&params, "n"), Ok(-7));
+    }
+
+    #[test]
+    fn parse_optional_param_reports_present_invalid_value() {
+        let mut params = RouteParams::new();
+        params.insert("page".to_string(), "x".to_string());
+        assert!(parse_optional_param::<u32>(&params, "page").is_err());
+    }
+
+    #[test]
+    fn catch_all_segments_empty_and_missing() {
+        let mut params = RouteParams::new();
+        assert!(catch_all_segments(&params, "slug").is_empty());
+        params.insert("slug".to_string(), String::new());
+        assert!(catch_all_segments(&params, "slug").is_empty());
+        params.insert("slug".to_string(), "a//b".to_string());
+        assert_eq!(catch_all_segments(&params, "slug"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn parse_param_failure_yields_400() {
+        let params = raw_params(&[("postId", "abc")]);
+        let err = parse_param::<u64>(&params, "postId").unwrap_err();
+        assert_eq!(err.name, "postId");
+        assert_eq!(err.value, "abc");
+        assert!(parse_param::<u64>(&RouteParams::new(), "postId").is_err());
+
+        let response = err.into_response();
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response
+            .headers()
+            .contains(&("cache-control".to_string(), "no-store".to_string())));
+        let body = std::str::from_utf8(response.body()).unwrap();
+        assert!(body.contains("postId"), "{body}");
+        assert!(body.contains("\"abc\""), "{body}");
+    }
 
     #[test]
     fn parse_query_basic() {
@@ -534,6 +933,48 @@ mod tests {
         let _ = sp.q;
     }
 
+    #[test]
+    fn deserialize_search_params_strict_accepts_valid_and_missing() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Sp {
+            page: Option<u32>,
+            filter: Option<String>,
+        }
+
+        let sp: Sp = deserialize_search_params_strict("?page=3").unwrap();
+        assert_eq!(sp.page, Some(3));
+        assert_eq!(sp.filter, None);
+    }
+
+    #[test]
+    fn deserialize_search_params_strict_type_mismatch_is_400() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Sp {
+            #[allow(dead_code)]
+            page: Option<u32>,
+        }
+
+        let err = deserialize_search_params_strict::<Sp>("page=abc").unwrap_err();
+        assert!(!err.message.is_empty());
+
+        let response = err.into_response();
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["error"], "invalid_search_params");
+        assert!(body["message"].is_string());
+    }
+
+    #[test]
+    fn deserialize_search_params_strict_missing_required_field() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Sp {
+            #[allow(dead_code)]
+            page: u32,
+        }
+
+        assert!(deserialize_search_params_strict::<Sp>("").is_err());
+    }
+
     // --- 6.8: url_decode tests ---
 
     #[test]
@@ -659,9 +1100,64 @@ mod tests {
             body,
             url: String::new(),
             wildcard: None,
+            app_state: AppState::new(),
+            extensions: Extensions::new(),
+            ic: IcContext::new(
+                ExecutionMode::Update,
+                0,
+                candid::Principal::anonymous(),
+                None,
+            ),
         }
     }
 
+    // state tests
+
+    #[test]
+    fn state_reads_app_state_by_type() {
+        let mut ctx = test_ctx(vec![], vec![]);
+        assert!(ctx.try_state::<u32>().is_none());
+        ctx.app_state = AppState::new().with(42u32);
+        assert_eq!(*ctx.state::<u32>(), 42);
+    }
+
+    // extensions tests
+
+    #[test]
+    fn extensions_reads_values_attached_by_middleware() {
+        let mut ctx = test_ctx(vec![], vec![]);
+        assert!(ctx.extensions().get::<u32>().is_none());
+        ctx.extensions.insert(42u32);
+        assert_eq!(ctx.extensions().get::<u32>(), Some(&42));
+    }
+
+    // cookie tests
+
+    #[test]
+    fn cookie_reads_request_cookies() {
+        let ctx = test_ctx(
+            vec![("cookie".to_string(), "theme=dark; lang=en".to_string())],
+            vec![],
+        );
+        assert_eq!(ctx.cookie("lang").as_deref(), Some("en"));
+        assert_eq!(ctx.cookie("missing"), None);
+    }
+
+    // csrf tests
+
+    #[test]
+    fn csrf_helpers_embed_the_attached_token() {
+        let mut ctx = test_ctx(vec![], vec![]);
+        assert_eq!(ctx.csrf_token(), None);
+        assert_eq!(ctx.csrf_field(), None);
+        ctx.extensions.insert(CsrfToken::new("t.1"));
+        assert_eq!(ctx.csrf_token(), Some("t.1"));
+        assert_eq!(
+            ctx.csrf_field().as_deref(),
+            Some(r#"<input type="hidden" name="csrf_token" value="t.1">"#)
+        );
+    }
+
     // header tests
 
     #[test]
@@ -713,6 +1209,33 @@ mod tests {
         assert_eq!(ctx.body_to_str(), Ok(""));
     }
 
+    // multipart tests
+
+    #[test]
+    fn multipart_uses_content_type_boundary() {
+        let ctx = test_ctx(
+            vec![(
+                "Content-Type".to_string(),
+                "multipart/form-data; boundary=b".to_string(),
+            )],
+            b"--b\r\nContent-Disposition: form-data; name=\"q\"\r\n\r\nhi\r\n--b--".to_vec(),
+        );
+        assert_eq!(ctx.multipart().unwrap().field("q"), Some("hi"));
+        assert!(matches!(
+            ctx.multipart_with_max_size(4),
+            Err(MultipartError::TooLarge { limit: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn multipart_without_content_type() {
+        let ctx = test_ctx(vec![], b"--b--".to_vec());
+        assert!(matches!(
+            ctx.multipart(),
+            Err(MultipartError::MissingBoundary)
+        ));
+    }
+
     // json tests
 
     #[test]
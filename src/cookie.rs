@@ -0,0 +1,367 @@
+//! Cookie parsing and `Set-Cookie` serialization.
+//!
+//! Handlers read request cookies with
+//! [`RouteContext::cookie`](crate::RouteContext::cookie) and set them by
+//! adding a [`Cookie`] header to the response:
+//!
+//! ```rust,ignore
+//! let theme = ctx.cookie("theme").unwrap_or_else(|| "light".to_string());
+//!
+//! HttpResponse::builder()
+//!     .with_headers(vec![Cookie::new("theme", "dark").with_path("/").into_header()])
+//!     .build()
+//! ```
+//!
+//! Values are written as given: encode anything outside the cookie value
+//! alphabet (no whitespace, `"`, `,`, `;` or `\`) before passing it in.
+//!
+//! A response that sets a cookie belongs to one client, so route it with
+//! `certification = "skip"` or certify it over the `cookie` request header.
+//! Outside full certification the router never caches a response that
+//! sets a cookie or carries `Vary: Cookie`, so later queries do not hand it
+//! to other clients.
+//!
+//! [`sign`] and [`verify`] protect cookie values the client must not
+//! change, such as session ids.
+
+use std::cell::Cell;
+use std::fmt;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use ic_http_certification::{HeaderField, HttpResponse};
+use sha2::Sha256;
+
+thread_local! {
+    pub(crate) static NEXT_TOKEN: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Parse a `Cookie` request header value (`a=1; b=2`) into name/value pairs,
+/// in order. Pairs without `=` are skipped; surrounding double quotes are
+/// removed from values.
+pub fn parse_cookie_header(header: &str) -> Vec<(String, String)> {
+    header
+        .split(';')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            let name = name.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// The value of the first cookie named `name` across all `Cookie` headers.
+pub fn find_cookie(headers: &[HeaderField], name: &str) -> Option<String> {
+    headers
+        .iter()
+        .filter(|(k, _)| k.eq_ignore_ascii_case("cookie"))
+        .flat_map(|(_, v)| parse_cookie_header(v))
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v)
+}
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    /// Sent only with same-site requests.
+    Strict,
+    /// Also sent with top-level cross-site navigations.
+    Lax,
+    /// Sent with all requests; requires `Secure`.
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        })
+    }
+}
+
+/// A cookie to set with a `Set-Cookie` response header.
+///
+/// New cookies are `Secure` and `HttpOnly`; turn those off with
+/// [`with_secure`](Self::with_secure) and
+/// [`with_http_only`](Self::with_http_only).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// A session cookie (no `Max-Age`) named `name` with `value`.
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            secure: true,
+            http_only: true,
+            same_site: None,
+        }
+    }
+
+    /// A cookie that makes the client delete `name` (empty, `Max-Age=0`).
+    ///
+    /// Use the same path and domain as the cookie being deleted.
+    pub fn removal(name: &str) -> Self {
+        Self::new(name, "").with_max_age(Duration::ZERO)
+    }
+
+    /// Set the `Path` attribute.
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    /// Set the `Domain` attribute.
+    pub fn with_domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    /// Set the `Max-Age` attribute, in whole seconds.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Set or clear the `Secure` attribute.
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set or clear the `HttpOnly` attribute.
+    pub fn with_http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the `SameSite` attribute.
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// The cookie name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The cookie value.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The `("set-cookie", value)` response header.
+    pub fn into_header(self) -> HeaderField {
+        ("set-cookie".to_string(), self.to_string())
+    }
+}
+
+impl fmt::Display for Cookie {
+    /// The `Set-Cookie` header value.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={same_site}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Add `Vary: Cookie` to `response` unless it already varies by cookie,
+/// marking it as belonging to one client.
+pub(crate) fn vary_by_cookie(response: &mut HttpResponse) {
+    let varies = response.headers().iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("vary")
+            && value
+                .split(',')
+                .any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case("cookie"))
+    });
+    if !varies {
+        response.add_header(("vary".to_string(), "cookie".to_string()));
+    }
+}
+
+/// `value` followed by `.` and the hex HMAC-SHA256 of `value` under
+/// `secret`, for cookies the client must not be able to forge.
+pub fn sign(value: &str, secret: &[u8]) -> String {
+    format!("{value}.{}", hex(&hmac_sha256(secret, value.as_bytes())))
+}
+
+/// The value inside `signed`, if it was made by [`sign`] with `secret`.
+pub fn verify<'a>(signed: &'a str, secret: &[u8]) -> Option<&'a str> {
+    let (value, signature) = signed.rsplit_once('.')?;
+    let expected = hex(&hmac_sha256(secret, value.as_bytes()));
+    constant_time_eq(signature.as_bytes(), expected.as_bytes()).then_some(value)
+}
+
+/// A fresh token: a keyed hash of a counter, the time and `entropy`, so
+/// tokens cannot be predicted without the secret.
+///
+/// The counter makes tokens minted in update calls unique. Query calls
+/// discard their state changes, so every query at the same `time()` sees
+/// the same counter; tokens minted there pass a digest of the request as
+/// `entropy` to tell clients apart.
+pub(crate) fn new_token(secret: &[u8], now: u64, entropy: &[u8]) -> String {
+    let counter = NEXT_TOKEN.replace(NEXT_TOKEN.get().wrapping_add(1));
+    let mut message = counter.to_be_bytes().to_vec();
+    message.extend_from_slice(&now.to_be_bytes());
+    message.extend_from_slice(entropy);
+    hex(&hmac_sha256(secret, &message)[..16])
+}
+
+/// Compare in time independent of where `a` and `b` differ.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cookie_header_pairs() {
+        assert_eq!(
+            parse_cookie_header(r#"a=1; b="two words";c=;=x; bare; d=e=f"#),
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "two words".to_string()),
+                ("c".to_string(), String::new()),
+                ("d".to_string(), "e=f".to_string()),
+            ]
+        );
+        assert!(parse_cookie_header("").is_empty());
+    }
+
+    #[test]
+    fn find_cookie_searches_all_cookie_headers() {
+        let headers = vec![
+            ("Cookie".to_string(), "a=1".to_string()),
+            ("x-other".to_string(), "b=9".to_string()),
+            ("cookie".to_string(), "b=2; a=3".to_string()),
+        ];
+        assert_eq!(find_cookie(&headers, "a").as_deref(), Some("1"));
+        assert_eq!(find_cookie(&headers, "b").as_deref(), Some("2"));
+        assert_eq!(find_cookie(&headers, "c"), None);
+    }
+
+    #[test]
+    fn serializes_set_cookie_attributes() {
+        let cookie = Cookie::new("sid", "abc")
+            .with_path("/")
+            .with_domain("example.com")
+            .with_max_age(Duration::from_secs(3600))
+            .with_same_site(SameSite::Lax);
+        assert_eq!(
+            cookie.to_string(),
+            "sid=abc; Path=/; Domain=example.com; Max-Age=3600; Secure; HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(
+            Cookie::new("theme", "dark")
+                .with_secure(false)
+                .with_http_only(false)
+                .into_header(),
+            ("set-cookie".to_string(), "theme=dark".to_string())
+        );
+    }
+
+    #[test]
+    fn removal_expires_the_cookie() {
+        assert_eq!(
+            Cookie::removal("sid").with_path("/").to_string(),
+            "sid=; Path=/; Max-Age=0; Secure; HttpOnly"
+        );
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231_test_case_2() {
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn hmac_hashes_long_keys() {
+        // RFC 4231 test case 6: a 131-byte key.
+        assert_eq!(
+            hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn signed_values_verify_only_with_the_same_secret() {
+        let signed = sign("abc", b"secret");
+        assert_eq!(verify(&signed, b"secret"), Some("abc"));
+        assert_eq!(verify(&signed, b"other"), None);
+        assert_eq!(verify("abc.00", b"secret"), None);
+        assert_eq!(verify("abc", b"secret"), None);
+    }
+
+    #[test]
+    fn new_tokens_differ() {
+        assert_ne!(new_token(b"secret", 1, &[]), new_token(b"secret", 1, &[]));
+        assert_eq!(new_token(b"secret", 1, &[]).len(), 32);
+    }
+
+    #[test]
+    fn new_tokens_with_the_same_counter_differ_by_entropy() {
+        NEXT_TOKEN.set(7);
+        let a = new_token(b"secret", 1, b"a");
+        NEXT_TOKEN.set(7);
+        let b = new_token(b"secret", 1, b"b");
+        assert_ne!(a, b);
+    }
+}
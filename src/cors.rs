@@ -0,0 +1,355 @@
+//! Cross-origin resource sharing: preflight responses and `Access-Control-*`
+//! headers for route responses.
+//!
+//! The request-independent part of a [`CorsConfig`] (exposed headers,
+//! credentials, `Vary: Origin`) is merged into the certified response
+//! headers. `Access-Control-Allow-Origin` depends on the request's `Origin`
+//! header while a certified response is cached once per path, so it is
+//! excluded from certification ([`exclude_allow_origin`]) and set per
+//! request by [`apply_allow_origin`].
+
+use std::borrow::Cow;
+
+use ic_http_certification::{HttpRequest, HttpResponse, Method, StatusCode};
+
+use crate::certification::CertificationMode;
+use crate::config::{layer_headers, CorsConfig};
+use crate::route_config::HeaderField;
+
+/// Name of the per-request, uncertified CORS header.
+pub const ALLOW_ORIGIN_HEADER: &str = "access-control-allow-origin";
+
+/// Returns `true` if `request` is a CORS preflight: an `OPTIONS` request
+/// carrying both `Origin` and `Access-Control-Request-Method`.
+pub fn is_preflight(request: &HttpRequest) -> bool {
+    request.method() == Method::OPTIONS
+        && find_header(request, "origin").is_some()
+        && find_header(request, "access-control-request-method").is_some()
+}
+
+/// Headers added to every response of a CORS-enabled route, except
+/// `Access-Control-Allow-Origin`.
+///
+/// `existing` are the headers the response already carries; a `Vary`
+/// header among them is extended with `Origin` rather than replaced.
+pub fn response_headers(config: &CorsConfig, existing: &[HeaderField]) -> Vec<HeaderField> {
+    let mut headers = Vec::new();
+    if !config.exposed_headers.is_empty() {
+        headers.push((
+            "access-control-expose-headers".to_string(),
+            config.exposed_headers.join(", "),
+        ));
+    }
+    if config.allow_credentials {
+        headers.push((
+            "access-control-allow-credentials".to_string(),
+            "true".to_string(),
+        ));
+    }
+    if config.varies_by_origin() {
+        headers.push(("vary".to_string(), vary_with_origin(existing)));
+    }
+    headers
+}
+
+/// Build the `204 No Content` answer to a preflight request.
+///
+/// `route_methods` are the methods registered for the route; they are
+/// advertised when [`CorsConfig::allowed_methods`] is empty. The response
+/// does not depend on the request, so it can be certified and cached; add
+/// `Access-Control-Allow-Origin` with [`apply_allow_origin`] when serving.
+pub fn preflight_response(config: &CorsConfig, route_methods: &[Method]) -> HttpResponse<'static> {
+    let mut methods: Vec<String> = if config.allowed_methods.is_empty() {
+        route_methods
+            .iter()
+            .map(|m| m.as_str().to_string())
+            .collect()
+    } else {
+        config
+            .allowed_methods
+            .iter()
+            .map(|m| m.to_ascii_uppercase())
+            .collect()
+    };
+    // Route methods come from a HashMap; sort so the certified response is
+    // identical on every replica.
+    methods.sort();
+    methods.dedup();
+
+    let mut headers = vec![(
+        "access-control-allow-methods".to_string(),
+        methods.join(", "),
+    )];
+    if !config.allowed_headers.is_empty() {
+        headers.push((
+            "access-control-allow-headers".to_string(),
+            config.allowed_headers.join(", "),
+        ));
+    }
+    if let Some(max_age) = config.max_age {
+        headers.push(("access-control-max-age".to_string(), max_age.to_string()));
+    }
+    let static_headers = response_headers(config, &[]);
+    layer_headers(&mut headers, &static_headers);
+
+    HttpResponse::builder()
+        .with_status_code(StatusCode::NO_CONTENT)
+        .with_headers(headers)
+        .with_body(Cow::<[u8]>::Owned(Vec::new()))
+        .build()
+}
+
+/// Set `Access-Control-Allow-Origin` on `response` if the request's `Origin`
+/// is allowed by `config`.
+///
+/// Requests without an `Origin` header, or from a disallowed origin, leave
+/// the response unchanged, so the browser blocks the cross-origin read.
+pub fn apply_allow_origin(
+    response: &mut HttpResponse<'static>,
+    config: &CorsConfig,
+    request: &HttpRequest,
+) {
+    let Some(origin) = find_header(request, "origin") else {
+        return;
+    };
+    if let Some(allowed) = config.allow_origin_for(origin) {
+        layer_headers(
+            response.headers_mut(),
+            &[(ALLOW_ORIGIN_HEADER.to_string(), allowed)],
+        );
+    }
+}
+
+/// Exclude `Access-Control-Allow-Origin` from the response headers that
+/// `mode` certifies.
+pub fn exclude_allow_origin(mode: &mut CertificationMode) {
+    let response = match mode {
+        CertificationMode::Skip => return,
+        CertificationMode::ResponseOnly(config) => config,
+        CertificationMode::Full(config) => &mut config.response,
+    };
+    if !response
+        .exclude_headers
+        .iter()
+        .any(|h| h.eq_ignore_ascii_case(ALLOW_ORIGIN_HEADER))
+    {
+        response
+            .exclude_headers
+            .push(ALLOW_ORIGIN_HEADER.to_string());
+    }
+}
+
+/// The `Vary` value for a CORS response: any existing `Vary` list with
+/// `Origin` appended.
+fn vary_with_origin(existing: &[HeaderField]) -> String {
+    let current = existing
+        .iter()
+        .rev()
+        .find(|(k, _)| k.eq_ignore_ascii_case("vary"))
+        .map(|(_, v)| v.as_str())
+        .unwrap_or("");
+    let mut values: Vec<&str> = current
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+    if values
+        .iter()
+        .any(|v| *v == "*" || v.eq_ignore_ascii_case("origin"))
+    {
+        return values.join(", ");
+    }
+    values.push("origin");
+    values.join(", ")
+}
+
+fn find_header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header<'a>(response: &'a HttpResponse, name: &str) -> Option<&'a str> {
+        response
+            .headers()
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> HttpRequest<'static> {
+        HttpRequest::builder()
+            .with_method(method)
+            .with_url("/api/items")
+            .with_headers(
+                headers
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn preflight_requires_origin_and_request_method() {
+        assert!(is_preflight(&request(
+            Method::OPTIONS,
+            &[
+                ("Origin", "https://a.example"),
+                ("Access-Control-Request-Method", "POST"),
+            ],
+        )));
+        assert!(!is_preflight(&request(
+            Method::OPTIONS,
+            &[("origin", "https://a.example")],
+        )));
+        assert!(!is_preflight(&request(
+            Method::GET,
+            &[
+                ("origin", "https://a.example"),
+                ("access-control-request-method", "POST"),
+            ],
+        )));
+    }
+
+    #[test]
+    fn preflight_response_advertises_sorted_route_methods() {
+        let config = CorsConfig {
+            max_age: Some(600),
+            ..CorsConfig::any_origin()
+        };
+        let response = preflight_response(&config, &[Method::POST, Method::GET]);
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+        assert!(response.body().is_empty());
+        assert_eq!(
+            header(&response, "access-control-allow-methods"),
+            Some("GET, POST")
+        );
+        assert_eq!(
+            header(&response, "access-control-allow-headers"),
+            Some("content-type")
+        );
+        assert_eq!(header(&response, "access-control-max-age"), Some("600"));
+        assert_eq!(header(&response, "vary"), None);
+        assert_eq!(header(&response, ALLOW_ORIGIN_HEADER), None);
+    }
+
+    #[test]
+    fn preflight_response_prefers_configured_methods() {
+        let config = CorsConfig {
+            allowed_methods: vec!["put".to_string(), "GET".to_string()],
+            allow_credentials: true,
+            ..CorsConfig::with_origins(&["https://a.example"])
+        };
+        let response = preflight_response(&config, &[Method::POST]);
+        assert_eq!(
+            header(&response, "access-control-allow-methods"),
+            Some("GET, PUT")
+        );
+        assert_eq!(
+            header(&response, "access-control-allow-credentials"),
+            Some("true")
+        );
+        assert_eq!(header(&response, "vary"), Some("origin"));
+    }
+
+    #[test]
+    fn response_headers_extend_existing_vary() {
+        let config = CorsConfig {
+            exposed_headers: vec!["x-request-id".to_string()],
+            ..CorsConfig::with_origins(&["https://a.example"])
+        };
+        let headers = response_headers(
+            &config,
+            &[("Vary".to_string(), "Accept-Encoding".to_string())],
+        );
+        assert_eq!(
+            headers,
+            vec![
+                (
+                    "access-control-expose-headers".to_string(),
+                    "x-request-id".to_string()
+                ),
+                ("vary".to_string(), "Accept-Encoding, origin".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn response_headers_keep_vary_already_listing_origin() {
+        let config = CorsConfig::with_origins(&["https://a.example"]);
+        let headers = response_headers(&config, &[("vary".to_string(), "Origin".to_string())]);
+        assert_eq!(headers, vec![("vary".to_string(), "Origin".to_string())]);
+    }
+
+    #[test]
+    fn apply_allow_origin_sets_header_for_allowed_origin_only() {
+        let config = CorsConfig::with_origins(&["https://a.example"]);
+        let mut response = HttpResponse::ok(b"ok", vec![]).build();
+        apply_allow_origin(
+            &mut response,
+            &config,
+            &request(Method::GET, &[("origin", "https://evil.example")]),
+        );
+        assert_eq!(header(&response, ALLOW_ORIGIN_HEADER), None);
+
+        apply_allow_origin(
+            &mut response,
+            &config,
+            &request(Method::GET, &[("origin", "https://a.example")]),
+        );
+        assert_eq!(
+            header(&response, ALLOW_ORIGIN_HEADER),
+            Some("https://a.example")
+        );
+    }
+
+    #[test]
+    fn apply_allow_origin_ignores_requests_without_origin() {
+        let mut response = HttpResponse::ok(b"ok", vec![]).build();
+        apply_allow_origin(
+            &mut response,
+            &CorsConfig::any_origin(),
+            &request(Method::GET, &[]),
+        );
+        assert_eq!(header(&response, ALLOW_ORIGIN_HEADER), None);
+    }
+
+    #[test]
+    fn exclude_allow_origin_updates_response_only_and_full() {
+        let mut mode = CertificationMode::response_only();
+        exclude_allow_origin(&mut mode);
+        exclude_allow_origin(&mut mode);
+        let CertificationMode::ResponseOnly(config) = &mode else {
+            panic!("expected response-only mode");
+        };
+        assert_eq!(
+            config
+                .exclude_headers
+                .iter()
+                .filter(|h| *h == ALLOW_ORIGIN_HEADER)
+                .count(),
+            1
+        );
+
+        let mut mode = CertificationMode::authenticated();
+        exclude_allow_origin(&mut mode);
+        let CertificationMode::Full(config) = &mode else {
+            panic!("expected full mode");
+        };
+        assert!(config
+            .response
+            .exclude_headers
+            .contains(&ALLOW_ORIGIN_HEADER.to_string()));
+
+        let mut mode = CertificationMode::skip();
+        exclude_allow_origin(&mut mode);
+        assert!(matches!(mode, CertificationMode::Skip));
+    }
+}
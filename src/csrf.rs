@@ -0,0 +1,615 @@
+//! Cross-site request forgery protection for form routes.
+//!
+//! Every request with an unsafe method (anything but `GET`, `HEAD`,
+//! `OPTIONS` and `TRACE`) is upgraded to an update call, so a form posted
+//! from another site changes canister state like one posted from this site.
+//! [`middleware`] rejects such requests with `403 Forbidden` unless:
+//!
+//! - the `Origin` header, or the `Referer` header when there is no
+//!   `Origin`, names this host or one of [`CsrfConfig::trusted_origins`];
+//!   requests carrying neither pass this check, and
+//! - the request carries the token from the CSRF cookie, in the
+//!   [`CsrfConfig::header_name`] header or the [`CsrfConfig::field_name`]
+//!   field of a urlencoded or multipart form body (double-submit cookie).
+//!
+//! Cookies are HMAC-signed with [`CsrfConfig::secret`], so a token that did
+//! not come from this canister is refused. Other sites can neither read the
+//! cookie nor guess its value, so they cannot submit a matching token.
+//!
+//! The middleware attaches a [`CsrfToken`] to the request
+//! [extensions](crate::extensions); handlers embed it in forms with
+//! [`RouteContext::csrf_field`](crate::RouteContext::csrf_field):
+//!
+//! ```rust,ignore
+//! // src/routes/middleware.rs
+//! pub fn middleware(
+//!     req: HttpRequest,
+//!     params: &RouteParams,
+//!     next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+//! ) -> HttpResponse<'static> {
+//!     ic_asset_router::csrf::middleware(req, params, next)
+//! }
+//!
+//! // src/routes/comments.rs
+//! #[route(certification = "skip")]
+//! pub fn get(ctx: RouteContext<()>) -> HttpResponse<'static> {
+//!     let form = format!(
+//!         r#"<form method="post">{}<textarea name="body"></textarea></form>"#,
+//!         ctx.csrf_field().unwrap_or_default(),
+//!     );
+//!     // ...
+//! }
+//! ```
+//!
+//! A client without a CSRF cookie gets one with the first response that
+//! reads the token. Such responses belong to one client: the middleware
+//! marks them `Vary: Cookie`, so the router does not cache them outside
+//! full certification. Routes that embed the token should use
+//! `certification = "skip"` to be answered from the query call.
+//!
+//! Register a [`CsrfConfig`] as application state with
+//! [`SetupBuilder::with_state`](crate::SetupBuilder::with_state).
+
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::rc::Rc;
+
+use ic_http_certification::{HttpRequest, HttpResponse, Method, StatusCode};
+use sha2::{Digest, Sha256};
+
+use crate::context::parse_form_body;
+use crate::cookie::{self, Cookie, SameSite};
+use crate::extensions;
+use crate::middleware;
+use crate::multipart::{Multipart, DEFAULT_MAX_MULTIPART_SIZE};
+use crate::router::RouteParams;
+use crate::state::AppState;
+
+/// CSRF settings, registered as application state.
+#[derive(Clone)]
+pub struct CsrfConfig {
+    /// Key for signing tokens and deriving new ones. Use at least 32 random
+    /// bytes.
+    pub secret: Vec<u8>,
+    /// Name of the CSRF cookie. Defaults to `csrf_token`.
+    pub cookie_name: String,
+    /// Request header that carries the token. Defaults to `x-csrf-token`.
+    pub header_name: String,
+    /// Form field that carries the token. Defaults to `csrf_token`.
+    pub field_name: String,
+    /// Origins other than this host allowed to send unsafe requests, such as
+    /// `https://app.example.com`.
+    pub trusted_origins: Vec<String>,
+}
+
+impl CsrfConfig {
+    /// Default settings with the given signing secret.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            cookie_name: "csrf_token".to_string(),
+            header_name: "x-csrf-token".to_string(),
+            field_name: "csrf_token".to_string(),
+            trusted_origins: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for CsrfConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CsrfConfig")
+            .field("cookie_name", &self.cookie_name)
+            .field("header_name", &self.header_name)
+            .field("field_name", &self.field_name)
+            .field("trusted_origins", &self.trusted_origins)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The CSRF token of the request being handled.
+#[derive(Clone, Debug)]
+pub struct CsrfToken {
+    value: String,
+    field_name: String,
+    header_name: String,
+    /// Whether the client does not have the token in its cookie yet.
+    issued: bool,
+    /// Whether the response may contain the token.
+    read: Rc<Cell<bool>>,
+}
+
+impl CsrfToken {
+    /// A token with `value` and the default field and header names, for
+    /// hand-built contexts in tests.
+    pub fn new(value: &str) -> Self {
+        let config = CsrfConfig::new(Vec::new());
+        Self {
+            value: value.to_string(),
+            field_name: config.field_name,
+            header_name: config.header_name,
+            issued: false,
+            read: Rc::default(),
+        }
+    }
+
+    /// The token to submit with unsafe requests.
+    pub fn value(&self) -> &str {
+        self.read.set(true);
+        &self.value
+    }
+
+    /// A hidden `<input>` carrying the token, for forms.
+    pub fn hidden_field(&self) -> String {
+        format!(
+            r#"<input type="hidden" name="{}" value="{}">"#,
+            self.field_name,
+            self.value()
+        )
+    }
+
+    /// The form field that carries the token.
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+
+    /// The request header that carries the token, for `fetch` calls and
+    /// htmx `hx-headers`.
+    pub fn header_name(&self) -> &str {
+        &self.header_name
+    }
+}
+
+/// Error returned when an unsafe request fails the CSRF checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsrfError {
+    /// The `Origin` or `Referer` header names another site.
+    CrossOrigin,
+    /// The request carries no validly signed CSRF cookie.
+    MissingCookie,
+    /// The request carries no token in the header or form body.
+    MissingToken,
+    /// The submitted token does not match the cookie.
+    TokenMismatch,
+}
+
+impl std::fmt::Display for CsrfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CrossOrigin => write!(f, "cross-origin request"),
+            Self::MissingCookie => write!(f, "missing CSRF cookie"),
+            Self::MissingToken => write!(f, "missing CSRF token"),
+            Self::TokenMismatch => write!(f, "CSRF token does not match"),
+        }
+    }
+}
+
+impl std::error::Error for CsrfError {}
+
+/// Whether `method` is safe: it must not change state, so it needs no
+/// CSRF checks.
+pub fn is_safe_method(method: &Method) -> bool {
+    [Method::GET, Method::HEAD, Method::OPTIONS, Method::TRACE].contains(method)
+}
+
+/// Check the origin and the double-submitted token of an unsafe request.
+pub fn verify_request(req: &HttpRequest, config: &CsrfConfig) -> Result<(), CsrfError> {
+    if !origin_allowed(req, config) {
+        return Err(CsrfError::CrossOrigin);
+    }
+    let cookie = signed_cookie(req, config).ok_or(CsrfError::MissingCookie)?;
+    let submitted = submitted_token(req, config).ok_or(CsrfError::MissingToken)?;
+    if cookie::constant_time_eq(submitted.as_bytes(), cookie.as_bytes()) {
+        Ok(())
+    } else {
+        Err(CsrfError::TokenMismatch)
+    }
+}
+
+/// Middleware that rejects forged unsafe requests and hands the request's
+/// CSRF token to the handler.
+///
+/// # Panics
+///
+/// Panics if no [`CsrfConfig`] is registered as application state.
+pub fn middleware(
+    req: HttpRequest,
+    params: &RouteParams,
+    next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+) -> HttpResponse<'static> {
+    protect(req, params, next, ic_cdk::api::time())
+}
+
+fn protect(
+    req: HttpRequest,
+    params: &RouteParams,
+    next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    now: u64,
+) -> HttpResponse<'static> {
+    let state = AppState::current();
+    let config = state.expect::<CsrfConfig>();
+    // An outer CSRF middleware may have checked the request already.
+    if extensions::with(|current| current.contains::<CsrfToken>()) {
+        return next(req, params);
+    }
+    if !is_safe_method(req.method()) {
+        if let Err(e) = verify_request(&req, config) {
+            return forbidden(&e);
+        }
+    }
+    let cookie = signed_cookie(&req, config);
+    let token = CsrfToken {
+        issued: cookie.is_none(),
+        value: cookie.unwrap_or_else(|| {
+            let token = cookie::new_token(&config.secret, now, &request_digest(&req));
+            cookie::sign(&token, &config.secret)
+        }),
+        field_name: config.field_name.clone(),
+        header_name: config.header_name.clone(),
+        read: Rc::default(),
+    };
+    extensions::insert(token.clone());
+
+    let cookie_name = config.cookie_name.clone();
+    middleware::on_response(move |mut response| {
+        if !token.read.get() {
+            return response;
+        }
+        cookie::vary_by_cookie(&mut response);
+        if token.issued && !sets_cookie(&response, &cookie_name) {
+            let cookie = Cookie::new(&cookie_name, &token.value)
+                .with_path("/")
+                .with_http_only(false)
+                .with_same_site(SameSite::Lax);
+            response.add_header(cookie.into_header());
+        }
+        response
+    });
+    next(req, params)
+}
+
+/// SHA-256 of the request's method, URL and headers.
+///
+/// Tokens are usually minted in query calls, whose token counter never
+/// advances, so this keeps two clients asking at the same time from
+/// getting the same token.
+fn request_digest(req: &HttpRequest) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in [req.method().as_str(), req.url()] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part.as_bytes());
+    }
+    for (name, value) in req.headers() {
+        for part in [name, value] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part.as_bytes());
+        }
+    }
+    hasher.finalize().into()
+}
+
+/// The request's CSRF cookie, if it was signed with the configured secret.
+fn signed_cookie(req: &HttpRequest, config: &CsrfConfig) -> Option<String> {
+    cookie::find_cookie(req.headers(), &config.cookie_name)
+        .filter(|value| cookie::verify(value, &config.secret).is_some())
+}
+
+/// The token in the configured header, or else in the form body.
+fn submitted_token(req: &HttpRequest, config: &CsrfConfig) -> Option<String> {
+    if let Some(token) = header(req, &config.header_name) {
+        return Some(token.trim().to_string());
+    }
+    let content_type = header(req, "content-type")?;
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    if mime.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+        parse_form_body(req.body()).remove(&config.field_name)
+    } else if mime.eq_ignore_ascii_case("multipart/form-data") {
+        Multipart::parse(content_type, req.body(), DEFAULT_MAX_MULTIPART_SIZE)
+            .ok()?
+            .field(&config.field_name)
+            .map(str::to_string)
+    } else {
+        None
+    }
+}
+
+/// Whether the request's `Origin`, or else `Referer`, names this host or a
+/// trusted origin. Requests with neither header are allowed.
+fn origin_allowed(req: &HttpRequest, config: &CsrfConfig) -> bool {
+    let origin = match (header(req, "origin"), header(req, "referer")) {
+        (Some(origin), _) => origin.trim().to_string(),
+        (None, Some(referer)) => match referer_origin(referer) {
+            Some(origin) => origin,
+            None => return false,
+        },
+        (None, None) => return true,
+    };
+    if config
+        .trusted_origins
+        .iter()
+        .any(|trusted| trusted.eq_ignore_ascii_case(&origin))
+    {
+        return true;
+    }
+    let Some((_, authority)) = origin.split_once("://") else {
+        return false;
+    };
+    header(req, "host").is_some_and(|host| host.trim().eq_ignore_ascii_case(authority))
+}
+
+/// The `scheme://authority` part of a `Referer` URL.
+fn referer_origin(referer: &str) -> Option<String> {
+    let (scheme, rest) = referer.trim().split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    (!authority.is_empty()).then(|| format!("{scheme}://{authority}"))
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers()
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+fn sets_cookie(response: &HttpResponse, cookie_name: &str) -> bool {
+    let prefix = format!("{cookie_name}=");
+    response
+        .headers()
+        .iter()
+        .any(|(name, value)| name.eq_ignore_ascii_case("set-cookie") && value.starts_with(&prefix))
+}
+
+fn forbidden(error: &CsrfError) -> HttpResponse<'static> {
+    HttpResponse::builder()
+        .with_status_code(StatusCode::FORBIDDEN)
+        .with_headers(vec![("content-type".to_string(), "text/plain".to_string())])
+        .with_body(Cow::<[u8]>::Owned(
+            format!("Forbidden: {error}").into_bytes(),
+        ))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+    const HOST: &str = "aaaaa-aa.icp0.io";
+
+    fn config() -> CsrfConfig {
+        CsrfConfig::new(SECRET.to_vec())
+    }
+
+    fn request(method: Method, headers: &[(&str, &str)], body: &str) -> HttpRequest<'static> {
+        let mut all = vec![("host".to_string(), HOST.to_string())];
+        all.extend(headers.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        HttpRequest::builder()
+            .with_method(method)
+            .with_url("/comments")
+            .with_headers(all)
+            .with_body(body.as_bytes().to_vec())
+            .build()
+    }
+
+    fn token() -> String {
+        cookie::sign("abc", SECRET)
+    }
+
+    fn cookie_header() -> String {
+        format!("csrf_token={}", token())
+    }
+
+    fn header_value<'a>(response: &'a HttpResponse, name: &str) -> Option<&'a str> {
+        response
+            .headers()
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Run [`protect`] in a fresh dispatch scope; the handler reads the token
+    /// when `read` is set.
+    fn dispatch(req: HttpRequest, read: bool) -> HttpResponse<'static> {
+        AppState::set_current(AppState::new().with(config()));
+        let next = move |_req: HttpRequest, _params: &RouteParams| {
+            let token = extensions::with(|current| current.get::<CsrfToken>().cloned())
+                .expect("token attached");
+            let body = if read {
+                token.hidden_field()
+            } else {
+                String::new()
+            };
+            middleware::handle(|| HttpResponse::ok(body.into_bytes(), vec![]).build())
+        };
+        extensions::scope(|| middleware::scope(|| protect(req, &RouteParams::new(), &next, 1)))
+    }
+
+    #[test]
+    fn safe_requests_pass_and_issue_a_cookie_when_the_token_is_read() {
+        let response = dispatch(request(Method::GET, &[], ""), true);
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(header_value(&response, "vary"), Some("cookie"));
+        let set_cookie = header_value(&response, "set-cookie").expect("set-cookie");
+        assert!(set_cookie.ends_with("; Path=/; Secure; SameSite=Lax"));
+
+        let value = set_cookie
+            .split(';')
+            .next()
+            .unwrap()
+            .strip_prefix("csrf_token=")
+            .unwrap();
+        assert!(cookie::verify(value, SECRET).is_some());
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert_eq!(
+            body,
+            format!(r#"<input type="hidden" name="csrf_token" value="{value}">"#)
+        );
+    }
+
+    #[test]
+    fn query_mode_tokens_minted_at_the_same_time_differ() {
+        // Query calls discard state changes, so each one starts from the
+        // same token counter.
+        let mint = |user_agent: &str| {
+            cookie::NEXT_TOKEN.set(0);
+            let response = dispatch(
+                request(Method::GET, &[("user-agent", user_agent)], ""),
+                true,
+            );
+            header_value(&response, "set-cookie")
+                .expect("set-cookie")
+                .to_string()
+        };
+        assert_ne!(mint("client-a"), mint("client-b"));
+    }
+
+    #[test]
+    fn unread_tokens_leave_the_response_cacheable() {
+        let response = dispatch(request(Method::GET, &[], ""), false);
+        assert_eq!(header_value(&response, "vary"), None);
+        assert_eq!(header_value(&response, "set-cookie"), None);
+    }
+
+    #[test]
+    fn existing_cookie_is_reused_without_set_cookie() {
+        let cookie = cookie_header();
+        let response = dispatch(request(Method::GET, &[("cookie", &cookie)], ""), true);
+        assert_eq!(header_value(&response, "set-cookie"), None);
+        assert_eq!(header_value(&response, "vary"), Some("cookie"));
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert!(body.contains(&token()));
+    }
+
+    #[test]
+    fn unsafe_request_with_matching_header_or_form_token_passes() {
+        let cookie = cookie_header();
+        let token = token();
+        let by_header = request(
+            Method::POST,
+            &[("cookie", &cookie), ("x-csrf-token", &token)],
+            "",
+        );
+        assert_eq!(dispatch(by_header, false).status_code(), StatusCode::OK);
+
+        let body = format!("body=hi&csrf_token={token}");
+        let by_form = request(
+            Method::POST,
+            &[
+                ("cookie", &cookie),
+                ("content-type", "application/x-www-form-urlencoded"),
+                ("origin", &format!("https://{HOST}")),
+            ],
+            &body,
+        );
+        assert_eq!(dispatch(by_form, false).status_code(), StatusCode::OK);
+    }
+
+    #[test]
+    fn multipart_form_token_is_accepted() {
+        let cookie = cookie_header();
+        let body = format!(
+            "--b\r\nContent-Disposition: form-data; name=\"csrf_token\"\r\n\r\n{}\r\n--b--\r\n",
+            token()
+        );
+        let req = request(
+            Method::POST,
+            &[
+                ("cookie", &cookie),
+                ("content-type", "multipart/form-data; boundary=b"),
+            ],
+            &body,
+        );
+        assert_eq!(verify_request(&req, &config()), Ok(()));
+    }
+
+    #[test]
+    fn unsafe_request_without_valid_token_is_forbidden() {
+        let cookie = cookie_header();
+        let forged_cookie = format!("csrf_token={}", cookie::sign("abc", b"other"));
+        let cases = [
+            (vec![("x-csrf-token", token())], CsrfError::MissingCookie),
+            (
+                vec![
+                    ("cookie", forged_cookie.clone()),
+                    ("x-csrf-token", cookie::sign("abc", b"other")),
+                ],
+                CsrfError::MissingCookie,
+            ),
+            (vec![("cookie", cookie.clone())], CsrfError::MissingToken),
+            (
+                vec![
+                    ("cookie", cookie.clone()),
+                    ("x-csrf-token", cookie::sign("abd", SECRET)),
+                ],
+                CsrfError::TokenMismatch,
+            ),
+        ];
+        for (headers, expected) in cases {
+            let headers: Vec<(&str, &str)> =
+                headers.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            let req = request(Method::POST, &headers, "");
+            assert_eq!(verify_request(&req, &config()), Err(expected));
+        }
+
+        let response = dispatch(request(Method::DELETE, &[("cookie", &cookie)], ""), false);
+        assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(response.body(), b"Forbidden: missing CSRF token");
+    }
+
+    #[test]
+    fn cross_origin_requests_are_forbidden() {
+        let cookie = cookie_header();
+        let token = token();
+        let with = |name: &str, value: &str| {
+            let req = request(
+                Method::POST,
+                &[("cookie", &cookie), ("x-csrf-token", &token), (name, value)],
+                "",
+            );
+            verify_request(&req, &config())
+        };
+        assert_eq!(
+            with("origin", "https://evil.example"),
+            Err(CsrfError::CrossOrigin)
+        );
+        assert_eq!(with("origin", "null"), Err(CsrfError::CrossOrigin));
+        assert_eq!(
+            with("referer", "https://evil.example/aaaaa-aa.icp0.io"),
+            Err(CsrfError::CrossOrigin)
+        );
+        assert_eq!(
+            with("referer", &format!("https://{HOST}/posts/1?x")),
+            Ok(())
+        );
+
+        let mut trusting = config();
+        trusting.trusted_origins = vec!["https://app.example".to_string()];
+        let req = request(
+            Method::POST,
+            &[
+                ("cookie", &cookie),
+                ("x-csrf-token", &token),
+                ("origin", "https://app.example"),
+            ],
+            "",
+        );
+        assert_eq!(verify_request(&req, &trusting), Ok(()));
+    }
+
+    #[test]
+    fn referer_origin_keeps_scheme_and_authority() {
+        assert_eq!(
+            referer_origin("https://a.b:8080/x?y#z").as_deref(),
+            Some("https://a.b:8080")
+        );
+        assert_eq!(referer_origin("/relative"), None);
+        assert_eq!(referer_origin("https://"), None);
+    }
+
+    #[test]
+    fn safe_methods() {
+        assert!(is_safe_method(&Method::GET));
+        assert!(is_safe_method(&Method::HEAD));
+        assert!(!is_safe_method(&Method::POST));
+        assert!(!is_safe_method(&Method::PATCH));
+    }
+}
@@ -0,0 +1,217 @@
+//! Typed per-request values passed from middleware to handlers.
+//!
+//! Middleware attaches values with [`insert`] before calling `next`;
+//! the handler reads them through
+//! [`RouteContext::extensions`](crate::RouteContext::extensions). Values are
+//! looked up by type, at most one per type:
+//!
+//! ```rust,ignore
+//! pub struct User { pub id: String }
+//!
+//! pub fn middleware(
+//!     req: HttpRequest,
+//!     params: &RouteParams,
+//!     next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+//! ) -> HttpResponse<'static> {
+//!     let Some(user) = authenticate(&req) else { return unauthorized() };
+//!     ic_asset_router::extensions::insert(user);
+//!     next(req, params)
+//! }
+//!
+//! pub fn get(ctx: RouteContext<()>) -> HttpResponse<'static> {
+//!     let user = ctx.extensions().get::<User>().unwrap();
+//!     // ...
+//! }
+//! ```
+//!
+//! The values belong to the request being dispatched: the router starts
+//! every middleware chain with no extensions and discards anything left
+//! over when it ends. Middleware further in can read what outer middleware
+//! attached with [`with`].
+
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+thread_local! {
+    static CURRENT: RefCell<Extensions> = RefCell::new(Extensions::new());
+}
+
+/// A set of values, at most one per type.
+///
+/// Cloning is cheap: clones share the same values. While a value is shared,
+/// [`get_mut`](Self::get_mut) returns `None`, and [`insert`](Self::insert)
+/// and [`remove`](Self::remove) do not hand it back.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    values: HashMap<TypeId, Rc<dyn Any>>,
+}
+
+impl Extensions {
+    /// An empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `value`, returning the previous value of the same type.
+    pub fn insert<T: 'static>(&mut self, value: T) -> Option<T> {
+        unwrap(self.values.insert(TypeId::of::<T>(), Rc::new(value))?)
+    }
+
+    /// The value of type `T`, if one was added.
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    /// A mutable reference to the value of type `T`, if one was added.
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        Rc::get_mut(self.values.get_mut(&TypeId::of::<T>())?)?.downcast_mut()
+    }
+
+    /// Remove and return the value of type `T`.
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        unwrap(self.values.remove(&TypeId::of::<T>())?)
+    }
+
+    /// Whether a value of type `T` was added.
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// The number of values.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether the set holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
+
+/// The value behind `value`, unless it is shared.
+fn unwrap<T: 'static>(value: Rc<dyn Any>) -> Option<T> {
+    Rc::try_unwrap(value.downcast::<T>().ok()?).ok()
+}
+
+/// Attach `value` to the request being dispatched, returning the value of
+/// the same type attached earlier.
+///
+/// # Panics
+///
+/// Panics if called from inside the closure passed to [`with`].
+pub fn insert<T: 'static>(value: T) -> Option<T> {
+    CURRENT.with_borrow_mut(|current| current.insert(value))
+}
+
+/// Read the extensions attached to the request being dispatched so far.
+pub fn with<R>(f: impl FnOnce(&Extensions) -> R) -> R {
+    CURRENT.with_borrow(f)
+}
+
+/// Take the extensions attached to the request being dispatched, leaving
+/// none behind.
+///
+/// The generated handler wiring calls this to fill
+/// [`RouteContext::extensions`](crate::RouteContext::extensions).
+pub fn take() -> Extensions {
+    CURRENT.with_borrow_mut(std::mem::take)
+}
+
+/// Replace the extensions of the request being dispatched.
+pub(crate) fn restore(extensions: Extensions) {
+    CURRENT.with_borrow_mut(|current| *current = extensions);
+}
+
+/// Run `f` as the dispatch of a new request: it starts with no extensions,
+/// and any left over when it returns are dropped.
+pub(crate) fn scope<R>(f: impl FnOnce() -> R) -> R {
+    scope_with(Extensions::new(), f)
+}
+
+/// Like [`scope`], but starting with `extensions`.
+pub(crate) fn scope_with<R>(extensions: Extensions, f: impl FnOnce() -> R) -> R {
+    restore(extensions);
+    let result = f();
+    restore(Extensions::new());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct User(&'static str);
+
+    #[test]
+    fn values_are_looked_up_by_type() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.is_empty());
+        assert_eq!(extensions.insert(User("alice")), None);
+        extensions.insert(7u32);
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(extensions.get::<User>(), Some(&User("alice")));
+        assert!(extensions.contains::<u32>());
+        assert!(extensions.get::<u64>().is_none());
+    }
+
+    #[test]
+    fn insert_replaces_and_returns_previous_value() {
+        let mut extensions = Extensions::new();
+        extensions.insert(User("alice"));
+        assert_eq!(extensions.insert(User("bob")), Some(User("alice")));
+        assert_eq!(extensions.get::<User>(), Some(&User("bob")));
+    }
+
+    #[test]
+    fn get_mut_and_remove() {
+        let mut extensions = Extensions::new();
+        extensions.insert(1u32);
+        *extensions.get_mut::<u32>().unwrap() += 1;
+        assert_eq!(extensions.remove::<u32>(), Some(2));
+        assert_eq!(extensions.remove::<u32>(), None);
+        assert!(extensions.is_empty());
+    }
+
+    #[test]
+    fn clones_share_values() {
+        let mut extensions = Extensions::new();
+        extensions.insert(1u32);
+        let mut clone = extensions.clone();
+        assert_eq!(clone.get::<u32>(), Some(&1));
+        assert!(clone.get_mut::<u32>().is_none());
+        assert_eq!(clone.remove::<u32>(), None);
+        assert!(!clone.contains::<u32>());
+        assert_eq!(extensions.remove::<u32>(), Some(1));
+    }
+
+    #[test]
+    fn current_request_values_are_taken_once() {
+        scope(|| {
+            insert(User("alice"));
+            assert!(with(|current| current.contains::<User>()));
+            let taken = take();
+            assert_eq!(taken.get::<User>(), Some(&User("alice")));
+            assert!(with(Extensions::is_empty));
+        });
+    }
+
+    #[test]
+    fn scope_drops_leftover_values() {
+        scope(|| {
+            insert(User("alice"));
+        });
+        assert!(with(Extensions::is_empty));
+    }
+}
@@ -0,0 +1,154 @@
+//! Internet Computer metadata for the request being handled.
+//!
+//! [`http_request`](crate::http_request) runs handlers in
+//! [`ExecutionMode::Query`], where state changes are discarded and responses
+//! must come from the certified cache or skip certification.
+//! [`http_request_update`](crate::http_request_update) and
+//! [`http_request_update_async`](crate::http_request_update_async) run them
+//! in [`ExecutionMode::Update`]. Handlers read the mode and the rest of the
+//! metadata from [`RouteContext::ic`](crate::RouteContext::ic):
+//!
+//! ```rust,ignore
+//! pub fn post(ctx: RouteContext<()>) -> HttpResponse<'static> {
+//!     if ctx.ic.is_query() {
+//!         return conflict("writes need an update call");
+//!     }
+//!     let caller = ctx.ic.caller();
+//!     // ...
+//! }
+//! ```
+
+use std::cell::Cell;
+
+use candid::Principal;
+
+thread_local! {
+    static MODE: Cell<ExecutionMode> = const { Cell::new(ExecutionMode::Query) };
+}
+
+/// Whether the router is handling a query or an update call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExecutionMode {
+    /// `http_request`: state changes are discarded after the call.
+    Query,
+    /// `http_request_update`: state changes are committed.
+    Update,
+}
+
+/// IC metadata for the request being handled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IcContext {
+    mode: ExecutionMode,
+    time: u64,
+    canister_id: Principal,
+    caller: Option<Principal>,
+}
+
+impl IcContext {
+    /// Metadata with the given values, for hand-built contexts in tests.
+    pub fn new(
+        mode: ExecutionMode,
+        time: u64,
+        canister_id: Principal,
+        caller: Option<Principal>,
+    ) -> Self {
+        Self {
+            mode,
+            time,
+            canister_id,
+            caller,
+        }
+    }
+
+    /// Metadata for the call being executed, read from the IC system API.
+    ///
+    /// The generated handler wiring calls this to fill
+    /// [`RouteContext::ic`](crate::RouteContext::ic). Middleware can call it
+    /// directly. Must be called from inside a canister call.
+    pub fn current() -> Self {
+        let mode = current_mode();
+        Self {
+            mode,
+            time: ic_cdk::api::time(),
+            canister_id: ic_cdk::api::canister_self(),
+            caller: match mode {
+                ExecutionMode::Query => None,
+                ExecutionMode::Update => Some(ic_cdk::api::msg_caller()),
+            },
+        }
+    }
+
+    /// Whether the request is handled in a query or an update call.
+    pub fn mode(&self) -> ExecutionMode {
+        self.mode
+    }
+
+    /// Whether the request is handled in a query call, where state changes
+    /// are discarded.
+    pub fn is_query(&self) -> bool {
+        self.mode == ExecutionMode::Query
+    }
+
+    /// Whether the request is handled in an update call.
+    pub fn is_update(&self) -> bool {
+        self.mode == ExecutionMode::Update
+    }
+
+    /// IC time in nanoseconds since the Unix epoch at which the request is
+    /// handled.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// The id of the canister handling the request.
+    pub fn canister_id(&self) -> Principal {
+        self.canister_id
+    }
+
+    /// The principal that made the update call; `None` in query mode.
+    ///
+    /// Calls relayed by the HTTP gateway come from the anonymous principal.
+    pub fn caller(&self) -> Option<Principal> {
+        self.caller
+    }
+}
+
+/// The mode reported by [`IcContext::current`].
+pub(crate) fn current_mode() -> ExecutionMode {
+    MODE.get()
+}
+
+/// Set the mode reported by [`IcContext::current`].
+pub(crate) fn set_mode(mode: ExecutionMode) {
+    MODE.set(mode);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessors_report_constructed_values() {
+        let caller = Principal::anonymous();
+        let ic = IcContext::new(
+            ExecutionMode::Update,
+            42,
+            Principal::management_canister(),
+            Some(caller),
+        );
+        assert_eq!(ic.mode(), ExecutionMode::Update);
+        assert!(ic.is_update());
+        assert!(!ic.is_query());
+        assert_eq!(ic.time(), 42);
+        assert_eq!(ic.canister_id(), Principal::management_canister());
+        assert_eq!(ic.caller(), Some(caller));
+    }
+
+    #[test]
+    fn set_mode_updates_current_mode() {
+        set_mode(ExecutionMode::Update);
+        assert_eq!(current_mode(), ExecutionMode::Update);
+        set_mode(ExecutionMode::Query);
+        assert_eq!(current_mode(), ExecutionMode::Query);
+    }
+}
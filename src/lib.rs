@@ -198,6 +198,10 @@
 //!   transform headers, body, or status before returning.
 //! - **Short-circuit** — return a response without calling `next` at all
 //!   (e.g. return 401 for unauthorized requests). The handler never executes.
+//! - **Hook the handler's response** — register a closure with
+//!   [`middleware::on_response`] before calling `next`. Around async
+//!   handlers this is the only way to change the response: the chain runs
+//!   before the handler is awaited, so `next` returns a placeholder.
 //!
 //! ## Composition Order
 //!
@@ -252,6 +256,25 @@
 //! See the [`json-api`](https://github.com/kristoferlund/ic-asset-router/tree/main/examples/json-api)
 //! example for a working CORS middleware.
 //!
+//! ## Middleware and Async Routes
+//!
+//! Middleware is synchronous, so around an `async` handler the chain runs
+//! before the handler is awaited and `next` returns an empty `200`
+//! placeholder. Changes made to it after `next`, like the CORS headers
+//! above, are discarded, and a middleware checking the status sees `200`.
+//! Register the change with [`middleware::on_response`] instead:
+//!
+//! ```rust,ignore
+//! ic_asset_router::middleware::on_response(|mut response| {
+//!     response.add_header(("access-control-allow-origin".into(), "*".into()));
+//!     response
+//! });
+//! next(req, params)
+//! ```
+//!
+//! The build script fails with an error pointing at `on_response` when a
+//! `middleware.rs` that uses the result of `next` covers an async route.
+//!
 //! # Certification Modes
 //!
 //! Every response served by an IC canister can be cryptographically certified
@@ -433,15 +456,17 @@ macro_rules! debug_log {
     ($($arg:tt)*) => {};
 }
 
-use std::{borrow::Cow, cell::RefCell, rc::Rc};
+use std::{borrow::Cow, cell::RefCell, rc::Rc, thread::LocalKey};
 
 use assets::get_asset_headers;
 use ic_cdk::api::{certified_data_set, data_certificate};
 use ic_http_certification::{
-    utils::add_v2_certificate_header, DefaultCelBuilder, HttpCertification, HttpCertificationPath,
-    HttpCertificationTree, HttpCertificationTreeEntry, CERTIFICATE_EXPRESSION_HEADER_NAME,
+    utils::add_v2_certificate_header, DefaultCelBuilder, HeaderField, HttpCertification,
+    HttpCertificationPath, HttpCertificationTree, HttpCertificationTreeEntry,
+    CERTIFICATE_EXPRESSION_HEADER_NAME,
 };
 use router::{RouteNode, RouteResult};
+use streaming::{StreamingCallbackHttpResponse, StreamingCallbackToken, StreamingHttpResponse};
 
 /// Canonical path used to cache the single certified 404 response.
 ///
@@ -515,49 +540,466 @@ fn is_asset_expired(asset: &asset_router::CertifiedAsset, path: &str, now_ns: u6
     }
 }
 
+/// Merge a route's [`RouteConfig::headers`] into a handler response.
+///
+/// Route headers are layered on top of the headers the handler set, using
+/// the same case-insensitive last-write-wins rule as
+/// [`AssetConfig::merged_headers`].
+fn apply_route_headers(
+    mut response: HttpResponse<'static>,
+    route_config: Option<&RouteConfig>,
+) -> HttpResponse<'static> {
+    if let Some(rc) = route_config {
+        config::layer_headers(response.headers_mut(), &rc.headers);
+    }
+    response
+}
+
+/// Whether `response` belongs to one client: it sets a cookie or varies
+/// with the request's cookies.
+fn is_per_client(response: &HttpResponse) -> bool {
+    response.headers().iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("set-cookie")
+            || (name.eq_ignore_ascii_case("vary")
+                && value
+                    .split(',')
+                    .any(|v| matches!(v.trim(), "*") || v.trim().eq_ignore_ascii_case("cookie")))
+    })
+}
+
+/// Whether `response` forbids storing it (`Cache-Control: no-store`), as
+/// the `400`s for malformed typed params and strict search params do.
+fn is_no_store(response: &HttpResponse) -> bool {
+    response.headers().iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("cache-control")
+            && value
+                .split(',')
+                .any(|v| v.trim().eq_ignore_ascii_case("no-store"))
+    })
+}
+
+/// Drop the body of a response to a `HEAD` request, keeping status and
+/// headers.
+fn without_body_for_head(response: HttpResponse<'static>, is_head: bool) -> HttpResponse<'static> {
+    if !is_head || response.body().is_empty() {
+        return response;
+    }
+    HttpResponse::builder()
+        .with_status_code(response.status_code())
+        .with_headers(response.headers().to_vec())
+        .build()
+}
+
+/// Resolve the CORS policy for a route matched at `route_path`: the route's
+/// own [`RouteConfig::cors`], then the longest matching prefix in
+/// [`AssetConfig::cors_prefixes`], then [`AssetConfig::cors`].
+fn effective_cors(route_path: &str, route_config: Option<&RouteConfig>) -> Option<CorsConfig> {
+    if let Some(cors) = route_config.and_then(|rc| rc.cors.clone()) {
+        return Some(cors);
+    }
+    ROUTER_CONFIG.with(|c| {
+        let config = c.borrow();
+        config
+            .cors_prefixes
+            .iter()
+            .filter(|(prefix, _)| {
+                let prefix = prefix.trim_end_matches('/');
+                prefix.is_empty() || router::path_matches_prefix(route_path, prefix)
+            })
+            .max_by_key(|(prefix, _)| prefix.trim_end_matches('/').len())
+            .map(|(_, cors)| cors.clone())
+            .or_else(|| config.cors.clone())
+    })
+}
+
+/// The `413` or `415` response for a request body the matched route
+/// refuses: larger than [`RouteConfig::max_body`] (falling back to
+/// [`AssetConfig::max_body`]), or not one of [`RouteConfig::accepts`].
+fn body_rejection(
+    req: &HttpRequest,
+    route_config: Option<&RouteConfig>,
+) -> Option<HttpResponse<'static>> {
+    let max_body = route_config
+        .and_then(|rc| rc.max_body)
+        .or_else(|| ROUTER_CONFIG.with(|c| c.borrow().max_body));
+    if max_body.is_some_and(|max| req.body().len() > max) {
+        return Some(error_response(413, "Payload Too Large"));
+    }
+    let content_type = req
+        .headers()
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-type"))
+        .map(|(_, v)| v.as_str());
+    match route_config {
+        Some(rc) if !req.body().is_empty() && !rc.accepts_content_type(content_type) => {
+            Some(error_response(415, "Unsupported Media Type"))
+        }
+        _ => None,
+    }
+}
+
+/// [`body_rejection`] for the route `req` resolves to at `route_path`.
+fn route_body_rejection(
+    req: &HttpRequest,
+    route_path: &str,
+    root_route_node: &RouteNode,
+) -> Option<HttpResponse<'static>> {
+    match root_route_node.resolve(route_path, req.method()) {
+        RouteResult::Found(_, _, _, pattern) => {
+            body_rejection(req, root_route_node.get_route_config(&pattern))
+        }
+        _ => None,
+    }
+}
+
+/// Add `Access-Control-Allow-Origin` to a response about to be returned.
+///
+/// Upgrade responses are left untouched: the update call produces the
+/// final response.
+fn with_allow_origin(
+    mut response: HttpResponse<'static>,
+    req: &HttpRequest,
+    cors: Option<&CorsConfig>,
+) -> HttpResponse<'static> {
+    if let Some(cors) = cors {
+        if response.upgrade() != Some(true) {
+            cors::apply_allow_origin(&mut response, cors, req);
+        }
+    }
+    response
+}
+
+/// Add every CORS header to an uncertified response.
+fn apply_cors(
+    mut response: HttpResponse<'static>,
+    req: &HttpRequest,
+    cors: Option<&CorsConfig>,
+) -> HttpResponse<'static> {
+    if let Some(cors) = cors {
+        let cors_headers = cors::response_headers(cors, response.headers());
+        config::layer_headers(response.headers_mut(), &cors_headers);
+    }
+    with_allow_origin(response, req, cors)
+}
+
+/// A CORS preflight the library answers on behalf of a route.
+struct Preflight<'a> {
+    cors: CorsConfig,
+    methods: Vec<Method>,
+    route_config: Option<&'a RouteConfig>,
+}
+
+/// Match a CORS preflight request against the route tree.
+///
+/// Returns `None` unless `req` is a preflight for an existing route with a
+/// CORS policy. Routes that export their own `options` handler answer
+/// preflights themselves.
+fn match_preflight<'a>(
+    req: &HttpRequest,
+    path: &str,
+    root: &'a RouteNode,
+) -> Option<Preflight<'a>> {
+    if !cors::is_preflight(req) {
+        return None;
+    }
+    let (handlers, _, _, pattern) = root.match_path(path)?;
+    if handlers.contains_key(&Method::OPTIONS) {
+        return None;
+    }
+    let route_config = root.get_route_config(&pattern);
+    Some(Preflight {
+        cors: effective_cors(path, route_config)?,
+        methods: handlers.keys().cloned().collect(),
+        route_config,
+    })
+}
+
+/// Returns `true` if the route's preflights are served without
+/// certification.
+fn preflight_skips_certification(preflight: &Preflight, certify: bool) -> bool {
+    !certify
+        || matches!(
+            preflight.route_config.map(|rc| &rc.certification),
+            Some(certification::CertificationMode::Skip)
+        )
+}
+
+/// Answer a CORS preflight on the query path.
+///
+/// Skip-mode routes get a fresh response with a skip proof. Otherwise the
+/// certified preflight for `path` is served, or the request is upgraded so
+/// that [`certify_preflight`] can create it.
+fn serve_preflight_query(
+    req: &HttpRequest,
+    path: &str,
+    preflight: Preflight,
+    certify: bool,
+) -> HttpResponse<'static> {
+    if preflight_skips_certification(&preflight, certify) {
+        let mut response = cors::preflight_response(&preflight.cors, &preflight.methods);
+        if let Err(err_resp) = attach_skip_certification(path, &mut response) {
+            return err_resp;
+        }
+        return with_allow_origin(response, req, Some(&preflight.cors));
+    }
+
+    let response = PREFLIGHT_ROUTER.with_borrow(|preflight_router| {
+        let cert = data_certificate()?;
+        let (mut response, witness, expr_path) = preflight_router.serve_asset(req)?;
+        add_v2_certificate_header(&cert, &mut response, &witness, &expr_path);
+        Some(response)
+    });
+    match response {
+        Some(response) => {
+            debug_log!("serving certified preflight for {}", path);
+            with_allow_origin(response, req, Some(&preflight.cors))
+        }
+        None => {
+            debug_log!("upgrading (no certified preflight: {})", path);
+            HttpResponse::builder().with_upgrade(true).build()
+        }
+    }
+}
+
+/// Answer a CORS preflight on the update path, certifying the response so
+/// later preflights for `path` are served from the query path.
+///
+/// `Access-Control-Allow-Origin` is excluded from the certified headers and
+/// added per request.
+fn certify_preflight(req: &HttpRequest, path: &str, preflight: Preflight) -> HttpResponse<'static> {
+    let response = cors::preflight_response(&preflight.cors, &preflight.methods);
+    if preflight_skips_certification(&preflight, true) {
+        return with_allow_origin(response, req, Some(&preflight.cors));
+    }
+
+    let mut mode = certification::CertificationMode::response_only();
+    cors::exclude_allow_origin(&mut mode);
+    let config = asset_router::AssetCertificationConfig {
+        mode,
+        content_type: Some("text/plain".to_string()),
+        status_code: response.status_code(),
+        headers: response.headers().to_vec(),
+        certified_at: ic_cdk::api::time(),
+        ..Default::default()
+    };
+
+    PREFLIGHT_ROUTER.with_borrow_mut(|preflight_router| {
+        preflight_router.delete_asset(path);
+        if let Err(_err) = preflight_router.certify_asset(path, Vec::new(), config) {
+            debug_log!(
+                "certify_preflight: failed to certify preflight for path '{}': {}",
+                path,
+                _err
+            );
+            return;
+        }
+        certified_data_set(preflight_router.root_hash());
+    });
+
+    with_allow_origin(response, req, Some(&preflight.cors))
+}
+
+/// Check `req` against [`AssetConfig::path_normalization`].
+fn non_canonical(req: &HttpRequest) -> Option<canonical::NonCanonical> {
+    ROUTER_CONFIG.with(|c| canonical::check(req.url(), &c.borrow().path_normalization))
+}
+
+/// Redirects are cached per path while `Location` carries the query string,
+/// so only query-less `GET`/`HEAD` redirects are certified. Others are
+/// answered from the update call.
+fn redirect_is_cacheable(req: &HttpRequest) -> bool {
+    (req.method() == Method::GET || req.method() == Method::HEAD)
+        && !req
+            .url()
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .contains('?')
+}
+
+/// Answer a non-canonical path on the query path: a cached certified
+/// redirect, the not-found response, or an upgrade.
+fn serve_non_canonical_query(
+    req: HttpRequest,
+    path: &str,
+    non_canonical: canonical::NonCanonical,
+    root: &RouteNode,
+    certify: bool,
+) -> HttpResponse<'static> {
+    match non_canonical {
+        canonical::NonCanonical::NotFound => handle_not_found_query(req, path, root, certify),
+        canonical::NonCanonical::Redirect { status, location } => {
+            serve_redirect_query(&req, path, status, &location, certify)
+        }
+    }
+}
+
+/// Answer a non-canonical path on the update path, certifying cacheable
+/// redirects at the requested (non-canonical) path.
+fn serve_non_canonical_update(
+    req: HttpRequest,
+    path: &str,
+    non_canonical: canonical::NonCanonical,
+    root: &RouteNode,
+) -> HttpResponse<'static> {
+    match non_canonical {
+        canonical::NonCanonical::NotFound => handle_not_found_update(req, path, root),
+        canonical::NonCanonical::Redirect { status, location } => {
+            certify_redirect(&req, path, status, &location)
+        }
+    }
+}
+
+/// Match `req` against the registered [`Redirect`]s.
+fn match_redirect(req: &HttpRequest) -> Option<(StatusCode, String)> {
+    REDIRECT_RULES.with_borrow(|rules| rules.redirect_for(req.url()))
+}
+
+/// The path a request for `path` is routed as: the destination of the
+/// matching [`Rewrite`], or `path` itself.
+fn routing_path(path: &str) -> String {
+    REDIRECT_RULES
+        .with_borrow(|rules| rules.rewrite(path))
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Answer a redirect on the query path: the cached certified redirect for
+/// `path`, or an upgrade so that [`certify_redirect`] can create it.
+fn serve_redirect_query(
+    req: &HttpRequest,
+    path: &str,
+    status: StatusCode,
+    location: &str,
+    certify: bool,
+) -> HttpResponse<'static> {
+    if !certify {
+        canonical::redirect_response(status, location)
+    } else if redirect_is_cacheable(req) {
+        serve_from_cache_or_upgrade(req, path)
+    } else {
+        HttpResponse::builder().with_upgrade(true).build()
+    }
+}
+
+/// Answer a redirect on the update path, certifying cacheable redirects at
+/// the requested path.
+fn certify_redirect(
+    req: &HttpRequest,
+    path: &str,
+    status: StatusCode,
+    location: &str,
+) -> HttpResponse<'static> {
+    let response = canonical::redirect_response(status, location);
+    if !redirect_is_cacheable(req) {
+        return response;
+    }
+    certify_dynamic_response_with_ttl(
+        response,
+        path,
+        None,
+        certification::CertificationMode::response_only(),
+        Some(req),
+        None,
+        None,
+    )
+}
+
 /// Custom asset router with per-asset certification modes.
 pub mod asset_router;
 /// Static and dynamic asset certification, invalidation, and serving helpers.
 pub mod assets;
+/// Signed-request authentication middleware for IC identities.
+#[cfg(feature = "auth")]
+pub mod auth;
 /// Build-script utilities for file-based route generation.
 pub mod build;
+/// Canonical request paths: non-canonical detection and redirects.
+pub mod canonical;
 /// Certification mode configuration types.
 pub mod certification;
+/// Runtime gzip and Brotli encoding for dynamic responses.
+pub mod compression;
+/// Conditional request helpers: ETags, HTTP dates, and `304 Not Modified` checks.
+pub mod conditional;
 /// Global configuration types: security headers, cache control, TTL settings.
 pub mod config;
 /// Request context types passed to route handlers.
 pub mod context;
+/// Cookie parsing and `Set-Cookie` serialization.
+pub mod cookie;
+/// Cross-origin resource sharing: preflight responses and CORS headers.
+pub mod cors;
+/// Cross-site request forgery protection for form routes.
+pub mod csrf;
+/// Typed per-request values passed from middleware to handlers.
+pub mod extensions;
+/// IC metadata for the request being handled: execution mode, time, canister id, caller.
+pub mod ic_context;
 /// Middleware type definition.
 pub mod middleware;
 /// MIME type detection from file extensions.
 pub mod mime;
+/// `multipart/form-data` body parsing for file-upload forms.
+pub mod multipart;
+/// Token-bucket rate limits for requests that reach the update path.
+pub mod rate_limit;
+/// Declarative redirects and rewrites.
+pub mod redirect;
 /// Per-route configuration types (certification mode, TTL, headers).
 pub mod route_config;
 /// Route trie, handler types, and dispatch logic.
 pub mod router;
-
+/// Signed session cookies backed by a canister-side session store.
+#[cfg(feature = "sessions")]
+pub mod session;
+/// Typed application state shared by handlers and middleware.
+pub mod state;
+/// Candid types for streaming large assets through IC streaming callbacks.
+pub mod streaming;
+
+pub use asset_router::AssetRouterSnapshot;
 pub use assets::{
-    delete_assets, invalidate_all_dynamic, invalidate_path, invalidate_prefix, last_certified_at,
+    delete_assets, dynamic_cache_snapshot, invalidate_all_dynamic, invalidate_path,
+    invalidate_prefix, last_certified_at, post_upgrade, pre_upgrade, restore_dynamic_cache,
 };
 pub use certification::{CertificationMode, FullConfig, FullConfigBuilder, ResponseOnlyConfig};
-pub use config::{AssetConfig, CacheConfig, CacheControl, SecurityHeaders};
+pub use config::{
+    AssetConfig, CacheConfig, CacheControl, CompressionConfig, CorsConfig, PathNormalization,
+    PathPolicy, SecurityHeaders,
+};
 pub use context::{
-    deserialize_search_params, parse_form_body, parse_query, url_decode, FormBodyError,
-    JsonBodyError, QueryParams, RouteContext,
+    catch_all_segments, deserialize_search_params, deserialize_search_params_strict,
+    parse_form_body, parse_optional_param, parse_param, parse_query, url_decode, FormBodyError,
+    JsonBodyError, ParamParseError, QueryParams, RouteContext, SearchParamsError,
 };
+pub use cookie::{Cookie, SameSite};
+pub use extensions::Extensions;
 pub use ic_asset_router_macros::route;
+pub use ic_context::{ExecutionMode, IcContext};
 pub use ic_http_certification::{HttpRequest, HttpResponse, Method, StatusCode};
+pub use multipart::{Multipart, MultipartError, MultipartPart, DEFAULT_MAX_MULTIPART_SIZE};
+pub use rate_limit::{RateLimit, RateLimitKey};
+pub use redirect::{Redirect, Rewrite};
 pub use route_config::RouteConfig;
-pub use router::{HandlerResult, RouteParams};
+pub use router::{AsyncHandlerFn, HandlerFuture, HandlerResult, RouteParams};
+pub use state::AppState;
 
 thread_local! {
     static HTTP_TREE: Rc<RefCell<HttpCertificationTree>> = Default::default();
     static ASSET_ROUTER: RefCell<asset_router::AssetRouter> = RefCell::new(asset_router::AssetRouter::with_tree(HTTP_TREE.with(|tree| tree.clone())));
     static ROUTER_CONFIG: RefCell<AssetConfig> = RefCell::new(AssetConfig::default());
+    /// Certified CORS preflight responses. Kept apart from `ASSET_ROUTER`
+    /// because a preflight shares its path with the route's own response.
+    static PREFLIGHT_ROUTER: RefCell<asset_router::AssetRouter> = RefCell::new(asset_router::AssetRouter::with_tree(HTTP_TREE.with(|tree| tree.clone())));
+    static REDIRECT_RULES: RefCell<redirect::RuleTable> = RefCell::new(redirect::RuleTable::default());
 }
 
 /// Set the global router configuration.
 fn set_asset_config(config: AssetConfig) {
+    let prefix_policies = config.cors_prefixes.iter().map(|(_, cors)| cors);
+    for cors in config.cors.iter().chain(prefix_policies) {
+        cors.assert_valid();
+    }
     ROUTER_CONFIG.with(|c| {
         *c.borrow_mut() = config;
     });
@@ -624,7 +1066,12 @@ pub fn setup(routes: &router::RouteNode) -> SetupBuilder<'_> {
         routes,
         config: None,
         asset_dirs: Vec::new(),
+        asset_compression: CompressionConfig::disabled(),
         delete_paths: Vec::new(),
+        redirects: Vec::new(),
+        rewrites: Vec::new(),
+        rate_limits: Vec::new(),
+        state: AppState::new(),
     }
 }
 
@@ -633,8 +1080,14 @@ pub fn setup(routes: &router::RouteNode) -> SetupBuilder<'_> {
 /// Calling [`.build()`](SetupBuilder::build) executes the following steps
 /// in order:
 ///
-/// 1. Sets the global [`AssetConfig`] (or uses the default).
-/// 2. Certifies each registered asset directory.
+/// 1. Sets the global [`AssetConfig`] (or uses the default) and the
+///    [`with_state`](SetupBuilder::with_state) values, and compiles the
+///    [`with_redirects`](SetupBuilder::with_redirects) and
+///    [`with_rewrites`](SetupBuilder::with_rewrites) rules and the
+///    [`with_rate_limits`](SetupBuilder::with_rate_limits) limits.
+/// 2. Certifies each registered asset directory, generating compressed
+///    variants if [`with_asset_compression`](SetupBuilder::with_asset_compression)
+///    was called.
 /// 3. Deletes any paths registered via [`delete_assets`](SetupBuilder::delete_assets).
 /// 4. Registers skip-certification tree entries for all skip-mode routes.
 /// 5. Calls `certified_data_set` with the final root hash.
@@ -645,7 +1098,12 @@ pub struct SetupBuilder<'r> {
         &'static include_dir::Dir<'static>,
         certification::CertificationMode,
     )>,
+    asset_compression: CompressionConfig,
     delete_paths: Vec<&'static str>,
+    redirects: Vec<Redirect>,
+    rewrites: Vec<Rewrite>,
+    rate_limits: Vec<RateLimit>,
+    state: AppState,
 }
 
 impl<'r> SetupBuilder<'r> {
@@ -675,6 +1133,17 @@ impl<'r> SetupBuilder<'r> {
         self
     }
 
+    /// Generate gzip/Brotli variants for static assets while certifying them.
+    ///
+    /// Applies to every directory registered with this builder. Text-like
+    /// files without a pre-compressed `.br` / `.gz` sibling are encoded in
+    /// the canister; variants that do not shrink the file are dropped. See
+    /// [`certify_assets_with_compression`](assets::certify_assets_with_compression).
+    pub fn with_asset_compression(mut self, compression: CompressionConfig) -> Self {
+        self.asset_compression = compression;
+        self
+    }
+
     /// Delete previously certified assets at the given paths.
     ///
     /// Useful when static assets (e.g. a SPA's `index.html`) should be
@@ -685,15 +1154,86 @@ impl<'r> SetupBuilder<'r> {
         self
     }
 
+    /// Answer requests matching each [`Redirect`]'s source with a certified
+    /// redirect to its destination, before any route is consulted.
+    ///
+    /// The certified redirect is cached per path, but the request's query
+    /// string is carried over to `Location`. A request with a query string,
+    /// or with a method other than `GET`/`HEAD`, is therefore always
+    /// upgraded and answered from an update call, which costs cycles and
+    /// counts against the [rate limits](Self::with_rate_limits).
+    ///
+    /// ```rust,ignore
+    /// ic_asset_router::setup(routes)
+    ///     .with_redirects(vec![
+    ///         Redirect::permanent("/blog/:slug", "/posts/:slug"),
+    ///         Redirect::found("/docs/*rest", "https://docs.example.com/*rest"),
+    ///     ])
+    ///     .build();
+    /// ```
+    pub fn with_redirects(mut self, redirects: Vec<Redirect>) -> Self {
+        self.redirects.extend(redirects);
+        self
+    }
+
+    /// Route requests matching each [`Rewrite`]'s source as if they were
+    /// made to its destination. The response is certified at the requested
+    /// path; redirects take precedence over rewrites.
+    pub fn with_rewrites(mut self, rewrites: Vec<Rewrite>) -> Self {
+        self.rewrites.extend(rewrites);
+        self
+    }
+
+    /// Limit the update calls for paths under each [`RateLimit`]'s prefix.
+    ///
+    /// [`http_request`] answers `429 Too Many Requests` instead of upgrading
+    /// a request whose bucket is empty, and [`http_request_update`] takes a
+    /// token from every matching bucket. See [`rate_limit`].
+    ///
+    /// ```rust,ignore
+    /// ic_asset_router::setup(routes)
+    ///     .with_rate_limits(vec![RateLimit::new("/", 100, Duration::from_secs(60))])
+    ///     .build();
+    /// ```
+    pub fn with_rate_limits(mut self, limits: Vec<RateLimit>) -> Self {
+        self.rate_limits.extend(limits);
+        self
+    }
+
+    /// Register a shared application state value, one per type.
+    ///
+    /// Handlers read it with [`RouteContext::state`], middleware with
+    /// [`AppState::current`]. Registering a second value of the same type
+    /// replaces the first.
+    ///
+    /// ```rust,ignore
+    /// ic_asset_router::setup(routes)
+    ///     .with_state(Store { items: RefCell::new(Vec::new()) })
+    ///     .build();
+    /// ```
+    pub fn with_state<T: 'static>(mut self, value: T) -> Self {
+        self.state = self.state.with(value);
+        self
+    }
+
     /// Execute the setup: apply config, certify assets, register skip
     /// routes, and commit the certification tree root hash.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a redirect or rewrite is invalid; see [`redirect`].
     pub fn build(self) {
-        // 1. Set config.
+        // 1. Set config and state, compile redirects and rewrites, and
+        //    register rate limits.
         set_asset_config(self.config.unwrap_or_default());
+        AppState::set_current(self.state);
+        let rules = redirect::RuleTable::new(&self.redirects, &self.rewrites);
+        REDIRECT_RULES.with_borrow_mut(|r| *r = rules);
+        rate_limit::set_rules(self.rate_limits);
 
         // 2. Certify asset directories.
         for (dir, mode) in &self.asset_dirs {
-            assets::certify_assets_with_mode(dir, mode.clone());
+            assets::certify_assets_with_compression(dir, mode.clone(), &self.asset_compression);
         }
 
         // 3. Delete specified asset paths.
@@ -899,16 +1439,23 @@ fn serve_from_cache_or_upgrade(req: &HttpRequest, path: &str) -> HttpResponse<'s
 /// Used both when the caller opts out of certification globally
 /// (`opts.certify == false`) and when a route is configured with
 /// [`CertificationMode::Skip`].
+///
+/// `route_path` is the (possibly rewritten) path the middleware chain runs
+/// for; the proof is issued for the requested path.
 fn serve_without_certification(
     root: &RouteNode,
-    path: &str,
+    route_path: &str,
     handler: router::HandlerFn,
     req: HttpRequest,
     params: router::RouteParams,
+    route_config: Option<&RouteConfig>,
+    cors: Option<&CorsConfig>,
 ) -> HttpResponse<'static> {
-    debug_log!("serving {} without certification", path);
-    let mut response = root.execute_with_middleware(path, handler, req, params);
-    match attach_skip_certification(path, &mut response) {
+    debug_log!("serving {} without certification", route_path);
+    let path = req.get_path().unwrap_or_else(|_| route_path.to_string());
+    let response = root.execute_with_middleware(route_path, handler, req.clone(), params);
+    let mut response = apply_cors(apply_route_headers(response, route_config), &req, cors);
+    match attach_skip_certification(&path, &mut response) {
         Ok(()) => response,
         Err(err_resp) => err_resp,
     }
@@ -926,10 +1473,147 @@ fn serve_without_certification(
 ///
 /// Non-GET/HEAD requests are always upgraded. GET requests for dynamic
 /// routes with expired TTLs are also upgraded.
+///
+/// `HEAD` requests for routes without a `head` handler are answered like
+/// `GET` with the body removed; cached assets serve a certified bodiless
+/// response.
+///
+/// Before routing, non-canonical paths are handled according to
+/// [`AssetConfig::path_normalization`], then [`Redirect`]s and [`Rewrite`]s
+/// registered with [`SetupBuilder`] are applied.
+///
+/// A request that would be upgraded while one of its [`RateLimit`] buckets
+/// is empty is answered with `429 Too Many Requests` instead. A body the
+/// matched route refuses (see [`RouteConfig::max_body`] and
+/// [`RouteConfig::accepts`]) is answered with `413` or `415`.
 pub fn http_request(
     req: HttpRequest,
     root_route_node: &RouteNode,
     opts: HttpRequestOptions,
+) -> HttpResponse<'static> {
+    ic_context::set_mode(ExecutionMode::Query);
+    let is_head = req.method() == Method::HEAD;
+    let limited = rate_limit::is_enabled()
+        .then(|| rate_limited(&req, root_route_node, false, ic_cdk::api::time()))
+        .flatten();
+    let response = query_response(req, root_route_node, opts);
+    // Refuse here rather than spend an update call on a limited client.
+    let response = match limited {
+        Some(limited) if response.upgrade() == Some(true) => limited,
+        _ => response,
+    };
+    without_body_for_head(response, is_head)
+}
+
+/// Handle an HTTP query-path request like [`http_request`], streaming large
+/// assets through IC streaming callbacks.
+///
+/// A `GET` without a `Range` header for an asset larger than
+/// [`ASSET_CHUNK_SIZE`](asset_router::ASSET_CHUNK_SIZE) is answered with
+/// the certified full response carrying the first chunk, plus a
+/// [`StreamingStrategy`](streaming::StreamingStrategy) through which the
+/// HTTP gateway fetches the rest from
+/// [`http_request_streaming_callback`]. [`http_request`] answers the same
+/// request with the full body in one `200`, which the IC refuses to send
+/// once it exceeds the response size limit. Every other request is
+/// answered exactly as by [`http_request`]. See [`streaming`] for the
+/// canister methods to declare.
+pub fn http_request_streaming(
+    req: HttpRequest,
+    root_route_node: &RouteNode,
+    opts: HttpRequestOptions,
+) -> StreamingHttpResponse {
+    let response = http_request(req.clone(), root_route_node, opts);
+    // A chunked asset answers a `GET` without `Range` with its full body;
+    // stream that instead.
+    if response.status_code() != StatusCode::OK
+        || req.method() != Method::GET
+        || req
+            .headers()
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("range"))
+    {
+        return response.into();
+    }
+    stream_asset(&req, &response).unwrap_or_else(|| response.into())
+}
+
+/// The streamed answer to `req`, keeping headers such as
+/// `access-control-allow-origin` that were added to `full`, the `200` the
+/// query path served. `None` if the asset is not chunked.
+fn stream_asset(req: &HttpRequest, full: &HttpResponse) -> Option<StreamingHttpResponse> {
+    let cert = data_certificate()?;
+    let (mut response, witness, expr_path, token) =
+        ASSET_ROUTER.with_borrow(|asset_router| asset_router.serve_asset_streaming(req))?;
+    let token = token?;
+    add_v2_certificate_header(&cert, &mut response, &witness, &expr_path);
+    for (name, value) in full.headers() {
+        if !name.eq_ignore_ascii_case("ic-certificate")
+            && !response.headers().iter().any(|(n, _)| n == name)
+        {
+            response.add_header((name.clone(), value.clone()));
+        }
+    }
+    let mut streamed = StreamingHttpResponse::from(response);
+    streamed.streaming_strategy = Some(streaming::StreamingStrategy::callback(
+        ic_cdk::api::canister_self(),
+        token,
+    ));
+    Some(streamed)
+}
+
+/// Answer the HTTP gateway's request for the next chunk of an asset
+/// streamed by [`http_request_streaming`].
+///
+/// Export it as the `http_request_streaming_callback` query method.
+///
+/// # Traps
+///
+/// Traps if the token names no chunk, for example because the asset was
+/// deleted or re-certified with a different body since streaming started.
+pub fn http_request_streaming_callback(
+    token: StreamingCallbackToken,
+) -> StreamingCallbackHttpResponse {
+    match ASSET_ROUTER.with_borrow(|asset_router| asset_router.streaming_chunk(&token)) {
+        Some(chunk) => chunk,
+        None => ic_cdk::trap(format!(
+            "no chunk {} of {} to stream",
+            token.index, token.path
+        )),
+    }
+}
+
+/// The `429` response for `req` if a [`RateLimit`] bucket it draws from is
+/// empty at `now`. With `consume`, a token is taken from each bucket
+/// instead of only checking them.
+fn rate_limited(
+    req: &HttpRequest,
+    root_route_node: &RouteNode,
+    consume: bool,
+    now: u64,
+) -> Option<HttpResponse<'static>> {
+    let path = req.get_path().ok()?;
+    let method = match req.method() {
+        m if *m == Method::HEAD => Method::GET,
+        m => m.clone(),
+    };
+    let route = match root_route_node.resolve(&routing_path(&path), &method) {
+        RouteResult::Found(_, _, _, pattern) => Some(pattern),
+        _ => None,
+    };
+    let result = if consume {
+        rate_limit::consume(req, route.as_deref(), now)
+    } else {
+        rate_limit::check(req, route.as_deref(), now)
+    };
+    result.err().map(rate_limit::RateLimited::into_response)
+}
+
+/// Route a query-path request; see [`http_request`].
+fn query_response(
+    req: HttpRequest,
+    root_route_node: &RouteNode,
+    opts: HttpRequestOptions,
 ) -> HttpResponse<'static> {
     debug_log!("http_request: {:?}", req.url());
 
@@ -938,8 +1622,28 @@ pub fn http_request(
         Err(_) => return error_response(400, "Bad Request: malformed URL"),
     };
 
+    if let Some(non_canonical) = non_canonical(&req) {
+        return serve_non_canonical_query(req, &path, non_canonical, root_route_node, opts.certify);
+    }
+
+    if let Some((status, location)) = match_redirect(&req) {
+        return serve_redirect_query(&req, &path, status, &location, opts.certify);
+    }
+
+    // Routing and middleware use the rewritten path; caching and
+    // certification stay keyed on the requested one.
+    let route_path = routing_path(&path);
     let method = req.method().clone();
 
+    if let Some(preflight) = match_preflight(&req, &route_path, root_route_node) {
+        return serve_preflight_query(&req, &path, preflight, opts.certify);
+    }
+
+    // Refuse oversized or mistyped bodies without spending an update call.
+    if let Some(rejection) = route_body_rejection(&req, &route_path, root_route_node) {
+        return rejection;
+    }
+
     // Non-GET requests arriving at the query endpoint must be upgraded to an
     // update call so that state-mutating handlers execute in the update path.
     if method != Method::GET && method != Method::HEAD {
@@ -950,43 +1654,86 @@ pub fn http_request(
         return HttpResponse::builder().with_upgrade(true).build();
     }
 
-    match root_route_node.resolve(&path, &method) {
-        RouteResult::Found(handler, params, _result_handler, pattern) => match opts.certify {
-            false => serve_without_certification(root_route_node, &path, handler, req, params),
-            true => {
-                let route_config = root_route_node.get_route_config(&pattern);
-                let cert_mode = route_config.map(|rc| &rc.certification);
+    match root_route_node.resolve(&route_path, &method) {
+        RouteResult::Found(handler, params, _result_handler, pattern) => {
+            let route_config = root_route_node.get_route_config(&pattern);
+            let cors = effective_cors(&route_path, route_config);
+
+            // Async handlers only run in `http_request_update_async`, so
+            // routes that would run the handler here are upgraded instead.
+            if root_route_node
+                .resolve_async(&route_path, &method)
+                .is_some()
+                && (!opts.certify
+                    || matches!(
+                        route_config.map(|rc| &rc.certification),
+                        Some(certification::CertificationMode::Skip)
+                    ))
+            {
+                debug_log!("upgrading (async handler: {})", path);
+                return HttpResponse::builder().with_upgrade(true).build();
+            }
 
-                // Full mode binds proof to request headers — always upgrade.
-                if matches!(cert_mode, Some(certification::CertificationMode::Full(_))) {
-                    debug_log!("upgrading (full certification mode: {})", path);
-                    return HttpResponse::builder().with_upgrade(true).build();
-                }
+            if !opts.certify {
+                return serve_without_certification(
+                    root_route_node,
+                    &route_path,
+                    handler,
+                    req,
+                    params,
+                    route_config,
+                    cors.as_ref(),
+                );
+            }
 
-                if matches!(cert_mode, Some(certification::CertificationMode::Skip)) {
-                    return serve_without_certification(
-                        root_route_node,
-                        &path,
-                        handler,
-                        req,
-                        params,
-                    );
-                }
+            let cert_mode = route_config.map(|rc| &rc.certification);
+
+            // Full mode binds proof to request headers — always upgrade.
+            if matches!(cert_mode, Some(certification::CertificationMode::Full(_))) {
+                debug_log!("upgrading (full certification mode: {})", path);
+                return HttpResponse::builder().with_upgrade(true).build();
+            }
 
-                serve_from_cache_or_upgrade(&req, &path)
+            if matches!(cert_mode, Some(certification::CertificationMode::Skip)) {
+                return serve_without_certification(
+                    root_route_node,
+                    &route_path,
+                    handler,
+                    req,
+                    params,
+                    route_config,
+                    cors.as_ref(),
+                );
             }
-        },
+
+            with_allow_origin(
+                serve_from_cache_or_upgrade(&req, &path),
+                &req,
+                cors.as_ref(),
+            )
+        }
         RouteResult::MethodNotAllowed(allowed) => method_not_allowed(&allowed),
         RouteResult::NotFound => handle_not_found_query(req, &path, root_route_node, opts.certify),
     }
 }
 
-/// Certify a dynamically generated response and store it for future query-path
-/// serving.
-///
-/// The response body is stored in the `AssetRouter` via `certify_asset()`,
-/// which lets the query path use `serve_asset()`. All responses — including
-/// not-found handler output — go through this single path. The not-found
+/// The headers certified with a dynamic response: the global headers, then
+/// the dynamic `cache-control`, the route's headers and `cors_headers` on
+/// top.
+fn dynamic_asset_headers(
+    route_config: Option<&RouteConfig>,
+    cors_headers: Vec<HeaderField>,
+) -> Vec<HeaderField> {
+    let dynamic_cache_control =
+        ROUTER_CONFIG.with(|c| c.borrow().cache_control.dynamic_assets.clone());
+    let mut additional_headers = vec![("cache-control".to_string(), dynamic_cache_control)];
+    if let Some(rc) = route_config {
+        additional_headers.extend(rc.headers.iter().cloned());
+    }
+    additional_headers.extend(cors_headers);
+    get_asset_headers(additional_headers)
+}
+
 /// Certify a dynamically generated response and store it for future query-path
 /// serving.
 ///
@@ -1005,27 +1752,76 @@ pub fn http_request(
 /// - `Full` — uses `certify_dynamic_asset()` with the original request.
 ///
 /// The `request` parameter is required for `Full` mode and ignored otherwise.
+///
+/// When `route_config` is `Some`, its TTL overrides the global
+/// [`CacheConfig`] and its headers are merged into both the returned
+/// response and the certified asset.
+///
+/// Gzip/Brotli variants are built according to
+/// [`AssetConfig::compression`] (or the route's `compress` override) and
+/// certified alongside the identity body, except in `Full` mode.
+///
+/// When `cors` is `Some`, its headers are merged into the certified asset
+/// and the returned response, and `Access-Control-Allow-Origin` is excluded
+/// from certification and set for `request`'s origin.
 fn certify_dynamic_response_with_ttl(
     response: HttpResponse<'static>,
     path: &str,
     fallback_for: Option<String>,
-    mode: certification::CertificationMode,
+    mut mode: certification::CertificationMode,
     request: Option<&HttpRequest>,
-    ttl_override: Option<std::time::Duration>,
+    route_config: Option<&RouteConfig>,
+    cors: Option<&CorsConfig>,
 ) -> HttpResponse<'static> {
+    // A per-client response must not be cached. Full mode never serves it
+    // from the cache; any other mode would serve it to everyone. A no-store
+    // response is returned as is in every mode: the update call needs no
+    // certificate, and a cached copy would answer other query strings.
+    if is_no_store(&response)
+        || (is_per_client(&response) && !matches!(mode, certification::CertificationMode::Full(_)))
+    {
+        let response = apply_route_headers(response, route_config);
+        return match request {
+            Some(req) => apply_cors(response, req, cors),
+            None => response,
+        };
+    }
+
+    let mut response = apply_route_headers(response, route_config);
+    let cors_headers = match cors {
+        Some(cors) => {
+            cors::exclude_allow_origin(&mut mode);
+            cors::response_headers(cors, response.headers())
+        }
+        None => vec![],
+    };
+    config::layer_headers(response.headers_mut(), &cors_headers);
     let content_type = extract_content_type(&response);
-    let effective_ttl = ttl_override
+    let effective_ttl = route_config
+        .and_then(|rc| rc.ttl)
         .or_else(|| ROUTER_CONFIG.with(|c| c.borrow().cache_config.effective_ttl(path)));
 
-    let dynamic_cache_control =
-        ROUTER_CONFIG.with(|c| c.borrow().cache_control.dynamic_assets.clone());
+    let headers = dynamic_asset_headers(route_config, cors_headers);
+
+    // Full-mode assets are never served from the cache (every query
+    // upgrades), so encoded variants would only cost cycles.
+    let encodings = if matches!(mode, certification::CertificationMode::Full(_)) {
+        vec![]
+    } else {
+        let compression = ROUTER_CONFIG.with(|c| {
+            c.borrow()
+                .compression
+                .for_route(route_config.and_then(|rc| rc.compress))
+        });
+        compression::encode_variants(response.body(), &content_type, &compression)
+    };
 
     let config = asset_router::AssetCertificationConfig {
         mode: mode.clone(),
         content_type: Some(content_type),
         status_code: response.status_code(),
-        headers: get_asset_headers(vec![("cache-control".to_string(), dynamic_cache_control)]),
-        encodings: vec![],
+        headers,
+        encodings,
         fallback_for,
         aliases: vec![],
         certified_at: ic_cdk::api::time(),
@@ -1091,7 +1887,10 @@ fn certify_dynamic_response_with_ttl(
         );
     }
 
-    response
+    match request {
+        Some(req) => with_allow_origin(response, req, cors),
+        None => response,
+    }
 }
 
 /// Handle the `NotFound` branch of `http_request_update`.
@@ -1143,6 +1942,7 @@ fn handle_not_found_update(
         certification::CertificationMode::response_only(),
         None,
         None,
+        None,
     )
 }
 
@@ -1150,14 +1950,30 @@ fn handle_not_found_update(
 ///
 /// Resets the TTL timer on the cached asset (if TTL-based caching is active),
 /// then serves the existing cached response from the asset router.
-fn handle_not_modified(req: &HttpRequest, path: &str) -> HttpResponse<'static> {
+fn handle_not_modified(
+    req: &HttpRequest,
+    path: &str,
+    cors: Option<&CorsConfig>,
+) -> HttpResponse<'static> {
     debug_log!("handler returned NotModified for {}", path);
 
-    // Reset the certified_at timestamp so the TTL timer restarts.
+    // Reset the certified_at timestamp so the TTL timer restarts. The
+    // timestamp is certified as `Last-Modified`, so re-certify with it.
     ASSET_ROUTER.with_borrow_mut(|asset_router| {
-        if let Some(asset) = asset_router.get_asset_mut(path) {
-            if asset.ttl.is_some() {
-                asset.certified_at = ic_cdk::api::time();
+        let has_ttl = asset_router
+            .get_asset(path)
+            .is_some_and(|asset| asset.ttl.is_some());
+        if !has_ttl {
+            return;
+        }
+        match asset_router.recertify_at(path, ic_cdk::api::time(), Some(req)) {
+            Ok(_) => certified_data_set(asset_router.root_hash()),
+            Err(_err) => {
+                debug_log!(
+                    "handle_not_modified: failed to re-certify '{}': {}",
+                    path,
+                    _err
+                );
             }
         }
     });
@@ -1168,7 +1984,7 @@ fn handle_not_modified(req: &HttpRequest, path: &str) -> HttpResponse<'static> {
             if let Some(cert) = data_certificate() {
                 add_v2_certificate_header(&cert, &mut resp, &witness, &expr_path);
             }
-            resp
+            with_allow_origin(resp, req, cors)
         }
         None => error_response(
             500,
@@ -1187,36 +2003,110 @@ fn handle_not_modified(req: &HttpRequest, path: &str) -> HttpResponse<'static> {
 /// route, it is called first to check for [`HandlerResult::NotModified`].
 /// A `NotModified` result preserves the existing cached response and resets
 /// the TTL timer (if TTL-based caching is active).
-pub fn http_request_update(req: HttpRequest, root_route_node: &RouteNode) -> HttpResponse<'static> {
-    debug_log!("http_request_update: {:?}", req.url());
+///
+/// CORS preflights for routes without their own `options` handler are
+/// answered from the route's [`CorsConfig`] and certified.
+///
+/// `HEAD` requests run the route's `GET` handler when it has no `head`
+/// handler; the full response is certified and the body is removed from the
+/// returned copy.
+///
+/// Routes with an async handler answer `500 Internal Server Error`; use
+/// [`http_request_update_async`] for them.
+///
+/// Each request takes a token from its [`RateLimit`] buckets first and is
+/// answered with `429 Too Many Requests` if one is empty.
+pub fn http_request_update(req: HttpRequest, root_route_node: &RouteNode) -> HttpResponse<'static> {
+    ic_context::set_mode(ExecutionMode::Update);
+    if let Some(limited) = consume_rate_limit(&req, root_route_node) {
+        return limited;
+    }
+    let is_head = req.method() == Method::HEAD;
+    without_body_for_head(update_response(req, root_route_node), is_head)
+}
+
+/// Take a token for an update request from the [`RateLimit`] buckets it
+/// draws from, or return the `429` response if one is empty.
+fn consume_rate_limit(
+    req: &HttpRequest,
+    root_route_node: &RouteNode,
+) -> Option<HttpResponse<'static>> {
+    rate_limit::is_enabled()
+        .then(|| rate_limited(req, root_route_node, true, ic_cdk::api::time()))
+        .flatten()
+}
+
+/// Route an update-path request; see [`http_request_update`].
+fn update_response(req: HttpRequest, root_route_node: &RouteNode) -> HttpResponse<'static> {
+    debug_log!("http_request_update: {:?}", req.url());
 
     let path = match req.get_path() {
         Ok(p) => p,
         Err(_) => return error_response(400, "Bad Request: malformed URL"),
     };
 
+    if let Some(non_canonical) = non_canonical(&req) {
+        return serve_non_canonical_update(req, &path, non_canonical, root_route_node);
+    }
+
+    if let Some((status, location)) = match_redirect(&req) {
+        return certify_redirect(&req, &path, status, &location);
+    }
+
+    let route_path = routing_path(&path);
+
+    if let Some(preflight) = match_preflight(&req, &route_path, root_route_node) {
+        return certify_preflight(&req, &path, preflight);
+    }
+
+    if let Some(rejection) = route_body_rejection(&req, &route_path, root_route_node) {
+        return rejection;
+    }
+
     let method = req.method().clone();
 
-    match root_route_node.resolve(&path, &method) {
+    match root_route_node.resolve(&route_path, &method) {
         RouteResult::Found(handler, params, result_handler, pattern) => {
+            if root_route_node
+                .resolve_async(&route_path, &method)
+                .is_some()
+            {
+                return error_response(
+                    500,
+                    "Internal Server Error: async handler requires http_request_update_async",
+                );
+            }
             let route_config = root_route_node.get_route_config(&pattern);
+            let cors = effective_cors(&route_path, route_config);
             let cert_mode = route_config
                 .map(|rc| rc.certification.clone())
                 .unwrap_or_else(certification::CertificationMode::response_only);
-            let route_ttl = route_config.and_then(|rc| rc.ttl);
 
             // Skip-mode routes are handled on the query path; if one arrives
             // here (stale upgrade), just run the handler without re-certifying.
             if matches!(&cert_mode, certification::CertificationMode::Skip) {
                 debug_log!("skip mode in update path (unexpected): {}", path);
-                return root_route_node.execute_with_middleware(&path, handler, req, params);
+                let response = root_route_node.execute_with_middleware(
+                    &route_path,
+                    handler,
+                    req.clone(),
+                    params,
+                );
+                let response = apply_route_headers(response, route_config);
+                return apply_cors(response, &req, cors.as_ref());
             }
 
-            // Check for NotModified before running the full pipeline.
+            // Result handlers run through the middleware chain too; a
+            // NotModified result keeps the existing certified response.
             if let Some(result_fn) = result_handler {
-                match result_fn(req.clone(), params.clone()) {
+                match root_route_node.execute_result_with_middleware(
+                    &route_path,
+                    result_fn,
+                    req.clone(),
+                    params.clone(),
+                ) {
                     router::HandlerResult::NotModified => {
-                        return handle_not_modified(&req, &path);
+                        return handle_not_modified(&req, &path, cors.as_ref());
                     }
                     router::HandlerResult::Response(response) => {
                         return certify_dynamic_response_with_ttl(
@@ -1225,7 +2115,8 @@ pub fn http_request_update(req: HttpRequest, root_route_node: &RouteNode) -> Htt
                             None,
                             cert_mode,
                             Some(&req),
-                            route_ttl,
+                            route_config,
+                            cors.as_ref(),
                         );
                     }
                 }
@@ -1233,14 +2124,15 @@ pub fn http_request_update(req: HttpRequest, root_route_node: &RouteNode) -> Htt
 
             // Standard path: call handler through middleware, then certify.
             let response =
-                root_route_node.execute_with_middleware(&path, handler, req.clone(), params);
+                root_route_node.execute_with_middleware(&route_path, handler, req.clone(), params);
             certify_dynamic_response_with_ttl(
                 response,
                 &path,
                 None,
                 cert_mode,
                 Some(&req),
-                route_ttl,
+                route_config,
+                cors.as_ref(),
             )
         }
         RouteResult::MethodNotAllowed(allowed) => method_not_allowed(&allowed),
@@ -1248,6 +2140,107 @@ pub fn http_request_update(req: HttpRequest, root_route_node: &RouteNode) -> Htt
     }
 }
 
+/// Handle an HTTP update-path request, awaiting async route handlers.
+///
+/// Behaves like [`http_request_update`], except that routes registered with
+/// an [`AsyncHandlerFn`] (`pub async fn` route
+/// handlers) are awaited, so they can make inter-canister calls. Use it from
+/// an `async` update method:
+///
+/// ```rust,ignore
+/// #[update]
+/// async fn http_request_update(req: HttpRequest<'static>) -> HttpResponse<'static> {
+///     ic_asset_router::http_request_update_async(req, &route_tree::ROUTES).await
+/// }
+/// ```
+///
+/// The route tree is taken as its thread-local key because it cannot be
+/// borrowed across an `await`; it is only accessed before the handler runs.
+/// Middleware runs around async handlers as described in
+/// [`execute_async_with_middleware`](router::execute_async_with_middleware).
+pub async fn http_request_update_async(
+    req: HttpRequest<'static>,
+    routes: &'static LocalKey<RouteNode>,
+) -> HttpResponse<'static> {
+    ic_context::set_mode(ExecutionMode::Update);
+    if let Some(limited) = routes.with(|root| consume_rate_limit(&req, root)) {
+        return limited;
+    }
+    let Some(route) = routes.with(|root| match_async_route(&req, root)) else {
+        let is_head = req.method() == Method::HEAD;
+        return routes.with(|root| without_body_for_head(update_response(req, root), is_head));
+    };
+    let is_head = req.method() == Method::HEAD;
+    without_body_for_head(run_async_route(req, route).await, is_head)
+}
+
+/// An async route resolved for an update request, holding everything needed
+/// after the route tree borrow ends.
+struct AsyncRoute {
+    path: String,
+    handler: router::AsyncHandlerFn,
+    params: router::RouteParams,
+    middlewares: Vec<middleware::MiddlewareFn>,
+    route_config: Option<RouteConfig>,
+}
+
+/// Resolve `req` to an async route, or `None` if the request is answered
+/// some other way (malformed URL, redirect, preflight, synchronous route, or
+/// no route at all).
+fn match_async_route(req: &HttpRequest, root: &RouteNode) -> Option<AsyncRoute> {
+    let path = req.get_path().ok()?;
+    if non_canonical(req).is_some() || match_redirect(req).is_some() {
+        return None;
+    }
+    let route_path = routing_path(&path);
+    if match_preflight(req, &route_path, root).is_some() {
+        return None;
+    }
+    let (handler, params, pattern) = root.resolve_async(&route_path, req.method())?;
+    Some(AsyncRoute {
+        middlewares: root.matching_middlewares(&route_path),
+        route_config: root.get_route_config(&pattern).cloned(),
+        path,
+        handler,
+        params,
+    })
+}
+
+/// Await an async route through its middleware and certify the response, as
+/// [`update_response`] does for synchronous routes.
+async fn run_async_route(req: HttpRequest<'static>, route: AsyncRoute) -> HttpResponse<'static> {
+    let route_config = route.route_config.as_ref();
+    if let Some(rejection) = body_rejection(&req, route_config) {
+        return rejection;
+    }
+    let cors = effective_cors(&routing_path(&route.path), route_config);
+    let cert_mode = route_config
+        .map(|rc| rc.certification.clone())
+        .unwrap_or_else(certification::CertificationMode::response_only);
+
+    let response = router::execute_async_with_middleware(
+        route.middlewares,
+        route.handler,
+        req.clone(),
+        route.params,
+    )
+    .await;
+
+    if matches!(&cert_mode, certification::CertificationMode::Skip) {
+        let response = apply_route_headers(response, route_config);
+        return apply_cors(response, &req, cors.as_ref());
+    }
+    certify_dynamic_response_with_ttl(
+        response,
+        &route.path,
+        None,
+        cert_mode,
+        Some(&req),
+        route_config,
+        cors.as_ref(),
+    )
+}
+
 // Test coverage audit (Session 7, Spec 5.5):
 //
 // Covered:
@@ -1396,6 +2389,95 @@ mod tests {
         assert_eq!(extract_content_type(&response), "text/plain");
     }
 
+    // ---- RouteConfig headers are merged into handler responses ----
+
+    fn route_config_with_headers(headers: &[(&str, &str)]) -> RouteConfig {
+        RouteConfig {
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..RouteConfig::default()
+        }
+    }
+
+    #[test]
+    fn apply_route_headers_appends_new_headers() {
+        let response = HttpResponse::builder()
+            .with_headers(vec![("content-type".to_string(), "text/html".to_string())])
+            .build();
+        let rc = route_config_with_headers(&[("x-frame-options", "DENY")]);
+        let response = apply_route_headers(response, Some(&rc));
+        assert_eq!(
+            response.headers(),
+            &[
+                ("content-type".to_string(), "text/html".to_string()),
+                ("x-frame-options".to_string(), "DENY".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_route_headers_overrides_case_insensitively() {
+        let response = HttpResponse::builder()
+            .with_headers(vec![("X-Robots-Tag".to_string(), "all".to_string())])
+            .build();
+        let rc = route_config_with_headers(&[("x-robots-tag", "noindex")]);
+        let response = apply_route_headers(response, Some(&rc));
+        assert_eq!(
+            response.headers(),
+            &[("x-robots-tag".to_string(), "noindex".to_string())]
+        );
+    }
+
+    #[test]
+    fn apply_route_headers_without_config_is_noop() {
+        let response = HttpResponse::builder()
+            .with_headers(vec![("content-type".to_string(), "text/html".to_string())])
+            .build();
+        let response = apply_route_headers(response, None);
+        assert_eq!(response.headers().len(), 1);
+    }
+
+    #[test]
+    fn route_headers_override_global_headers_in_asset_config() {
+        set_asset_config(AssetConfig {
+            security_headers: config::SecurityHeaders::none(),
+            custom_headers: vec![
+                ("X-Frame-Options".to_string(), "SAMEORIGIN".to_string()),
+                ("x-global".to_string(), "1".to_string()),
+            ],
+            ..AssetConfig::default()
+        });
+        let rc = route_config_with_headers(&[("x-frame-options", "DENY")]);
+        let headers = dynamic_asset_headers(Some(&rc), vec![]);
+        set_asset_config(AssetConfig::default());
+        assert_eq!(
+            headers,
+            vec![
+                ("x-global".to_string(), "1".to_string()),
+                (
+                    "cache-control".to_string(),
+                    "public, no-cache, no-store".to_string()
+                ),
+                ("x-frame-options".to_string(), "DENY".to_string()),
+            ]
+        );
+
+        // The handler's own header is overridden on the response as well.
+        let response = HttpResponse::builder()
+            .with_headers(vec![(
+                "x-frame-options".to_string(),
+                "SAMEORIGIN".to_string(),
+            )])
+            .build();
+        let response = apply_route_headers(response, Some(&rc));
+        assert_eq!(
+            response.headers(),
+            &[("x-frame-options".to_string(), "DENY".to_string())]
+        );
+    }
+
     // ---- 8.6.5: is_asset_expired unit tests ----
 
     fn make_asset_router() -> asset_router::AssetRouter {
@@ -1533,4 +2615,681 @@ mod tests {
         // Way past the TTL, but static assets never expire.
         assert!(!is_asset_expired(asset, "/page", u64::MAX));
     }
+
+    // ---- CORS ----
+
+    fn preflight_request(path: &str) -> HttpRequest<'static> {
+        HttpRequest::builder()
+            .with_method(Method::OPTIONS)
+            .with_url(path)
+            .with_headers(vec![
+                ("origin".to_string(), "https://app.example".to_string()),
+                (
+                    "access-control-request-method".to_string(),
+                    "POST".to_string(),
+                ),
+            ])
+            .build()
+    }
+
+    fn cors_router() -> RouteNode {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/api/items", Method::GET, noop_handler);
+        root.insert("/api/items", Method::POST, noop_handler);
+        root.insert("/api/own", Method::GET, noop_handler);
+        root.insert("/api/own", Method::OPTIONS, noop_handler);
+        root.set_route_config(
+            "/api/strict",
+            RouteConfig {
+                cors: Some(CorsConfig::with_origins(&["https://app.example"])),
+                ..RouteConfig::default()
+            },
+        );
+        root.insert("/api/strict", Method::PUT, noop_handler);
+        root
+    }
+
+    #[test]
+    fn effective_cors_prefers_route_policy_over_global() {
+        set_asset_config(AssetConfig {
+            cors: Some(CorsConfig::any_origin()),
+            ..AssetConfig::default()
+        });
+        let route = RouteConfig {
+            cors: Some(CorsConfig::with_origins(&["https://app.example"])),
+            ..RouteConfig::default()
+        };
+        assert_eq!(
+            effective_cors("/api", Some(&route)),
+            Some(CorsConfig::with_origins(&["https://app.example"]))
+        );
+        assert_eq!(
+            effective_cors("/api", Some(&RouteConfig::default())),
+            Some(CorsConfig::any_origin())
+        );
+        assert_eq!(effective_cors("/api", None), Some(CorsConfig::any_origin()));
+    }
+
+    #[test]
+    fn effective_cors_uses_longest_matching_prefix() {
+        set_asset_config(AssetConfig {
+            cors: Some(CorsConfig::any_origin()),
+            cors_prefixes: vec![
+                (
+                    "/api".to_string(),
+                    CorsConfig::with_origins(&["https://app.example"]),
+                ),
+                (
+                    "/api/admin/".to_string(),
+                    CorsConfig::with_origins(&["https://admin.example"]),
+                ),
+            ],
+            ..AssetConfig::default()
+        });
+        assert_eq!(
+            effective_cors("/api/items", None),
+            Some(CorsConfig::with_origins(&["https://app.example"]))
+        );
+        assert_eq!(
+            effective_cors("/api/admin/users", None),
+            Some(CorsConfig::with_origins(&["https://admin.example"]))
+        );
+        assert_eq!(
+            effective_cors("/apis", None),
+            Some(CorsConfig::any_origin())
+        );
+        let route = RouteConfig {
+            cors: Some(CorsConfig::with_origins(&["https://route.example"])),
+            ..RouteConfig::default()
+        };
+        assert_eq!(
+            effective_cors("/api/items", Some(&route)),
+            Some(CorsConfig::with_origins(&["https://route.example"]))
+        );
+        set_asset_config(AssetConfig::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "allow_credentials cannot be combined")]
+    fn wildcard_credentialed_cors_is_rejected_at_setup() {
+        set_asset_config(AssetConfig {
+            cors_prefixes: vec![(
+                "/api".to_string(),
+                CorsConfig {
+                    allow_credentials: true,
+                    ..CorsConfig::any_origin()
+                },
+            )],
+            ..AssetConfig::default()
+        });
+    }
+
+    #[test]
+    fn effective_cors_is_none_when_disabled() {
+        set_asset_config(AssetConfig::default());
+        assert_eq!(effective_cors("/api", None), None);
+    }
+
+    #[test]
+    fn match_preflight_requires_cors_policy() {
+        set_asset_config(AssetConfig::default());
+        let root = cors_router();
+        assert!(match_preflight(&preflight_request("/api/items"), "/api/items", &root).is_none());
+
+        let strict = match_preflight(&preflight_request("/api/strict"), "/api/strict", &root)
+            .expect("route-level policy applies without a global one");
+        assert_eq!(strict.methods, vec![Method::PUT]);
+        assert_eq!(
+            strict.cors,
+            CorsConfig::with_origins(&["https://app.example"])
+        );
+    }
+
+    #[test]
+    fn match_preflight_uses_global_policy_and_route_methods() {
+        set_asset_config(AssetConfig {
+            cors: Some(CorsConfig::any_origin()),
+            ..AssetConfig::default()
+        });
+        let root = cors_router();
+        let preflight = match_preflight(&preflight_request("/api/items"), "/api/items", &root)
+            .expect("preflight should match");
+        let mut methods = preflight.methods;
+        methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(methods, vec![Method::GET, Method::POST]);
+        assert!(preflight.route_config.is_none());
+    }
+
+    #[test]
+    fn match_preflight_defers_to_options_handler_and_ignores_others() {
+        set_asset_config(AssetConfig {
+            cors: Some(CorsConfig::any_origin()),
+            ..AssetConfig::default()
+        });
+        let root = cors_router();
+        assert!(match_preflight(&preflight_request("/api/own"), "/api/own", &root).is_none());
+        assert!(match_preflight(&preflight_request("/missing"), "/missing", &root).is_none());
+        let plain_options = HttpRequest::builder()
+            .with_method(Method::OPTIONS)
+            .with_url("/api/items")
+            .build();
+        assert!(match_preflight(&plain_options, "/api/items", &root).is_none());
+    }
+
+    #[test]
+    fn with_allow_origin_leaves_upgrades_alone() {
+        let req = preflight_request("/api/items");
+        let cors = CorsConfig::any_origin();
+        let upgrade = with_allow_origin(
+            HttpResponse::builder().with_upgrade(true).build(),
+            &req,
+            Some(&cors),
+        );
+        assert!(upgrade.headers().is_empty());
+
+        let response = with_allow_origin(
+            noop_handler(req.clone(), RouteParams::new()),
+            &req,
+            Some(&cors),
+        );
+        assert!(response
+            .headers()
+            .contains(&(cors::ALLOW_ORIGIN_HEADER.to_string(), "*".to_string())));
+    }
+
+    // ---- HEAD ----
+
+    #[test]
+    fn without_body_for_head_keeps_status_and_headers() {
+        let response = HttpResponse::builder()
+            .with_status_code(StatusCode::OK)
+            .with_headers(vec![("content-type".to_string(), "text/html".to_string())])
+            .with_body(b"<h1>hi</h1>" as &[u8])
+            .build();
+
+        let head = without_body_for_head(response.clone(), true);
+        assert_eq!(head.status_code(), StatusCode::OK);
+        assert_eq!(head.headers(), response.headers());
+        assert!(head.body().is_empty());
+
+        let get = without_body_for_head(response.clone(), false);
+        assert_eq!(get.body(), response.body());
+    }
+
+    #[test]
+    fn head_request_without_head_handler_is_not_405() {
+        let root = setup_router();
+        let req = HttpRequest::builder()
+            .with_method(Method::HEAD)
+            .with_url("/")
+            .build();
+        assert!(matches!(
+            root.resolve(&req.get_path().unwrap(), req.method()),
+            RouteResult::Found(..)
+        ));
+    }
+
+    // ---- Path normalization ----
+
+    fn get(url: &str) -> HttpRequest<'static> {
+        HttpRequest::builder()
+            .with_method(Method::GET)
+            .with_url(url)
+            .build()
+    }
+
+    fn with_normalization(normalization: PathNormalization) {
+        set_asset_config(AssetConfig {
+            path_normalization: normalization,
+            ..AssetConfig::default()
+        });
+    }
+
+    #[test]
+    fn lenient_normalization_routes_non_canonical_paths() {
+        with_normalization(PathNormalization::lenient());
+        assert!(non_canonical(&get("/about/")).is_none());
+    }
+
+    #[test]
+    fn redirect_normalization_answers_uncertified_redirect() {
+        with_normalization(PathNormalization {
+            lowercase: true,
+            ..PathNormalization::redirect()
+        });
+        let root = setup_router();
+        let req = get("/About/?tab=2");
+        let response = serve_non_canonical_query(
+            req.clone(),
+            &req.get_path().unwrap(),
+            non_canonical(&req).expect("non-canonical"),
+            &root,
+            false,
+        );
+        assert_eq!(response.status_code(), StatusCode::PERMANENT_REDIRECT);
+        assert!(response
+            .headers()
+            .contains(&("location".to_string(), "/about?tab=2".to_string())));
+        assert!(non_canonical(&get("/about?tab=2")).is_none());
+    }
+
+    #[test]
+    fn redirect_with_query_or_mutating_method_is_not_cached() {
+        with_normalization(PathNormalization::redirect());
+        let root = setup_router();
+        let req = get("/about/?x=1");
+        let response = serve_non_canonical_query(
+            req.clone(),
+            "/about/",
+            non_canonical(&req).unwrap(),
+            &root,
+            true,
+        );
+        assert_eq!(response.upgrade(), Some(true));
+
+        assert!(redirect_is_cacheable(&get("/about/")));
+        assert!(!redirect_is_cacheable(&get("/about/?x=1")));
+        let post = HttpRequest::builder()
+            .with_method(Method::POST)
+            .with_url("/about/")
+            .build();
+        assert!(!redirect_is_cacheable(&post));
+    }
+
+    #[test]
+    fn strict_normalization_answers_not_found() {
+        with_normalization(PathNormalization::strict());
+        let root = setup_router();
+        let req = get("//");
+        let non_canonical = non_canonical(&req).expect("non-canonical");
+        let response = serve_non_canonical_query(req, "//", non_canonical, &root, false);
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+        assert!(super::non_canonical(&get("/")).is_none());
+    }
+
+    // ---- Async handlers ----
+
+    fn async_ok(_: HttpRequest<'static>, params: RouteParams) -> router::HandlerFuture {
+        Box::pin(async move {
+            let id = params.get("id").cloned().unwrap_or_default();
+            HttpResponse::ok(format!("async {id}").into_bytes(), vec![]).build()
+        })
+    }
+
+    fn tag_middleware(
+        req: HttpRequest,
+        params: &RouteParams,
+        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    ) -> HttpResponse<'static> {
+        middleware::on_response(|mut response| {
+            response.add_header(("x-mw".to_string(), "1".to_string()));
+            response
+        });
+        next(req, params)
+    }
+
+    fn async_router() -> RouteNode {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert_async("/items/:id", Method::POST, async_ok);
+        root.insert("/sync", Method::POST, noop_handler);
+        for path in ["/items/:id", "/sync"] {
+            root.set_route_config(
+                path,
+                RouteConfig {
+                    certification: certification::CertificationMode::skip(),
+                    ..RouteConfig::default()
+                },
+            );
+        }
+        root.set_middleware("/", tag_middleware);
+        root
+    }
+
+    thread_local! {
+        static ASYNC_ROUTES: RouteNode = async_router();
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        match future.as_mut().poll(&mut cx) {
+            std::task::Poll::Ready(output) => output,
+            std::task::Poll::Pending => panic!("future did not complete"),
+        }
+    }
+
+    fn post(url: &str) -> HttpRequest<'static> {
+        HttpRequest::builder()
+            .with_method(Method::POST)
+            .with_url(url)
+            .build()
+    }
+
+    #[test]
+    fn update_async_awaits_handler_through_middleware() {
+        let response = block_on(http_request_update_async(post("/items/3"), &ASYNC_ROUTES));
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.body(), b"async 3");
+        assert!(response
+            .headers()
+            .contains(&("x-mw".to_string(), "1".to_string())));
+    }
+
+    #[test]
+    fn update_async_runs_sync_routes_as_before() {
+        let response = block_on(http_request_update_async(post("/sync"), &ASYNC_ROUTES));
+        assert_eq!(response.body(), b"ok");
+        let get = HttpRequest::builder()
+            .with_method(Method::GET)
+            .with_url("/items/3")
+            .build();
+        let response = block_on(http_request_update_async(get, &ASYNC_ROUTES));
+        assert_eq!(response.status_code(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[test]
+    fn sync_paths_do_not_run_async_handlers() {
+        let root = async_router();
+        let response = http_request_update(post("/items/3"), &root);
+        assert_eq!(response.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let get = HttpRequest::builder()
+            .with_method(Method::GET)
+            .with_url("/items/3")
+            .build();
+        let mut root = async_router();
+        root.insert_async("/items/:id", Method::GET, async_ok);
+        let response = http_request(get, &root, HttpRequestOptions { certify: false });
+        assert_eq!(response.upgrade(), Some(true));
+    }
+
+    // ---- IC context ----
+
+    fn mode_handler(_: HttpRequest, _: RouteParams) -> HttpResponse<'static> {
+        let mode = format!("{:?}", ic_context::current_mode());
+        HttpResponse::ok(mode.into_bytes(), vec![]).build()
+    }
+
+    fn mode_router() -> RouteNode {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/mode", Method::GET, mode_handler);
+        root.set_route_config(
+            "/mode",
+            RouteConfig {
+                certification: certification::CertificationMode::skip(),
+                ..RouteConfig::default()
+            },
+        );
+        root
+    }
+
+    #[test]
+    fn handlers_see_the_execution_mode_of_the_entry_point() {
+        let root = mode_router();
+        let response = http_request_update(get("/mode"), &root);
+        assert_eq!(response.body(), b"Update");
+
+        // Serving the handler on the query path needs the IC certificate
+        // API, so check the mode the entry point sets instead.
+        let malformed = HttpRequest::builder()
+            .with_method(Method::GET)
+            .with_url("http://[::bad")
+            .build();
+        http_request(malformed, &root, HttpRequestOptions { certify: false });
+        assert_eq!(ic_context::current_mode(), ExecutionMode::Query);
+    }
+
+    // ---- Cookies ----
+
+    fn login_handler(_: HttpRequest, _: RouteParams) -> HttpResponse<'static> {
+        HttpResponse::ok(
+            b"welcome".to_vec(),
+            vec![cookie::Cookie::new("sid", "abc").into_header()],
+        )
+        .build()
+    }
+
+    #[test]
+    fn responses_that_set_cookies_are_not_cached() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/login", Method::GET, login_handler);
+        let response = http_request_update(get("/login"), &root);
+        assert_eq!(response.body(), b"welcome");
+        assert!(is_per_client(&response));
+        assert!(!dynamic_cache_snapshot()
+            .assets
+            .iter()
+            .any(|asset| asset.path == "/login"));
+    }
+
+    #[derive(serde::Deserialize)]
+    struct PageSearch {
+        page: u32,
+    }
+
+    fn strict_page_handler(req: HttpRequest, _: RouteParams) -> HttpResponse<'static> {
+        let url = req.url().to_string();
+        let query = url.split_once('?').map_or("", |(_, q)| q);
+        match deserialize_search_params_strict::<PageSearch>(query) {
+            Ok(search) => {
+                HttpResponse::ok(format!("page {}", search.page).into_bytes(), vec![]).build()
+            }
+            Err(e) => e.into_response(),
+        }
+    }
+
+    #[test]
+    fn bad_search_params_are_neither_certified_nor_cached() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/pages", Method::GET, strict_page_handler);
+
+        let response = http_request_update(get("/pages?page=abc"), &root);
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(!response
+            .headers()
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("ic-certificate")));
+        assert!(!dynamic_cache_snapshot()
+            .assets
+            .iter()
+            .any(|asset| asset.path == "/pages"));
+
+        // With nothing cached for the path, a good query upgrades instead of
+        // being answered with the earlier 400, and its update call runs the
+        // handler. (Certifying the 200 needs the IC time API.)
+        let good = get("/pages?page=2");
+        let response = http_request(good.clone(), &root, HttpRequestOptions { certify: true });
+        assert_eq!(response.upgrade(), Some(true));
+        let response =
+            root.execute_with_middleware("/pages", strict_page_handler, good, RouteParams::new());
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.body(), b"page 2");
+    }
+
+    #[test]
+    fn vary_cookie_marks_a_response_per_client() {
+        let with = |headers: Vec<(&str, &str)>| {
+            let headers = headers
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            is_per_client(&HttpResponse::ok(Vec::<u8>::new(), headers).build())
+        };
+        assert!(with(vec![("Vary", "Accept-Encoding, Cookie")]));
+        assert!(with(vec![("vary", "*")]));
+        assert!(!with(vec![("vary", "accept-encoding")]));
+        assert!(!with(vec![("cookie-policy", "x")]));
+    }
+
+    // ---- Rate limits ----
+
+    #[test]
+    fn rate_limited_checks_on_query_and_consumes_on_update() {
+        rate_limit::set_rules(vec![RateLimit::new(
+            "/",
+            1,
+            std::time::Duration::from_secs(60),
+        )
+        .per_route()]);
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/posts/:id", Method::GET, noop_handler);
+        let req = get("/posts/1");
+        assert!(rate_limited(&req, &root, false, 0).is_none());
+        assert!(rate_limited(&req, &root, true, 0).is_none());
+
+        // `/posts/2` resolves to the same route and shares its bucket.
+        let response = rate_limited(&get("/posts/2"), &root, false, 0).expect("limited");
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(rate_limited(&req, &root, true, 0).is_some());
+        // Paths that match no route have a bucket of their own.
+        assert!(rate_limited(&get("/missing"), &root, true, 0).is_none());
+        rate_limit::set_rules(Vec::new());
+    }
+
+    // ---- Request body limits ----
+
+    fn body_limit_router() -> RouteNode {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/api/items", Method::POST, noop_handler);
+        root.insert("/upload", Method::POST, noop_handler);
+        root.set_route_config(
+            "/api/items",
+            RouteConfig {
+                max_body: Some(8),
+                accepts: vec!["application/json".to_string()],
+                ..RouteConfig::default()
+            },
+        );
+        root
+    }
+
+    fn post_with_body(url: &str, content_type: &str, body: &[u8]) -> HttpRequest<'static> {
+        HttpRequest::builder()
+            .with_method(Method::POST)
+            .with_url(url)
+            .with_headers(vec![("Content-Type".to_string(), content_type.to_string())])
+            .with_body(body.to_vec())
+            .build()
+    }
+
+    #[test]
+    fn oversized_body_is_refused_before_upgrade() {
+        set_asset_config(AssetConfig::default());
+        let root = body_limit_router();
+        let response = http_request(
+            post_with_body("/api/items", "application/json", b"{\"a\":12345}"),
+            &root,
+            HttpRequestOptions { certify: true },
+        );
+        assert_eq!(response.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.upgrade(), None);
+
+        let response = http_request(
+            post_with_body("/api/items", "application/json", b"{}"),
+            &root,
+            HttpRequestOptions { certify: true },
+        );
+        assert_eq!(response.upgrade(), Some(true));
+    }
+
+    #[test]
+    fn unaccepted_content_type_is_refused() {
+        set_asset_config(AssetConfig::default());
+        let root = body_limit_router();
+        let response =
+            http_request_update(post_with_body("/api/items", "text/plain", b"{}"), &root);
+        assert_eq!(response.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        // An empty body carries no content to check.
+        assert!(route_body_rejection(
+            &post_with_body("/api/items", "text/plain", b""),
+            "/api/items",
+            &root
+        )
+        .is_none());
+        // Routes without `accepts` take any content type.
+        assert!(route_body_rejection(
+            &post_with_body("/upload", "text/plain", b"hi"),
+            "/upload",
+            &root
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn global_max_body_applies_unless_route_overrides_it() {
+        set_asset_config(AssetConfig {
+            max_body: Some(4),
+            ..AssetConfig::default()
+        });
+        let root = body_limit_router();
+        let response = route_body_rejection(
+            &post_with_body("/upload", "image/png", b"12345"),
+            "/upload",
+            &root,
+        )
+        .expect("over the global limit");
+        assert_eq!(response.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        // `/api/items` allows 8 bytes.
+        assert!(route_body_rejection(
+            &post_with_body("/api/items", "application/json", b"[1,2,3]"),
+            "/api/items",
+            &root
+        )
+        .is_none());
+        // Unmatched paths are left to the 404 handling.
+        assert!(route_body_rejection(
+            &post_with_body("/missing", "text/plain", b"12345"),
+            "/missing",
+            &root
+        )
+        .is_none());
+        set_asset_config(AssetConfig::default());
+    }
+
+    // ---- Redirects and rewrites ----
+
+    fn with_rules(redirects: &[Redirect], rewrites: &[Rewrite]) {
+        set_asset_config(AssetConfig::default());
+        REDIRECT_RULES.with_borrow_mut(|r| *r = redirect::RuleTable::new(redirects, rewrites));
+    }
+
+    #[test]
+    fn redirect_rule_answers_before_routing() {
+        with_rules(
+            &[Redirect::moved_permanently("/blog/:slug", "/posts/:slug")],
+            &[],
+        );
+        let root = setup_router();
+        let opts = HttpRequestOptions { certify: false };
+        let response = http_request(get("/blog/hello?ref=feed"), &root, opts);
+        assert_eq!(response.status_code(), StatusCode::MOVED_PERMANENTLY);
+        assert!(response
+            .headers()
+            .contains(&("location".to_string(), "/posts/hello?ref=feed".to_string())));
+    }
+
+    #[test]
+    fn redirect_rule_is_upgraded_when_not_cacheable() {
+        with_rules(&[Redirect::permanent("/old", "/new")], &[]);
+        let (status, location) = match_redirect(&get("/old?x=1")).expect("redirect");
+        let response = serve_redirect_query(&get("/old?x=1"), "/old", status, &location, true);
+        assert_eq!(response.upgrade(), Some(true));
+        assert!(match_redirect(&get("/new")).is_none());
+    }
+
+    #[test]
+    fn rewrite_resolves_destination_route() {
+        with_rules(&[], &[Rewrite::new("/u/:id", "/users/:id")]);
+        assert_eq!(routing_path("/u/7"), "/users/7");
+        assert_eq!(routing_path("/about"), "/about");
+
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/users/:id", Method::GET, noop_handler);
+        let put = HttpRequest::builder()
+            .with_method(Method::PUT)
+            .with_url("/u/7")
+            .build();
+        let response = http_request_update(put, &root);
+        assert_eq!(response.status_code(), StatusCode::METHOD_NOT_ALLOWED);
+    }
 }
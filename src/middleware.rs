@@ -1,13 +1,38 @@
+use std::cell::RefCell;
+
 use ic_http_certification::{HttpRequest, HttpResponse};
 
 use crate::router::RouteParams;
 
+thread_local! {
+    static RESPONSE_HOOKS: RefCell<Vec<ResponseHook>> = const { RefCell::new(Vec::new()) };
+}
+
 /// A middleware function that wraps route handler execution.
 ///
 /// Middleware can inspect/modify the request before calling `next`, short-circuit
 /// by returning a response without calling `next`, or inspect/modify the response
 /// after calling `next`.
 ///
+/// Shared application state is available through
+/// [`AppState::current`](crate::AppState::current), and IC metadata through
+/// [`IcContext::current`](crate::IcContext::current).
+/// Values for the handler can be attached with
+/// [`extensions::insert`](crate::extensions::insert).
+///
+/// # Async routes
+///
+/// Around an async handler the chain runs once, before the handler is
+/// awaited: `next` returns an empty `200` placeholder, and the chain's
+/// response is only used when it short-circuits. Code after `next` sees
+/// the placeholder, not the handler's response, and its changes are
+/// discarded. Middleware that changes the handler's response must do so
+/// with [`on_response`], which works around synchronous and async handlers
+/// alike; see
+/// [`execute_async_with_middleware`](crate::router::execute_async_with_middleware).
+/// The build script refuses to generate the route tree when a
+/// `middleware.rs` that uses the result of `next` covers an async route.
+///
 /// # Example
 ///
 /// ```ignore
@@ -27,3 +52,63 @@ pub type MiddlewareFn = fn(
     params: &RouteParams,
     next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
 ) -> HttpResponse<'static>;
+
+/// A change to the handler's response, registered with [`on_response`].
+pub type ResponseHook = Box<dyn FnOnce(HttpResponse<'static>) -> HttpResponse<'static>>;
+
+/// Run `hook` on the handler's response once the handler has returned it.
+///
+/// Call this before `next`. Each hook runs exactly once, including around
+/// async handlers, where code after `next` only sees a placeholder. Hooks
+/// run innermost middleware first, before any code after `next`. They are
+/// dropped when the chain short-circuits or the handler returns
+/// [`HandlerResult::NotModified`](crate::HandlerResult::NotModified).
+///
+/// ```ignore
+/// pub fn middleware(
+///     req: HttpRequest,
+///     params: &RouteParams,
+///     next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+/// ) -> HttpResponse<'static> {
+///     ic_asset_router::middleware::on_response(|mut response| {
+///         response.add_header(("x-frame-options".into(), "DENY".into()));
+///         response
+///     });
+///     next(req, params)
+/// }
+/// ```
+pub fn on_response(hook: impl FnOnce(HttpResponse<'static>) -> HttpResponse<'static> + 'static) {
+    RESPONSE_HOOKS.with_borrow_mut(|hooks| hooks.push(Box::new(hook)));
+}
+
+/// Take the hooks registered so far by the chain being dispatched.
+pub(crate) fn take_response_hooks() -> Vec<ResponseHook> {
+    RESPONSE_HOOKS.with_borrow_mut(std::mem::take)
+}
+
+/// Run `hooks` on `response`, the last registered first.
+pub(crate) fn run_response_hooks(
+    hooks: Vec<ResponseHook>,
+    response: HttpResponse<'static>,
+) -> HttpResponse<'static> {
+    hooks
+        .into_iter()
+        .rev()
+        .fold(response, |response, hook| hook(response))
+}
+
+/// Call the handler at the center of a chain and run the hooks registered
+/// on the way in on its response.
+pub(crate) fn handle(handler: impl FnOnce() -> HttpResponse<'static>) -> HttpResponse<'static> {
+    let hooks = take_response_hooks();
+    run_response_hooks(hooks, handler())
+}
+
+/// Run `f` as the dispatch of a new chain: it starts with no response
+/// hooks, and any left over when it returns are dropped.
+pub(crate) fn scope<R>(f: impl FnOnce() -> R) -> R {
+    take_response_hooks();
+    let result = f();
+    take_response_hooks();
+    result
+}
@@ -0,0 +1,474 @@
+//! `multipart/form-data` request body parsing (RFC 7578).
+//!
+//! Used by [`RouteContext::multipart()`](crate::context::RouteContext::multipart)
+//! to handle HTML forms with file uploads. The whole body is already in
+//! memory on the IC, so the parser works on a byte slice and returns owned
+//! parts; there is no streaming API.
+
+use std::str::Utf8Error;
+
+/// Default maximum body size accepted by [`RouteContext::multipart()`](crate::context::RouteContext::multipart): 2 MiB.
+///
+/// Matches the ingress message limit of the IC, so in practice only
+/// smaller explicit limits change behavior.
+pub const DEFAULT_MAX_MULTIPART_SIZE: usize = 2 * 1024 * 1024;
+
+/// Error returned when a `multipart/form-data` body cannot be parsed.
+#[derive(Debug)]
+pub enum MultipartError {
+    /// The `content-type` header is missing, is not `multipart/form-data`,
+    /// or has no `boundary` parameter.
+    MissingBoundary,
+    /// The body exceeds the configured maximum size.
+    TooLarge {
+        /// The configured maximum in bytes.
+        limit: usize,
+        /// The actual body size in bytes.
+        actual: usize,
+    },
+    /// The body does not follow the multipart format.
+    Malformed(String),
+    /// A text field is not valid UTF-8 (returned by [`Multipart::fields_as()`]).
+    Utf8 {
+        /// The field name.
+        name: String,
+        /// The underlying UTF-8 error.
+        source: Utf8Error,
+    },
+    /// Deserializing the text fields into the target type failed.
+    Deserialize(serde_urlencoded::de::Error),
+}
+
+impl std::fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingBoundary => {
+                write!(f, "content-type is not multipart/form-data with a boundary")
+            }
+            Self::TooLarge { limit, actual } => {
+                write!(f, "multipart body is {actual} bytes, limit is {limit}")
+            }
+            Self::Malformed(msg) => write!(f, "malformed multipart body: {msg}"),
+            Self::Utf8 { name, source } => {
+                write!(f, "multipart field `{name}` is not valid UTF-8: {source}")
+            }
+            Self::Deserialize(e) => write!(f, "multipart deserialization failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Utf8 { source, .. } => Some(source),
+            Self::Deserialize(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A single part of a `multipart/form-data` body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultipartPart {
+    /// The form field name from `Content-Disposition: form-data; name="..."`.
+    pub name: String,
+    /// The uploaded file name, present for file inputs.
+    pub filename: Option<String>,
+    /// The part's `Content-Type`, if the client sent one.
+    pub content_type: Option<String>,
+    /// The raw part body.
+    pub data: Vec<u8>,
+}
+
+impl MultipartPart {
+    /// Returns `true` if this part is a file upload (it has a filename).
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// Returns the part body as a UTF-8 string.
+    pub fn text(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.data)
+    }
+}
+
+/// A parsed `multipart/form-data` body.
+///
+/// Parts are kept in request order. Text fields and file uploads are told
+/// apart by the presence of a filename.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// pub fn post(ctx: RouteContext<()>) -> HttpResponse<'static> {
+///     let form = match ctx.multipart() {
+///         Ok(form) => form,
+///         Err(e) => return bad_request(&e.to_string()),
+///     };
+///     let title = form.field("title").unwrap_or_default();
+///     if let Some(avatar) = form.file("avatar") {
+///         store(avatar.filename.as_deref(), avatar.content_type.as_deref(), &avatar.data);
+///     }
+///     // ...
+///     # todo!()
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Multipart {
+    parts: Vec<MultipartPart>,
+}
+
+impl Multipart {
+    /// Parse `body` using the boundary from the request's `content_type`
+    /// header value.
+    ///
+    /// Returns [`MultipartError::TooLarge`] without parsing if `body` is
+    /// longer than `max_size` bytes.
+    pub fn parse(content_type: &str, body: &[u8], max_size: usize) -> Result<Self, MultipartError> {
+        if body.len() > max_size {
+            return Err(MultipartError::TooLarge {
+                limit: max_size,
+                actual: body.len(),
+            });
+        }
+        let boundary = boundary_from_content_type(content_type)?;
+        parse_parts(body, &boundary).map(|parts| Multipart { parts })
+    }
+
+    /// All parts in request order.
+    pub fn parts(&self) -> &[MultipartPart] {
+        &self.parts
+    }
+
+    /// Text fields (parts without a filename).
+    pub fn fields(&self) -> impl Iterator<Item = &MultipartPart> {
+        self.parts.iter().filter(|p| !p.is_file())
+    }
+
+    /// File uploads (parts with a filename).
+    pub fn files(&self) -> impl Iterator<Item = &MultipartPart> {
+        self.parts.iter().filter(|p| p.is_file())
+    }
+
+    /// Returns the value of the first text field named `name`.
+    ///
+    /// Returns `None` if there is no such field or it is not valid UTF-8.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields()
+            .find(|p| p.name == name)
+            .and_then(|p| p.text().ok())
+    }
+
+    /// Returns the first file upload named `name`.
+    pub fn file(&self, name: &str) -> Option<&MultipartPart> {
+        self.files().find(|p| p.name == name)
+    }
+
+    /// Deserialize the text fields into `T`, ignoring file parts.
+    ///
+    /// Uses the same rules as [`RouteContext::form()`](crate::context::RouteContext::form):
+    /// values arrive as strings and are parsed by `serde_urlencoded`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// #[derive(serde::Deserialize)]
+    /// struct Upload { title: String, public: Option<bool> }
+    ///
+    /// let meta: Upload = ctx.multipart()?.fields_as()?;
+    /// ```
+    pub fn fields_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, MultipartError> {
+        let pairs = self
+            .fields()
+            .map(|p| {
+                p.text()
+                    .map(|value| (p.name.as_str(), value))
+                    .map_err(|source| MultipartError::Utf8 {
+                        name: p.name.clone(),
+                        source,
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Round-trip through the urlencoded form so text fields deserialize
+        // exactly like `RouteContext::form()` bodies.
+        let encoded = serde_urlencoded::to_string(pairs)
+            .map_err(|e| MultipartError::Malformed(e.to_string()))?;
+        serde_urlencoded::from_str(&encoded).map_err(MultipartError::Deserialize)
+    }
+}
+
+/// Extract the `boundary` parameter from a `multipart/form-data` content type.
+fn boundary_from_content_type(content_type: &str) -> Result<String, MultipartError> {
+    let mut params = content_type.split(';');
+    let mime = params.next().unwrap_or_default().trim();
+    if !mime.eq_ignore_ascii_case("multipart/form-data") {
+        return Err(MultipartError::MissingBoundary);
+    }
+    params
+        .filter_map(|param| param.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .filter(|boundary| !boundary.is_empty())
+        .ok_or(MultipartError::MissingBoundary)
+}
+
+fn parse_parts(body: &[u8], boundary: &str) -> Result<Vec<MultipartPart>, MultipartError> {
+    let delimiter = format!("--{boundary}").into_bytes();
+    let part_end = format!("\r\n--{boundary}").into_bytes();
+
+    // Anything before the first delimiter is preamble and ignored.
+    let mut pos = find(body, &delimiter, 0)
+        .ok_or_else(|| MultipartError::Malformed("missing opening boundary".into()))?
+        + delimiter.len();
+    let mut parts = Vec::new();
+
+    loop {
+        let rest = &body[pos..];
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        if !rest.starts_with(b"\r\n") {
+            return Err(MultipartError::Malformed(
+                "boundary not followed by CRLF".into(),
+            ));
+        }
+        pos += 2;
+
+        let headers_end = find(body, b"\r\n\r\n", pos)
+            .ok_or_else(|| MultipartError::Malformed("unterminated part headers".into()))?;
+        let headers = std::str::from_utf8(&body[pos..headers_end])
+            .map_err(|_| MultipartError::Malformed("part headers are not valid UTF-8".into()))?;
+        let data_start = headers_end + 4;
+        let data_end = find(body, &part_end, data_start)
+            .ok_or_else(|| MultipartError::Malformed("missing closing boundary".into()))?;
+
+        parts.push(parse_part(headers, body[data_start..data_end].to_vec())?);
+        pos = data_end + part_end.len();
+    }
+}
+
+fn parse_part(headers: &str, data: Vec<u8>) -> Result<MultipartPart, MultipartError> {
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+
+    for line in headers.split("\r\n") {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.eq_ignore_ascii_case("content-disposition") {
+            for (param, value) in disposition_params(value) {
+                if param.eq_ignore_ascii_case("name") {
+                    name = Some(value);
+                } else if param.eq_ignore_ascii_case("filename") {
+                    filename = Some(value);
+                }
+            }
+        } else if key.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.trim().to_string());
+        }
+    }
+
+    let name = name.ok_or_else(|| {
+        MultipartError::Malformed("part is missing a Content-Disposition name".into())
+    })?;
+    Ok(MultipartPart {
+        name,
+        filename,
+        content_type,
+        data,
+    })
+}
+
+/// Parse `form-data; name="a"; filename="b.txt"` into `(key, value)` pairs,
+/// unquoting quoted values and honouring `\"` escapes.
+fn disposition_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = value.chars().peekable();
+
+    loop {
+        // Skip to the next parameter.
+        while chars.next_if(|&c| c != ';').is_some() {}
+        if chars.next().is_none() {
+            return params;
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '=' && c != ';') {
+            key.push(c);
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            continue;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut val = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => val.extend(chars.next()),
+                    '"' => break,
+                    c => val.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ';') {
+                val.push(c);
+            }
+            val = val.trim_end().to_string();
+        }
+        params.push((key.trim().to_string(), val));
+    }
+}
+
+/// Find the first occurrence of `needle` in `haystack` at or after `from`.
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| i + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CT: &str = "multipart/form-data; boundary=XyZ";
+
+    fn body(parts: &[&str]) -> Vec<u8> {
+        let mut out = String::from("preamble\r\n");
+        for part in parts {
+            out.push_str("--XyZ\r\n");
+            out.push_str(part);
+            out.push_str("\r\n");
+        }
+        out.push_str("--XyZ--\r\n");
+        out.into_bytes()
+    }
+
+    #[test]
+    fn parses_text_fields_and_files() {
+        let body = body(&[
+            "Content-Disposition: form-data; name=\"title\"\r\n\r\nHello world",
+            "Content-Disposition: form-data; name=\"avatar\"; filename=\"me.png\"\r\nContent-Type: image/png\r\n\r\n\u{1}\u{2}\r\n\u{3}",
+        ]);
+        let form = Multipart::parse(CT, &body, DEFAULT_MAX_MULTIPART_SIZE).unwrap();
+
+        assert_eq!(form.parts().len(), 2);
+        assert_eq!(form.field("title"), Some("Hello world"));
+        assert_eq!(form.fields().count(), 1);
+
+        let avatar = form.file("avatar").unwrap();
+        assert_eq!(avatar.filename.as_deref(), Some("me.png"));
+        assert_eq!(avatar.content_type.as_deref(), Some("image/png"));
+        assert_eq!(avatar.data, b"\x01\x02\r\n\x03");
+        assert!(form.field("avatar").is_none());
+    }
+
+    #[test]
+    fn empty_form_and_empty_values() {
+        let form = Multipart::parse(CT, b"--XyZ--", DEFAULT_MAX_MULTIPART_SIZE).unwrap();
+        assert!(form.parts().is_empty());
+
+        let body = body(&["Content-Disposition: form-data; name=\"note\"\r\n\r\n"]);
+        let form = Multipart::parse(CT, &body, DEFAULT_MAX_MULTIPART_SIZE).unwrap();
+        assert_eq!(form.field("note"), Some(""));
+    }
+
+    #[test]
+    fn quoted_boundary_and_escaped_filename() {
+        let body = b"--a b\r\nContent-Disposition: form-data; name=\"f\"; filename=\"say \\\"hi\\\".txt\"\r\n\r\nx\r\n--a b--";
+        let form = Multipart::parse(
+            "Multipart/Form-Data; charset=utf-8; boundary=\"a b\"",
+            body,
+            DEFAULT_MAX_MULTIPART_SIZE,
+        )
+        .unwrap();
+        assert_eq!(
+            form.file("f").unwrap().filename.as_deref(),
+            Some("say \"hi\".txt")
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_content_type_and_missing_boundary() {
+        for ct in [
+            "application/json",
+            "multipart/form-data",
+            "multipart/form-data; boundary=",
+        ] {
+            assert!(matches!(
+                Multipart::parse(ct, b"", DEFAULT_MAX_MULTIPART_SIZE),
+                Err(MultipartError::MissingBoundary)
+            ));
+        }
+    }
+
+    #[test]
+    fn enforces_max_size() {
+        let body = body(&["Content-Disposition: form-data; name=\"a\"\r\n\r\n0123456789"]);
+        let err = Multipart::parse(CT, &body, 16).unwrap_err();
+        assert!(matches!(
+            err,
+            MultipartError::TooLarge { limit: 16, actual } if actual == body.len()
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        let cases: [&[u8]; 4] = [
+            b"no boundary here",
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nvalue without end",
+            b"--XyZ\r\nContent-Type: text/plain\r\n\r\nx\r\n--XyZ--",
+            b"--XyZjunk",
+        ];
+        for case in cases {
+            assert!(
+                matches!(
+                    Multipart::parse(CT, case, DEFAULT_MAX_MULTIPART_SIZE),
+                    Err(MultipartError::Malformed(_))
+                ),
+                "expected Malformed for {:?}",
+                String::from_utf8_lossy(case)
+            );
+        }
+    }
+
+    #[test]
+    fn fields_as_deserializes_text_fields_only() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Upload {
+            title: String,
+            count: u32,
+            public: Option<bool>,
+        }
+
+        let body = body(&[
+            "Content-Disposition: form-data; name=\"title\"\r\n\r\nA & B = C",
+            "Content-Disposition: form-data; name=\"count\"\r\n\r\n3",
+            "Content-Disposition: form-data; name=\"doc\"; filename=\"d.txt\"\r\n\r\nignored",
+        ]);
+        let form = Multipart::parse(CT, &body, DEFAULT_MAX_MULTIPART_SIZE).unwrap();
+        let upload: Upload = form.fields_as().unwrap();
+        assert_eq!(upload.title, "A & B = C");
+        assert_eq!(upload.count, 3);
+        assert_eq!(upload.public, None);
+    }
+
+    #[test]
+    fn fields_as_reports_type_errors() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Upload {
+            #[allow(dead_code)]
+            count: u32,
+        }
+
+        let body = body(&["Content-Disposition: form-data; name=\"count\"\r\n\r\nmany"]);
+        let form = Multipart::parse(CT, &body, DEFAULT_MAX_MULTIPART_SIZE).unwrap();
+        assert!(matches!(
+            form.fields_as::<Upload>(),
+            Err(MultipartError::Deserialize(_))
+        ));
+    }
+}
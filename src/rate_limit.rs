@@ -0,0 +1,501 @@
+//! Rate limits for requests that reach the update path.
+//!
+//! Every upgrade to `http_request_update` costs cycles and a consensus
+//! round, and any client can force one by requesting an expired path, an
+//! unknown path or an unsafe method. A [`RateLimit`] gives the requests
+//! under a path prefix a token bucket: `capacity` requests, refilled evenly
+//! over `period`. Register limits with
+//! [`SetupBuilder::with_rate_limits`](crate::SetupBuilder::with_rate_limits):
+//!
+//! ```rust,ignore
+//! ic_asset_router::setup(routes)
+//!     .with_rate_limits(vec![
+//!         // At most 100 updates per minute across the whole site...
+//!         RateLimit::new("/", 100, Duration::from_secs(60)),
+//!         // ...and 10 per minute for each API key under /api.
+//!         RateLimit::new("/api", 10, Duration::from_secs(60)).per_header("x-api-key"),
+//!     ])
+//!     .build();
+//! ```
+//!
+//! Query calls cannot change canister state, so buckets are only drawn down
+//! in update calls. [`http_request`](crate::http_request) checks the buckets
+//! before it upgrades a request and answers `429 Too Many Requests` itself
+//! when one is empty, so a limited client no longer reaches the update path.
+//! [`http_request_update`](crate::http_request_update) takes a token from
+//! every matching bucket and answers `429` when any is empty. The query-path
+//! `429` is not certified, so the HTTP gateway may report it as a failed
+//! verification; either way the request does not get to the update path.
+//!
+//! Header-keyed buckets trust the header: a client can pick a new value for
+//! every request. Key by header to share a budget fairly between clients
+//! that authenticate, and keep a prefix-wide limit as the ceiling. At most
+//! [`MAX_BUCKETS`] buckets are kept: past that, full buckets are dropped and
+//! then the least recently used ones, so a flood of new keys can reset the
+//! buckets of idle clients but cannot grow the canister's memory.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ic_http_certification::{HttpRequest, HttpResponse, StatusCode};
+use sha2::{Digest, Sha256};
+
+thread_local! {
+    static LIMITER: RefCell<Limiter> = RefCell::new(Limiter::default());
+}
+
+/// Buckets kept before the limiter trims them.
+pub const MAX_BUCKETS: usize = 10_000;
+
+/// Buckets left after a trim. Trimming costs a pass over every bucket, so
+/// it leaves room for many new keys before the next one.
+const TRIMMED_BUCKETS: usize = MAX_BUCKETS - MAX_BUCKETS / 8;
+
+/// Longest header value used as a bucket key as is; longer values are
+/// replaced by their SHA-256 hash.
+const MAX_KEY_LEN: usize = 128;
+
+/// Bucket key for requests that match no route, under [`RateLimitKey::Route`].
+const NO_ROUTE: &str = "<no route>";
+
+/// How the requests under a [`RateLimit`]'s prefix are split into buckets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RateLimitKey {
+    /// One bucket for all requests under the prefix.
+    Prefix,
+    /// One bucket per route pattern, such as `/posts/:id`. Requests that
+    /// match no route share one bucket.
+    Route,
+    /// One bucket per value of the named request header. Requests without
+    /// the header share one bucket. Values longer than 128 bytes are keyed
+    /// by their hash.
+    Header(String),
+}
+
+/// A token-bucket limit on the update calls for the paths under a prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    prefix: String,
+    capacity: u32,
+    period: Duration,
+    key: RateLimitKey,
+}
+
+impl RateLimit {
+    /// Allow bursts of `capacity` update calls for paths under `prefix`,
+    /// refilled at `capacity` per `period`. `"/"` covers every path.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero or `period` is shorter than a
+    /// nanosecond.
+    pub fn new(prefix: &str, capacity: u32, period: Duration) -> Self {
+        assert!(capacity > 0, "rate limit capacity must be positive");
+        assert!(period.as_nanos() > 0, "rate limit period must be positive");
+        Self {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            capacity,
+            period,
+            key: RateLimitKey::Prefix,
+        }
+    }
+
+    /// Split the requests into buckets by `key`.
+    pub fn keyed_by(mut self, key: RateLimitKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Give every route pattern its own bucket.
+    pub fn per_route(self) -> Self {
+        self.keyed_by(RateLimitKey::Route)
+    }
+
+    /// Give every value of the `name` request header its own bucket.
+    pub fn per_header(self, name: &str) -> Self {
+        self.keyed_by(RateLimitKey::Header(name.to_string()))
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(self.prefix.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    fn bucket_key(&self, req: &HttpRequest, route: Option<&str>) -> String {
+        match &self.key {
+            RateLimitKey::Prefix => String::new(),
+            RateLimitKey::Route => route.unwrap_or(NO_ROUTE).to_string(),
+            RateLimitKey::Header(name) => req
+                .headers()
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| header_key(v))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Credit of a full bucket; a request costs [`Self::cost`].
+    fn full(&self) -> u128 {
+        u128::from(self.capacity) * self.cost()
+    }
+
+    fn cost(&self) -> u128 {
+        self.period.as_nanos()
+    }
+}
+
+/// A request refused because a bucket is empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimited {
+    /// How long until the bucket holds a token again.
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    /// A `429 Too Many Requests` response with a `Retry-After` header in
+    /// whole seconds.
+    pub fn into_response(self) -> HttpResponse<'static> {
+        let seconds = self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0);
+        HttpResponse::builder()
+            .with_status_code(StatusCode::TOO_MANY_REQUESTS)
+            .with_headers(vec![
+                ("content-type".to_string(), "text/plain".to_string()),
+                ("retry-after".to_string(), seconds.max(1).to_string()),
+            ])
+            .with_body(Cow::<[u8]>::Owned(b"Too Many Requests".to_vec()))
+            .build()
+    }
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rate limited; retry after {:?}", self.retry_after)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// The bucket key for header value `value`, at most [`MAX_KEY_LEN`] bytes
+/// long.
+fn header_key(value: &str) -> String {
+    if value.len() <= MAX_KEY_LEN {
+        return value.to_string();
+    }
+    Sha256::digest(value.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Token count, in nanosecond-weighted credit, as of `updated`.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    credit: u128,
+    updated: u64,
+}
+
+impl Bucket {
+    /// The credit at `now`, refilled at `rule`'s rate up to a full bucket.
+    fn credit_at(&self, rule: &RateLimit, now: u64) -> u128 {
+        let elapsed = u128::from(now.saturating_sub(self.updated));
+        (self.credit + elapsed * u128::from(rule.capacity)).min(rule.full())
+    }
+}
+
+#[derive(Default)]
+struct Limiter {
+    rules: Vec<RateLimit>,
+    buckets: HashMap<(usize, String), Bucket>,
+}
+
+impl Limiter {
+    /// The rules matching `req` with their bucket keys and current credit.
+    fn matching(
+        &self,
+        req: &HttpRequest,
+        route: Option<&str>,
+        now: u64,
+    ) -> Vec<((usize, String), u128)> {
+        let Ok(path) = req.get_path() else {
+            return Vec::new();
+        };
+        self.rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.matches(&path))
+            .map(|(index, rule)| {
+                let key = (index, rule.bucket_key(req, route));
+                let credit = self
+                    .buckets
+                    .get(&key)
+                    .map_or(rule.full(), |bucket| bucket.credit_at(rule, now));
+                (key, credit)
+            })
+            .collect()
+    }
+
+    /// The longest wait among the buckets in `matching` without a token.
+    fn refusal(&self, matching: &[((usize, String), u128)]) -> Option<RateLimited> {
+        matching
+            .iter()
+            .filter_map(|((index, _), credit)| {
+                let rule = &self.rules[*index];
+                let missing = rule.cost().checked_sub(*credit).filter(|m| *m > 0)?;
+                let nanos = missing.div_ceil(u128::from(rule.capacity));
+                Some(Duration::from_nanos(
+                    u64::try_from(nanos).unwrap_or(u64::MAX),
+                ))
+            })
+            .max()
+            .map(|retry_after| RateLimited { retry_after })
+    }
+
+    /// Shrink `buckets` to [`TRIMMED_BUCKETS`]: drop the full ones, which
+    /// behave like missing ones, then the least recently updated.
+    fn trim(&mut self, now: u64) {
+        let Limiter { rules, buckets } = self;
+        buckets.retain(|(index, _), bucket| {
+            let rule = &rules[*index];
+            bucket.credit_at(rule, now) < rule.full()
+        });
+        let excess = buckets.len().saturating_sub(TRIMMED_BUCKETS);
+        if excess == 0 {
+            return;
+        }
+        let mut updated: Vec<u64> = buckets.values().map(|b| b.updated).collect();
+        let cutoff = *updated.select_nth_unstable(excess - 1).1;
+        let mut ties = excess - updated.iter().filter(|u| **u < cutoff).count();
+        buckets.retain(|_, bucket| match bucket.updated.cmp(&cutoff) {
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal if ties > 0 => {
+                ties -= 1;
+                false
+            }
+            _ => true,
+        });
+    }
+}
+
+/// Replace the registered limits and forget all buckets.
+pub(crate) fn set_rules(rules: Vec<RateLimit>) {
+    LIMITER.with_borrow_mut(|limiter| {
+        *limiter = Limiter {
+            rules,
+            buckets: HashMap::new(),
+        }
+    });
+}
+
+/// Whether any limit is registered.
+pub(crate) fn is_enabled() -> bool {
+    LIMITER.with_borrow(|limiter| !limiter.rules.is_empty())
+}
+
+/// Whether `req` would be let through at `now` (IC time in nanoseconds),
+/// without drawing from any bucket.
+///
+/// `route` is the route pattern the request resolves to, used by
+/// [`RateLimitKey::Route`] limits.
+pub fn check(req: &HttpRequest, route: Option<&str>, now: u64) -> Result<(), RateLimited> {
+    LIMITER.with_borrow(
+        |limiter| match limiter.refusal(&limiter.matching(req, route, now)) {
+            Some(limited) => Err(limited),
+            None => Ok(()),
+        },
+    )
+}
+
+/// Take a token for `req` at `now` from every matching bucket, or none if
+/// any of them is empty.
+///
+/// Only call this from update calls: changes made in a query are discarded.
+pub fn consume(req: &HttpRequest, route: Option<&str>, now: u64) -> Result<(), RateLimited> {
+    LIMITER.with_borrow_mut(|limiter| {
+        let matching = limiter.matching(req, route, now);
+        if let Some(limited) = limiter.refusal(&matching) {
+            return Err(limited);
+        }
+        for (key, credit) in matching {
+            let cost = limiter.rules[key.0].cost();
+            limiter.buckets.insert(
+                key,
+                Bucket {
+                    credit: credit - cost,
+                    updated: now,
+                },
+            );
+        }
+        if limiter.buckets.len() > MAX_BUCKETS {
+            limiter.trim(now);
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    fn req(url: &str, headers: &[(&str, &str)]) -> HttpRequest<'static> {
+        HttpRequest::builder()
+            .with_url(url)
+            .with_headers(
+                headers
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn bucket_allows_a_burst_then_refills_over_the_period() {
+        set_rules(vec![RateLimit::new("/", 2, Duration::from_secs(10))]);
+        let r = req("/a", &[]);
+        assert_eq!(consume(&r, None, 0), Ok(()));
+        assert_eq!(consume(&r, None, 0), Ok(()));
+        assert_eq!(
+            consume(&r, None, SECOND),
+            Err(RateLimited {
+                retry_after: Duration::from_secs(4)
+            })
+        );
+        // One token comes back every 5 seconds.
+        assert!(check(&r, None, 4 * SECOND).is_err());
+        assert_eq!(check(&r, None, 5 * SECOND), Ok(()));
+        assert_eq!(consume(&r, None, 5 * SECOND), Ok(()));
+        assert!(consume(&r, None, 5 * SECOND).is_err());
+    }
+
+    #[test]
+    fn check_does_not_draw_from_the_bucket() {
+        set_rules(vec![RateLimit::new("/", 1, Duration::from_secs(60))]);
+        let r = req("/a", &[]);
+        for _ in 0..3 {
+            assert_eq!(check(&r, None, 0), Ok(()));
+        }
+        assert_eq!(consume(&r, None, 0), Ok(()));
+        assert!(check(&r, None, 0).is_err());
+    }
+
+    #[test]
+    fn limits_apply_only_under_their_prefix() {
+        set_rules(vec![RateLimit::new("/api/", 1, Duration::from_secs(60))]);
+        assert_eq!(consume(&req("/api/items", &[]), None, 0), Ok(()));
+        assert!(consume(&req("/api", &[]), None, 0).is_err());
+        assert_eq!(consume(&req("/apiary", &[]), None, 0), Ok(()));
+        assert_eq!(consume(&req("/about", &[]), None, 0), Ok(()));
+    }
+
+    #[test]
+    fn route_and_header_keys_split_buckets() {
+        set_rules(vec![
+            RateLimit::new("/", 1, Duration::from_secs(60)).per_route()
+        ]);
+        let r = req("/posts/1", &[]);
+        assert_eq!(consume(&r, Some("/posts/:id"), 0), Ok(()));
+        assert!(consume(&req("/posts/2", &[]), Some("/posts/:id"), 0).is_err());
+        assert_eq!(consume(&r, Some("/posts"), 0), Ok(()));
+        assert_eq!(consume(&req("/nope", &[]), None, 0), Ok(()));
+        assert!(consume(&req("/other", &[]), None, 0).is_err());
+
+        set_rules(vec![
+            RateLimit::new("/", 1, Duration::from_secs(60)).per_header("x-api-key")
+        ]);
+        assert_eq!(consume(&req("/", &[("X-Api-Key", "a")]), None, 0), Ok(()));
+        assert_eq!(consume(&req("/", &[("x-api-key", "b")]), None, 0), Ok(()));
+        assert!(consume(&req("/", &[("x-api-key", "a")]), None, 0).is_err());
+    }
+
+    #[test]
+    fn refused_requests_draw_from_no_bucket() {
+        set_rules(vec![
+            RateLimit::new("/", 2, Duration::from_secs(60)),
+            RateLimit::new("/api", 1, Duration::from_secs(60)),
+        ]);
+        assert_eq!(consume(&req("/api", &[]), None, 0), Ok(()));
+        assert!(consume(&req("/api", &[]), None, 0).is_err());
+        // The site-wide bucket still has the token the refusal did not take.
+        assert_eq!(consume(&req("/", &[]), None, 0), Ok(()));
+        assert!(consume(&req("/", &[]), None, 0).is_err());
+    }
+
+    fn consume_as(client: &str, now: u64) {
+        consume(&req("/", &[("x-client", client)]), None, now).unwrap();
+    }
+
+    fn bucket_count() -> usize {
+        LIMITER.with_borrow(|limiter| limiter.buckets.len())
+    }
+
+    fn has_bucket(client: &str) -> bool {
+        LIMITER.with_borrow(|limiter| limiter.buckets.contains_key(&(0, client.to_string())))
+    }
+
+    #[test]
+    fn full_buckets_are_dropped_past_the_bucket_cap() {
+        set_rules(vec![
+            RateLimit::new("/", 1, Duration::from_secs(1)).per_header("x-client")
+        ]);
+        for i in 0..MAX_BUCKETS / 2 {
+            consume_as(&i.to_string(), 0);
+        }
+        for i in MAX_BUCKETS / 2..MAX_BUCKETS {
+            consume_as(&i.to_string(), 2 * SECOND);
+        }
+        consume_as("late", 2 * SECOND);
+        assert_eq!(bucket_count(), MAX_BUCKETS / 2 + 1);
+        assert!(!has_bucket("0"));
+    }
+
+    #[test]
+    fn least_recently_used_buckets_are_dropped_past_the_bucket_cap() {
+        set_rules(vec![
+            RateLimit::new("/", 1, Duration::from_secs(60)).per_header("x-client")
+        ]);
+        for i in 0..=MAX_BUCKETS {
+            consume_as(&i.to_string(), i as u64);
+        }
+        assert_eq!(bucket_count(), TRIMMED_BUCKETS);
+        assert!(!has_bucket("0"));
+        assert!(has_bucket(&MAX_BUCKETS.to_string()));
+
+        for i in 0..3 * MAX_BUCKETS {
+            consume_as(&format!("flood-{i}"), SECOND);
+            assert!(bucket_count() <= MAX_BUCKETS);
+        }
+    }
+
+    #[test]
+    fn long_header_values_are_keyed_by_hash() {
+        set_rules(vec![
+            RateLimit::new("/", 1, Duration::from_secs(60)).per_header("x-client")
+        ]);
+        let long = "k".repeat(10_000);
+        consume_as(&long, 0);
+        LIMITER.with_borrow(|limiter| {
+            assert!(limiter
+                .buckets
+                .keys()
+                .all(|(_, key)| key.len() <= MAX_KEY_LEN));
+        });
+        assert!(consume(&req("/", &[("x-client", &long)]), None, 0).is_err());
+        consume_as(&"k".repeat(10_001), 0);
+    }
+
+    #[test]
+    fn response_is_429_with_retry_after_in_whole_seconds() {
+        let response = RateLimited {
+            retry_after: Duration::from_millis(1500),
+        }
+        .into_response();
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response
+            .headers()
+            .contains(&("retry-after".to_string(), "2".to_string())));
+    }
+}
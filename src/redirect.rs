@@ -0,0 +1,469 @@
+//! Declarative redirects and rewrites.
+//!
+//! A [`Redirect`] answers every request whose path matches its source pattern
+//! with a `301`/`302`/`307`/`308` pointing at its destination. A [`Rewrite`]
+//! routes the request as if it had been made to its destination, without the
+//! client noticing. Both are registered through
+//! [`SetupBuilder::with_redirects`](crate::SetupBuilder::with_redirects) and
+//! [`SetupBuilder::with_rewrites`](crate::SetupBuilder::with_rewrites).
+//!
+//! Source patterns use the route tree syntax (`:name`, `:name[int]`,
+//! `:name?`, `*`, `*name`) and are matched by a [`RouteNode`] trie, so they
+//! follow the same precedence as routes. Destinations refer to captured
+//! params by name: `:name` is replaced by the param value (and dropped when
+//! an optional param was absent), `*` or `*name` by the captured remainder.
+//! A redirect destination's query string may use them as values, as in
+//! `/find?q=:term`; a pair whose param was absent is dropped.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use ic_http_certification::{HttpRequest, HttpResponse, Method, StatusCode};
+
+use crate::canonical;
+use crate::router::{split_param_constraint, NodeType, RouteNode, RouteParams};
+
+/// A redirect from requests matching `source` to `destination`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirect {
+    source: String,
+    destination: String,
+    status: StatusCode,
+}
+
+impl Redirect {
+    /// A `308 Permanent Redirect`: permanent, method and body preserved.
+    pub fn permanent(source: &str, destination: &str) -> Self {
+        Self::new(source, destination, StatusCode::PERMANENT_REDIRECT)
+    }
+
+    /// A `307 Temporary Redirect`: temporary, method and body preserved.
+    pub fn temporary(source: &str, destination: &str) -> Self {
+        Self::new(source, destination, StatusCode::TEMPORARY_REDIRECT)
+    }
+
+    /// A `301 Moved Permanently`: permanent; clients may switch to `GET`.
+    pub fn moved_permanently(source: &str, destination: &str) -> Self {
+        Self::new(source, destination, StatusCode::MOVED_PERMANENTLY)
+    }
+
+    /// A `302 Found`: temporary; clients may switch to `GET`.
+    pub fn found(source: &str, destination: &str) -> Self {
+        Self::new(source, destination, StatusCode::FOUND)
+    }
+
+    fn new(source: &str, destination: &str, status: StatusCode) -> Self {
+        Self {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            status,
+        }
+    }
+
+    /// The source pattern.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The destination path or URL, with param placeholders.
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// The redirect status code.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+/// An internal rewrite of requests matching `source` to the route at
+/// `destination`.
+///
+/// The destination is resolved through the route tree; the handler receives
+/// the destination's params and the unchanged request. The response is
+/// certified and cached at the requested path. Destinations must be paths
+/// served by a route: static assets and query strings are not supported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rewrite {
+    source: String,
+    destination: String,
+}
+
+impl Rewrite {
+    /// Rewrite requests matching `source` to `destination`.
+    pub fn new(source: &str, destination: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            destination: destination.to_string(),
+        }
+    }
+
+    /// The source pattern.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The destination path, with param placeholders.
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+}
+
+/// The registered redirects and rewrites, with their source patterns
+/// compiled into route tries.
+pub(crate) struct RuleTable {
+    redirects: RouteNode,
+    redirect_targets: HashMap<String, (String, StatusCode)>,
+    rewrites: RouteNode,
+    rewrite_targets: HashMap<String, String>,
+}
+
+impl Default for RuleTable {
+    fn default() -> Self {
+        Self {
+            redirects: RouteNode::new(NodeType::Static(String::new())),
+            redirect_targets: HashMap::new(),
+            rewrites: RouteNode::new(NodeType::Static(String::new())),
+            rewrite_targets: HashMap::new(),
+        }
+    }
+}
+
+impl RuleTable {
+    /// Compile `redirects` and `rewrites`. A later rule with the same source
+    /// pattern replaces an earlier one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a destination refers to a param its source does not
+    /// capture, if a rewrite destination is not a path, or if a source
+    /// pattern has an invalid param constraint.
+    pub(crate) fn new(redirects: &[Redirect], rewrites: &[Rewrite]) -> Self {
+        let mut table = Self::default();
+        for redirect in redirects {
+            validate_destination(&redirect.source, &redirect.destination);
+            table
+                .redirects
+                .insert(&redirect.source, Method::GET, rule_marker);
+            table.redirect_targets.insert(
+                canonical::canonical_path(&redirect.source, false),
+                (redirect.destination.clone(), redirect.status),
+            );
+        }
+        for rewrite in rewrites {
+            assert!(
+                rewrite.destination.starts_with('/') && !rewrite.destination.contains(['?', '#']),
+                "rewrite destination `{}` must be a path without a query string",
+                rewrite.destination
+            );
+            validate_destination(&rewrite.source, &rewrite.destination);
+            table
+                .rewrites
+                .insert(&rewrite.source, Method::GET, rule_marker);
+            table.rewrite_targets.insert(
+                canonical::canonical_path(&rewrite.source, false),
+                rewrite.destination.clone(),
+            );
+        }
+        table
+    }
+
+    /// The redirect status and `Location` for `url`, if a redirect matches.
+    ///
+    /// Matching runs on the raw, still percent-encoded path, so substituted
+    /// params stay encoded in the `Location`. The request's query string is
+    /// appended to the destination's.
+    pub(crate) fn redirect_for(&self, url: &str) -> Option<(StatusCode, String)> {
+        let (_, _, params, pattern) = self.redirects.match_path(canonical::raw_path(url))?;
+        let (destination, status) = self.redirect_targets.get(&pattern)?;
+        let (path, query) = match destination.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (destination.as_str(), None),
+        };
+        let mut location = substitute(path, &params);
+        let query = query
+            .map(|q| substitute_query(q, &params))
+            .filter(|q| !q.is_empty());
+        match (query, canonical::query(url)) {
+            (Some(q), Some(extra)) => location.push_str(&format!("?{q}&{extra}")),
+            (Some(q), None) => location.push_str(&format!("?{q}")),
+            (None, Some(q)) => location.push_str(&format!("?{q}")),
+            (None, None) => {}
+        }
+        Some((*status, location))
+    }
+
+    /// The path to route `path` (percent-decoded) as, if a rewrite matches.
+    pub(crate) fn rewrite(&self, path: &str) -> Option<String> {
+        let (_, _, params, pattern) = self.rewrites.match_path(path)?;
+        let destination = self.rewrite_targets.get(&pattern)?;
+        Some(substitute(destination, &params))
+    }
+}
+
+/// Placeholder handler marking a rule's source pattern in a [`RuleTable`]
+/// trie; never called.
+fn rule_marker(_: HttpRequest, _: RouteParams) -> HttpResponse<'static> {
+    HttpResponse::builder()
+        .with_status_code(StatusCode::INTERNAL_SERVER_ERROR)
+        .with_body(Cow::<[u8]>::Owned(Vec::new()))
+        .build()
+}
+
+/// The path part of a redirect destination, which may carry a query string.
+fn destination_path(destination: &str) -> &str {
+    destination.split(['?', '#']).next().unwrap_or_default()
+}
+
+/// The `key=value` pairs of a redirect destination's query string.
+fn destination_query(destination: &str) -> impl Iterator<Item = &str> {
+    destination
+        .split_once('?')
+        .map(|(_, query)| query.split('#').next().unwrap_or_default())
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+}
+
+/// The value of a query pair, or the whole pair if it has no `=`.
+fn query_value(pair: &str) -> &str {
+    pair.split_once('=').map_or(pair, |(_, value)| value)
+}
+
+/// The params key a source or destination segment refers to, if any.
+fn param_key(segment: &str) -> Option<&str> {
+    if let Some(name) = segment.strip_prefix(':') {
+        let (name, _) = split_param_constraint(name);
+        return Some(name.strip_suffix('?').unwrap_or(name));
+    }
+    match segment.strip_prefix('*') {
+        Some("") => Some("*"),
+        Some(name) => Some(name),
+        None => None,
+    }
+}
+
+/// Panic if `destination` refers to a param that `source` does not capture,
+/// in its path or in its query values.
+fn validate_destination(source: &str, destination: &str) {
+    let captured: Vec<&str> = source.split('/').filter_map(param_key).collect();
+    let path_segments = destination_path(destination).split('/');
+    for segment in path_segments.chain(destination_query(destination).map(query_value)) {
+        if let Some(key) = param_key(segment) {
+            assert!(
+                captured.contains(&key),
+                "destination `{destination}` refers to `{segment}`, which source `{source}` does not capture"
+            );
+        }
+    }
+}
+
+/// Replace the param placeholders in `destination` with their values.
+/// Placeholders without a value (an absent optional param) are dropped.
+fn substitute(destination: &str, params: &RouteParams) -> String {
+    let segments: Vec<&str> = destination
+        .split('/')
+        .filter_map(|segment| match param_key(segment) {
+            Some(key) => params.get(key).map(String::as_str),
+            None => Some(segment),
+        })
+        .collect();
+    let joined = segments.join("/");
+    if destination.starts_with('/') {
+        // Dropped or empty placeholders may leave `//` or a trailing slash.
+        canonical::canonical_path(&joined, false)
+    } else {
+        joined
+    }
+}
+
+/// Replace the param placeholders among the values of `query` with their
+/// values, escaped for a query string. Pairs whose param is absent are
+/// dropped.
+fn substitute_query(query: &str, params: &RouteParams) -> String {
+    let pairs: Vec<String> = query
+        .split('&')
+        .filter_map(|pair| {
+            let Some(key) = param_key(query_value(pair)) else {
+                return Some(pair.to_string());
+            };
+            let value = params.get(key)?;
+            let value = value
+                .replace('&', "%26")
+                .replace('=', "%3D")
+                .replace('+', "%2B")
+                .replace('#', "%23");
+            Some(match pair.split_once('=') {
+                Some((name, _)) => format!("{name}={value}"),
+                None => value,
+            })
+        })
+        .collect();
+    pairs.join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(redirects: &[Redirect], rewrites: &[Rewrite]) -> RuleTable {
+        RuleTable::new(redirects, rewrites)
+    }
+
+    #[test]
+    fn redirect_substitutes_params() {
+        let t = table(&[Redirect::permanent("/blog/:slug", "/posts/:slug")], &[]);
+        assert_eq!(
+            t.redirect_for("/blog/hello"),
+            Some((StatusCode::PERMANENT_REDIRECT, "/posts/hello".to_string()))
+        );
+        assert_eq!(t.redirect_for("/blog"), None);
+        assert_eq!(t.redirect_for("/blog/a/b"), None);
+    }
+
+    #[test]
+    fn redirect_statuses() {
+        assert_eq!(
+            Redirect::moved_permanently("/a", "/b").status(),
+            StatusCode::MOVED_PERMANENTLY
+        );
+        assert_eq!(Redirect::found("/a", "/b").status(), StatusCode::FOUND);
+        assert_eq!(
+            Redirect::temporary("/a", "/b").status(),
+            StatusCode::TEMPORARY_REDIRECT
+        );
+    }
+
+    #[test]
+    fn redirect_keeps_encoding_and_merges_query() {
+        let t = table(
+            &[Redirect::found("/search/:term", "/find?q=:term&src=old")],
+            &[],
+        );
+        assert_eq!(
+            t.redirect_for("/search/a%20b?page=2").map(|(_, l)| l),
+            Some("/find?q=a%20b&src=old&page=2".to_string())
+        );
+        assert_eq!(
+            t.redirect_for("/search/a&b=c+d").map(|(_, l)| l),
+            Some("/find?q=a%26b%3Dc%2Bd&src=old".to_string())
+        );
+        let t = table(&[Redirect::found("/old/:id", "/new/:id")], &[]);
+        assert_eq!(
+            t.redirect_for("/old/a%20b?page=2").map(|(_, l)| l),
+            Some("/new/a%20b?page=2".to_string())
+        );
+    }
+
+    #[test]
+    fn redirect_to_absolute_url_with_catch_all() {
+        let t = table(
+            &[Redirect::permanent(
+                "/docs/*rest",
+                "https://docs.example.com/*rest",
+            )],
+            &[],
+        );
+        assert_eq!(
+            t.redirect_for("/docs/guide/intro").map(|(_, l)| l),
+            Some("https://docs.example.com/guide/intro".to_string())
+        );
+    }
+
+    #[test]
+    fn optional_param_is_dropped_when_absent() {
+        let t = table(
+            &[Redirect::permanent("/:lang?/about", "/:lang/company")],
+            &[],
+        );
+        assert_eq!(
+            t.redirect_for("/de/about").map(|(_, l)| l),
+            Some("/de/company".to_string())
+        );
+        assert_eq!(
+            t.redirect_for("/about").map(|(_, l)| l),
+            Some("/company".to_string())
+        );
+    }
+
+    #[test]
+    fn constrained_sources_follow_trie_precedence() {
+        let t = table(
+            &[
+                Redirect::permanent("/items/:id[int]", "/products/:id"),
+                Redirect::permanent("/items/:name", "/search/:name"),
+                Redirect::permanent("/items/new", "/products/new"),
+            ],
+            &[],
+        );
+        let location = |url| t.redirect_for(url).map(|(_, l)| l);
+        assert_eq!(location("/items/42"), Some("/products/42".to_string()));
+        assert_eq!(location("/items/shoes"), Some("/search/shoes".to_string()));
+        assert_eq!(location("/items/new"), Some("/products/new".to_string()));
+    }
+
+    #[test]
+    fn rewrite_substitutes_params_and_catch_all() {
+        let t = table(
+            &[],
+            &[
+                Rewrite::new("/u/:user", "/users/:user/profile"),
+                Rewrite::new("/app/*", "/spa"),
+                Rewrite::new("/files/*path", "/storage/*path"),
+            ],
+        );
+        assert_eq!(
+            t.rewrite("/u/alice"),
+            Some("/users/alice/profile".to_string())
+        );
+        assert_eq!(t.rewrite("/app/settings/billing"), Some("/spa".to_string()));
+        assert_eq!(
+            t.rewrite("/files/a/b.txt"),
+            Some("/storage/a/b.txt".to_string())
+        );
+        assert_eq!(t.rewrite("/users/alice"), None);
+        assert_eq!(t.redirect_for("/u/alice"), None);
+    }
+
+    #[test]
+    fn later_rule_with_same_source_wins() {
+        let t = table(
+            &[],
+            &[
+                Rewrite::new("/a/:x", "/one/:x"),
+                Rewrite::new("/a/:x/", "/two/:x"),
+            ],
+        );
+        assert_eq!(t.rewrite("/a/1"), Some("/two/1".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not capture")]
+    fn unknown_destination_param_panics() {
+        table(&[Redirect::permanent("/blog/:slug", "/posts/:id")], &[]);
+    }
+
+    #[test]
+    fn query_pairs_with_an_absent_param_are_dropped() {
+        let t = table(&[Redirect::found("/search/:term?", "/find?q=:term")], &[]);
+        assert_eq!(
+            t.redirect_for("/search/x").map(|(_, l)| l),
+            Some("/find?q=x".to_string())
+        );
+        assert_eq!(
+            t.redirect_for("/search").map(|(_, l)| l),
+            Some("/find".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "does not capture")]
+    fn unknown_destination_query_param_panics() {
+        table(&[Redirect::found("/search/:term", "/find?q=:query")], &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be a path")]
+    fn rewrite_destination_with_query_panics() {
+        table(&[], &[Rewrite::new("/a", "/b?x=1")]);
+    }
+}
@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use crate::certification::CertificationMode;
+use crate::config::CorsConfig;
 
 /// Type alias for HTTP header name-value pairs.
 pub type HeaderField = (String, String);
@@ -21,6 +22,10 @@ pub type HeaderField = (String, String);
 /// | `certification` | [`CertificationMode::response_only()`] | Response-only with wildcard headers |
 /// | `ttl` | `None` | Uses the global [`CacheConfig`](crate::config::CacheConfig) TTL |
 /// | `headers` | `[]` | No additional headers |
+/// | `compress` | `None` | Uses the global [`CompressionConfig`](crate::config::CompressionConfig) |
+/// | `cors` | `None` | Uses the global [`AssetConfig::cors`](crate::config::AssetConfig::cors) policy |
+/// | `max_body` | `None` | Uses the global [`AssetConfig::max_body`](crate::config::AssetConfig::max_body) limit |
+/// | `accepts` | `[]` | Any request content type |
 ///
 /// # Usage with the `#[route]` Macro
 ///
@@ -34,9 +39,24 @@ pub type HeaderField = (String, String);
 /// // Authenticated endpoint with per-route TTL:
 /// #[route(certification = "authenticated", ttl = 60)]
 /// pub fn get(_ctx: RouteContext<()>) -> HttpResponse<'static> { /* ... */ }
+///
+/// // Extra headers merged into every response for this route:
+/// #[route(headers = [("x-robots-tag", "noindex")])]
+/// pub fn get(_ctx: RouteContext<()>) -> HttpResponse<'static> { /* ... */ }
+///
+/// // Compress this route's responses even if global compression is off:
+/// #[route(compress = true)]
+/// pub fn get(_ctx: RouteContext<()>) -> HttpResponse<'static> { /* ... */ }
+///
+/// // Allow cross-origin calls from one origin, answering preflights:
+/// #[route(cors(origins = ["https://app.example.com"], max_age = 600))]
+/// pub fn post(_ctx: RouteContext<()>) -> HttpResponse<'static> { /* ... */ }
+///
+/// // Accept JSON bodies of at most 64 kB:
+/// #[route(max_body = 64_000, accepts = ["application/json"])]
+/// pub fn post(_ctx: RouteContext<()>) -> HttpResponse<'static> { /* ... */ }
 /// ```
-#[derive(Clone, Debug)]
-#[derive(Default)]
+#[derive(Clone, Debug, Default)]
 pub struct RouteConfig {
     /// Certification mode for this route.
     pub certification: CertificationMode,
@@ -48,9 +68,71 @@ pub struct RouteConfig {
     pub ttl: Option<Duration>,
 
     /// Additional headers to include in all responses for this route.
+    ///
+    /// Merged on top of the handler's own headers and the global
+    /// [`AssetConfig`](crate::config::AssetConfig) headers; a route header
+    /// replaces any earlier header with the same name (case-insensitive).
     pub headers: Vec<HeaderField>,
+
+    /// Optional runtime compression override for this route.
+    ///
+    /// `Some(true)` enables gzip and Brotli for this route's dynamic
+    /// responses, `Some(false)` disables them, and `None` falls back to
+    /// [`AssetConfig::compression`](crate::config::AssetConfig::compression).
+    pub compress: Option<bool>,
+
+    /// Optional CORS policy for this route.
+    ///
+    /// When set, replaces [`AssetConfig::cors`](crate::config::AssetConfig::cors)
+    /// for this route; `None` falls back to the global policy.
+    pub cors: Option<CorsConfig>,
+
+    /// Optional request body size limit for this route, in bytes.
+    ///
+    /// Larger bodies are refused with `413 Payload Too Large` before
+    /// middleware and the handler run. `None` falls back to
+    /// [`AssetConfig::max_body`](crate::config::AssetConfig::max_body).
+    pub max_body: Option<usize>,
+
+    /// Media types this route accepts request bodies in, such as
+    /// `"application/json"` or `"image/*"`.
+    ///
+    /// A request with a non-empty body whose `Content-Type` matches none of
+    /// them is refused with `415 Unsupported Media Type` before middleware
+    /// and the handler run. Parameters (`; charset=utf-8`) are ignored and
+    /// names compare case-insensitively. Empty accepts any content type.
+    pub accepts: Vec<String>,
 }
 
+impl RouteConfig {
+    /// Whether a body of `content_type` (the `Content-Type` header value, if
+    /// any) is allowed by [`accepts`](Self::accepts).
+    pub fn accepts_content_type(&self, content_type: Option<&str>) -> bool {
+        if self.accepts.is_empty() {
+            return true;
+        }
+        let Some(content_type) = content_type else {
+            return false;
+        };
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let Some((ty, _)) = essence.split_once('/') else {
+            return false;
+        };
+        self.accepts.iter().any(|accepted| {
+            let accepted = accepted.trim().to_ascii_lowercase();
+            match accepted.strip_suffix("/*") {
+                Some("*") => true,
+                Some(accepted_ty) => accepted_ty == ty,
+                None => accepted == essence,
+            }
+        })
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -65,6 +147,10 @@ mod tests {
         ));
         assert!(config.ttl.is_none());
         assert!(config.headers.is_empty());
+        assert!(config.compress.is_none());
+        assert!(config.cors.is_none());
+        assert!(config.max_body.is_none());
+        assert!(config.accepts.is_empty());
     }
 
     #[test]
@@ -73,6 +159,10 @@ mod tests {
             certification: CertificationMode::skip(),
             ttl: Some(Duration::from_secs(300)),
             headers: vec![("x-custom".to_string(), "value".to_string())],
+            compress: None,
+            cors: None,
+            max_body: None,
+            accepts: vec![],
         };
         let cloned = config.clone();
         let _debug = format!("{:?}", cloned);
@@ -87,7 +177,40 @@ mod tests {
             certification: CertificationMode::authenticated(),
             ttl: None,
             headers: vec![],
+            compress: None,
+            cors: None,
+            max_body: None,
+            accepts: vec![],
         };
         assert!(matches!(config.certification, CertificationMode::Full(_)));
     }
+
+    #[test]
+    fn accepts_content_type_matches_media_type_essence() {
+        let config = RouteConfig {
+            accepts: vec!["application/json".to_string(), "Image/*".to_string()],
+            ..RouteConfig::default()
+        };
+        assert!(config.accepts_content_type(Some("application/json")));
+        assert!(config.accepts_content_type(Some("Application/JSON; charset=utf-8")));
+        assert!(config.accepts_content_type(Some("image/png")));
+        assert!(!config.accepts_content_type(Some("text/plain")));
+        assert!(!config.accepts_content_type(Some("application/json-seq")));
+        assert!(!config.accepts_content_type(Some("image")));
+        assert!(!config.accepts_content_type(None));
+    }
+
+    #[test]
+    fn empty_accepts_allows_any_content_type() {
+        let config = RouteConfig::default();
+        assert!(config.accepts_content_type(Some("text/plain")));
+        assert!(config.accepts_content_type(None));
+
+        let any = RouteConfig {
+            accepts: vec!["*/*".to_string()],
+            ..RouteConfig::default()
+        };
+        assert!(any.accepts_content_type(Some("video/mp4")));
+        assert!(!any.accepts_content_type(None));
+    }
 }
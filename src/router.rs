@@ -1,7 +1,12 @@
-use ic_http_certification::{HttpRequest, HttpResponse, Method};
+use ic_http_certification::{HttpRequest, HttpResponse, Method, StatusCode};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 
-use crate::middleware::MiddlewareFn;
+use crate::extensions::{self, Extensions};
+use crate::middleware::{self, MiddlewareFn};
 use crate::route_config::RouteConfig;
 
 /// Dynamic route parameters extracted from the URL path.
@@ -9,7 +14,8 @@ use crate::route_config::RouteConfig;
 /// Maps parameter names to their captured values. For example, a route
 /// registered as `/:postId/edit` matched against `/42/edit` produces
 /// `{"postId": "42"}`. Wildcard routes store the remaining path under
-/// the key `"*"`.
+/// the key `"*"`, named catch-alls (`/*slug`) under their name. An optional
+/// segment (`/:lang?`) that was skipped has no entry.
 pub type RouteParams = HashMap<String, String>;
 
 /// A synchronous route handler function.
@@ -19,6 +25,33 @@ pub type RouteParams = HashMap<String, String>;
 /// used by the router's middleware chain and certification pipeline.
 pub type HandlerFn = fn(HttpRequest, RouteParams) -> HttpResponse<'static>;
 
+/// The future returned by an [`AsyncHandlerFn`].
+pub type HandlerFuture = Pin<Box<dyn Future<Output = HttpResponse<'static>>>>;
+
+/// An asynchronous route handler function.
+///
+/// Async handlers can `await` inter-canister calls, so they only run in
+/// [`http_request_update_async`](crate::http_request_update_async). The
+/// query path serves their certified response from the cache or upgrades;
+/// the synchronous [`http_request_update`](crate::http_request_update)
+/// answers `500 Internal Server Error`.
+///
+/// # Generated wiring
+///
+/// The build script registers an async handler for any route function
+/// declared `pub async fn`, e.g.
+/// `pub async fn post(ctx: RouteContext<Params>) -> HttpResponse<'static>`.
+/// Manual registration via [`RouteNode::insert_async`] remains available.
+///
+/// # Extensions
+///
+/// The [`extensions`] attached by middleware are available from
+/// [`extensions::take`] while the function itself runs, and are cleared as
+/// soon as it returns its future. A handler must take them before building
+/// the future, as the generated wiring does: code inside the future may run
+/// after other messages have used the same thread-local.
+pub type AsyncHandlerFn = fn(HttpRequest<'static>, RouteParams) -> HandlerFuture;
+
 /// Result of matching a path against the route tree (without method dispatch).
 ///
 /// Contains references to the handler maps, the extracted route parameters,
@@ -30,6 +63,10 @@ type MatchResult<'a> = (
     String,
 );
 
+/// The matched node, the extracted route parameters and the matched route
+/// pattern.
+type NodeMatch<'a> = (&'a RouteNode, RouteParams, String);
+
 /// A route handler that returns [`HandlerResult`] instead of a bare response.
 ///
 /// This variant supports conditional regeneration: the handler can return
@@ -37,20 +74,18 @@ type MatchResult<'a> = (
 /// is still valid, avoiding a full recertification cycle.
 ///
 /// A standard [`HandlerFn`] must also be registered at the same path/method
-/// as a fallback for the query path and middleware chain. The
-/// `HandlerResultFn` is only called during `http_request_update`.
+/// as a fallback for the query path. The `HandlerResultFn` is only called
+/// during `http_request_update`, through the same middleware chain as
+/// ordinary handlers.
 ///
-/// # Note on signature
+/// # Generated wiring
 ///
-/// This type intentionally uses the internal `(HttpRequest, RouteParams)`
-/// signature rather than the public `RouteContext`-based signature used by
-/// generated route handlers. The build script currently does not generate
-/// wrappers for `insert_result` calls — result handlers are registered
-/// manually via [`RouteNode::insert_result`].
-///
-/// If `insert_result` is ever wired into `__route_tree.rs` code generation,
-/// the same wrapper pattern used for `HandlerFn` (bridging `RouteParams` to
-/// `RouteContext<Params, SearchParams>`) should be applied here as well.
+/// The build script registers a result handler automatically for any route
+/// function that returns `HandlerResult`, for example
+/// `pub fn get(ctx: RouteContext<Params>) -> HandlerResult`. It generates a
+/// wrapper with this internal `(HttpRequest, RouteParams)` signature plus a
+/// `HandlerFn` fallback built with [`HandlerResult::into_response`].
+/// Manual registration via [`RouteNode::insert_result`] remains available.
 pub type HandlerResultFn = fn(HttpRequest, RouteParams) -> HandlerResult;
 
 /// Result type for route handlers that supports conditional regeneration.
@@ -82,6 +117,27 @@ pub enum HandlerResult {
     NotModified,
 }
 
+impl HandlerResult {
+    /// Convert into a plain response for contexts that cannot serve a
+    /// previously certified copy, such as the `HandlerFn` fallback on the
+    /// query path.
+    ///
+    /// `NotModified` has no cached response to fall back on there, so it
+    /// becomes a `500 Internal Server Error`.
+    pub fn into_response(self) -> HttpResponse<'static> {
+        match self {
+            HandlerResult::Response(resp) => resp,
+            HandlerResult::NotModified => HttpResponse::builder()
+                .with_status_code(StatusCode::INTERNAL_SERVER_ERROR)
+                .with_headers(vec![("content-type".to_string(), "text/plain".to_string())])
+                .with_body(Cow::<[u8]>::Owned(
+                    b"Internal Server Error: NotModified but no cached asset found".to_vec(),
+                ))
+                .build(),
+        }
+    }
+}
+
 impl From<HttpResponse<'static>> for HandlerResult {
     fn from(resp: HttpResponse<'static>) -> Self {
         HandlerResult::Response(resp)
@@ -92,7 +148,8 @@ impl From<HttpResponse<'static>> for HandlerResult {
 ///
 /// Each segment of a route path corresponds to a [`RouteNode`] with one of
 /// these types. During path resolution the trie tries `Static` first, then
-/// `Param`, then `Wildcard` — giving static segments the highest priority.
+/// `ConstrainedParam`, then `Param`, then `OptionalParam`, then `Wildcard` /
+/// `CatchAll` — giving static segments the highest priority.
 #[derive(Debug, PartialEq, Eq)]
 pub enum NodeType {
     /// A literal path segment (e.g. `"users"` in `/users`).
@@ -100,9 +157,131 @@ pub enum NodeType {
     /// A dynamic parameter segment (e.g. `:id` in `/users/:id`).
     /// The contained string is the parameter name without the leading colon.
     Param(String),
+    /// A dynamic parameter segment that only matches values accepted by a
+    /// [`ParamConstraint`] (e.g. `:id[int]` in `/users/:id[int]`).
+    ConstrainedParam(String, ParamConstraint),
+    /// An optional parameter segment (e.g. `:lang?` in `/docs/:lang?/intro`).
+    /// Matches with the segment (capturing it) or without it, so the example
+    /// serves both `/docs/en/intro` and `/docs/intro`. Capturing is tried
+    /// first.
+    OptionalParam(String),
     /// A catch-all wildcard (`*`). Matches one or more remaining segments
     /// and stores the captured tail in [`RouteParams`] under the key `"*"`.
     Wildcard,
+    /// A named catch-all (e.g. `*slug` in `/docs/*slug`). Matches like
+    /// [`Wildcard`](NodeType::Wildcard) but stores the captured tail under
+    /// the given name instead of `"*"`, and always requires at least one
+    /// segment (`/docs` does not match `/docs/*slug`).
+    CatchAll(String),
+}
+
+/// A restriction on the values a parameter segment matches.
+///
+/// Constraints are written in brackets after the parameter name in a route
+/// pattern:
+///
+/// | Pattern | Matches |
+/// |---------|---------|
+/// | `:id[int]` | An optional `-` followed by one or more ASCII digits |
+/// | `:lang[en,fr,de]` | Exactly one of the comma-separated values |
+/// | `:slug[re=[a-z0-9-]+]` | The whole segment matches the regular expression |
+///
+/// Constraints test the raw (still percent-encoded) segment. Sibling
+/// constrained params are tried before an unconstrained `:param` at the same
+/// level, in the order: value lists, then `int`, then regular expressions;
+/// params of the same kind are tried in registration order. A segment that
+/// fails every constraint falls through to the unconstrained param or
+/// wildcard, if any.
+#[derive(Clone, Debug)]
+pub struct ParamConstraint {
+    /// The constraint as written between the brackets, used to rebuild the
+    /// route pattern exactly as registered.
+    spec: String,
+    kind: ConstraintKind,
+}
+
+#[derive(Clone, Debug)]
+enum ConstraintKind {
+    OneOf(Vec<String>),
+    Int,
+    Regex(regex_lite::Regex),
+}
+
+impl ParamConstraint {
+    /// Parse the text between the brackets of a constrained param segment.
+    ///
+    /// Returns an error message for an empty value list or an invalid
+    /// regular expression.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let kind = if spec == "int" {
+            ConstraintKind::Int
+        } else if let Some(pattern) = spec.strip_prefix("re=") {
+            let regex = regex_lite::Regex::new(&format!("^(?:{pattern})$"))
+                .map_err(|e| format!("invalid regular expression `{pattern}`: {e}"))?;
+            ConstraintKind::Regex(regex)
+        } else {
+            let values: Vec<String> = spec.split(',').map(|v| v.trim().to_string()).collect();
+            if values.iter().any(String::is_empty) {
+                return Err(format!(
+                    "invalid constraint `{spec}`: expected `int`, `re=<regex>`, or a comma-separated list of values"
+                ));
+            }
+            ConstraintKind::OneOf(values)
+        };
+        Ok(Self {
+            spec: spec.to_string(),
+            kind,
+        })
+    }
+
+    /// Returns `true` if `segment` satisfies the constraint.
+    pub fn matches(&self, segment: &str) -> bool {
+        match &self.kind {
+            ConstraintKind::OneOf(values) => values.iter().any(|v| v == segment),
+            ConstraintKind::Int => {
+                let digits = segment.strip_prefix('-').unwrap_or(segment);
+                !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+            }
+            ConstraintKind::Regex(regex) => regex.is_match(segment),
+        }
+    }
+
+    /// The constraint as written between the brackets (e.g. `"int"`).
+    pub fn as_str(&self) -> &str {
+        &self.spec
+    }
+
+    /// Match order among sibling constrained params: most specific first.
+    fn rank(&self) -> u8 {
+        match self.kind {
+            ConstraintKind::OneOf(_) => 0,
+            ConstraintKind::Int => 1,
+            ConstraintKind::Regex(_) => 2,
+        }
+    }
+}
+
+impl PartialEq for ParamConstraint {
+    fn eq(&self, other: &Self) -> bool {
+        self.spec == other.spec
+    }
+}
+
+impl Eq for ParamConstraint {}
+
+impl std::fmt::Display for ParamConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+/// Split a `:name[constraint]` segment body (without the colon) into the
+/// parameter name and the constraint text, if any.
+pub(crate) fn split_param_constraint(segment: &str) -> (&str, Option<&str>) {
+    match (segment.find('['), segment.strip_suffix(']')) {
+        (Some(open), Some(inner)) => (&segment[..open], Some(&inner[open + 1..])),
+        _ => (segment, None),
+    }
 }
 
 /// Result of resolving a path and HTTP method against the route tree.
@@ -144,7 +323,14 @@ pub struct RouteNode {
     /// At most one param child is allowed per node — this is enforced
     /// structurally by using `Option` instead of a collection.
     pub param_child: Option<Box<RouteNode>>,
-    /// Optional single wildcard child (`*` segments).
+    /// Constrained dynamic-parameter children (`:name[constraint]` segments),
+    /// kept in match order. Unlike `param_child`, several may coexist as long
+    /// as their name or constraint differs.
+    pub constrained_children: Vec<RouteNode>,
+    /// Optional single optional-parameter child (`:name?` segments).
+    /// At most one optional param child is allowed per node.
+    pub optional_child: Option<Box<RouteNode>>,
+    /// Optional single wildcard child (`*` or named `*name` segments).
     /// At most one wildcard child is allowed per node.
     pub wildcard_child: Option<Box<RouteNode>>,
     /// Method → handler map for this node. A handler is present only for
@@ -155,6 +341,10 @@ pub struct RouteNode {
     /// this handler first to check for `NotModified` before falling back to the
     /// standard `HandlerFn` + middleware pipeline.
     result_handlers: HashMap<Method, HandlerResultFn>,
+    /// Async handlers, run by `http_request_update_async`. Each has a
+    /// placeholder `HandlerFn` in `handlers` so matching, `405` responses and
+    /// `HEAD` handling treat async routes like any other.
+    async_handlers: HashMap<Method, AsyncHandlerFn>,
     /// Middleware registry stored at the root node.
     /// Each entry is a `(prefix, middleware_fn)` pair, sorted by prefix segment
     /// count (shortest/outermost first). Only the root node's list is used at
@@ -178,9 +368,12 @@ impl RouteNode {
             node_type,
             static_children: HashMap::new(),
             param_child: None,
+            constrained_children: Vec::new(),
+            optional_child: None,
             wildcard_child: None,
             handlers: HashMap::new(),
             result_handlers: HashMap::new(),
+            async_handlers: HashMap::new(),
             middlewares: Vec::new(),
             not_found_handler: None,
             route_configs: HashMap::new(),
@@ -230,7 +423,14 @@ impl RouteNode {
     /// Multiple methods on the same path share the same config.
     ///
     /// If a config already exists for the path, it is replaced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the config's CORS policy allows credentials for any origin.
     pub fn set_route_config(&mut self, path: &str, config: RouteConfig) {
+        if let Some(cors) = &config.cors {
+            cors.assert_valid();
+        }
         self.route_configs.insert(path.to_string(), config);
     }
 
@@ -263,9 +463,17 @@ impl RouteNode {
 
     /// Register a handler for the given path and HTTP method.
     ///
-    /// Path segments starting with `:` are treated as dynamic parameters;
-    /// a lone `*` segment is a catch-all wildcard. If a handler already
-    /// exists for the same path and method it is silently replaced.
+    /// Path segments starting with `:` are treated as dynamic parameters,
+    /// optionally restricted by a bracketed [`ParamConstraint`] (`:id[int]`)
+    /// or made optional with a trailing `?` (`:lang?`); a lone `*` segment is
+    /// a catch-all wildcard and `*name` a named catch-all. If a handler
+    /// already exists for the same path and method it is silently replaced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a param constraint fails to parse, or if a catch-all or
+    /// optional segment conflicts with an earlier route's (`*` vs `*slug`,
+    /// or two different names at the same position).
     pub fn insert(&mut self, path: &str, method: Method, handler: HandlerFn) {
         let node = self.get_or_create_node(path);
         node.handlers.insert(method, handler);
@@ -285,28 +493,78 @@ impl RouteNode {
         node.result_handlers.insert(method, handler);
     }
 
+    /// Register an [`AsyncHandlerFn`] for the given path and method.
+    ///
+    /// Also registers a placeholder `HandlerFn` (replacing any existing one)
+    /// that answers `500 Internal Server Error`, for callers that cannot
+    /// await the handler.
+    pub fn insert_async(&mut self, path: &str, method: Method, handler: AsyncHandlerFn) {
+        let node = self.get_or_create_node(path);
+        node.handlers.insert(method.clone(), async_only);
+        node.async_handlers.insert(method, handler);
+    }
+
     /// Walk (or create) the trie path for the given segments, returning
     /// a mutable reference to the terminal node.
     ///
     /// Each segment is parsed into a [`NodeType`]: `"*"` becomes
-    /// [`Wildcard`](NodeType::Wildcard), a leading `:` becomes
-    /// [`Param`](NodeType::Param), and anything else becomes
+    /// [`Wildcard`](NodeType::Wildcard) and `*name` [`CatchAll`](NodeType::CatchAll),
+    /// a leading `:` becomes [`Param`](NodeType::Param) (or
+    /// [`ConstrainedParam`](NodeType::ConstrainedParam) when followed by a
+    /// `[constraint]`, [`OptionalParam`](NodeType::OptionalParam) when
+    /// followed by `?`), and anything else becomes
     /// [`Static`](NodeType::Static). Intermediate nodes are created on
     /// demand. Calling this twice with the same path returns the same
     /// node (idempotent).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a catch-all or optional segment differs from the one an
+    /// earlier route registered at the same position (`*` vs `*slug`, or
+    /// two different names), since both routes would share one node.
     fn get_or_create_node(&mut self, path: &str) -> &mut RouteNode {
         let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
         let mut current = self;
         for seg in segments {
             match seg {
                 "*" => {
-                    if current.wildcard_child.is_none() {
-                        current.wildcard_child = Some(Box::new(RouteNode::new(NodeType::Wildcard)));
-                    }
-                    current = current.wildcard_child.as_mut().unwrap();
+                    current = child_slot(&mut current.wildcard_child, NodeType::Wildcard, path);
+                }
+                s if s.starts_with('*') => {
+                    let node_type = NodeType::CatchAll(s[1..].to_string());
+                    current = child_slot(&mut current.wildcard_child, node_type, path);
+                }
+                s if s.starts_with(':') && s.ends_with('?') => {
+                    let node_type = NodeType::OptionalParam(s[1..s.len() - 1].to_string());
+                    current = child_slot(&mut current.optional_child, node_type, path);
                 }
                 s if s.starts_with(':') => {
-                    let name = s[1..].to_string();
+                    let (name, constraint) = split_param_constraint(&s[1..]);
+                    if let Some(spec) = constraint {
+                        let constraint = ParamConstraint::parse(spec).unwrap_or_else(|e| {
+                            panic!("invalid param constraint in route `{path}`: {e}")
+                        });
+                        let rank = constraint.rank();
+                        let node_type = NodeType::ConstrainedParam(name.to_string(), constraint);
+                        let children = &mut current.constrained_children;
+                        let index = match children.iter().position(|c| c.node_type == node_type) {
+                            Some(index) => index,
+                            None => {
+                                // Insert after every child of the same or a
+                                // lower rank, so same-rank constraints keep
+                                // registration order.
+                                let index = children
+                                    .iter()
+                                    .position(|c| c.constraint_rank() > rank)
+                                    .unwrap_or(children.len());
+                                children.insert(index, RouteNode::new(node_type));
+                                index
+                            }
+                        };
+                        current = &mut current.constrained_children[index];
+                        continue;
+                    }
+                    let name = name.to_string();
                     if current.param_child.is_none() {
                         current.param_child = Some(Box::new(RouteNode::new(NodeType::Param(name))));
                     }
@@ -323,6 +581,14 @@ impl RouteNode {
         current
     }
 
+    /// Rank of a constrained param node; see [`ParamConstraint::rank`].
+    fn constraint_rank(&self) -> u8 {
+        match &self.node_type {
+            NodeType::ConstrainedParam(_, constraint) => constraint.rank(),
+            _ => u8::MAX,
+        }
+    }
+
     /// Execute the middleware chain for a resolved route.
     ///
     /// Collects all middleware whose prefix matches `path` (sorted outermost
@@ -336,22 +602,73 @@ impl RouteNode {
         req: HttpRequest,
         params: RouteParams,
     ) -> HttpResponse<'static> {
-        let matching: Vec<MiddlewareFn> = self
-            .middlewares
-            .iter()
-            .filter(|(prefix, _)| path_matches_prefix(path, prefix))
-            .map(|(_, mw)| *mw)
-            .collect();
-
-        if matching.is_empty() {
-            return handler(req, params);
-        }
+        let matching = self.matching_middlewares(path);
 
         // Build the chain from innermost to outermost.
         // Start with the handler as the innermost function.
         // Then wrap each middleware around it, from the last (innermost) to the
         // first (outermost).
-        build_chain(&matching, handler, req, &params)
+        dispatch(Extensions::new(), || {
+            build_chain(
+                &matching,
+                &|req: HttpRequest, params: &RouteParams| {
+                    middleware::handle(|| handler(req, params.clone()))
+                },
+                req,
+                &params,
+            )
+        })
+    }
+
+    /// Execute the middleware chain around a [`HandlerResultFn`].
+    ///
+    /// Middleware runs exactly as for [`execute_with_middleware`](Self::execute_with_middleware),
+    /// so it can still short-circuit (e.g. reject unauthenticated requests).
+    /// If the handler returns [`HandlerResult::NotModified`], the response
+    /// produced by the middleware chain is discarded and `NotModified` is
+    /// returned so the caller can serve the cached copy.
+    pub fn execute_result_with_middleware(
+        &self,
+        path: &str,
+        handler: HandlerResultFn,
+        req: HttpRequest,
+        params: RouteParams,
+    ) -> HandlerResult {
+        let matching = self.matching_middlewares(path);
+
+        let not_modified = Cell::new(false);
+        let inner = |req: HttpRequest, params: &RouteParams| {
+            let hooks = middleware::take_response_hooks();
+            match handler(req, params.clone()) {
+                HandlerResult::Response(resp) => middleware::run_response_hooks(hooks, resp),
+                HandlerResult::NotModified => {
+                    not_modified.set(true);
+                    HttpResponse::builder()
+                        .with_status_code(StatusCode::NOT_MODIFIED)
+                        .build()
+                }
+            }
+        };
+        let response = dispatch(Extensions::new(), || {
+            build_chain(&matching, &inner, req, &params)
+        });
+        if not_modified.get() {
+            HandlerResult::NotModified
+        } else {
+            HandlerResult::Response(response)
+        }
+    }
+
+    /// Middleware whose prefix matches `path`, outermost first.
+    ///
+    /// Pass the result to [`execute_async_with_middleware`], which cannot
+    /// borrow the route tree across an `await`.
+    pub fn matching_middlewares(&self, path: &str) -> Vec<MiddlewareFn> {
+        self.middlewares
+            .iter()
+            .filter(|(prefix, _)| path_matches_prefix(path, prefix))
+            .map(|(_, mw)| *mw)
+            .collect()
     }
 
     /// Execute the middleware chain for a not-found request.
@@ -373,17 +690,29 @@ impl RouteNode {
     /// Resolve a path and method to a `RouteResult`.
     ///
     /// 1. Finds the trie node matching `path`.
-    /// 2. If found, looks up `method` in the node's `handlers` map.
+    /// 2. If found, looks up `method` in the node's `handlers` map. A `HEAD`
+    ///    request for a route without its own `head` handler resolves to the
+    ///    `GET` handler; the caller strips the response body.
     /// 3. Returns `Found` / `MethodNotAllowed` / `NotFound` accordingly.
     pub fn resolve(&self, path: &str, method: &Method) -> RouteResult {
         let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
         match self._match(&segments) {
-            Some((handlers, result_handlers, params, pattern)) => {
+            Some((node, params, pattern)) => {
+                let (handlers, result_handlers) = (&node.handlers, &node.result_handlers);
+                let method = if *method == Method::HEAD && !handlers.contains_key(method) {
+                    &Method::GET
+                } else {
+                    method
+                };
                 if let Some(&handler) = handlers.get(method) {
                     let result_handler = result_handlers.get(method).copied();
                     RouteResult::Found(handler, params, result_handler, pattern)
                 } else {
-                    let allowed: Vec<Method> = handlers.keys().cloned().collect();
+                    let mut allowed: Vec<Method> = handlers.keys().cloned().collect();
+                    if handlers.contains_key(&Method::GET) && !handlers.contains_key(&Method::HEAD)
+                    {
+                        allowed.push(Method::HEAD);
+                    }
                     RouteResult::MethodNotAllowed(allowed)
                 }
             }
@@ -391,6 +720,27 @@ impl RouteNode {
         }
     }
 
+    /// Resolve a path and method to an [`AsyncHandlerFn`], with the extracted
+    /// params and the matched route pattern.
+    ///
+    /// Returns `None` if no route matches or the matched method's handler is
+    /// synchronous. `HEAD` falls back to `GET` as in [`resolve`](Self::resolve).
+    pub fn resolve_async(
+        &self,
+        path: &str,
+        method: &Method,
+    ) -> Option<(AsyncHandlerFn, RouteParams, String)> {
+        let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let (node, params, pattern) = self._match(&segments)?;
+        let method = if *method == Method::HEAD && !node.handlers.contains_key(method) {
+            &Method::GET
+        } else {
+            method
+        };
+        let handler = *node.async_handlers.get(method)?;
+        Some((handler, params, pattern))
+    }
+
     /// Match a path and return the handlers map and params for the matched node.
     ///
     /// This performs path-only matching without method dispatch.
@@ -398,24 +748,27 @@ impl RouteNode {
     pub fn match_path(&self, path: &str) -> Option<MatchResult<'_>> {
         let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
         self._match(&segments)
+            .map(|(node, params, pattern)| (&node.handlers, &node.result_handlers, params, pattern))
     }
 
-    fn _match(&self, segments: &[&str]) -> Option<MatchResult<'_>> {
+    fn _match(&self, segments: &[&str]) -> Option<NodeMatch<'_>> {
         if segments.is_empty() {
             if !self.handlers.is_empty() {
-                return Some((
-                    &self.handlers,
-                    &self.result_handlers,
-                    HashMap::new(),
-                    "/".to_string(),
-                ));
+                return Some((self, HashMap::new(), "/".to_string()));
             }
-            // No handlers on this node — check for a wildcard child (empty wildcard match)
+            // No handlers on this node — an optional segment may be skipped
+            if let Some(ref child) = self.optional_child {
+                if let Some(m) = child.match_optional(segments, None) {
+                    return Some(m);
+                }
+            }
+            // ... or a wildcard child may match empty (named catch-alls
+            // need at least one segment)
             if let Some(ref wc) = self.wildcard_child {
-                if !wc.handlers.is_empty() {
+                if wc.node_type == NodeType::Wildcard && !wc.handlers.is_empty() {
                     let mut params = HashMap::new();
                     params.insert("*".to_string(), String::new());
-                    return Some((&wc.handlers, &wc.result_handlers, params, "/*".to_string()));
+                    return Some((wc, params, "/*".to_string()));
                 }
             }
             return None;
@@ -428,58 +781,121 @@ impl RouteNode {
 
         // Static match — O(1) via HashMap lookup
         if let Some(child) = self.static_children.get(head) {
-            if let Some((h, rh, p, pattern)) = child._match(tail) {
+            if let Some((n, p, pattern)) = child._match(tail) {
                 debug_log!("Static match: {:?}", segments);
-                let full_pattern = if pattern == "/" {
-                    format!("/{head}")
-                } else {
-                    format!("/{head}{pattern}")
-                };
-                return Some((h, rh, p, full_pattern));
+                return Some((n, p, join_pattern(head, &pattern)));
+            }
+        }
+
+        // Constrained param match — in rank order, first accepting child wins
+        for child in &self.constrained_children {
+            if let NodeType::ConstrainedParam(ref name, ref constraint) = child.node_type {
+                if !constraint.matches(head) {
+                    continue;
+                }
+                if let Some((n, mut p, pattern)) = child._match(tail) {
+                    p.insert(name.clone(), head.to_string());
+                    debug_log!("Constrained param match: {:?}", segments);
+                    let segment = format!(":{name}[{constraint}]");
+                    return Some((n, p, join_pattern(&segment, &pattern)));
+                }
             }
         }
 
         // Param match — O(1) via Option
         if let Some(ref child) = self.param_child {
             if let NodeType::Param(ref name) = child.node_type {
-                if let Some((h, rh, mut p, pattern)) = child._match(tail) {
+                if let Some((n, mut p, pattern)) = child._match(tail) {
                     p.insert(name.clone(), head.to_string());
                     debug_log!("Param match: {:?}", segments);
-                    let full_pattern = if pattern == "/" {
-                        format!("/:{name}")
-                    } else {
-                        format!("/:{name}{pattern}")
-                    };
-                    return Some((h, rh, p, full_pattern));
+                    return Some((n, p, join_pattern(&format!(":{name}"), &pattern)));
                 }
             }
         }
 
+        // Optional param match — with the segment first, then without it
+        if let Some(ref child) = self.optional_child {
+            if let Some(m) = child
+                .match_optional(tail, Some(head))
+                .or_else(|| child.match_optional(segments, None))
+            {
+                debug_log!("Optional param match: {:?}", segments);
+                return Some(m);
+            }
+        }
+
         // Wildcard match — O(1) via Option
         if let Some(ref child) = self.wildcard_child {
             if !segments.is_empty() && !child.handlers.is_empty() {
                 debug_log!("Wildcard match: {:?}", segments);
+                let (key, pattern) = child.wildcard_capture();
                 let remaining = segments.join("/");
                 let mut params = HashMap::new();
-                params.insert("*".to_string(), remaining);
-                return Some((
-                    &child.handlers,
-                    &child.result_handlers,
-                    params,
-                    "/*".to_string(),
-                ));
+                params.insert(key.to_string(), remaining);
+                return Some((child, params, pattern));
             }
         }
 
         None
     }
+
+    /// Match `segments` below an optional param node, recording `value` as
+    /// the param when the segment was present.
+    fn match_optional(&self, segments: &[&str], value: Option<&str>) -> Option<NodeMatch<'_>> {
+        let NodeType::OptionalParam(ref name) = self.node_type else {
+            return None;
+        };
+        let (n, mut p, pattern) = self._match(segments)?;
+        if let Some(value) = value {
+            p.insert(name.clone(), value.to_string());
+        }
+        Some((n, p, join_pattern(&format!(":{name}?"), &pattern)))
+    }
+
+    /// The params key and route pattern of a wildcard or named catch-all node.
+    fn wildcard_capture(&self) -> (&str, String) {
+        match self.node_type {
+            NodeType::CatchAll(ref name) => (name, format!("/*{name}")),
+            _ => ("*", "/*".to_string()),
+        }
+    }
+}
+
+/// The node in a single-child `slot`, created as `node_type` if empty.
+///
+/// Panics if the slot already holds a node of another type or name, which
+/// `path` would otherwise silently share.
+fn child_slot<'a>(
+    slot: &'a mut Option<Box<RouteNode>>,
+    node_type: NodeType,
+    path: &str,
+) -> &'a mut RouteNode {
+    if let Some(existing) = slot.as_ref() {
+        if existing.node_type != node_type {
+            panic!(
+                "route `{path}` registers {node_type:?} where another route already \
+                 registered {:?}; use the same segment in both routes",
+                existing.node_type
+            );
+        }
+    }
+    slot.get_or_insert_with(|| Box::new(RouteNode::new(node_type)))
+}
+
+/// Prefix the pattern matched below a node with that node's segment.
+fn join_pattern(segment: &str, rest: &str) -> String {
+    if rest == "/" {
+        format!("/{segment}")
+    } else {
+        format!("/{segment}{rest}")
+    }
 }
 
 /// Check whether a request path matches a middleware prefix.
 ///
 /// `"/"` matches all paths. Otherwise, the path must start with the prefix
 /// followed by either end-of-string or a `"/"` separator.
-fn path_matches_prefix(path: &str, prefix: &str) -> bool {
+pub(crate) fn path_matches_prefix(path: &str, prefix: &str) -> bool {
     if prefix == "/" {
         return true;
     }
@@ -493,12 +909,12 @@ fn path_matches_prefix(path: &str, prefix: &str) -> bool {
 /// `build_chain(middlewares[1..], handler, ...)`.
 fn build_chain(
     middlewares: &[MiddlewareFn],
-    handler: HandlerFn,
+    handler: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
     req: HttpRequest,
     params: &RouteParams,
 ) -> HttpResponse<'static> {
     match middlewares.split_first() {
-        None => handler(req, params.clone()),
+        None => handler(req, params),
         Some((&mw, rest)) => {
             let next =
                 |inner_req: HttpRequest, inner_params: &RouteParams| -> HttpResponse<'static> {
@@ -509,6 +925,93 @@ fn build_chain(
     }
 }
 
+/// Run `f` as the dispatch of a new request, starting with `extensions`
+/// and no response hooks.
+fn dispatch<R>(extensions: Extensions, f: impl FnOnce() -> R) -> R {
+    extensions::scope_with(extensions, || middleware::scope(f))
+}
+
+/// Run an [`AsyncHandlerFn`] inside a middleware chain.
+///
+/// Middleware is synchronous, so it cannot wait for the handler inside its
+/// `next` call. The chain runs once, before the handler: `next` records
+/// the (possibly modified) request and params and returns an empty `200`
+/// placeholder. If no middleware calls `next`, the chain short-circuited
+/// and its response is returned without running the handler. Otherwise the
+/// handler is awaited with the recorded request, and its response goes
+/// through the hooks middleware registered with
+/// [`middleware::on_response`]. What the
+/// chain returned after calling `next` is discarded.
+///
+/// The handler sees the [`extensions`] attached by the chain.
+///
+/// `middlewares` come from [`RouteNode::matching_middlewares`].
+pub async fn execute_async_with_middleware(
+    middlewares: Vec<MiddlewareFn>,
+    handler: AsyncHandlerFn,
+    req: HttpRequest<'static>,
+    params: RouteParams,
+) -> HttpResponse<'static> {
+    if middlewares.is_empty() {
+        let future = extensions::scope(|| handler(req, params));
+        return future.await;
+    }
+
+    type Inbound = (
+        HttpRequest<'static>,
+        RouteParams,
+        Extensions,
+        Vec<middleware::ResponseHook>,
+    );
+    let inbound: RefCell<Option<Inbound>> = RefCell::new(None);
+    let record = |inner_req: HttpRequest, inner_params: &RouteParams| {
+        *inbound.borrow_mut() = Some((
+            owned_request(&inner_req),
+            inner_params.clone(),
+            extensions::take(),
+            middleware::take_response_hooks(),
+        ));
+        HttpResponse::builder()
+            .with_status_code(StatusCode::OK)
+            .build()
+    };
+    let short_circuit = dispatch(Extensions::new(), || {
+        build_chain(&middlewares, &record, req, &params)
+    });
+    let Some((inner_req, inner_params, inner_extensions, hooks)) = inbound.into_inner() else {
+        return short_circuit;
+    };
+
+    // The handler takes the extensions when it is called; see
+    // `AsyncHandlerFn`.
+    let future = extensions::scope_with(inner_extensions, || handler(inner_req, inner_params));
+    middleware::run_response_hooks(hooks, future.await)
+}
+
+/// Copy a request into one that owns its body.
+fn owned_request(req: &HttpRequest) -> HttpRequest<'static> {
+    let builder = HttpRequest::builder()
+        .with_method(req.method().clone())
+        .with_url(req.url())
+        .with_headers(req.headers().to_vec())
+        .with_body(req.body().to_vec());
+    match req.certificate_version() {
+        Some(version) => builder.with_certificate_version(version).build(),
+        None => builder.build(),
+    }
+}
+
+/// Placeholder [`HandlerFn`] registered alongside an [`AsyncHandlerFn`].
+fn async_only(_: HttpRequest, _: RouteParams) -> HttpResponse<'static> {
+    HttpResponse::builder()
+        .with_status_code(StatusCode::INTERNAL_SERVER_ERROR)
+        .with_headers(vec![("content-type".to_string(), "text/plain".to_string())])
+        .with_body(Cow::<[u8]>::Owned(
+            b"Internal Server Error: async handler requires http_request_update_async".to_vec(),
+        ))
+        .build()
+}
+
 /// Normalize a middleware prefix to a canonical form: `"/"` for root, otherwise
 /// `"/segment1/segment2"` with no trailing slash.
 fn normalize_prefix(prefix: &str) -> String {
@@ -548,8 +1051,7 @@ fn segment_count(prefix: &str) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ic_http_certification::{Method, StatusCode};
-    use std::{borrow::Cow, str};
+    use std::str;
 
     fn test_request(path: &str) -> HttpRequest<'_> {
         HttpRequest::builder()
@@ -834,7 +1336,8 @@ mod tests {
             RouteResult::MethodNotAllowed(allowed) => {
                 let mut names: Vec<&str> = allowed.iter().map(|m| m.as_str()).collect();
                 names.sort();
-                assert_eq!(names, vec!["GET", "POST"]);
+                // HEAD is implied by GET.
+                assert_eq!(names, vec!["GET", "HEAD", "POST"]);
             }
             other => panic!(
                 "expected MethodNotAllowed, got {}",
@@ -886,104 +1389,486 @@ mod tests {
         }
     }
 
-    // ---- 2.2 Middleware tests ----
-
-    use std::cell::RefCell;
-
-    thread_local! {
-        static LOG: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    #[test]
+    fn head_falls_back_to_get_handler() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/posts/:id", Method::GET, matched_get_handler);
+        match root.resolve("/posts/1", &Method::HEAD) {
+            RouteResult::Found(handler, params, _, pattern) => {
+                assert_eq!(params.get("id").map(String::as_str), Some("1"));
+                assert_eq!(
+                    body_str(handler(test_request("/posts/1"), params)),
+                    "get_handler"
+                );
+                assert_eq!(pattern, "/posts/:id");
+            }
+            other => panic!("expected Found, got {}", route_result_name(&other)),
+        }
     }
 
-    fn clear_log() {
-        LOG.with(|l| l.borrow_mut().clear());
+    #[test]
+    fn explicit_head_handler_wins_over_get() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/test", Method::GET, matched_get_handler);
+        root.insert("/test", Method::HEAD, matched_post_handler);
+        match root.resolve("/test", &Method::HEAD) {
+            RouteResult::Found(handler, params, _, _) => {
+                assert_eq!(
+                    body_str(handler(test_request("/test"), params)),
+                    "post_handler"
+                );
+            }
+            other => panic!("expected Found, got {}", route_result_name(&other)),
+        }
     }
 
-    fn get_log() -> Vec<String> {
-        LOG.with(|l| l.borrow().clone())
+    #[test]
+    fn head_without_get_is_method_not_allowed() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/test", Method::POST, matched_post_handler);
+        match root.resolve("/test", &Method::HEAD) {
+            RouteResult::MethodNotAllowed(allowed) => assert_eq!(allowed, vec![Method::POST]),
+            other => panic!(
+                "expected MethodNotAllowed, got {}",
+                route_result_name(&other)
+            ),
+        }
     }
 
-    fn log_entry(msg: &str) {
-        LOG.with(|l| l.borrow_mut().push(msg.to_string()));
-    }
+    // ---- Constrained param segments ----
 
-    fn logging_handler(_: HttpRequest, _: RouteParams) -> HttpResponse<'static> {
-        log_entry("handler");
-        response_with_text("handler_response")
+    fn matched_int(_: HttpRequest, params: RouteParams) -> HttpResponse<'static> {
+        response_with_text(&format!("int {}", params["id"]))
     }
 
-    fn root_middleware(
-        req: HttpRequest,
-        params: &RouteParams,
-        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
-    ) -> HttpResponse<'static> {
-        log_entry("root_mw_before");
-        let resp = next(req, params);
-        log_entry("root_mw_after");
-        resp
+    fn matched_lang(_: HttpRequest, params: RouteParams) -> HttpResponse<'static> {
+        response_with_text(&format!("lang {}", params["lang"]))
     }
 
-    fn api_middleware(
-        req: HttpRequest,
-        params: &RouteParams,
-        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
-    ) -> HttpResponse<'static> {
-        log_entry("api_mw_before");
-        let resp = next(req, params);
-        log_entry("api_mw_after");
-        resp
+    fn matched_slug(_: HttpRequest, params: RouteParams) -> HttpResponse<'static> {
+        response_with_text(&format!("slug {}", params["slug"]))
     }
 
-    fn api_v2_middleware(
-        req: HttpRequest,
-        params: &RouteParams,
-        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
-    ) -> HttpResponse<'static> {
-        log_entry("api_v2_mw_before");
-        let resp = next(req, params);
-        log_entry("api_v2_mw_after");
-        resp
+    fn matched_other(_: HttpRequest, params: RouteParams) -> HttpResponse<'static> {
+        response_with_text(&format!("other {}", params["other"]))
     }
 
-    /// 2.2.6a: Root middleware runs on all requests
-    #[test]
-    fn test_root_middleware_runs_on_all_requests() {
-        clear_log();
+    fn constrained_router() -> RouteNode {
         let mut root = RouteNode::new(NodeType::Static("".into()));
-        root.insert("/", Method::GET, logging_handler);
-        root.insert("/about", Method::GET, logging_handler);
-        root.insert("/api/users", Method::GET, logging_handler);
-        root.set_middleware("/", root_middleware);
-
-        // Root path
-        let (handler, params) = resolve_get(&root, "/");
-        root.execute_with_middleware("/", handler, test_request("/"), params);
-        assert!(get_log().contains(&"root_mw_before".to_string()));
-        assert!(get_log().contains(&"handler".to_string()));
-        assert!(get_log().contains(&"root_mw_after".to_string()));
-
-        // /about
-        clear_log();
-        let (handler, params) = resolve_get(&root, "/about");
-        root.execute_with_middleware("/about", handler, test_request("/about"), params);
-        assert!(get_log().contains(&"root_mw_before".to_string()));
-        assert!(get_log().contains(&"handler".to_string()));
+        // Registered out of match order on purpose.
+        root.insert("/posts/:slug[re=[a-z0-9-]+]", Method::GET, matched_slug);
+        root.insert("/posts/:other", Method::GET, matched_other);
+        root.insert("/posts/:id[int]", Method::GET, matched_int);
+        root.insert("/posts/:lang[en,fr]", Method::GET, matched_lang);
+        root
+    }
 
-        // /api/users
-        clear_log();
-        let (handler, params) = resolve_get(&root, "/api/users");
-        root.execute_with_middleware("/api/users", handler, test_request("/api/users"), params);
-        assert!(get_log().contains(&"root_mw_before".to_string()));
-        assert!(get_log().contains(&"handler".to_string()));
+    fn resolve_pattern(root: &RouteNode, path: &str) -> (String, String) {
+        match root.resolve(path, &Method::GET) {
+            RouteResult::Found(handler, params, _, pattern) => {
+                (body_str(handler(test_request(path), params)), pattern)
+            }
+            other => panic!("expected Found, got {}", route_result_name(&other)),
+        }
     }
 
-    /// 2.2.6b: Scoped middleware runs only on matching prefix
     #[test]
-    fn test_scoped_middleware_only_matching_prefix() {
-        clear_log();
-        let mut root = RouteNode::new(NodeType::Static("".into()));
-        root.insert("/api/users", Method::GET, logging_handler);
-        root.insert("/pages/home", Method::GET, logging_handler);
-        root.set_middleware("/api", api_middleware);
+    fn constrained_params_coexist_with_unconstrained_param() {
+        let root = constrained_router();
+        assert_eq!(
+            resolve_pattern(&root, "/posts/42"),
+            ("int 42".to_string(), "/posts/:id[int]".to_string())
+        );
+        assert_eq!(
+            resolve_pattern(&root, "/posts/-7"),
+            ("int -7".to_string(), "/posts/:id[int]".to_string())
+        );
+        assert_eq!(
+            resolve_pattern(&root, "/posts/fr"),
+            ("lang fr".to_string(), "/posts/:lang[en,fr]".to_string())
+        );
+        assert_eq!(
+            resolve_pattern(&root, "/posts/hello-world"),
+            (
+                "slug hello-world".to_string(),
+                "/posts/:slug[re=[a-z0-9-]+]".to_string()
+            )
+        );
+        assert_eq!(
+            resolve_pattern(&root, "/posts/Hello_World"),
+            ("other Hello_World".to_string(), "/posts/:other".to_string())
+        );
+    }
+
+    #[test]
+    fn constrained_params_are_tried_in_rank_order() {
+        let root = constrained_router();
+        let kinds: Vec<&NodeType> = root.static_children["posts"]
+            .constrained_children
+            .iter()
+            .map(|c| &c.node_type)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &NodeType::ConstrainedParam(
+                    "lang".into(),
+                    ParamConstraint::parse("en,fr").unwrap()
+                ),
+                &NodeType::ConstrainedParam("id".into(), ParamConstraint::parse("int").unwrap()),
+                &NodeType::ConstrainedParam(
+                    "slug".into(),
+                    ParamConstraint::parse("re=[a-z0-9-]+").unwrap()
+                ),
+            ]
+        );
+        // "123" also matches the regex; `int` ranks first.
+        assert_eq!(resolve_pattern(&root, "/posts/123").0, "int 123");
+    }
+
+    #[test]
+    fn constrained_param_without_fallback_is_not_found() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/items/:id[int]", Method::GET, matched_int);
+        assert!(matches!(
+            root.resolve("/items/abc", &Method::GET),
+            RouteResult::NotFound
+        ));
+        assert!(matches!(
+            root.resolve("/items/", &Method::GET),
+            RouteResult::NotFound
+        ));
+    }
+
+    #[test]
+    fn constrained_param_backtracks_into_sibling_subtree() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/items/:id[int]", Method::GET, matched_int);
+        root.insert("/items/:other/edit", Method::GET, matched_other);
+        // "5" satisfies `int`, but only the unconstrained subtree has `/edit`.
+        assert_eq!(
+            resolve_pattern(&root, "/items/5/edit"),
+            ("other 5".to_string(), "/items/:other/edit".to_string())
+        );
+    }
+
+    #[test]
+    fn constrained_param_route_config_uses_registered_pattern() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/items/:id[int]", Method::GET, matched_int);
+        root.set_route_config(
+            "/items/:id[int]",
+            RouteConfig {
+                ttl: Some(std::time::Duration::from_secs(5)),
+                ..RouteConfig::default()
+            },
+        );
+        let (_, pattern) = resolve_pattern(&root, "/items/9");
+        assert_eq!(
+            root.get_route_config(&pattern).and_then(|c| c.ttl),
+            Some(std::time::Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn param_constraint_parse_and_match() {
+        let int = ParamConstraint::parse("int").unwrap();
+        assert!(int.matches("0") && int.matches("-12") && int.matches("007"));
+        assert!(!int.matches("") && !int.matches("-") && !int.matches("1.5"));
+
+        let one_of = ParamConstraint::parse("en, fr").unwrap();
+        assert!(one_of.matches("en") && one_of.matches("fr"));
+        assert!(!one_of.matches("de") && !one_of.matches("EN"));
+        assert_eq!(one_of.to_string(), "en, fr");
+
+        // Regexes are anchored to the whole segment.
+        let re = ParamConstraint::parse("re=v[0-9]+").unwrap();
+        assert!(re.matches("v12"));
+        assert!(!re.matches("xv12") && !re.matches("v12x"));
+
+        assert!(ParamConstraint::parse("re=(").is_err());
+        assert!(ParamConstraint::parse("").is_err());
+        assert!(ParamConstraint::parse("a,,b").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid param constraint")]
+    fn insert_panics_on_invalid_constraint() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/items/:id[re=(]", Method::GET, matched_int);
+    }
+
+    // ---- Optional params and named catch-alls ----
+
+    fn matched_params(_: HttpRequest, params: RouteParams) -> HttpResponse<'static> {
+        let mut entries: Vec<_> = params.into_iter().collect();
+        entries.sort();
+        response_with_text(&format!("{entries:?}"))
+    }
+
+    #[test]
+    fn optional_param_matches_with_and_without_segment() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/docs/:lang?/intro", Method::GET, matched_params);
+        assert_eq!(
+            resolve_pattern(&root, "/docs/en/intro"),
+            (
+                r#"[("lang", "en")]"#.to_string(),
+                "/docs/:lang?/intro".to_string()
+            )
+        );
+        assert_eq!(
+            resolve_pattern(&root, "/docs/intro"),
+            ("[]".to_string(), "/docs/:lang?/intro".to_string())
+        );
+        assert!(matches!(
+            root.resolve("/docs/en/fr/intro", &Method::GET),
+            RouteResult::NotFound
+        ));
+    }
+
+    #[test]
+    fn optional_param_as_last_segment() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/docs/:lang?", Method::GET, matched_params);
+        assert_eq!(
+            resolve_pattern(&root, "/docs"),
+            ("[]".to_string(), "/docs/:lang?".to_string())
+        );
+        assert_eq!(
+            resolve_pattern(&root, "/docs/fr"),
+            (
+                r#"[("lang", "fr")]"#.to_string(),
+                "/docs/:lang?".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn optional_param_skips_when_capturing_leaves_nothing_to_match() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/docs/:lang?/:page", Method::GET, matched_params);
+        assert_eq!(
+            resolve_pattern(&root, "/docs/intro").0,
+            r#"[("page", "intro")]"#
+        );
+        assert_eq!(
+            resolve_pattern(&root, "/docs/en/intro").0,
+            r#"[("lang", "en"), ("page", "intro")]"#
+        );
+    }
+
+    #[test]
+    fn static_and_param_siblings_win_over_optional_param() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/docs/:lang?/intro", Method::GET, matched_params);
+        root.insert("/docs/intro", Method::GET, matched_about);
+        assert_eq!(resolve_pattern(&root, "/docs/intro").0, "about");
+        assert_eq!(
+            resolve_pattern(&root, "/docs/de/intro").1,
+            "/docs/:lang?/intro"
+        );
+    }
+
+    #[test]
+    fn named_catch_all_captures_under_its_name() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/files/*path", Method::GET, matched_params);
+        assert_eq!(
+            resolve_pattern(&root, "/files/a/b/c"),
+            (
+                r#"[("path", "a/b/c")]"#.to_string(),
+                "/files/*path".to_string()
+            )
+        );
+        // Unlike `*`, a named catch-all never matches an empty tail.
+        assert!(matches!(
+            root.resolve("/files", &Method::GET),
+            RouteResult::NotFound
+        ));
+        assert!(matches!(
+            root.static_children["files"]
+                .wildcard_child
+                .as_ref()
+                .unwrap()
+                .node_type,
+            NodeType::CatchAll(ref name) if name == "path"
+        ));
+    }
+
+    #[test]
+    fn same_catch_all_and_optional_segments_share_a_node() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/files/*path", Method::GET, matched_params);
+        root.insert("/files/*path", Method::POST, matched_params);
+        root.insert("/docs/:lang?", Method::GET, matched_params);
+        root.insert("/docs/:lang?/intro", Method::GET, matched_params);
+        assert_eq!(
+            resolve_pattern(&root, "/docs/en/intro").1,
+            "/docs/:lang?/intro"
+        );
+        assert_eq!(resolve_pattern(&root, "/files/a/b").1, "/files/*path");
+        assert!(matches!(
+            root.resolve("/files/a/b", &Method::POST),
+            RouteResult::Found(..)
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "where another route already registered")]
+    fn insert_panics_on_catch_all_after_wildcard() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/files/*", Method::GET, matched_params);
+        root.insert("/files/*slug", Method::POST, matched_params);
+    }
+
+    #[test]
+    #[should_panic(expected = "where another route already registered")]
+    fn insert_panics_on_differently_named_catch_alls() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/files/*path", Method::GET, matched_params);
+        root.insert("/files/*rest", Method::POST, matched_params);
+    }
+
+    #[test]
+    #[should_panic(expected = "where another route already registered")]
+    fn insert_panics_on_differently_named_optional_params() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/docs/:lang?", Method::GET, matched_params);
+        root.insert("/docs/:locale?/intro", Method::GET, matched_params);
+    }
+
+    #[test]
+    fn optional_param_before_named_catch_all() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/:lang?/docs/*slug", Method::GET, matched_params);
+        assert_eq!(
+            resolve_pattern(&root, "/docs/guide/setup"),
+            (
+                r#"[("slug", "guide/setup")]"#.to_string(),
+                "/:lang?/docs/*slug".to_string()
+            )
+        );
+        assert_eq!(
+            resolve_pattern(&root, "/fr/docs/guide").0,
+            r#"[("lang", "fr"), ("slug", "guide")]"#
+        );
+    }
+
+    #[test]
+    fn optional_param_does_not_capture_into_empty_catch_all() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/docs/:lang?/intro", Method::GET, matched_about);
+        root.insert("/docs/:lang?/*slug", Method::GET, matched_params);
+        assert_eq!(resolve_pattern(&root, "/docs/intro").0, "about");
+        assert_eq!(
+            resolve_pattern(&root, "/docs/en/guide").0,
+            r#"[("lang", "en"), ("slug", "guide")]"#
+        );
+        assert_eq!(
+            resolve_pattern(&root, "/docs/guide").0,
+            r#"[("slug", "guide")]"#
+        );
+    }
+
+    // ---- 2.2 Middleware tests ----
+
+    use std::cell::RefCell;
+
+    thread_local! {
+        static LOG: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn clear_log() {
+        LOG.with(|l| l.borrow_mut().clear());
+    }
+
+    fn get_log() -> Vec<String> {
+        LOG.with(|l| l.borrow().clone())
+    }
+
+    fn log_entry(msg: &str) {
+        LOG.with(|l| l.borrow_mut().push(msg.to_string()));
+    }
+
+    fn logging_handler(_: HttpRequest, _: RouteParams) -> HttpResponse<'static> {
+        log_entry("handler");
+        response_with_text("handler_response")
+    }
+
+    fn root_middleware(
+        req: HttpRequest,
+        params: &RouteParams,
+        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    ) -> HttpResponse<'static> {
+        log_entry("root_mw_before");
+        let resp = next(req, params);
+        log_entry("root_mw_after");
+        resp
+    }
+
+    fn api_middleware(
+        req: HttpRequest,
+        params: &RouteParams,
+        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    ) -> HttpResponse<'static> {
+        log_entry("api_mw_before");
+        let resp = next(req, params);
+        log_entry("api_mw_after");
+        resp
+    }
+
+    fn api_v2_middleware(
+        req: HttpRequest,
+        params: &RouteParams,
+        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    ) -> HttpResponse<'static> {
+        log_entry("api_v2_mw_before");
+        let resp = next(req, params);
+        log_entry("api_v2_mw_after");
+        resp
+    }
+
+    /// 2.2.6a: Root middleware runs on all requests
+    #[test]
+    fn test_root_middleware_runs_on_all_requests() {
+        clear_log();
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/", Method::GET, logging_handler);
+        root.insert("/about", Method::GET, logging_handler);
+        root.insert("/api/users", Method::GET, logging_handler);
+        root.set_middleware("/", root_middleware);
+
+        // Root path
+        let (handler, params) = resolve_get(&root, "/");
+        root.execute_with_middleware("/", handler, test_request("/"), params);
+        assert!(get_log().contains(&"root_mw_before".to_string()));
+        assert!(get_log().contains(&"handler".to_string()));
+        assert!(get_log().contains(&"root_mw_after".to_string()));
+
+        // /about
+        clear_log();
+        let (handler, params) = resolve_get(&root, "/about");
+        root.execute_with_middleware("/about", handler, test_request("/about"), params);
+        assert!(get_log().contains(&"root_mw_before".to_string()));
+        assert!(get_log().contains(&"handler".to_string()));
+
+        // /api/users
+        clear_log();
+        let (handler, params) = resolve_get(&root, "/api/users");
+        root.execute_with_middleware("/api/users", handler, test_request("/api/users"), params);
+        assert!(get_log().contains(&"root_mw_before".to_string()));
+        assert!(get_log().contains(&"handler".to_string()));
+    }
+
+    /// 2.2.6b: Scoped middleware runs only on matching prefix
+    #[test]
+    fn test_scoped_middleware_only_matching_prefix() {
+        clear_log();
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/api/users", Method::GET, logging_handler);
+        root.insert("/pages/home", Method::GET, logging_handler);
+        root.set_middleware("/api", api_middleware);
 
         // /api/users — api_middleware should run
         let (handler, params) = resolve_get(&root, "/api/users");
@@ -1497,6 +2382,428 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_handler_result_into_response() {
+        let resp = HandlerResult::Response(response_with_text("ok")).into_response();
+        assert_eq!(resp.body(), b"ok");
+        let resp = HandlerResult::NotModified.into_response();
+        assert_eq!(resp.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    /// Result handlers run inside the middleware chain, and NotModified
+    /// survives middleware post-processing.
+    #[test]
+    fn test_execute_result_with_middleware() {
+        fn header_middleware(
+            req: HttpRequest,
+            params: &RouteParams,
+            next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+        ) -> HttpResponse<'static> {
+            log_entry("mw");
+            let mut resp = next(req, params);
+            resp.add_header(("x-mw".to_string(), "1".to_string()));
+            resp
+        }
+        fn fresh(_: HttpRequest, _: RouteParams) -> HandlerResult {
+            response_with_text("fresh").into()
+        }
+        fn unchanged(_: HttpRequest, _: RouteParams) -> HandlerResult {
+            HandlerResult::NotModified
+        }
+
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/", header_middleware);
+
+        clear_log();
+        match root.execute_result_with_middleware("/a", fresh, test_request("/a"), HashMap::new()) {
+            HandlerResult::Response(resp) => {
+                assert_eq!(resp.body(), b"fresh");
+                assert!(resp.headers().iter().any(|(k, _)| k == "x-mw"));
+            }
+            HandlerResult::NotModified => panic!("expected Response"),
+        }
+        assert_eq!(get_log(), vec!["mw".to_string()]);
+
+        clear_log();
+        let result = root.execute_result_with_middleware(
+            "/a",
+            unchanged,
+            test_request("/a"),
+            HashMap::new(),
+        );
+        assert!(matches!(result, HandlerResult::NotModified));
+        assert_eq!(get_log(), vec!["mw".to_string()]);
+    }
+
+    /// A short-circuiting middleware prevents the result handler from running.
+    #[test]
+    fn test_execute_result_with_middleware_short_circuit() {
+        fn reject(
+            _req: HttpRequest,
+            _params: &RouteParams,
+            _next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+        ) -> HttpResponse<'static> {
+            HttpResponse::builder()
+                .with_status_code(StatusCode::UNAUTHORIZED)
+                .build()
+        }
+        fn unchanged(_: HttpRequest, _: RouteParams) -> HandlerResult {
+            HandlerResult::NotModified
+        }
+
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/", reject);
+        match root.execute_result_with_middleware(
+            "/a",
+            unchanged,
+            test_request("/a"),
+            HashMap::new(),
+        ) {
+            HandlerResult::Response(resp) => {
+                assert_eq!(resp.status_code(), StatusCode::UNAUTHORIZED)
+            }
+            HandlerResult::NotModified => panic!("middleware should have short-circuited"),
+        }
+    }
+
+    // ---- Async handlers ----
+
+    /// Poll a future that completes without waiting, as the async handlers
+    /// in these tests do.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        match future.as_mut().poll(&mut cx) {
+            std::task::Poll::Ready(output) => output,
+            std::task::Poll::Pending => panic!("future did not complete"),
+        }
+    }
+
+    fn async_echo(req: HttpRequest<'static>, params: RouteParams) -> HandlerFuture {
+        Box::pin(async move {
+            log_entry("async_handler");
+            let injected = req
+                .headers()
+                .iter()
+                .any(|(k, _)| k == "x-injected")
+                .to_string();
+            let id = params.get("id").cloned().unwrap_or_default();
+            response_with_text(&format!("{id}:{injected}"))
+        })
+    }
+
+    #[test]
+    fn insert_async_registers_placeholder_and_resolves() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert_async("/items/:id", Method::POST, async_echo);
+        root.insert("/items/:id", Method::GET, matched_get_handler);
+
+        match root.resolve("/items/7", &Method::POST) {
+            RouteResult::Found(handler, ..) => {
+                let resp = handler(test_request("/items/7"), HashMap::new());
+                assert_eq!(resp.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+            }
+            other => panic!("expected Found, got {}", route_result_name(&other)),
+        }
+        let (handler, params, pattern) = root
+            .resolve_async("/items/7", &Method::POST)
+            .expect("async handler");
+        assert_eq!(pattern, "/items/:id");
+        assert_eq!(
+            body_str(block_on(handler(test_request("/items/7"), params))),
+            "7:false"
+        );
+        assert!(root.resolve_async("/items/7", &Method::GET).is_none());
+        assert!(root.resolve_async("/other", &Method::POST).is_none());
+    }
+
+    #[test]
+    fn resolve_async_head_falls_back_to_get() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert_async("/a", Method::GET, async_echo);
+        assert!(root.resolve_async("/a", &Method::HEAD).is_some());
+    }
+
+    /// The handler sees the request as modified by middleware, and the
+    /// chain and handler each run once. The response goes through the
+    /// middleware's response hook; what the chain returns after `next` is
+    /// discarded.
+    #[test]
+    fn execute_async_with_middleware_runs_chain_and_handler_once() {
+        fn inject(
+            req: HttpRequest,
+            params: &RouteParams,
+            next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+        ) -> HttpResponse<'static> {
+            log_entry("mw_before");
+            middleware::on_response(|mut resp| {
+                log_entry("mw_hook");
+                resp.add_header(("x-mw".to_string(), "1".to_string()));
+                resp
+            });
+            let mut headers = req.headers().to_vec();
+            headers.push(("x-injected".to_string(), "1".to_string()));
+            let modified = HttpRequest::builder()
+                .with_method(req.method().clone())
+                .with_url(req.url())
+                .with_headers(headers)
+                .build();
+            let mut resp = next(modified, params);
+            resp.add_header(("x-after".to_string(), "1".to_string()));
+            resp
+        }
+
+        clear_log();
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/", inject);
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), "9".to_string());
+        let resp = block_on(execute_async_with_middleware(
+            root.matching_middlewares("/a"),
+            async_echo,
+            test_request("/a"),
+            params,
+        ));
+        assert!(resp
+            .headers()
+            .contains(&("x-mw".to_string(), "1".to_string())));
+        assert!(!resp.headers().iter().any(|(k, _)| k == "x-after"));
+        assert_eq!(body_str(resp), "9:true");
+        assert_eq!(get_log(), vec!["mw_before", "async_handler", "mw_hook"]);
+    }
+
+    #[test]
+    fn execute_async_with_middleware_short_circuit_skips_handler() {
+        fn reject(
+            _req: HttpRequest,
+            _params: &RouteParams,
+            _next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+        ) -> HttpResponse<'static> {
+            HttpResponse::builder()
+                .with_status_code(StatusCode::UNAUTHORIZED)
+                .build()
+        }
+
+        clear_log();
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/", reject);
+        let resp = block_on(execute_async_with_middleware(
+            root.matching_middlewares("/a"),
+            async_echo,
+            test_request("/a"),
+            HashMap::new(),
+        ));
+        assert_eq!(resp.status_code(), StatusCode::UNAUTHORIZED);
+        assert!(get_log().is_empty());
+    }
+
+    fn hook_outer(
+        req: HttpRequest,
+        params: &RouteParams,
+        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    ) -> HttpResponse<'static> {
+        middleware::on_response(|resp| {
+            log_entry("outer_hook");
+            resp
+        });
+        next(req, params)
+    }
+
+    fn hook_inner(
+        req: HttpRequest,
+        params: &RouteParams,
+        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    ) -> HttpResponse<'static> {
+        middleware::on_response(|resp| {
+            log_entry("inner_hook");
+            resp
+        });
+        let resp = next(req, params);
+        log_entry("inner_after");
+        resp
+    }
+
+    /// Response hooks run once on the handler's response, innermost first
+    /// and before code after `next`.
+    #[test]
+    fn response_hooks_run_innermost_first_on_the_handler_response() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/", hook_outer);
+        root.set_middleware("/api", hook_inner);
+
+        clear_log();
+        root.execute_with_middleware(
+            "/api/a",
+            matched_get_handler,
+            test_request("/api/a"),
+            HashMap::new(),
+        );
+        assert_eq!(get_log(), vec!["inner_hook", "outer_hook", "inner_after"]);
+
+        clear_log();
+        block_on(execute_async_with_middleware(
+            root.matching_middlewares("/api/a"),
+            async_echo,
+            test_request("/api/a"),
+            HashMap::new(),
+        ));
+        assert_eq!(
+            get_log(),
+            vec!["inner_after", "async_handler", "inner_hook", "outer_hook"]
+        );
+    }
+
+    /// Hooks registered before a middleware further in refuses the request
+    /// are dropped with the chain.
+    #[test]
+    fn response_hooks_are_dropped_when_the_chain_short_circuits() {
+        fn reject(
+            _req: HttpRequest,
+            _params: &RouteParams,
+            _next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+        ) -> HttpResponse<'static> {
+            HttpResponse::builder()
+                .with_status_code(StatusCode::FORBIDDEN)
+                .build()
+        }
+
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/", hook_outer);
+        root.set_middleware("/admin", reject);
+
+        clear_log();
+        let resp = block_on(execute_async_with_middleware(
+            root.matching_middlewares("/admin"),
+            async_echo,
+            test_request("/admin"),
+            HashMap::new(),
+        ));
+        assert_eq!(resp.status_code(), StatusCode::FORBIDDEN);
+        root.execute_with_middleware(
+            "/public",
+            matched_get_handler,
+            test_request("/public"),
+            HashMap::new(),
+        );
+        assert_eq!(get_log(), vec!["outer_hook"]);
+    }
+
+    // ---- Request extensions ----
+
+    #[derive(Debug, PartialEq)]
+    struct Caller(&'static str);
+
+    fn attach_caller(
+        req: HttpRequest,
+        params: &RouteParams,
+        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    ) -> HttpResponse<'static> {
+        extensions::insert(Caller("alice"));
+        next(req, params)
+    }
+
+    fn caller_handler(_: HttpRequest, _: RouteParams) -> HttpResponse<'static> {
+        let caller = extensions::take().get::<Caller>().map(|c| c.0);
+        response_with_text(caller.unwrap_or("anonymous"))
+    }
+
+    fn async_caller_handler(_: HttpRequest<'static>, _: RouteParams) -> HandlerFuture {
+        let caller = extensions::take().get::<Caller>().map(|c| c.0);
+        Box::pin(async move { response_with_text(caller.unwrap_or("anonymous")) })
+    }
+
+    #[test]
+    fn middleware_extensions_reach_the_handler() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/api", attach_caller);
+
+        let resp = root.execute_with_middleware(
+            "/api/me",
+            caller_handler,
+            test_request("/api/me"),
+            HashMap::new(),
+        );
+        assert_eq!(body_str(resp), "alice");
+
+        // Requests outside the middleware scope start with no extensions.
+        let resp = root.execute_with_middleware(
+            "/me",
+            caller_handler,
+            test_request("/me"),
+            HashMap::new(),
+        );
+        assert_eq!(body_str(resp), "anonymous");
+    }
+
+    #[test]
+    fn extensions_do_not_leak_from_a_short_circuited_chain() {
+        fn attach_and_reject(
+            _req: HttpRequest,
+            _params: &RouteParams,
+            _next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+        ) -> HttpResponse<'static> {
+            extensions::insert(Caller("mallory"));
+            HttpResponse::builder()
+                .with_status_code(StatusCode::FORBIDDEN)
+                .build()
+        }
+
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/admin", attach_and_reject);
+        let resp = root.execute_with_middleware(
+            "/admin",
+            caller_handler,
+            test_request("/admin"),
+            HashMap::new(),
+        );
+        assert_eq!(resp.status_code(), StatusCode::FORBIDDEN);
+        assert!(extensions::with(Extensions::is_empty));
+
+        let resp = root.execute_with_middleware(
+            "/public",
+            caller_handler,
+            test_request("/public"),
+            HashMap::new(),
+        );
+        assert_eq!(body_str(resp), "anonymous");
+    }
+
+    #[test]
+    fn middleware_extensions_reach_async_handler() {
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/", attach_caller);
+        let resp = block_on(execute_async_with_middleware(
+            root.matching_middlewares("/a"),
+            async_caller_handler,
+            test_request("/a"),
+            HashMap::new(),
+        ));
+        assert_eq!(body_str(resp), "alice");
+        assert!(extensions::with(Extensions::is_empty));
+    }
+
+    /// Extensions are handed over when the async handler is called; a
+    /// future that looks them up later finds none.
+    #[test]
+    fn async_handler_extensions_are_cleared_once_it_returns_its_future() {
+        fn late_caller_handler(_: HttpRequest<'static>, _: RouteParams) -> HandlerFuture {
+            Box::pin(async move {
+                let caller = extensions::take().get::<Caller>().map(|c| c.0);
+                response_with_text(caller.unwrap_or("anonymous"))
+            })
+        }
+
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.set_middleware("/", attach_caller);
+        let resp = block_on(execute_async_with_middleware(
+            root.matching_middlewares("/a"),
+            late_caller_handler,
+            test_request("/a"),
+            HashMap::new(),
+        ));
+        assert_eq!(body_str(resp), "anonymous");
+    }
+
     /// match_path returns handlers and params without method dispatch.
     #[test]
     fn test_match_path_returns_handlers() {
@@ -1841,6 +3148,10 @@ mod tests {
             certification: crate::certification::CertificationMode::skip(),
             ttl: Some(std::time::Duration::from_secs(60)),
             headers: vec![],
+            compress: None,
+            cors: None,
+            max_body: None,
+            accepts: vec![],
         };
         root.set_route_config("/api/users", config);
 
@@ -1869,6 +3180,10 @@ mod tests {
             certification: crate::certification::CertificationMode::skip(),
             ttl: None,
             headers: vec![],
+            compress: None,
+            cors: None,
+            max_body: None,
+            accepts: vec![],
         };
         root.set_route_config("/test", config1);
 
@@ -1876,6 +3191,10 @@ mod tests {
             certification: crate::certification::CertificationMode::authenticated(),
             ttl: Some(std::time::Duration::from_secs(300)),
             headers: vec![],
+            compress: None,
+            cors: None,
+            max_body: None,
+            accepts: vec![],
         };
         root.set_route_config("/test", config2);
 
@@ -1979,6 +3298,10 @@ mod tests {
             certification: crate::certification::CertificationMode::skip(),
             ttl: None,
             headers: vec![],
+            compress: None,
+            cors: None,
+            max_body: None,
+            accepts: vec![],
         };
         root.set_route_config("/users/:id", config);
 
@@ -0,0 +1,662 @@
+//! Signed session cookies backed by a canister-side session store (requires
+//! the `sessions` feature).
+//!
+//! [`middleware`] loads the session named by the request's session cookie
+//! and attaches it to the request [extensions](crate::extensions); handlers
+//! read and change it through
+//! [`RouteContext::session`](crate::RouteContext::session):
+//!
+//! ```rust,ignore
+//! // src/routes/middleware.rs
+//! pub fn middleware(
+//!     req: HttpRequest,
+//!     params: &RouteParams,
+//!     next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+//! ) -> HttpResponse<'static> {
+//!     ic_asset_router::session::middleware(req, params, next)
+//! }
+//!
+//! // src/routes/cart.rs
+//! #[route(certification = "skip")]
+//! pub fn post(ctx: RouteContext<()>) -> HttpResponse<'static> {
+//!     let session = ctx.session().unwrap();
+//!     let count = session.get::<u32>("items").unwrap_or(0);
+//!     session.insert("items", &(count + 1)).unwrap();
+//!     // ...
+//! }
+//! ```
+//!
+//! Session data lives in the canister heap, keyed by a random-looking id.
+//! The cookie carries the id and an HMAC-SHA256 signature over it, so
+//! clients cannot forge or guess ids. Sessions are created lazily: a
+//! request that never writes to its session sets no cookie.
+//!
+//! Sessions are written only in
+//! [`http_request_update`](crate::http_request_update). When a handler
+//! changes the session during a query call, the middleware discards the
+//! response and asks the gateway to repeat the request as an update call,
+//! where the handler runs again and the change is stored.
+//!
+//! Configure sessions by registering a [`SessionConfig`] as application
+//! state with [`SetupBuilder::with_state`](crate::SetupBuilder::with_state).
+//! The secret should be random and kept across upgrades, for example drawn
+//! once from `raw_rand` and stored with the rest of the canister state:
+//!
+//! ```rust,ignore
+//! ic_asset_router::setup(routes)
+//!     .with_state(SessionConfig::new(secret))
+//!     .build();
+//! ```
+//!
+//! A session expires [`SessionConfig::ttl`] after it was last written. An
+//! IC timer started by the first update call through [`middleware`] removes
+//! expired sessions every [`SessionConfig::sweep_interval`]. The store is
+//! heap memory: carry it across upgrades with [`snapshot`] and [`restore`].
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use candid::CandidType;
+use ic_http_certification::{HttpRequest, HttpResponse};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::cookie::{self, Cookie, SameSite};
+use crate::extensions;
+use crate::ic_context::{self, ExecutionMode};
+use crate::middleware;
+use crate::router::RouteParams;
+use crate::state::AppState;
+
+thread_local! {
+    static STORE: RefCell<HashMap<String, StoredSession>> = RefCell::new(HashMap::new());
+    static SWEEPER_STARTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Session settings, registered as application state.
+#[derive(Clone)]
+pub struct SessionConfig {
+    /// Key for signing session ids and deriving new ones. Use at least 32
+    /// random bytes.
+    pub secret: Vec<u8>,
+    /// Name of the session cookie. Defaults to `session`.
+    pub cookie_name: String,
+    /// `Path` attribute of the session cookie. Defaults to `/`.
+    pub cookie_path: String,
+    /// `SameSite` attribute of the session cookie. Defaults to
+    /// [`SameSite::Lax`].
+    pub same_site: SameSite,
+    /// How long a session lives after it was last written. Defaults to 24
+    /// hours.
+    pub ttl: Duration,
+    /// How often expired sessions are removed from the store. Defaults to 1
+    /// hour.
+    pub sweep_interval: Duration,
+}
+
+impl SessionConfig {
+    /// Default settings with the given signing secret.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            cookie_name: "session".to_string(),
+            cookie_path: "/".to_string(),
+            same_site: SameSite::Lax,
+            ttl: Duration::from_secs(24 * 60 * 60),
+            sweep_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl fmt::Debug for SessionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionConfig")
+            .field("cookie_name", &self.cookie_name)
+            .field("cookie_path", &self.cookie_path)
+            .field("same_site", &self.same_site)
+            .field("ttl", &self.ttl)
+            .field("sweep_interval", &self.sweep_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A session as kept in the store.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct StoredSession {
+    /// Session id.
+    pub id: String,
+    /// JSON-encoded values by key.
+    pub data: BTreeMap<String, String>,
+    /// IC time in nanoseconds after which the session is void.
+    pub expires_at: u64,
+}
+
+/// Serializable copy of the session store. See [`snapshot`].
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    /// Stored sessions, in no particular order.
+    pub sessions: Vec<StoredSession>,
+}
+
+/// The session of the request being handled.
+///
+/// Values are stored as JSON under string keys. Cloning is cheap: clones
+/// share the same session, so changes made by the handler are seen by the
+/// middleware that stores them.
+#[derive(Clone, Default)]
+pub struct Session {
+    inner: Rc<RefCell<SessionState>>,
+}
+
+#[derive(Default)]
+struct SessionState {
+    id: Option<String>,
+    data: BTreeMap<String, String>,
+    modified: bool,
+    read: bool,
+    destroyed: Option<String>,
+}
+
+impl Session {
+    /// An empty session that has not been stored, for hand-built contexts in
+    /// tests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn stored(id: String, data: BTreeMap<String, String>) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(SessionState {
+                id: Some(id),
+                data,
+                ..SessionState::default()
+            })),
+        }
+    }
+
+    /// The session id, or `None` if the session has not been stored yet.
+    pub fn id(&self) -> Option<String> {
+        let mut state = self.inner.borrow_mut();
+        state.read = true;
+        state.id.clone()
+    }
+
+    /// The value stored under `key`, or `None` if there is none or it does
+    /// not deserialize as `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let mut state = self.inner.borrow_mut();
+        state.read = true;
+        serde_json::from_str(state.data.get(key)?).ok()
+    }
+
+    /// Store `value` under `key`.
+    pub fn insert<T: Serialize + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+    ) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_string(value)?;
+        let mut state = self.inner.borrow_mut();
+        state.data.insert(key.to_string(), value);
+        state.modified = true;
+        Ok(())
+    }
+
+    /// Remove the value under `key`, returning whether there was one.
+    pub fn remove(&self, key: &str) -> bool {
+        let mut state = self.inner.borrow_mut();
+        let removed = state.data.remove(key).is_some();
+        state.modified |= removed;
+        removed
+    }
+
+    /// Whether the session holds no values.
+    pub fn is_empty(&self) -> bool {
+        let mut state = self.inner.borrow_mut();
+        state.read = true;
+        state.data.is_empty()
+    }
+
+    /// Delete the session from the store and the client.
+    ///
+    /// Values inserted afterwards start a new session with a new id.
+    pub fn destroy(&self) {
+        let mut state = self.inner.borrow_mut();
+        if let Some(id) = state.id.take() {
+            state.destroyed.get_or_insert(id);
+        }
+        state.data.clear();
+        state.modified = true;
+    }
+
+    /// Whether the session was changed since it was loaded or last stored.
+    pub fn is_modified(&self) -> bool {
+        self.inner.borrow().modified
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.inner.borrow();
+        f.debug_struct("Session")
+            .field("id", &state.id)
+            .field("len", &state.data.len())
+            .field("modified", &state.modified)
+            .finish()
+    }
+}
+
+/// Middleware that loads the request's session and stores changes made to
+/// it.
+///
+/// Responses to requests whose session was loaded from the store or read
+/// by the handler carry `Vary: Cookie`, so the router does not cache them
+/// for other clients.
+///
+/// # Panics
+///
+/// Panics if no [`SessionConfig`] is registered as application state.
+pub fn middleware(
+    req: HttpRequest,
+    params: &RouteParams,
+    next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+) -> HttpResponse<'static> {
+    let mode = ic_context::current_mode();
+    let now = ic_cdk::api::time();
+    if mode == ExecutionMode::Update && !SWEEPER_STARTED.replace(true) {
+        let interval = AppState::current().expect::<SessionConfig>().sweep_interval;
+        ic_cdk_timers::set_timer_interval(interval, || {
+            purge_expired(ic_cdk::api::time());
+        });
+    }
+    run(req, params, next, mode, now)
+}
+
+fn run(
+    req: HttpRequest,
+    params: &RouteParams,
+    next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    mode: ExecutionMode,
+    now: u64,
+) -> HttpResponse<'static> {
+    let state = AppState::current();
+    let config = state.expect::<SessionConfig>();
+    // An outer session middleware may have loaded the session already.
+    if extensions::with(|current| current.contains::<Session>()) {
+        return next(req, params);
+    }
+    let session = load(&req, config, now);
+    extensions::insert(session.clone());
+
+    let loaded = session.inner.borrow().id.is_some();
+    let config = config.clone();
+    middleware::on_response(move |mut response| {
+        if loaded || session.inner.borrow().read {
+            cookie::vary_by_cookie(&mut response);
+        }
+        if !session.is_modified() {
+            return response;
+        }
+        match mode {
+            ExecutionMode::Query => HttpResponse::builder().with_upgrade(true).build(),
+            ExecutionMode::Update => {
+                if let Some(cookie) = save(&session, &config, now) {
+                    response.add_header(cookie.into_header());
+                }
+                response
+            }
+        }
+    });
+    next(req, params)
+}
+
+/// The session named by the request's session cookie, or a new empty one if
+/// the cookie is missing, badly signed or names no live session.
+fn load(req: &HttpRequest, config: &SessionConfig, now: u64) -> Session {
+    let id = cookie::find_cookie(req.headers(), &config.cookie_name)
+        .and_then(|value| cookie::verify(&value, &config.secret).map(str::to_string));
+    let stored = id.and_then(|id| {
+        STORE.with_borrow(|store| {
+            store
+                .get(&id)
+                .filter(|stored| stored.expires_at > now)
+                .cloned()
+        })
+    });
+    match stored {
+        Some(stored) => Session::stored(stored.id, stored.data),
+        None => Session::new(),
+    }
+}
+
+/// Write `session` to the store and return the cookie that points the client
+/// at it, or removes the client's cookie when the session ended.
+fn save(session: &Session, config: &SessionConfig, now: u64) -> Option<Cookie> {
+    let mut state = session.inner.borrow_mut();
+    state.modified = false;
+    let destroyed = state.destroyed.take();
+    if let Some(id) = &destroyed {
+        STORE.with_borrow_mut(|store| store.remove(id));
+    }
+
+    if state.data.is_empty() {
+        let id = state.id.take();
+        if let Some(id) = &id {
+            STORE.with_borrow_mut(|store| store.remove(id));
+        }
+        return (id.is_some() || destroyed.is_some())
+            .then(|| Cookie::removal(&config.cookie_name).with_path(&config.cookie_path));
+    }
+
+    // Sessions are saved only in update calls, where the token counter
+    // persists, so ids need no request entropy.
+    let id = state
+        .id
+        .get_or_insert_with(|| cookie::new_token(&config.secret, now, &[]))
+        .clone();
+    let stored = StoredSession {
+        id: id.clone(),
+        data: state.data.clone(),
+        expires_at: now.saturating_add(config.ttl.as_nanos() as u64),
+    };
+    STORE.with_borrow_mut(|store| store.insert(id.clone(), stored));
+    Some(
+        Cookie::new(&config.cookie_name, &cookie::sign(&id, &config.secret))
+            .with_path(&config.cookie_path)
+            .with_max_age(config.ttl)
+            .with_same_site(config.same_site),
+    )
+}
+
+/// Remove sessions that expired at or before `now` (IC time in
+/// nanoseconds), returning how many were removed.
+///
+/// The timer started by [`middleware`] calls this periodically.
+pub fn purge_expired(now: u64) -> usize {
+    STORE.with_borrow_mut(|store| {
+        let before = store.len();
+        store.retain(|_, stored| stored.expires_at > now);
+        before - store.len()
+    })
+}
+
+/// Capture the session store as a serializable snapshot.
+///
+/// Store it in stable memory during `pre_upgrade` alongside the rest of
+/// the canister state and hand it to [`restore`] in `post_upgrade`.
+pub fn snapshot() -> SessionSnapshot {
+    STORE.with_borrow(|store| SessionSnapshot {
+        sessions: store.values().cloned().collect(),
+    })
+}
+
+/// Replace the session store with a snapshot captured by [`snapshot`].
+pub fn restore(snapshot: SessionSnapshot) {
+    STORE.with_borrow_mut(|store| {
+        *store = snapshot
+            .sessions
+            .into_iter()
+            .map(|stored| (stored.id.clone(), stored))
+            .collect();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::{NodeType, RouteNode};
+    use ic_http_certification::{Method, StatusCode};
+
+    const SECOND: u64 = 1_000_000_000;
+
+    fn config() -> SessionConfig {
+        SessionConfig::new(b"0123456789abcdef0123456789abcdef".to_vec())
+    }
+
+    fn request(cookie: Option<&str>) -> HttpRequest<'static> {
+        let headers = cookie
+            .map(|c| vec![("cookie".to_string(), c.to_string())])
+            .unwrap_or_default();
+        HttpRequest::builder()
+            .with_method(Method::POST)
+            .with_url("/cart")
+            .with_headers(headers)
+            .build()
+    }
+
+    fn ok() -> HttpResponse<'static> {
+        HttpResponse::builder()
+            .with_status_code(StatusCode::OK)
+            .build()
+    }
+
+    fn set_cookie(response: &HttpResponse) -> Option<String> {
+        response
+            .headers()
+            .iter()
+            .find(|(k, _)| k == "set-cookie")
+            .map(|(_, v)| v.clone())
+    }
+
+    /// Run [`run`] in a fresh dispatch scope with `handler` as the handler.
+    fn dispatch(
+        cookie: Option<&str>,
+        mode: ExecutionMode,
+        now: u64,
+        handler: impl Fn(&Session),
+    ) -> HttpResponse<'static> {
+        AppState::set_current(AppState::new().with(config()));
+        let next = |_req: HttpRequest, _params: &RouteParams| {
+            let session = extensions::with(|current| current.get::<Session>().cloned());
+            handler(&session.expect("session attached"));
+            middleware::handle(ok)
+        };
+        extensions::scope(|| {
+            middleware::scope(|| run(request(cookie), &RouteParams::new(), &next, mode, now))
+        })
+    }
+
+    /// The `name=value` pair of a `Set-Cookie` header.
+    fn cookie_pair(set_cookie: &str) -> String {
+        set_cookie.split(';').next().unwrap().to_string()
+    }
+
+    #[test]
+    fn unchanged_session_sets_no_cookie() {
+        let response = dispatch(None, ExecutionMode::Update, SECOND, |session| {
+            assert!(session.id().is_none());
+            assert!(session.is_empty());
+        });
+        assert_eq!(set_cookie(&response), None);
+        assert!(snapshot().sessions.is_empty());
+    }
+
+    #[test]
+    fn update_stores_session_and_sets_signed_cookie() {
+        let response = dispatch(None, ExecutionMode::Update, SECOND, |session| {
+            session.insert("items", &3u32).unwrap();
+        });
+        let header = set_cookie(&response).expect("set-cookie");
+        assert!(header.ends_with("; Path=/; Max-Age=86400; Secure; HttpOnly; SameSite=Lax"));
+
+        let cookie = cookie_pair(&header);
+        dispatch(Some(&cookie), ExecutionMode::Query, 2 * SECOND, |session| {
+            assert!(session.id().is_some());
+            assert_eq!(session.get::<u32>("items"), Some(3));
+            assert!(!session.is_modified());
+        });
+    }
+
+    #[test]
+    fn query_changes_upgrade_without_storing() {
+        let response = dispatch(None, ExecutionMode::Query, SECOND, |session| {
+            session.insert("items", &1u32).unwrap();
+        });
+        assert_eq!(response.upgrade(), Some(true));
+        assert_eq!(set_cookie(&response), None);
+        assert!(snapshot().sessions.is_empty());
+    }
+
+    #[test]
+    fn forged_and_expired_cookies_start_a_new_session() {
+        let response = dispatch(None, ExecutionMode::Update, SECOND, |session| {
+            session.insert("user", "alice").unwrap();
+        });
+        let cookie = cookie_pair(&set_cookie(&response).unwrap());
+
+        let forged = format!("{}0", cookie);
+        dispatch(
+            Some(&forged),
+            ExecutionMode::Update,
+            2 * SECOND,
+            |session| {
+                assert!(session.get::<String>("user").is_none());
+            },
+        );
+
+        let expired = SECOND + config().ttl.as_nanos() as u64;
+        dispatch(Some(&cookie), ExecutionMode::Update, expired, |session| {
+            assert!(session.id().is_none());
+        });
+    }
+
+    #[test]
+    fn destroy_removes_session_and_cookie() {
+        let response = dispatch(None, ExecutionMode::Update, SECOND, |session| {
+            session.insert("user", "alice").unwrap();
+        });
+        let cookie = cookie_pair(&set_cookie(&response).unwrap());
+
+        let response = dispatch(
+            Some(&cookie),
+            ExecutionMode::Update,
+            2 * SECOND,
+            |session| {
+                session.destroy();
+            },
+        );
+        assert_eq!(
+            set_cookie(&response).as_deref(),
+            Some("session=; Path=/; Max-Age=0; Secure; HttpOnly")
+        );
+        assert!(snapshot().sessions.is_empty());
+    }
+
+    #[test]
+    fn inner_middleware_reuses_the_loaded_session() {
+        AppState::set_current(AppState::new().with(config()));
+        let handler = |_req: HttpRequest, _params: &RouteParams| {
+            extensions::with(|current| current.get::<Session>().cloned())
+                .unwrap()
+                .insert("n", &1u8)
+                .unwrap();
+            middleware::handle(ok)
+        };
+        let inner = |req: HttpRequest, params: &RouteParams| {
+            run(req, params, &handler, ExecutionMode::Update, SECOND)
+        };
+        let response = extensions::scope(|| {
+            middleware::scope(|| {
+                run(
+                    request(None),
+                    &RouteParams::new(),
+                    &inner,
+                    ExecutionMode::Update,
+                    SECOND,
+                )
+            })
+        });
+        let cookies = response
+            .headers()
+            .iter()
+            .filter(|(k, _)| k == "set-cookie")
+            .count();
+        assert_eq!(cookies, 1);
+        assert_eq!(snapshot().sessions.len(), 1);
+    }
+
+    fn vary(response: &HttpResponse) -> Option<String> {
+        response
+            .headers()
+            .iter()
+            .find(|(k, _)| k == "vary")
+            .map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn reading_or_loading_a_session_varies_by_cookie() {
+        let response = dispatch(None, ExecutionMode::Query, SECOND, |_| {});
+        assert_eq!(vary(&response), None);
+
+        let response = dispatch(None, ExecutionMode::Query, SECOND, |session| {
+            assert!(session.get::<String>("user").is_none());
+        });
+        assert_eq!(vary(&response).as_deref(), Some("cookie"));
+
+        let response = dispatch(None, ExecutionMode::Update, SECOND, |session| {
+            session.insert("user", "alice").unwrap();
+        });
+        let cookie = cookie_pair(&set_cookie(&response).unwrap());
+        let response = dispatch(Some(&cookie), ExecutionMode::Query, 2 * SECOND, |_| {});
+        assert_eq!(vary(&response).as_deref(), Some("cookie"));
+    }
+
+    fn test_middleware(
+        req: HttpRequest,
+        params: &RouteParams,
+        next: &dyn Fn(HttpRequest, &RouteParams) -> HttpResponse<'static>,
+    ) -> HttpResponse<'static> {
+        run(req, params, next, ExecutionMode::Update, SECOND)
+    }
+
+    fn greeting(_req: HttpRequest, _params: RouteParams) -> HttpResponse<'static> {
+        let session = extensions::with(|current| current.get::<Session>().cloned()).unwrap();
+        let name = session.get::<String>("user").unwrap_or_default();
+        HttpResponse::builder()
+            .with_status_code(StatusCode::OK)
+            .with_body(format!("signed in as {name}").into_bytes())
+            .build()
+    }
+
+    #[test]
+    fn responses_that_read_the_session_are_not_cached() {
+        AppState::set_current(AppState::new().with(config()));
+        let mut root = RouteNode::new(NodeType::Static("".into()));
+        root.insert("/account", Method::GET, greeting);
+        root.set_middleware("/", test_middleware);
+        let req = HttpRequest::builder()
+            .with_method(Method::GET)
+            .with_url("/account")
+            .build();
+        let response = crate::http_request_update(req, &root);
+        assert_eq!(response.body(), b"signed in as ");
+        assert!(!crate::dynamic_cache_snapshot()
+            .assets
+            .iter()
+            .any(|asset| asset.path == "/account"));
+    }
+
+    #[test]
+    fn purge_expired_and_snapshot_round_trip() {
+        restore(SessionSnapshot {
+            sessions: vec![
+                StoredSession {
+                    id: "old".to_string(),
+                    data: BTreeMap::new(),
+                    expires_at: 10,
+                },
+                StoredSession {
+                    id: "new".to_string(),
+                    data: BTreeMap::new(),
+                    expires_at: 20,
+                },
+            ],
+        });
+        assert_eq!(purge_expired(10), 1);
+        let ids: Vec<_> = snapshot().sessions.into_iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec!["new".to_string()]);
+    }
+}
@@ -0,0 +1,132 @@
+//! Typed application state shared by handlers and middleware.
+//!
+//! State values are registered once with
+//! [`SetupBuilder::with_state`](crate::SetupBuilder::with_state), one per
+//! type, and looked up by type. Handlers read them through
+//! [`RouteContext::state`](crate::RouteContext::state); middleware and other
+//! code call [`AppState::current`]. Values are shared, so mutable state is
+//! stored behind interior mutability such as a `RefCell`:
+//!
+//! ```rust,ignore
+//! struct Store { items: RefCell<Vec<Item>> }
+//!
+//! ic_asset_router::setup(routes)
+//!     .with_state(Store { items: RefCell::new(Vec::new()) })
+//!     .build();
+//!
+//! pub fn get(ctx: RouteContext<()>) -> HttpResponse<'static> {
+//!     let count = ctx.state::<Store>().items.borrow().len();
+//!     // ...
+//! }
+//! ```
+//!
+//! In tests, build a [`RouteContext`](crate::RouteContext) with
+//! `app_state: AppState::new().with(mock)` to hand a handler its own state.
+
+use std::any::{type_name, Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+thread_local! {
+    static CURRENT: RefCell<AppState> = RefCell::new(AppState::new());
+}
+
+/// A set of shared state values, at most one per type.
+///
+/// Cloning is cheap: clones share the same values.
+#[derive(Clone, Default)]
+pub struct AppState {
+    values: Rc<HashMap<TypeId, Rc<dyn Any>>>,
+}
+
+impl AppState {
+    /// An empty state set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `value`, replacing any value of the same type.
+    pub fn with<T: 'static>(mut self, value: T) -> Self {
+        Rc::make_mut(&mut self.values).insert(TypeId::of::<T>(), Rc::new(value));
+        self
+    }
+
+    /// The value of type `T`, if one was added.
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    /// The value of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no value of type `T` was added.
+    pub fn expect<T: 'static>(&self) -> &T {
+        self.get().unwrap_or_else(|| {
+            panic!(
+                "no application state of type `{}`; register it with `setup(..).with_state(..)`",
+                type_name::<T>()
+            )
+        })
+    }
+
+    /// The state registered with [`SetupBuilder::with_state`](crate::SetupBuilder::with_state).
+    pub fn current() -> AppState {
+        CURRENT.with_borrow(Clone::clone)
+    }
+
+    /// Replace the state returned by [`current`](Self::current).
+    pub(crate) fn set_current(state: AppState) {
+        CURRENT.with_borrow_mut(|current| *current = state);
+    }
+}
+
+impl fmt::Debug for AppState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppState")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter(RefCell<u32>);
+
+    #[test]
+    fn values_are_looked_up_by_type() {
+        let state = AppState::new().with(Counter(RefCell::new(1))).with("name");
+        assert_eq!(*state.expect::<Counter>().0.borrow(), 1);
+        assert_eq!(state.get::<&str>(), Some(&"name"));
+        assert!(state.get::<u64>().is_none());
+    }
+
+    #[test]
+    fn clones_share_values_and_with_replaces() {
+        let state = AppState::new().with(Counter(RefCell::new(1)));
+        let clone = state.clone();
+        *clone.expect::<Counter>().0.borrow_mut() += 1;
+        assert_eq!(*state.expect::<Counter>().0.borrow(), 2);
+
+        let replaced = state.clone().with(Counter(RefCell::new(10)));
+        assert_eq!(*replaced.expect::<Counter>().0.borrow(), 10);
+        assert_eq!(*state.expect::<Counter>().0.borrow(), 2);
+    }
+
+    #[test]
+    fn current_is_set_by_set_current() {
+        assert!(AppState::current().get::<u8>().is_none());
+        AppState::set_current(AppState::new().with(7u8));
+        assert_eq!(AppState::current().get::<u8>(), Some(&7));
+    }
+
+    #[test]
+    #[should_panic(expected = "no application state of type `u16`")]
+    fn expect_panics_for_missing_type() {
+        AppState::new().expect::<u16>();
+    }
+}
@@ -0,0 +1,171 @@
+//! Candid types for streaming large assets through IC streaming callbacks.
+//!
+//! Assets larger than [`ASSET_CHUNK_SIZE`](crate::asset_router::ASSET_CHUNK_SIZE)
+//! are served in chunks. A request with a `Range` header gets the chunk it
+//! asks for as a `206 Partial Content`, but clients that never send `Range`
+//! (downloads, `curl`) need the whole body. The HTTP interface delivers it
+//! with a streaming strategy: the first response carries the first chunk
+//! and a [`StreamingCallbackToken`], and the HTTP gateway calls the
+//! canister's `http_request_streaming_callback` query with the token for
+//! each following chunk. The gateway verifies the assembled body against
+//! the certification of the full response.
+//!
+//! [`http_request`](crate::http_request) returns an [`HttpResponse`], which
+//! cannot carry a streaming strategy. To stream, declare the query method
+//! with [`StreamingHttpResponse`], call
+//! [`http_request_streaming`](crate::http_request_streaming), and export the
+//! callback:
+//!
+//! ```rust,ignore
+//! use ic_asset_router::streaming::{
+//!     StreamingCallbackHttpResponse, StreamingCallbackToken, StreamingHttpResponse,
+//! };
+//!
+//! #[query]
+//! fn http_request(req: HttpRequest) -> StreamingHttpResponse {
+//!     route_tree::ROUTES.with(|routes| {
+//!         ic_asset_router::http_request_streaming(req, routes, HttpRequestOptions::default())
+//!     })
+//! }
+//!
+//! #[query]
+//! fn http_request_streaming_callback(
+//!     token: StreamingCallbackToken,
+//! ) -> StreamingCallbackHttpResponse {
+//!     ic_asset_router::http_request_streaming_callback(token)
+//! }
+//! ```
+
+use candid::{CandidType, Principal};
+use ic_http_certification::{HeaderField, HttpResponse};
+use serde::Deserialize;
+
+/// Name of the query method the HTTP gateway calls for the next chunk.
+pub const STREAMING_CALLBACK_METHOD: &str = "http_request_streaming_callback";
+
+/// Names the next chunk of a streamed asset.
+///
+/// The gateway passes the token back unchanged. `etag` is the identity
+/// ETag of the asset when streaming started; if the asset was re-certified
+/// since, the callback refuses rather than mix chunks of two bodies.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct StreamingCallbackToken {
+    /// Canonical path of the streamed asset.
+    pub path: String,
+    /// Index of the chunk to return.
+    pub index: u64,
+    /// ETag of the asset's identity body.
+    pub etag: String,
+}
+
+candid::define_function!(pub StreamingCallback : (StreamingCallbackToken) -> (StreamingCallbackHttpResponse) query);
+
+/// How the gateway fetches the rest of a streamed body.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum StreamingStrategy {
+    /// Call `callback` with `token`, then with each token it returns.
+    Callback {
+        /// The canister's `http_request_streaming_callback` query.
+        callback: StreamingCallback,
+        /// Token for the second chunk.
+        token: StreamingCallbackToken,
+    },
+}
+
+impl StreamingStrategy {
+    /// A strategy calling [`STREAMING_CALLBACK_METHOD`] on `canister` with
+    /// `token`.
+    pub fn callback(canister: Principal, token: StreamingCallbackToken) -> Self {
+        StreamingStrategy::Callback {
+            callback: StreamingCallback::new(canister, STREAMING_CALLBACK_METHOD.to_string()),
+            token,
+        }
+    }
+}
+
+/// A chunk returned by the streaming callback.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct StreamingCallbackHttpResponse {
+    /// The chunk's bytes.
+    pub body: Vec<u8>,
+    /// Token for the following chunk, or `None` after the last one.
+    pub token: Option<StreamingCallbackToken>,
+}
+
+/// An [`HttpResponse`] that can carry a [`StreamingStrategy`].
+///
+/// Has the same Candid fields as [`HttpResponse`] plus the optional
+/// `streaming_strategy` of the HTTP interface.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct StreamingHttpResponse {
+    /// HTTP status code.
+    pub status_code: u16,
+    /// Response headers.
+    pub headers: Vec<HeaderField>,
+    /// The body, or its first chunk when streaming.
+    pub body: Vec<u8>,
+    /// Whether the request should be upgraded to an update call.
+    pub upgrade: Option<bool>,
+    /// How to fetch the rest of the body, if it is streamed.
+    pub streaming_strategy: Option<StreamingStrategy>,
+}
+
+impl From<HttpResponse<'static>> for StreamingHttpResponse {
+    fn from(response: HttpResponse<'static>) -> Self {
+        StreamingHttpResponse {
+            status_code: response.status_code().as_u16(),
+            headers: response.headers().to_vec(),
+            upgrade: response.upgrade(),
+            body: response.body().to_vec(),
+            streaming_strategy: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_http_certification::StatusCode;
+
+    #[test]
+    fn converts_plain_responses_without_a_strategy() {
+        let response = HttpResponse::builder()
+            .with_status_code(StatusCode::NOT_FOUND)
+            .with_headers(vec![("content-type".to_string(), "text/plain".to_string())])
+            .with_body(b"missing".to_vec())
+            .build();
+        let streaming = StreamingHttpResponse::from(response);
+        assert_eq!(streaming.status_code, 404);
+        assert_eq!(streaming.body, b"missing");
+        assert_eq!(streaming.upgrade, None);
+        assert!(streaming.streaming_strategy.is_none());
+    }
+
+    #[test]
+    fn round_trips_through_candid() {
+        let token = StreamingCallbackToken {
+            path: "/movie.mp4".to_string(),
+            index: 1,
+            etag: "\"abc\"".to_string(),
+        };
+        let response = StreamingHttpResponse {
+            status_code: 200,
+            headers: vec![],
+            body: vec![1, 2, 3],
+            upgrade: None,
+            streaming_strategy: Some(StreamingStrategy::callback(
+                Principal::anonymous(),
+                token.clone(),
+            )),
+        };
+        let bytes = candid::encode_one(&response).unwrap();
+        let decoded: StreamingHttpResponse = candid::decode_one(&bytes).unwrap();
+        assert_eq!(decoded.body, vec![1, 2, 3]);
+        let Some(StreamingStrategy::Callback { callback, token: t }) = decoded.streaming_strategy
+        else {
+            panic!("expected a callback strategy");
+        };
+        assert_eq!(callback.0.method, STREAMING_CALLBACK_METHOD);
+        assert_eq!(t, token);
+    }
+}